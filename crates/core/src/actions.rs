@@ -1,5 +1,8 @@
     use crate::domain::*;
     use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tokio::sync::mpsc::UnboundedSender;
 
     //define actions
     pub enum Action {
@@ -16,13 +19,83 @@
             name: String,
         },
         SaveProject,
-        LoadProject(PathBuf),
+        /// Fails with `ProjectError::Locked` if another instance has the project open.
+        /// Retrying with `force` takes the lock over, or with `read_only` opens it for
+        /// review without taking the lock at all.
+        LoadProject{
+            path: PathBuf,
+            force: bool,
+            read_only: bool,
+        },
+        /// Sets whether files keep an embedded snapshot of their text by default
+        EmbedSources(bool),
     }
 
     pub enum FileAction {
-        AddFile(PathBuf),
+        /// `mime` is the type the file came labelled with, if any, see `FileLoader::add_file`
+        AddFile{
+            path: PathBuf,
+            mime: Option<String>,
+        },
         LoadFile(FileId),
-        //FindFile(FileId) save this for future. noting here because losing file ref is important MVP handling
+        /// Answers a `FileDrifted` report for a file whose contents changed
+        ResolveDrift{
+            file_id: FileId,
+            resolution: DriftResolution,
+        },
+        /// Looks for a file that went missing in the project root and `folders`
+        FindFile{
+            file_id: FileId,
+            folders: Vec<PathBuf>,
+        },
+        /// Points a file at `path`, e.g. a proposal from `FindFile`. With `whole_folder`, other
+        /// missing files from the same folder are relinked too if they turn up in the new one.
+        RelinkFile{
+            file_id: FileId,
+            path: PathBuf,
+            whole_folder: bool,
+        },
+        /// Overrides the project's embedding setting for one file. None follows the project again.
+        EmbedSource{
+            file_id: FileId,
+            embed: Option<bool>,
+        },
+        /// Reads a CSV or XLSX file's header and rows so the columns to import can be chosen.
+        /// `sheet` picks an XLSX worksheet, the first one when None.
+        PreviewSurvey{
+            path: PathBuf,
+            sheet: Option<String>,
+        },
+        /// Adds a spreadsheet's answers as survey files, one per text column or per respondent
+        ImportSurvey{
+            path: PathBuf,
+            selection: SurveySelection,
+            grouping: SurveyGrouping,
+        },
+        /// Lists the formats files can be added in
+        ListFormats,
+        /// Adds every file under `path` that `filter` lets through, leaving out files whose
+        /// content is already in the project. A few files are read at a time, each one finished
+        /// is reported on `progress`, and `cancel` stops the import once the files in flight are done.
+        AddFolder{
+            path: PathBuf,
+            filter: FolderFilter,
+            progress: Option<UnboundedSender<ImportProgress>>,
+            cancel: CancelToken,
+        },
+    }
+
+    /// Stops a long running action early. Clones share the same flag, so the UI keeps one and
+    /// passes another along with the action.
+    #[derive(Debug, Clone, Default)]
+    pub struct CancelToken(Arc<AtomicBool>);
+
+    impl CancelToken {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        pub fn cancel(&self) { self.0.store(true, Ordering::SeqCst); }
+        pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::SeqCst) }
     }
 
     pub enum SchemaAction {
@@ -38,11 +111,41 @@
             snippet: String,
         },
     }
+    #[derive(Debug)]
     pub enum ActionResult {
         Quit,
         Success,
         ThemeCreated(ThemeId),
         CodeCreated(CodeDefId),
         FileAdded(FileId),
+        FileLoaded(FileId),
+        /// The source couldn't be read, so the file was loaded from its embedded snapshot
+        FileLoadedFromSnapshot(FileId),
+        /// The file changed since it was last loaded and some highlights moved or were orphaned
+        FileReanchored {
+            file_id: FileId,
+            report: ReanchorReport,
+        },
+        /// The source file no longer matches what was coded. For edits the blocks are loaded
+        /// for review, but highlights stay put until a `ResolveDrift` action.
+        FileDrifted {
+            file_id: FileId,
+            drift: FileDrift,
+        },
+        /// Possible new locations for a missing file, best first. Empty if nothing was found.
+        RelinkProposals(Vec<RelinkProposal>),
+        FilesRelinked(Vec<FileId>),
+        SurveyPreview(SurveyTable),
+        /// Files created by one import, in order
+        FilesAdded(Vec<FileId>),
+        Formats(Vec<FormatInfo>),
+        FolderImported(FolderImportReport),
         CodeApplied(QualCodeId),
+        ProjectMigrated(MigrationReport),
+        /// Changes made since the last save were only partly recovered from the journal.
+        /// Carries the migration too if the project was also upgraded.
+        JournalRecovered {
+            recovery: JournalRecovery,
+            migration: Option<MigrationReport>,
+        },
     }
\ No newline at end of file
@@ -3,10 +3,16 @@ use crate::domain::*;
 use crate::ports::*;
 use crate::actions::*;
 
-use std::path::{ PathBuf };
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, VecDeque};
+use std::path::{ Path, PathBuf };
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
 use anyhow::{Result, Context};
+use futures::{stream, StreamExt};
 use serde::{Serialize, Deserialize};
+use tokio::sync::Notify;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
 
 /// **Shared app state wrapped for interior mutability**
 ///
@@ -21,12 +27,18 @@ pub type SharedState = Arc<RwLock<AppState>>;
 #[derive(Serialize, Deserialize)]
 pub struct AppConfig {
     pub theme: String,
+    /// How many files keep their blocks in memory before the least recently used is unloaded
+    #[serde(default = "default_max_loaded_files")]
+    pub max_loaded_files: usize,
 }
 
+fn default_max_loaded_files() -> usize { 20 }
+
 impl Default for AppConfig {
     fn default() -> Self {
         AppConfig {
             theme: "dark".to_string(),
+            max_loaded_files: default_max_loaded_files(),
         }
     }
 }
@@ -34,7 +46,8 @@ impl Default for AppConfig {
 pub struct ProjectContext {
     project: QualProject,
     path: PathBuf,
-    root: PathBuf
+    root: PathBuf,
+    read_only: bool,
 }
 
 impl ProjectContext {
@@ -43,20 +56,40 @@ impl ProjectContext {
             .expect("Project path must have parent")
             .to_path_buf();
 
-        ProjectContext { project, path, root }
+        ProjectContext { project, path, root, read_only: false }
+    }
+
+    /// Opens the project for review only: edits and saves are refused
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
     }
+
+    pub fn is_read_only(&self) -> bool { self.read_only }
 }
 
+/// How many characters of surrounding text are stored with a QualCode on each side of the highlight
+const CONTEXT_CHARS: usize = 80;
+/// How many files a folder import reads at once
+const FOLDER_IMPORT_CONCURRENCY: usize = 8;
+
 /// **Core application state container**
 ///
 /// Holds the current project context, codebook, file list, and configuration.
 /// Wrapped in [`SharedState`] (`Arc<Mutex<>>`) to allow shared access across
 /// the application while enabling safe mutation during brief, synchronous operations.
+///
+/// `revision` increases on every in-memory mutation. Saves snapshot it so a save that
+/// finishes after further edits doesn't mark the project clean.
+///
+/// `loaded_files` lists the files whose blocks are in memory, least recently used first.
 pub struct AppState {
     project: DataState<ProjectContext>,
     codebook: CodeBook,
     filemanager: FileList,
     config: AppConfig,
+    revision: u64,
+    loaded_files: VecDeque<FileId>,
 }
 
 
@@ -64,7 +97,106 @@ impl AppState {
     pub fn new(project: DataState<ProjectContext>, config: AppConfig) -> Self {
         let codebook = CodeBook::new();
         let filemanager = FileList::new();
-        AppState { project, codebook, filemanager, config }
+        AppState { project, codebook, filemanager, config, revision: 0, loaded_files: VecDeque::new() }
+    }
+
+    /// Flags the loaded project as having unsaved changes
+    fn mark_modified(&mut self) {
+        self.revision += 1;
+        self.project = match std::mem::replace(&mut self.project, DataState::Empty) {
+            DataState::Loaded(ctx) => DataState::Modified(ctx),
+            other => other,
+        };
+    }
+
+    /// Flags the project clean, unless it changed again after `revision` was snapshotted for saving
+    fn mark_saved(&mut self, revision: u64) {
+        if self.revision != revision {
+            return;
+        }
+        self.project = match std::mem::replace(&mut self.project, DataState::Empty) {
+            DataState::Modified(ctx) => DataState::Loaded(ctx),
+            other => other,
+        };
+    }
+
+    /// Marks `id` as the most recently used loaded file, then unloads the least recently
+    /// used files beyond `max_loaded_files`. Unloaded files go back to `DataState::Empty`
+    /// and are read again on their next LoadFile.
+    fn touch_loaded_file(&mut self, id: FileId) {
+        self.loaded_files.retain(|loaded| *loaded != id);
+        self.loaded_files.push_back(id);
+
+        while self.loaded_files.len() > self.config.max_loaded_files.max(1) {
+            let Some(evicted) = self.loaded_files.pop_front() else { break };
+            if let Some(file) = self.filemanager.file_mut(evicted) {
+                file.set_data_state(DataState::Empty);
+            }
+        }
+    }
+
+    /// Path of the open project, or an error if nothing is loaded
+    fn project_path(&self) -> Result<PathBuf, ProjectError> {
+        match &self.project {
+            DataState::Loaded(ctx) | DataState::Modified(ctx) => Ok(ctx.path.clone()),
+            _ => Err(ProjectError::Load("No project loaded".to_string())),
+        }
+    }
+
+    /// Directory the open project's file paths are relative to
+    fn project_root(&self) -> Result<PathBuf, ProjectError> {
+        match &self.project {
+            DataState::Loaded(ctx) | DataState::Modified(ctx) => Ok(ctx.root.clone()),
+            _ => Err(ProjectError::Load("No project loaded".to_string())),
+        }
+    }
+
+    /// The open project's default for keeping source snapshots
+    fn embed_sources(&self) -> bool {
+        matches!(&self.project, DataState::Loaded(ctx) | DataState::Modified(ctx) if ctx.project.embed_sources())
+    }
+
+    fn is_read_only(&self) -> bool {
+        matches!(&self.project, DataState::Loaded(ctx) | DataState::Modified(ctx) if ctx.read_only)
+    }
+
+    /// Path of the open project if it may be changed. Mutating actions go through this
+    /// before touching any state, so a read-only project is never modified.
+    fn writable_project_path(&self) -> Result<PathBuf, ProjectError> {
+        match &self.project {
+            DataState::Loaded(ctx) | DataState::Modified(ctx) if ctx.read_only => Err(ProjectError::ReadOnly),
+            _ => self.project_path(),
+        }
+    }
+
+    /// Path of the open project if it is still the writable project at `path`. Actions that read
+    /// files let go of the state while the loader runs, so by the time they come back to record
+    /// what they read, the project may have been closed or another one opened in its place.
+    fn still_open(&self, path: &Path) -> Result<PathBuf, ProjectError> {
+        match self.writable_project_path() {
+            Ok(open) if open == path => Ok(open),
+            Err(ProjectError::ReadOnly) => Err(ProjectError::ReadOnly),
+            _ => Err(ProjectError::Load("The project was closed while its files were being read".to_string())),
+        }
+    }
+
+    /// Text either side of a highlight, if its block is currently loaded
+    fn highlight_context(&self, highlight: &Highlight) -> (String, String) {
+        let block = self.filemanager.get_all_files()
+            .filter_map(|f| f.blocks())
+            .flatten()
+            .find(|b| b.id == highlight.block_id());
+
+        let Some(block) = block else {
+            return (String::new(), String::new());
+        };
+        let (Some(before), Some(after)) = (block.content.get(..highlight.start()), block.content.get(highlight.end()..)) else {
+            return (String::new(), String::new());
+        };
+
+        let before_start = before.char_indices().rev().nth(CONTEXT_CHARS - 1).map_or(0, |(i, _)| i);
+        let after_end = after.char_indices().nth(CONTEXT_CHARS).map_or(after.len(), |(i, _)| i);
+        (before[before_start..].to_string(), after[..after_end].to_string())
     }
 }
 
@@ -79,6 +211,7 @@ pub struct AppController <P: ProjectRepository, F: FileLoader, C: ConfigStore> {
     project_repo: P,
     file_loader: F,
     config_store: C,
+    autosave_signal: Arc<Notify>,
 }
 
 impl<P, F, C> AppController <P, F, C>
@@ -102,24 +235,71 @@ where
             project_repo,
             file_loader,
             config_store,
+            autosave_signal: Arc::new(Notify::new()),
         })
     }
 
+    /// Records an in-memory change and wakes the autosave task
+    fn mark_modified(&self, state: &mut AppState) {
+        state.mark_modified();
+        self.autosave_signal.notify_one();
+    }
+
+    /// Saves the project in the background if it has unsaved changes. Read-only projects are never written.
+    ///
+    /// Returns `Ok(true)` when a save was written. When the repository skips the write
+    /// (a manual save took priority, or another autosave was in flight) the autosave task
+    /// is re-armed so the changes are picked up on the next round.
+    pub async fn autosave(&self) -> Result<bool> {
+        let save_data = {
+            let state = self.state.read().unwrap();
+            match &state.project {
+                DataState::Modified(proj) if !proj.read_only => {
+                    // Marked under the same lock as the copy, so later edits stay in the journal
+                    let covered = self.project_repo.journal_mark(&proj.path);
+                    Some((proj.path.clone(), proj.project.clone(), state.codebook.clone(), state.filemanager.clone(), state.revision, covered))
+                }
+                _ => None
+            }
+        };
+
+        let Some((path, project, codebook, filemanager, revision, covered)) = save_data else {
+            return Ok(false);
+        };
+
+        let saved = self.project_repo.autosave_project(&path, project, codebook, filemanager, covered).await?;
+        if saved {
+            self.state.write().unwrap().mark_saved(revision);
+        } else {
+            self.autosave_signal.notify_one();
+        }
+        Ok(saved)
+    }
+
     /// Receives Action and routes to appropriate handling function
     pub async fn handle_action(&self, action: Action) -> Result<ActionResult> {
         match action {
             Action::Project(a) => self.handle_project_action(a).await,
             Action::File(a) => self.handle_file_action(a).await,
-            Action::Schema(a) => self.handle_schema_action(a),
-            Action::Coding(a) => self.handle_coding_action(a),
-            Action::Quit => Ok(ActionResult::Quit),
+            Action::Schema(a) => self.handle_schema_action(a).await,
+            Action::Coding(a) => self.handle_coding_action(a).await,
+            Action::Quit => {
+                // Best effort: a lock left behind by a failed release is cleaned up as stale
+                let path = self.state.read().unwrap().project_path();
+                if let Ok(path) = path {
+                    let _ = self.project_repo.unlock_project(&path).await;
+                }
+                Ok(ActionResult::Quit)
+            }
         }
     }
 
     async fn handle_project_action(&self, action: ProjectAction) -> Result<ActionResult> {
         match action {
             ProjectAction::NewProject { path, name } => {
+                let previous = self.lock_for_open(&path, false).await?;
                 let result = self.project_repo.new_project(&path, name).await;
+                self.settle_lock(&path, previous, result.is_ok(), true).await;
 
                 let mut state = self.state.write().unwrap();
                 match result {
@@ -136,17 +316,37 @@ where
                     }
                 }
             }
-            ProjectAction::LoadProject(path) => {
+            ProjectAction::LoadProject { path, force, read_only } => {
+                // Read-only opens don't take the lock, so they work while someone else edits
+                let previous = if read_only {
+                    self.state.read().unwrap().project_path().ok()
+                } else {
+                    self.lock_for_open(&path, force).await?
+                };
                 let result = self.project_repo.load_project(&path).await;
+                self.settle_lock(&path, previous, result.is_ok(), !read_only).await;
 
                 let mut state = self.state.write().unwrap();
                 match result {
                     Ok((project, codebook, filemanager)) => {
-                        let ctx = ProjectContext::new(path, project);
+                        let ctx = ProjectContext::new(path, project).with_read_only(read_only);
                         state.project = DataState::Loaded(ctx);
                         state.codebook = codebook;
                         state.filemanager = filemanager;
-                        Ok(ActionResult::Success)
+                        state.loaded_files.clear();
+
+                        // A migrated or partly recovered project only exists as loaded in memory
+                        // until it's saved
+                        let migration = self.project_repo.take_migration_report();
+                        let recovery = self.project_repo.take_journal_recovery();
+                        if (migration.is_some() || recovery.is_some()) && !read_only {
+                            self.mark_modified(&mut state);
+                        }
+                        match (recovery, migration) {
+                            (Some(recovery), migration) => Ok(ActionResult::JournalRecovered { recovery, migration }),
+                            (None, Some(report)) => Ok(ActionResult::ProjectMigrated(report)),
+                            (None, None) => Ok(ActionResult::Success),
+                        }
                     }
                     Err(e) => {
                         if let DataState::Empty = state.project {
@@ -156,22 +356,44 @@ where
                     }
                 }
             }
+            ProjectAction::EmbedSources(embed) => {
+                let ids: Vec<FileId> = {
+                    let mut state = self.state.write().unwrap();
+                    state.writable_project_path()?;
+                    if let DataState::Loaded(ctx) | DataState::Modified(ctx) = &mut state.project {
+                        ctx.project.set_embed_sources(embed);
+                    }
+                    // Project settings have no incremental write, the next save picks this up
+                    self.mark_modified(&mut state);
+                    state.filemanager.get_all_files().map(|f| f.id).collect()
+                };
+                let changed = self.sync_snapshots(&ids).await?;
+                self.persist_files(&changed).await?;
+                Ok(ActionResult::Success)
+            }
             ProjectAction::SaveProject => {
                 let save_data = {
                     let state = self.state.read().unwrap();
                     match &state.project {
+                        DataState::Loaded(proj) | DataState::Modified(proj) if proj.read_only => {
+                            return Err(ProjectError::ReadOnly.into());
+                        }
                         DataState::Loaded(proj) | DataState::Modified(proj) => {
-                            Some((proj.path.clone(), proj.project.clone(), state.codebook.clone(), state.filemanager.clone()))
+                            let covered = self.project_repo.journal_mark(&proj.path);
+                            Some((proj.path.clone(), proj.project.clone(), state.codebook.clone(), state.filemanager.clone(), state.revision, covered))
                         }
                         _ => None
                     }
                 };
 
                 match save_data {
-                    Some((path, project, codebook, filemanager)) => {
-                        match self.project_repo.save_project(&path, project, codebook, filemanager).await {
-                            Ok(_) => Ok(ActionResult::Success),
-                            Err(e) => Err(e.into())
+                    Some((path, project, codebook, filemanager, revision, covered)) => {
+                        match self.project_repo.save_project(&path, project, codebook, filemanager, covered).await {
+                            Ok(_) => {
+                                self.state.write().unwrap().mark_saved(revision);
+                                Ok(ActionResult::Success)
+                            }
+                            Err(e) => Err(e)
                         }
                     }
                     None => Err(ProjectError::Save("No project loaded".to_string()).into())
@@ -182,28 +404,629 @@ where
 
     async fn handle_file_action(&self, action: FileAction) -> Result<ActionResult> {
         match action {
-            FileAction::AddFile(path) => {
-                todo!("build out file adding")
+            FileAction::AddFile { path, mime } => {
+                let (root, project_path) = {
+                    let state = self.state.read().unwrap();
+                    (state.project_root()?, state.writable_project_path()?)
+                };
+                let import = self.file_loader.add_file(&root, &path, mime.as_deref()).await?;
+
+                let id = {
+                    let mut state = self.state.write().unwrap();
+                    state.still_open(&project_path)?;
+                    let id = state.filemanager.add_file(import.path, import.file_type);
+                    state.filemanager.file_mut(id)
+                        .ok_or(FileListError::FileNotFound(id))?
+                        .set_fingerprint(import.fingerprint);
+                    id
+                };
+                self.persist_added_files(&[id]).await?;
+                Ok(ActionResult::FileAdded(id))
+            }
+            FileAction::LoadFile(id) => self.load_file(id).await,
+            FileAction::ResolveDrift { file_id, resolution } => self.resolve_drift(file_id, resolution).await,
+            FileAction::FindFile { file_id, folders } => {
+                let (root, file) = {
+                    let state = self.state.read().unwrap();
+                    let file = state.filemanager.file(file_id)
+                        .ok_or(FileListError::FileNotFound(file_id))?;
+                    (state.project_root()?, file.clone())
+                };
+                // The project folder is the likeliest place, so it's searched first
+                let mut search = vec![root.clone()];
+                search.extend(folders.into_iter().filter(|folder| *folder != root));
+                let proposals = self.file_loader.find_candidates(&root, &search, &file).await?;
+                Ok(ActionResult::RelinkProposals(proposals))
+            }
+            FileAction::RelinkFile { file_id, path, whole_folder } => self.relink_file(file_id, &path, whole_folder).await,
+            FileAction::EmbedSource { file_id, embed } => {
+                {
+                    let mut state = self.state.write().unwrap();
+                    state.writable_project_path()?;
+                    state.filemanager.file_mut(file_id)
+                        .ok_or(FileListError::FileNotFound(file_id))?
+                        .set_embed_source(embed);
+                }
+                // Written even if the snapshot didn't change, the override itself is new
+                self.sync_snapshots(&[file_id]).await?;
+                self.persist_files(&[file_id]).await?;
+                Ok(ActionResult::Success)
+            }
+            FileAction::PreviewSurvey { path, sheet } => {
+                let table = self.file_loader.read_table(&path, sheet.as_deref()).await?;
+                Ok(ActionResult::SurveyPreview(table))
+            }
+            FileAction::ImportSurvey { path, selection, grouping } => self.import_survey(&path, selection, grouping).await,
+            FileAction::ListFormats => Ok(ActionResult::Formats(self.file_loader.formats())),
+            FileAction::AddFolder { path, filter, progress, cancel } => self.add_folder(&path, &filter, progress, &cancel).await,
+        }
+    }
+
+    /// Points a file at its new location, and optionally the missing files that were in the
+    /// same folder. Highlights reference files by id, so they all come along unchanged.
+    ///
+    /// The fingerprint is left alone: the next `LoadFile` reports the relinked file as moved,
+    /// or as edited if the user picked something with different contents.
+    async fn relink_file(&self, id: FileId, path: &Path, whole_folder: bool) -> Result<ActionResult> {
+        let (root, project_path, file) = {
+            let state = self.state.read().unwrap();
+            let project_path = state.writable_project_path()?;
+            let file = state.filemanager.file(id)
+                .ok_or(FileListError::FileNotFound(id))?;
+            (state.project_root()?, project_path, file.clone())
+        };
+        let import = self.file_loader.add_file(&root, path, None).await?;
+        let mut relinked = vec![(id, import.path.clone())];
+
+        let moved = moved_folder(&file.path_buf(), Path::new(&import.path)).filter(|_| whole_folder);
+        if let Some((old_dir, new_dir)) = moved {
+            let siblings: Vec<QualFile> = {
+                let state = self.state.read().unwrap();
+                state.filemanager.get_all_files()
+                    .filter(|f| f.id != id && f.path_buf().starts_with(&old_dir))
+                    .cloned()
+                    .collect()
+            };
+            for sibling in siblings {
+                let Ok(suffix) = sibling.path_buf().strip_prefix(&old_dir).map(Path::to_path_buf) else { continue };
+                if suffix.is_absolute() || self.file_loader.fingerprint(&root, &sibling).await?.is_some() {
+                    continue;
+                }
+                let mut candidate = sibling.clone();
+                candidate.set_path(new_dir.join(suffix).to_string_lossy().into_owned());
+                let Some(found) = self.file_loader.fingerprint(&root, &candidate).await? else { continue };
+                // Something else with the same name isn't the file, unless there's nothing to compare
+                if sibling.fingerprint().is_none_or(|stored| stored.sha256 == found.sha256) {
+                    relinked.push((sibling.id, candidate.path().to_string()));
+                }
+            }
+        }
+
+        let (files, write) = {
+            let mut state = self.state.write().unwrap();
+            let project_path = state.still_open(&project_path)?;
+            let mut files = Vec::with_capacity(relinked.len());
+            for (file_id, new_path) in &relinked {
+                let Some(file) = state.filemanager.file_mut(*file_id) else { continue };
+                file.set_path(new_path.clone());
+                // Anything loaded came from the old location
+                file.set_data_state(DataState::Empty);
+                files.push(file.clone());
+            }
+            (files, self.begin_write(&mut state, project_path))
+        };
+        let mut result = Ok(());
+        for file in files {
+            if result.is_ok() {
+                result = self.project_repo.insert_file(&write.path, file).await;
+            }
+        }
+        self.finish_write(write, result);
+        Ok(ActionResult::FilesRelinked(relinked.into_iter().map(|(id, _)| id).collect()))
+    }
+
+    /// Adds a spreadsheet's answers as survey files. They all point at the same spreadsheet and
+    /// each reads its own cells from it, so they are fingerprinted and loaded like any other file.
+    async fn import_survey(&self, path: &Path, mut selection: SurveySelection, grouping: SurveyGrouping) -> Result<ActionResult> {
+        let (root, project_path) = {
+            let state = self.state.read().unwrap();
+            (state.project_root()?, state.writable_project_path()?)
+        };
+        let import = self.file_loader.add_file(&root, path, None).await?;
+        if !matches!(import.file_type, FileType::Csv | FileType::Xlsx) {
+            return Err(FileError::Unsupported(format!("{} is not a CSV or XLSX spreadsheet", path.display())).into());
+        }
+        let table = self.file_loader.read_table(path, selection.sheet.as_deref()).await?;
+        // Pinned by name, so adding a sheet in front of it later doesn't change what the files read
+        if selection.sheet.is_none() {
+            selection.sheet = table.sheets.first().cloned();
+        }
+
+        // Checked for either grouping, so a bad id column shows up now rather than on every load
+        let respondents = table.respondents(&selection)?;
+        if respondents.is_empty() {
+            return Err(FileError::Parse(format!("{} has no answers in the chosen columns", path.display())).into());
+        }
+        let scopes: Vec<SurveyScope> = match grouping {
+            SurveyGrouping::ByColumn => selection.text_columns.iter().cloned().map(SurveyScope::Column).collect(),
+            SurveyGrouping::ByRespondent => respondents.into_iter().map(SurveyScope::Respondent).collect(),
+        };
+
+        let ids: Vec<FileId> = {
+            let mut state = self.state.write().unwrap();
+            state.still_open(&project_path)?;
+            scopes.into_iter().map(|scope| {
+                let id = state.filemanager.add_file(import.path.clone(), import.file_type.clone());
+                if let Some(file) = state.filemanager.file_mut(id) {
+                    file.set_fingerprint(import.fingerprint.clone());
+                    file.set_survey(Some(SurveyView { selection: selection.clone(), scope }));
+                }
+                id
+            }).collect()
+        };
+        self.persist_added_files(&ids).await?;
+        Ok(ActionResult::FilesAdded(ids))
+    }
+
+    /// Adds the files under a folder, reading a few at a time. Files are registered and persisted
+    /// in path order as they come in, so an import that is cancelled, or stopped by the project
+    /// closing, keeps what it got through. The project's own files and their companions are
+    /// passed over. Workbooks are reported as failed, as they need `ImportSurvey`.
+    async fn add_folder(&self, folder: &Path, filter: &FolderFilter, progress: Option<UnboundedSender<ImportProgress>>, cancel: &CancelToken) -> Result<ActionResult> {
+        let (root, project_path, mut known) = {
+            let state = self.state.read().unwrap();
+            let project_path = state.writable_project_path()?;
+            // Content already in the project, to the path it's stored under
+            let known: HashMap<String, String> = state.filemanager.get_all_files()
+                .filter_map(|file| file.fingerprint().map(|fingerprint| (fingerprint.sha256.clone(), file.path().to_string())))
+                .collect();
+            (state.project_root()?, project_path, known)
+        };
+        let paths: Vec<PathBuf> = self.file_loader.find_files(folder, filter).await?
+            .into_iter()
+            .filter(|path| !self.project_repo.is_project_file(&project_path, path))
+            .collect();
+        let total = paths.len();
+
+        // Checked as each file starts, so files already being read are finished
+        let mut imports = stream::iter(&paths)
+            .map(|path| async {
+                match cancel.is_cancelled() {
+                    true => None,
+                    false => Some(self.file_loader.add_file(&root, path, None).await),
+                }
+            })
+            .buffered(FOLDER_IMPORT_CONCURRENCY);
+
+        let mut report = FolderImportReport::default();
+        let mut closed = false;
+        while !closed && let Some(Some(import)) = imports.next().await {
+            let path = &paths[report.files.len()];
+            let mut outcome = match import {
+                Err(e) => ImportOutcome::Failed(e.to_string()),
+                Ok(import) if import.file_type == FileType::Xlsx => {
+                    ImportOutcome::Failed(format!("{} is a workbook, import it as a survey to choose the columns to code", path.display()))
+                }
+                Ok(import) => match known.get(&import.fingerprint.sha256) {
+                    Some(original) => ImportOutcome::Duplicate(original.clone()),
+                    None => {
+                        let mut state = self.state.write().unwrap();
+                        match state.still_open(&project_path) {
+                            Ok(_) => {
+                                let id = state.filemanager.add_file(import.path.clone(), import.file_type);
+                                if let Some(file) = state.filemanager.file_mut(id) {
+                                    file.set_fingerprint(import.fingerprint.clone());
+                                }
+                                known.insert(import.fingerprint.sha256, import.path);
+                                ImportOutcome::Added(id)
+                            }
+                            Err(e) => {
+                                closed = true;
+                                ImportOutcome::Failed(e.to_string())
+                            }
+                        }
+                    }
+                },
+            };
+            // Persisted one at a time, so stopping part way leaves nothing added but unsaved
+            if let ImportOutcome::Added(id) = outcome
+                && let Err(e) = self.persist_added_files(&[id]).await
+            {
+                // Only fails if the project has just been closed
+                closed = true;
+                outcome = ImportOutcome::Failed(e.to_string());
+            }
+            if let Some(progress) = &progress {
+                // The UI may have stopped listening, which doesn't stop the import
+                let _ = progress.send(ImportProgress { done: report.files.len() + 1, total, path: path.clone(), outcome: outcome.clone() });
+            }
+            report.files.push((path.clone(), outcome));
+        }
+        report.not_started = total - report.files.len();
+
+        Ok(ActionResult::FolderImported(report))
+    }
+
+    /// Adds or drops embedded snapshots so they match each file's setting, returning the files
+    /// that changed. Snapshots are only taken of the coded version of a file: loaded blocks are
+    /// used as they are, and files that aren't loaded are read and kept only if they still match
+    /// their anchors. Files that can't be read are left for the next load to snapshot.
+    async fn sync_snapshots(&self, ids: &[FileId]) -> Result<Vec<FileId>> {
+        let (root, to_read, mut changed) = {
+            let mut state = self.state.write().unwrap();
+            let root = state.project_root()?;
+            let embed_default = state.embed_sources();
+            let mut to_read = Vec::new();
+            let mut changed = Vec::new();
+            for id in ids {
+                let Some(file) = state.filemanager.file_mut(*id) else { continue };
+                match (file.embeds_source(embed_default), file.snapshot().is_some()) {
+                    (false, true) => {
+                        file.set_snapshot(None);
+                        changed.push(*id);
+                    }
+                    (true, false) => match file.data_state() {
+                        DataState::Loaded(blocks) => {
+                            let snapshot = SourceSnapshot::of(blocks);
+                            file.set_snapshot(Some(snapshot));
+                            changed.push(*id);
+                        }
+                        // Blocks under drift review aren't the coded version
+                        _ => to_read.push(file.clone()),
+                    },
+                    _ => {}
+                }
+            }
+            (root, to_read, changed)
+        };
+
+        for file in to_read {
+            let Ok(blocks) = self.file_loader.load_file(&root, &file).await else { continue };
+            let anchors: Vec<BlockAnchor> = blocks.iter().map(BlockAnchor::of).collect();
+            if !file.anchors().is_empty() && file.anchors() != anchors.as_slice() {
+                continue;
+            }
+            let mut state = self.state.write().unwrap();
+            let embed_default = state.embed_sources();
+            if let Some(target) = state.filemanager.file_mut(file.id)
+                && target.embeds_source(embed_default)
+                && target.snapshot().is_none()
+            {
+                target.set_snapshot(Some(SourceSnapshot::of(&blocks)));
+                changed.push(file.id);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Writes the current version of each file through the repository
+    async fn persist_files(&self, ids: &[FileId]) -> Result<()> {
+        self.write_files(ids, false).await
+    }
+
+    /// Writes files just added to the project. Adding files always leaves the project Modified,
+    /// even once the journal has them, so the next save writes them into the snapshot.
+    async fn persist_added_files(&self, ids: &[FileId]) -> Result<()> {
+        self.write_files(ids, true).await
+    }
+
+    async fn write_files(&self, ids: &[FileId], added: bool) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let (files, write) = {
+            let mut state = self.state.write().unwrap();
+            let project_path = state.writable_project_path()?;
+            let files: Vec<QualFile> = ids.iter()
+                .filter_map(|id| state.filemanager.file(*id).cloned())
+                .collect();
+            let mut write = self.begin_write(&mut state, project_path);
+            // Never settled back to clean, see `persist_added_files`
+            write.was_clean &= !added;
+            (files, write)
+        };
+        let mut result = Ok(());
+        for file in files {
+            if result.is_ok() {
+                result = self.project_repo.insert_file(&write.path, file).await;
+            }
+        }
+        self.finish_write(write, result);
+        Ok(())
+    }
+
+    /// Loads a file's blocks into memory, first checking the source still matches what was coded.
+    ///
+    /// Unchanged files, and files from before fingerprinting, are committed straight away,
+    /// moving highlights onto the blocks if needed. Edited files are loaded for review only,
+    /// as `DataState::Modified`, and reported so the user can pick a [`DriftResolution`].
+    /// Files whose source is missing or unreadable fall back to their embedded snapshot.
+    async fn load_file(&self, id: FileId) -> Result<ActionResult> {
+        let (root, file) = {
+            let mut state = self.state.write().unwrap();
+            let root = state.project_root()?;
+            let file = state.filemanager.file(id)
+                .ok_or(FileListError::FileNotFound(id))?;
+            if let DataState::Loaded(_) = file.data_state() {
+                state.touch_loaded_file(id);
+                return Ok(ActionResult::FileLoaded(id));
+            }
+            (root, file.clone())
+        };
+
+        let current = self.file_loader.fingerprint(&root, &file).await?;
+        if current.is_none() && file.snapshot().is_none() {
+            self.set_file_state(id, DataState::Error);
+            return Ok(ActionResult::FileDrifted { file_id: id, drift: FileDrift::Missing });
+        }
+        let (blocks, source) = match self.file_loader.load_or_snapshot(&root, &file).await {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                self.set_file_state(id, DataState::Error);
+                return Err(e).context("Failed to load file");
+            }
+        };
+        let (Some(current), BlockSource::Live) = (current, source) else {
+            // The snapshot is the coded version, so there's nothing to check or re-anchor
+            self.show_snapshot(id, blocks)?;
+            return Ok(ActionResult::FileLoadedFromSnapshot(id));
+        };
+        let drift = file.fingerprint().map(|stored| stored.drift_to(&current));
+
+        match drift {
+            Some(FileDrift::Edited(_)) => {
+                let summary = diff_blocks(file.anchors(), &blocks);
+                let mut state = self.state.write().unwrap();
+                state.filemanager.file_mut(id)
+                    .ok_or(FileListError::FileNotFound(id))?
+                    .set_data_state(DataState::Modified(blocks));
+                state.touch_loaded_file(id);
+                Ok(ActionResult::FileDrifted { file_id: id, drift: FileDrift::Edited(summary) })
+            }
+            Some(FileDrift::Moved) => {
+                self.commit_blocks(id, blocks, current, true).await?;
+                Ok(ActionResult::FileDrifted { file_id: id, drift: FileDrift::Moved })
+            }
+            _ => {
+                let report = self.commit_blocks(id, blocks, current, true).await?;
+                if report.is_empty() {
+                    Ok(ActionResult::FileLoaded(id))
+                } else {
+                    Ok(ActionResult::FileReanchored { file_id: id, report })
+                }
+            }
+        }
+    }
+
+    /// Applies the user's choice for a file whose source changed since it was coded
+    async fn resolve_drift(&self, id: FileId, resolution: DriftResolution) -> Result<ActionResult> {
+        let (root, file) = {
+            let state = self.state.read().unwrap();
+            if resolution != DriftResolution::RestoreOriginal {
+                state.writable_project_path()?;
             }
-            FileAction::LoadFile(id) => {
-                todo!("build out opening")
+            let file = state.filemanager.file(id)
+                .ok_or(FileListError::FileNotFound(id))?;
+            (state.project_root()?, file.clone())
+        };
+
+        if resolution == DriftResolution::RestoreOriginal {
+            let Some(snapshot) = file.snapshot() else {
+                self.set_file_state(id, DataState::Empty);
+                return Ok(ActionResult::Success);
+            };
+            self.show_snapshot(id, snapshot.blocks(id))?;
+            return Ok(ActionResult::FileLoadedFromSnapshot(id));
+        }
+
+        // Read again rather than trusting the blocks under review, the file may have changed since
+        let fingerprint = self.file_loader.fingerprint(&root, &file).await?
+            .ok_or_else(|| FileError::Read(format!("{} no longer exists", file.path())))?;
+        let blocks = self.read_blocks(id, &root, &file).await?;
+        let report = self.commit_blocks(id, blocks, fingerprint, resolution == DriftResolution::Reanchor).await?;
+        Ok(ActionResult::FileReanchored { file_id: id, report })
+    }
+
+    /// Reads a file through the loader, marking it as failed if that doesn't work
+    async fn read_blocks(&self, id: FileId, root: &Path, file: &QualFile) -> Result<Vec<TextBlock>> {
+        match self.file_loader.load_file(root, file).await {
+            Ok(blocks) => Ok(blocks),
+            Err(e) => {
+                self.set_file_state(id, DataState::Error);
+                Err(e).context("Failed to load file")
             }
         }
     }
 
-    fn handle_schema_action(&self, action: SchemaAction) -> Result<ActionResult> {
+    fn show_snapshot(&self, id: FileId, blocks: Vec<TextBlock>) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        state.filemanager.file_mut(id)
+            .ok_or(FileListError::FileNotFound(id))?
+            .set_data_state(DataState::Loaded(blocks));
+        state.touch_loaded_file(id);
+        Ok(())
+    }
+
+    fn set_file_state(&self, id: FileId, data_state: DataState<Vec<TextBlock>>) {
+        if let Some(file) = self.state.write().unwrap().filemanager.file_mut(id) {
+            file.set_data_state(data_state);
+        }
+    }
+
+    /// Makes `blocks` the accepted contents of a file.
+    ///
+    /// Highlights are moved onto the new blocks when `reanchor` is set, otherwise those on
+    /// blocks that no longer exist are orphaned. New anchors and the fingerprint are persisted
+    /// like any other edit, except in read-only projects where they only live in memory.
+    async fn commit_blocks(&self, id: FileId, blocks: Vec<TextBlock>, fingerprint: FileFingerprint, reanchor: bool) -> Result<ReanchorReport> {
+        let (report, changes) = {
+            let mut guard = self.state.write().unwrap();
+            let state = &mut *guard;
+            let embed_default = state.embed_sources();
+            let target = state.filemanager.file_mut(id)
+                .ok_or(FileListError::FileNotFound(id))?;
+
+            let report = if reanchor {
+                state.codebook.reanchor(target.anchors(), &blocks)
+            } else {
+                ReanchorReport { moved: Vec::new(), orphaned: state.codebook.orphan_changed(target.anchors(), &blocks) }
+            };
+            let anchors: Vec<BlockAnchor> = blocks.iter().map(BlockAnchor::of).collect();
+            let snapshot = target.embeds_source(embed_default).then(|| SourceSnapshot::of(&blocks));
+            let changed = target.anchors() != anchors.as_slice()
+                || target.fingerprint() != Some(&fingerprint)
+                || target.snapshot() != snapshot.as_ref();
+            target.set_anchors(anchors);
+            target.set_fingerprint(fingerprint);
+            target.set_snapshot(snapshot);
+            // Cloned before the blocks go in, they aren't persisted anyway
+            let changed_file = changed.then(|| target.clone());
+            target.set_data_state(DataState::Loaded(blocks));
+            state.touch_loaded_file(id);
+
+            let changes = match changed_file {
+                Some(file) if !state.is_read_only() => {
+                    let codes: Vec<QualCode> = state.codebook.get_all_qual_codes().iter()
+                        .filter(|qc| report.moved.contains(&qc.id) || report.orphaned.contains(&qc.id))
+                        .cloned()
+                        .collect();
+                    let path = state.project_path()?;
+                    Some((file, codes, self.begin_write(state, path)))
+                }
+                _ => None,
+            };
+            (report, changes)
+        };
+
+        if let Some((file, codes, write)) = changes {
+            let mut result = self.project_repo.insert_file(&write.path, file).await;
+            for code in codes {
+                if result.is_ok() {
+                    result = self.project_repo.insert_qual_code(&write.path, code).await;
+                }
+            }
+            self.finish_write(write, result);
+        }
+        Ok(report)
+    }
+
+    async fn handle_schema_action(&self, action: SchemaAction) -> Result<ActionResult> {
         match action {
             SchemaAction::CreateCode { name, color } => {
-                todo!("build out code creation")
+                let (id, code, write) = {
+                    let mut state = self.state.write().unwrap();
+                    let path = state.writable_project_path()?;
+                    let id = state.codebook.create_code_def(name, color, None);
+                    let code = state.codebook.code_def(id).cloned()
+                        .ok_or(CodeBookError::CodeDefNotFound(id))?;
+                    (id, code, self.begin_write(&mut state, path))
+                };
+                let result = self.project_repo.insert_code_def(&write.path, code).await;
+                self.finish_write(write, result);
+                Ok(ActionResult::CodeCreated(id))
             }
         }
     }
 
-    fn handle_coding_action(&self, action: CodingAction) -> Result<ActionResult> {
+    async fn handle_coding_action(&self, action: CodingAction) -> Result<ActionResult> {
         match action {
             CodingAction::ApplyCode { code_def_id, highlight, snippet } => {
-                todo!("build out code creation")
+                let (id, code, write) = {
+                    let mut state = self.state.write().unwrap();
+                    let path = state.writable_project_path()?;
+                    if state.codebook.code_def(code_def_id).is_none() {
+                        return Err(CodeBookError::CodeDefNotFound(code_def_id).into());
+                    }
+                    let (context_before, context_after) = state.highlight_context(&highlight);
+                    let id = state.codebook.apply_code(code_def_id, highlight, snippet, context_before, context_after);
+                    let code = state.codebook.get_all_qual_codes().iter()
+                        .find(|qc| qc.id == id)
+                        .cloned()
+                        .ok_or(CodeBookError::QualCodeNotFound(id))?;
+                    (id, code, self.begin_write(&mut state, path))
+                };
+                let result = self.project_repo.insert_qual_code(&write.path, code).await;
+                self.finish_write(write, result);
+                Ok(ActionResult::CodeApplied(id))
             }
         }
     }
+
+    /// Locks `path` before it is opened and returns the path of the project currently open.
+    /// A lock conflict is returned as is, so the caller can match on `ProjectError::Locked`.
+    async fn lock_for_open(&self, path: &Path, force: bool) -> Result<Option<PathBuf>> {
+        self.project_repo.lock_project(path, force).await?;
+        Ok(self.state.read().unwrap().project_path().ok())
+    }
+
+    /// Releases whichever lock is no longer needed once opening `path` has finished: the
+    /// previous project's if the open succeeded, otherwise the one just taken on `path`.
+    /// `locked` says whether `path` was locked for this open; reopening the current project
+    /// with its lock keeps that lock either way.
+    async fn settle_lock(&self, path: &Path, previous: Option<PathBuf>, opened: bool, locked: bool) {
+        let reopened = previous.as_deref() == Some(path);
+        let release = match (opened, previous) {
+            (true, Some(previous)) if !reopened || !locked => previous,
+            (false, _) if locked && !reopened => path.to_path_buf(),
+            _ => return,
+        };
+        let _ = self.project_repo.unlock_project(&release).await;
+    }
+
+    /// Marks an in-memory mutation and captures what's needed to persist it incrementally
+    fn begin_write(&self, state: &mut AppState, path: PathBuf) -> PendingWrite {
+        let was_clean = matches!(state.project, DataState::Loaded(_));
+        self.mark_modified(state);
+        PendingWrite { path, revision: state.revision, was_clean }
+    }
+
+    /// Settles the project state after an incremental write.
+    ///
+    /// If the project was clean before the mutation and the write landed, it is clean again.
+    /// Otherwise it stays Modified, and the next autosave or manual save (a full compaction)
+    /// persists it. In-memory state is authoritative, so a failed write doesn't fail the action.
+    fn finish_write(&self, write: PendingWrite, result: Result<()>) {
+        if result.is_ok() && write.was_clean {
+            self.state.write().unwrap().mark_saved(write.revision);
+        }
+    }
+}
+
+/// An in-memory mutation waiting on its incremental repository write
+struct PendingWrite {
+    path: PathBuf,
+    revision: u64,
+    was_clean: bool,
 }
+
+impl<P, F, C> AppController <P, F, C>
+where
+    P: ProjectRepository + 'static,
+    F: FileLoader + Send + Sync + 'static,
+    C: ConfigStore + Send + Sync + 'static,
+{
+    /// Starts the background autosave task.
+    ///
+    /// The task sleeps until something marks the project modified, then waits for
+    /// `debounce` of quiet before saving, so a burst of edits becomes a single write.
+    /// It holds only a weak reference and exits once the controller is dropped;
+    /// abort the returned handle to stop it sooner.
+    pub fn spawn_autosave(self: &Arc<Self>, debounce: Duration) -> JoinHandle<()> {
+        let controller: Weak<Self> = Arc::downgrade(self);
+        let signal = self.autosave_signal.clone();
+
+        tokio::spawn(async move {
+            loop {
+                signal.notified().await;
+                while tokio::time::timeout(debounce, signal.notified()).await.is_ok() {}
+
+                let Some(controller) = controller.upgrade() else { break };
+                // Failures leave the project Modified; the next edit or manual save retries.
+                let _ = controller.autosave().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
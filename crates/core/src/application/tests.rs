@@ -0,0 +1,1489 @@
+// Controller tests run against in-memory fakes of the ports so they exercise
+// routing and state transitions without touching the filesystem.
+
+use super::*;
+use async_trait::async_trait;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use chrono::{DateTime, Utc};
+
+// ===== Test Helpers =====
+
+/// Repository that counts writes instead of performing them
+#[derive(Default)]
+struct FakeRepo {
+    saves: AtomicUsize,
+    autosaves: AtomicUsize,
+    skip_autosave: AtomicBool,
+    fail_incremental: AtomicBool,
+    incremental_writes: AtomicUsize,
+    delay_ms: u64,
+    migration: std::sync::Mutex<Option<MigrationReport>>,
+    recovery: std::sync::Mutex<Option<JournalRecovery>>,
+    /// Lock held by "another instance"; lock_project fails with it unless forced
+    foreign_lock: std::sync::Mutex<Option<ProjectLock>>,
+    unlocked: std::sync::Mutex<Vec<PathBuf>>,
+}
+
+#[async_trait]
+impl ProjectRepository for FakeRepo {
+    async fn new_project(&self, _path: &Path, name: String) -> Result<QualProject> {
+        Ok(QualProject::new(name, 1, Utc::now(), Utc::now()))
+    }
+    async fn save_project(&self, _path: &Path, _project: QualProject, _codebook: CodeBook, _filemanager: FileList, _covered: u64) -> Result<()> {
+        self.saves.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+    async fn load_project(&self, _path: &Path) -> Result<(QualProject, CodeBook, FileList)> {
+        Ok((QualProject::new("Loaded".to_string(), 1, Utc::now(), Utc::now()), CodeBook::new(), FileList::new()))
+    }
+    async fn autosave_project(&self, _path: &Path, _project: QualProject, _codebook: CodeBook, _filemanager: FileList, _covered: u64) -> Result<bool> {
+        if self.delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.delay_ms)).await;
+        }
+        if self.skip_autosave.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+        self.autosaves.fetch_add(1, Ordering::SeqCst);
+        Ok(true)
+    }
+    fn take_migration_report(&self) -> Option<MigrationReport> {
+        self.migration.lock().unwrap().take()
+    }
+    fn take_journal_recovery(&self) -> Option<JournalRecovery> {
+        self.recovery.lock().unwrap().take()
+    }
+    fn is_project_file(&self, project: &Path, path: &Path) -> bool {
+        // The project and its backups, as the real repositories name them
+        let backup = |n: usize| {
+            let mut name = project.as_os_str().to_owned();
+            name.push(format!(".bak.{}", n));
+            PathBuf::from(name)
+        };
+        path == project || (1..=3).any(|n| path == backup(n))
+    }
+    async fn lock_project(&self, _path: &Path, force: bool) -> Result<()> {
+        let mut foreign = self.foreign_lock.lock().unwrap();
+        match foreign.take() {
+            Some(lock) if !force => {
+                *foreign = Some(lock.clone());
+                Err(ProjectError::Locked(lock).into())
+            }
+            _ => Ok(()),
+        }
+    }
+    async fn unlock_project(&self, path: &Path) -> Result<()> {
+        self.unlocked.lock().unwrap().push(path.to_path_buf());
+        Ok(())
+    }
+    async fn insert_code_def(&self, _path: &Path, _code: CodeDef) -> Result<()> { self.incremental() }
+    async fn insert_theme_def(&self, _path: &Path, _theme: ThemeDef) -> Result<()> { self.incremental() }
+    async fn insert_qual_code(&self, _path: &Path, _code: QualCode) -> Result<()> { self.incremental() }
+    async fn insert_file(&self, _path: &Path, _file: QualFile) -> Result<()> { self.incremental() }
+    async fn delete_code_def(&self, _path: &Path, _id: CodeDefId) -> Result<()> { self.incremental() }
+    async fn delete_theme_def(&self, _path: &Path, _id: ThemeId) -> Result<()> { self.incremental() }
+    async fn delete_qual_code(&self, _path: &Path, _id: QualCodeId) -> Result<()> { self.incremental() }
+    async fn delete_file(&self, _path: &Path, _id: FileId) -> Result<()> { self.incremental() }
+    async fn move_code_def(&self, _path: &Path, _id: CodeDefId, _new_index: usize) -> Result<()> { self.incremental() }
+    async fn move_theme_def(&self, _path: &Path, _id: ThemeId, _new_index: usize) -> Result<()> { self.incremental() }
+    async fn move_file(&self, _path: &Path, _id: FileId, _new_index: usize) -> Result<()> { self.incremental() }
+}
+
+impl FakeRepo {
+    /// Repository whose incremental writes fail, so edits stay unsaved until a full save
+    fn failing_incremental() -> Self {
+        FakeRepo { fail_incremental: AtomicBool::new(true), ..Default::default() }
+    }
+
+    /// Repository where another instance holds the lock on every project
+    fn locked_elsewhere() -> Self {
+        let lock = ProjectLock { host: "other-host".to_string(), pid: 42, acquired_at: Utc::now() };
+        FakeRepo { foreign_lock: std::sync::Mutex::new(Some(lock)), ..Default::default() }
+    }
+
+    fn incremental(&self) -> Result<()> {
+        if self.fail_incremental.load(Ordering::SeqCst) {
+            return Err(ProjectError::Save("incremental write failed".to_string()).into());
+        }
+        self.incremental_writes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Loader that accepts any `.txt` or `.csv` path under the root and rejects everything else.
+/// Files' contents stand in for their hashes, so files with the same contents are duplicates.
+/// Loading returns the paragraphs set in `sources`, or one block per `/`-separated path
+/// component for other files, and fails for `broken.txt`. Survey files read their cells from
+/// the spreadsheet in `tables` with the same file name.
+#[derive(Default)]
+struct FakeLoader {
+    sources: std::sync::Mutex<std::collections::HashMap<String, Vec<String>>>,
+    /// Paths whose fingerprint reports the file as gone
+    missing: std::sync::Mutex<Vec<String>>,
+    /// Paths whose modification time moved on without the content changing
+    touched: std::sync::Mutex<Vec<String>>,
+    /// Paths `find_candidates` and `find_files` can come across
+    on_disk: std::sync::Mutex<Vec<String>>,
+    tables: std::sync::Mutex<std::collections::HashMap<String, SurveyTable>>,
+    /// Cancelled when `add_file` reaches the path, to stop an import part way through
+    cancel_at: std::sync::Mutex<Option<(String, CancelToken)>>,
+    /// Closes the project when `add_file` reaches the path, as if the user closed it mid-import
+    close_at: std::sync::Mutex<Option<(String, Arc<RwLock<AppState>>)>>,
+}
+
+impl FakeLoader {
+    fn contents(&self, path: &str) -> String {
+        match self.sources.lock().unwrap().get(path) {
+            Some(paragraphs) => paragraphs.join("\n\n"),
+            None => path.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl FileLoader for FakeLoader {
+    async fn add_file(&self, root: &Path, path: &Path, _mime: Option<&str>) -> Result<FileImport> {
+        let file_type = match path.extension().and_then(|e| e.to_str()) {
+            Some("txt") => FileType::PlainText,
+            Some("csv") => FileType::Csv,
+            _ => return Err(FileError::Unsupported(path.display().to_string()).into()),
+        };
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let path = relative.to_string_lossy().into_owned();
+        if let Some((at, cancel)) = &*self.cancel_at.lock().unwrap() && *at == path {
+            cancel.cancel();
+        }
+        if let Some((at, state)) = &*self.close_at.lock().unwrap() && *at == path {
+            state.write().unwrap().project = DataState::Empty;
+        }
+        let contents = self.contents(&path);
+        let fingerprint = FileFingerprint { sha256: contents.clone(), size: contents.len() as u64, modified: None, path: Some(path.clone()) };
+        Ok(FileImport { path, file_type, fingerprint })
+    }
+    async fn load_file(&self, _root: &Path, file: &QualFile) -> Result<Vec<TextBlock>> {
+        if file.path().ends_with("broken.txt") {
+            return Err(FileError::Read(file.path().to_string()).into());
+        }
+        if let Some(view) = file.survey() {
+            let table = self.read_table(&file.path_buf(), None).await?;
+            return Ok(table.blocks(file.id, view)?);
+        }
+        if let Some(paragraphs) = self.sources.lock().unwrap().get(file.path()) {
+            return Ok(paragraphs.iter()
+                .enumerate()
+                .map(|(i, p)| TextBlock::new(file.id, i, p.clone()))
+                .collect());
+        }
+        Ok(file.path().split('/')
+            .enumerate()
+            .map(|(i, part)| TextBlock::new(file.id, i, part.to_string()))
+            .collect())
+    }
+    async fn fingerprint(&self, _root: &Path, file: &QualFile) -> Result<Option<FileFingerprint>> {
+        if self.missing.lock().unwrap().iter().any(|p| p == file.path()) {
+            return Ok(None);
+        }
+        // The contents stand in for the hash
+        let contents = self.contents(file.path());
+        let modified = self.touched.lock().unwrap().iter().any(|p| p == file.path())
+            .then(|| DateTime::<Utc>::from_timestamp(1_000_000, 0).unwrap());
+        Ok(Some(FileFingerprint { sha256: contents.clone(), size: contents.len() as u64, modified, path: Some(file.path().to_string()) }))
+    }
+    async fn find_candidates(&self, _root: &Path, _folders: &[PathBuf], file: &QualFile) -> Result<Vec<RelinkProposal>> {
+        let Some(stored) = file.fingerprint() else { return Ok(Vec::new()) };
+        Ok(self.on_disk.lock().unwrap().iter()
+            .filter(|path| self.contents(path) == stored.sha256)
+            .map(|path| RelinkProposal { file_id: file.id, path: path.clone(), matched_by: RelinkMatch::Content })
+            .collect())
+    }
+    async fn read_table(&self, path: &Path, _sheet: Option<&str>) -> Result<SurveyTable> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let table = self.tables.lock().unwrap().get(name.as_ref()).cloned();
+        Ok(table.ok_or_else(|| FileError::Read(path.display().to_string()))?)
+    }
+
+    /// Everything in `on_disk`, taken to be under `folder`. Filters are left to the real loader.
+    async fn find_files(&self, folder: &Path, _filter: &FolderFilter) -> Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = self.on_disk.lock().unwrap().iter().map(|path| folder.join(path)).collect();
+        paths.sort();
+        Ok(paths)
+    }
+    fn formats(&self) -> Vec<FormatInfo> {
+        vec![
+            FormatInfo::new(FileType::PlainText, "Plain text").with_extensions(&["txt"]),
+            FormatInfo::new(FileType::Csv, "CSV").with_extensions(&["csv"]).with_metadata(&[BlockMeta::CASE, BlockMeta::QUESTION]),
+        ]
+    }
+}
+
+struct FakeConfig;
+
+#[async_trait]
+impl ConfigStore for FakeConfig {
+    async fn load_config(&self) -> Result<AppConfig> { Ok(AppConfig::default()) }
+    async fn save_config(&self) -> Result<()> { Ok(()) }
+    async fn config_exists(&self) -> bool { true }
+}
+
+type TestController = AppController<FakeRepo, FakeLoader, FakeConfig>;
+
+/// Controller with a loaded, clean project
+async fn loaded_controller(repo: FakeRepo) -> Arc<TestController> {
+    let project = QualProject::new("Study".to_string(), 1, Utc::now(), Utc::now());
+    let ctx = ProjectContext::new(PathBuf::from("/tmp/study/project.json"), project);
+    let state = Arc::new(RwLock::new(AppState::new(DataState::Loaded(ctx), AppConfig::default())));
+    Arc::new(AppController::new(state, repo, FakeLoader::default(), FakeConfig).await.unwrap())
+}
+
+fn create_code(name: &str) -> Action {
+    Action::Schema(SchemaAction::CreateCode { name: name.to_string(), color: 1 })
+}
+
+fn is_modified(controller: &TestController) -> bool {
+    matches!(controller.state.read().unwrap().project, DataState::Modified(_))
+}
+
+// ===== Autosave =====
+
+mod autosave {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_burst_of_edits_autosaves_once() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::failing_incremental()).await;
+        let task = controller.spawn_autosave(Duration::from_millis(30));
+
+        // Execute: Several unsaved edits inside one debounce window
+        for name in ["A", "B", "C", "D", "E"] {
+            controller.handle_action(create_code(name)).await.unwrap();
+        }
+        assert!(is_modified(&controller), "Edits should mark the project modified");
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        // Assert: One write, project clean again
+        assert_eq!(controller.project_repo.autosaves.load(Ordering::SeqCst), 1, "Edits should be debounced into one autosave");
+        assert!(!is_modified(&controller), "Autosave should mark the project clean");
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_autosave_ignores_clean_project() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        let saved = controller.autosave().await.unwrap();
+
+        assert!(!saved, "Nothing to save on a clean project");
+        assert_eq!(controller.project_repo.autosaves.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_edit_during_autosave_keeps_project_modified() {
+        // Setup: Slow repository so an edit lands mid-save
+        let controller = loaded_controller(FakeRepo { delay_ms: 30, ..FakeRepo::failing_incremental() }).await;
+        controller.handle_action(create_code("A")).await.unwrap();
+
+        // Execute
+        let (saved, _) = tokio::join!(
+            controller.autosave(),
+            async {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                controller.handle_action(create_code("B")).await.unwrap();
+            }
+        );
+
+        // Assert: The save landed, but the later edit is still unsaved
+        assert!(saved.unwrap());
+        assert!(is_modified(&controller), "An edit made during the save must not be marked saved");
+    }
+
+    #[tokio::test]
+    async fn test_skipped_autosave_leaves_project_modified() {
+        let repo = FakeRepo::failing_incremental();
+        repo.skip_autosave.store(true, Ordering::SeqCst);
+        let controller = loaded_controller(repo).await;
+        controller.handle_action(create_code("A")).await.unwrap();
+
+        let saved = controller.autosave().await.unwrap();
+
+        assert!(!saved);
+        assert!(is_modified(&controller), "A skipped autosave should not mark the project clean");
+    }
+
+    #[tokio::test]
+    async fn test_manual_save_marks_project_clean() {
+        let controller = loaded_controller(FakeRepo::failing_incremental()).await;
+        controller.handle_action(create_code("A")).await.unwrap();
+
+        controller.handle_action(Action::Project(ProjectAction::SaveProject)).await.unwrap();
+
+        assert!(!is_modified(&controller));
+        assert_eq!(controller.project_repo.saves.load(Ordering::SeqCst), 1);
+    }
+}
+
+// ===== Project loading =====
+
+mod loading {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_migrated_load_reports_and_marks_modified() {
+        // Setup
+        let report = MigrationReport { from_version: 1, to_version: 2, applied: vec!["Step".to_string()] };
+        let repo = FakeRepo { migration: std::sync::Mutex::new(Some(report.clone())), ..Default::default() };
+        let controller = loaded_controller(repo).await;
+
+        // Execute
+        let result = controller.handle_action(Action::Project(ProjectAction::LoadProject { path: PathBuf::from("/tmp/old/project.json"), force: false, read_only: false })).await.unwrap();
+
+        // Assert
+        let ActionResult::ProjectMigrated(reported) = result else { panic!("Expected ProjectMigrated, got {:?}", result) };
+        assert_eq!(reported, report);
+        assert!(is_modified(&controller), "Migrated project should need saving");
+    }
+
+    #[tokio::test]
+    async fn test_damaged_journal_is_reported_with_the_migration() {
+        // Setup: An old project whose journal could only be partly replayed
+        let report = MigrationReport { from_version: 1, to_version: 2, applied: vec!["Step".to_string()] };
+        let recovery = JournalRecovery { replayed: 4, skipped: 2, kept_at: PathBuf::from("/tmp/old/project.json.journal.damaged") };
+        let repo = FakeRepo {
+            migration: std::sync::Mutex::new(Some(report.clone())),
+            recovery: std::sync::Mutex::new(Some(recovery.clone())),
+            ..Default::default()
+        };
+        let controller = loaded_controller(repo).await;
+
+        // Execute
+        let result = controller.handle_action(Action::Project(ProjectAction::LoadProject { path: PathBuf::from("/tmp/old/project.json"), force: false, read_only: false })).await.unwrap();
+
+        // Assert
+        let ActionResult::JournalRecovered { recovery: reported, migration } = result else { panic!("Expected JournalRecovered, got {:?}", result) };
+        assert_eq!(reported, recovery);
+        assert_eq!(migration, Some(report));
+        assert!(is_modified(&controller), "Recovered changes should be saved over the damaged journal");
+    }
+
+    #[tokio::test]
+    async fn test_plain_load_is_clean() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        let result = controller.handle_action(Action::Project(ProjectAction::LoadProject { path: PathBuf::from("/tmp/new/project.json"), force: false, read_only: false })).await.unwrap();
+
+        assert!(matches!(result, ActionResult::Success));
+        assert!(!is_modified(&controller));
+    }
+}
+
+// ===== Project locking =====
+
+mod locking {
+    use super::*;
+
+    fn load(path: &str, force: bool) -> Action {
+        Action::Project(ProjectAction::LoadProject { path: PathBuf::from(path), force, read_only: false })
+    }
+
+    fn open_path(controller: &TestController) -> PathBuf {
+        controller.state.read().unwrap().project_path().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_locked_project_is_refused_with_owner() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::locked_elsewhere()).await;
+
+        // Execute
+        let err = controller.handle_action(load("/tmp/shared/project.json", false)).await.unwrap_err();
+
+        // Assert: The owner is reported and the open project is untouched
+        let Some(ProjectError::Locked(lock)) = err.downcast_ref::<ProjectError>() else {
+            panic!("Expected Locked, got: {}", err)
+        };
+        assert_eq!(lock.host, "other-host");
+        assert_eq!(open_path(&controller), PathBuf::from("/tmp/study/project.json"));
+        assert!(controller.project_repo.unlocked.lock().unwrap().is_empty(), "Current project should keep its lock");
+    }
+
+    #[tokio::test]
+    async fn test_force_takes_over_lock() {
+        let controller = loaded_controller(FakeRepo::locked_elsewhere()).await;
+
+        controller.handle_action(load("/tmp/shared/project.json", true)).await.unwrap();
+
+        assert_eq!(open_path(&controller), PathBuf::from("/tmp/shared/project.json"));
+    }
+
+    #[tokio::test]
+    async fn test_opening_another_project_releases_previous_lock() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        controller.handle_action(load("/tmp/other/project.json", false)).await.unwrap();
+
+        assert_eq!(*controller.project_repo.unlocked.lock().unwrap(), vec![PathBuf::from("/tmp/study/project.json")]);
+    }
+
+    #[tokio::test]
+    async fn test_reopening_current_project_keeps_lock() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        controller.handle_action(load("/tmp/study/project.json", false)).await.unwrap();
+
+        assert!(controller.project_repo.unlocked.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_quit_releases_lock() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        controller.handle_action(Action::Quit).await.unwrap();
+
+        assert_eq!(*controller.project_repo.unlocked.lock().unwrap(), vec![PathBuf::from("/tmp/study/project.json")]);
+    }
+}
+
+// ===== Read-only projects =====
+
+mod read_only {
+    use super::*;
+
+    /// Controller that has opened a project read-only
+    async fn read_only_controller(repo: FakeRepo) -> Arc<TestController> {
+        let controller = loaded_controller(repo).await;
+        controller.handle_action(Action::Project(ProjectAction::LoadProject {
+            path: PathBuf::from("/tmp/student/project.json"),
+            force: false,
+            read_only: true,
+        })).await.unwrap();
+        controller
+    }
+
+    fn assert_read_only_error(result: Result<ActionResult>) {
+        let err = result.unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::ReadOnly)),
+            "Expected ReadOnly, got: {}", err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_only_open_ignores_lock() {
+        // Setup / Execute: Someone else is editing the project
+        let controller = read_only_controller(FakeRepo::locked_elsewhere()).await;
+
+        // Assert
+        let state = controller.state.read().unwrap();
+        let DataState::Loaded(ctx) = &state.project else { panic!("Project should be loaded") };
+        assert!(ctx.is_read_only());
+        assert_eq!(*controller.project_repo.unlocked.lock().unwrap(), vec![PathBuf::from("/tmp/study/project.json")],
+            "Previous project's lock should still be released");
+    }
+
+    #[tokio::test]
+    async fn test_edits_are_rejected_without_changing_state() {
+        // Setup
+        let controller = read_only_controller(FakeRepo::default()).await;
+        let file_id = FileList::new().add_file("a.txt".to_string(), FileType::PlainText);
+        let highlight = Highlight::new(TextBlock::new(file_id, 0, "text".to_string()).id, 0, 4);
+
+        // Execute
+        let create = controller.handle_action(create_code("Theme")).await;
+        let add = controller.handle_action(Action::File(FileAction::AddFile { path: PathBuf::from("/tmp/student/notes.txt"), mime: None })).await;
+        let folder = controller.handle_action(Action::File(FileAction::AddFolder {
+            path: PathBuf::from("/tmp/student"),
+            filter: FolderFilter::default(),
+            progress: None,
+            cancel: CancelToken::new(),
+        })).await;
+        let apply = controller.handle_action(Action::Coding(CodingAction::ApplyCode {
+            code_def_id: CodeBook::new().create_code_def("Elsewhere".to_string(), 1, None),
+            highlight,
+            snippet: "text".to_string(),
+        })).await;
+
+        // Assert
+        assert_read_only_error(create);
+        assert_read_only_error(add);
+        assert_read_only_error(folder);
+        assert_read_only_error(apply);
+        let state = controller.state.read().unwrap();
+        assert_eq!(state.codebook.get_all_code_defs().count(), 0);
+        assert_eq!(state.filemanager.file_count(), 0);
+        assert!(matches!(state.project, DataState::Loaded(_)), "Rejected edits should not mark the project modified");
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_save_is_refused() {
+        let controller = read_only_controller(FakeRepo::default()).await;
+
+        assert_read_only_error(controller.handle_action(Action::Project(ProjectAction::SaveProject)).await);
+        assert_eq!(controller.project_repo.saves.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_migrated_read_only_project_is_not_autosaved() {
+        // Setup: The project needs migrating, which would normally mark it modified
+        let report = MigrationReport { from_version: 1, to_version: 2, applied: vec!["Step".to_string()] };
+        let repo = FakeRepo { migration: std::sync::Mutex::new(Some(report)), ..Default::default() };
+        let controller = read_only_controller(repo).await;
+
+        // Execute: Even a project forced into Modified isn't written
+        controller.state.write().unwrap().mark_modified();
+        let saved = controller.autosave().await.unwrap();
+
+        // Assert
+        assert!(!saved);
+        assert_eq!(controller.project_repo.autosaves.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_drift_is_rejected() {
+        let controller = read_only_controller(FakeRepo::default()).await;
+        let id = controller.state.write().unwrap().filemanager.add_file("a.txt".to_string(), FileType::PlainText);
+
+        let err = controller.handle_action(Action::File(FileAction::ResolveDrift {
+            file_id: id,
+            resolution: DriftResolution::Reanchor,
+        })).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::ReadOnly)), "Got: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_relink_is_rejected() {
+        let controller = read_only_controller(FakeRepo::default()).await;
+        let id = controller.state.write().unwrap().filemanager.add_file("a.txt".to_string(), FileType::PlainText);
+
+        let err = controller.handle_action(Action::File(FileAction::RelinkFile {
+            file_id: id,
+            path: PathBuf::from("/tmp/study/moved/a.txt"),
+            whole_folder: true,
+        })).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::ReadOnly)), "Got: {}", err);
+        assert_eq!(controller.state.read().unwrap().filemanager.file(id).unwrap().path(), "a.txt");
+    }
+}
+
+// ===== File actions =====
+
+mod files {
+    use super::*;
+
+    fn add_file(path: &str) -> Action {
+        Action::File(FileAction::AddFile { path: PathBuf::from(path), mime: None })
+    }
+
+    #[tokio::test]
+    async fn test_add_file_registers_relative_path() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        // Execute
+        let result = controller.handle_action(add_file("/tmp/study/interviews/a.txt")).await.unwrap();
+
+        // Assert
+        let ActionResult::FileAdded(id) = result else { panic!("Expected FileAdded, got {:?}", result) };
+        let state = controller.state.read().unwrap();
+        let file = state.filemanager.file(id).expect("File should be registered");
+        assert_eq!(file.path_buf(), PathBuf::from("interviews/a.txt"));
+        assert_eq!(file.file_type(), &FileType::PlainText);
+        assert!(matches!(state.project, DataState::Modified(_)), "Adding a file should leave unsaved changes");
+    }
+
+    #[tokio::test]
+    async fn test_add_file_is_persisted_incrementally() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        controller.handle_action(add_file("/tmp/study/a.txt")).await.unwrap();
+
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst), 1);
+        assert!(is_modified(&controller), "A journaled file should still wait for a save");
+        controller.handle_action(Action::Project(ProjectAction::SaveProject)).await.unwrap();
+        assert!(!is_modified(&controller));
+    }
+
+    #[tokio::test]
+    async fn test_add_file_fails_if_the_project_closes_while_reading() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+        *controller.file_loader.close_at.lock().unwrap() = Some(("a.txt".to_string(), controller.state.clone()));
+
+        // Execute
+        let err = controller.handle_action(add_file("/tmp/study/a.txt")).await.unwrap_err();
+
+        // Assert
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Load(_))), "Got: {}", err);
+        assert_eq!(controller.state.read().unwrap().filemanager.file_count(), 0);
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_file_is_rejected() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        // Execute
+        let err = controller.handle_action(add_file("/tmp/study/photo.jpg")).await.unwrap_err();
+
+        // Assert: Loader error surfaces and nothing was registered
+        assert!(matches!(err.downcast_ref::<FileError>(), Some(FileError::Unsupported(_))), "Got: {}", err);
+        let state = controller.state.read().unwrap();
+        assert_eq!(state.filemanager.file_count(), 0);
+        assert!(matches!(state.project, DataState::Loaded(_)));
+    }
+
+    #[tokio::test]
+    async fn test_list_formats_reports_what_the_loader_reads() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        let result = controller.handle_action(Action::File(FileAction::ListFormats)).await.unwrap();
+
+        let ActionResult::Formats(formats) = result else { panic!("Expected Formats, got {:?}", result) };
+        let types: Vec<&FileType> = formats.iter().map(|format| &format.file_type).collect();
+        assert_eq!(types, vec![&FileType::PlainText, &FileType::Csv]);
+        assert!(formats[1].provides(BlockMeta::CASE));
+        assert!(!formats[0].provides(BlockMeta::CASE));
+    }
+
+    fn load_file(id: FileId) -> Action {
+        Action::File(FileAction::LoadFile(id))
+    }
+
+    fn register(controller: &TestController, path: &str) -> FileId {
+        controller.state.write().unwrap().filemanager.add_file(path.to_string(), FileType::PlainText)
+    }
+
+    fn block_ids(controller: &TestController, id: FileId) -> Option<Vec<BlockId>> {
+        let state = controller.state.read().unwrap();
+        state.filemanager.file(id)?.blocks().map(|blocks| blocks.iter().map(|b| b.id).collect())
+    }
+
+    #[tokio::test]
+    async fn test_load_file_stores_blocks_without_modifying_project() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let id = register(&controller, "interviews/a.txt");
+
+        // Execute
+        let result = controller.handle_action(load_file(id)).await.unwrap();
+
+        // Assert
+        assert!(matches!(result, ActionResult::FileLoaded(loaded) if loaded == id));
+        assert_eq!(block_ids(&controller, id).map(|ids| ids.len()), Some(2));
+        assert!(!is_modified(&controller), "Reading a source file is not a project change");
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_file_is_unloaded() {
+        // Setup: Room for two loaded files
+        let controller = loaded_controller(FakeRepo::default()).await;
+        controller.state.write().unwrap().config.max_loaded_files = 2;
+        let a = register(&controller, "a.txt");
+        let b = register(&controller, "b.txt");
+        let c = register(&controller, "c.txt");
+
+        // Execute: Load a and b, use a again, then load c
+        for id in [a, b, a, c] {
+            controller.handle_action(load_file(id)).await.unwrap();
+        }
+
+        // Assert: b was the least recently used
+        assert!(block_ids(&controller, a).is_some());
+        assert!(block_ids(&controller, b).is_none(), "Evicted file should go back to Empty");
+        assert!(block_ids(&controller, c).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reloaded_file_has_same_block_ids() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+        controller.state.write().unwrap().config.max_loaded_files = 1;
+        let a = register(&controller, "interviews/a.txt");
+        let b = register(&controller, "b.txt");
+        controller.handle_action(load_file(a)).await.unwrap();
+        let first = block_ids(&controller, a).unwrap();
+
+        // Execute: Evict a, then load it again
+        controller.handle_action(load_file(b)).await.unwrap();
+        controller.handle_action(load_file(a)).await.unwrap();
+
+        // Assert
+        assert_eq!(block_ids(&controller, a).unwrap(), first);
+    }
+
+    #[tokio::test]
+    async fn test_first_load_persists_block_anchors() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let id = register(&controller, "interviews/a.txt");
+
+        controller.handle_action(load_file(id)).await.unwrap();
+
+        let state = controller.state.read().unwrap();
+        assert_eq!(state.filemanager.file(id).unwrap().anchors().len(), 2);
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst), 1, "Anchors should be written with the file");
+    }
+
+    fn set_source(controller: &TestController, path: &str, paragraphs: &[&str]) {
+        let paragraphs = paragraphs.iter().map(|p| p.to_string()).collect();
+        controller.file_loader.sources.lock().unwrap().insert(path.to_string(), paragraphs);
+    }
+
+    fn unload(controller: &TestController, id: FileId) {
+        controller.state.write().unwrap().filemanager.file_mut(id).unwrap().set_data_state(DataState::Empty);
+    }
+
+    fn resolve(file_id: FileId, resolution: DriftResolution) -> Action {
+        Action::File(FileAction::ResolveDrift { file_id, resolution })
+    }
+
+    /// Loaded `a.txt` with a highlight on "key quote" in its second paragraph
+    async fn coded_file(controller: &TestController) -> (FileId, QualCodeId) {
+        let id = register(controller, "a.txt");
+        set_source(controller, "a.txt", &["Intro", "The key quote is here"]);
+        controller.handle_action(load_file(id)).await.unwrap();
+        let ActionResult::CodeCreated(code_def_id) = controller.handle_action(create_code("A")).await.unwrap() else {
+            panic!("Expected CodeCreated");
+        };
+        let block_id = block_ids(controller, id).unwrap()[1];
+        let ActionResult::CodeApplied(code_id) = controller.handle_action(Action::Coding(CodingAction::ApplyCode {
+            code_def_id,
+            highlight: Highlight::new(block_id, 4, 13),
+            snippet: "key quote".to_string(),
+        })).await.unwrap() else {
+            panic!("Expected CodeApplied");
+        };
+        (id, code_id)
+    }
+
+    #[tokio::test]
+    async fn test_added_file_records_fingerprint() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        let ActionResult::FileAdded(id) = controller.handle_action(add_file("/tmp/study/a.txt")).await.unwrap() else {
+            panic!("Expected FileAdded");
+        };
+
+        let state = controller.state.read().unwrap();
+        let fingerprint = state.filemanager.file(id).unwrap().fingerprint().expect("Fingerprint should be recorded");
+        assert_eq!(fingerprint.sha256, "a.txt");
+    }
+
+    #[tokio::test]
+    async fn test_edited_source_is_reported_before_reanchoring() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (id, code_id) = coded_file(&controller).await;
+        let anchors_before = controller.state.read().unwrap().filemanager.file(id).unwrap().anchors().to_vec();
+
+        // Execute: The file gains a paragraph and is loaded again in a later session
+        set_source(&controller, "a.txt", &["Recorded Tuesday", "Intro", "The key quote is here"]);
+        unload(&controller, id);
+        let writes_before = controller.project_repo.incremental_writes.load(Ordering::SeqCst);
+        let result = controller.handle_action(load_file(id)).await.unwrap();
+
+        // Assert: The edit is summarised, and nothing moved or was written until the user decides
+        let ActionResult::FileDrifted { file_id, drift: FileDrift::Edited(summary) } = result else {
+            panic!("Expected FileDrifted Edited, got {:?}", result)
+        };
+        assert_eq!(file_id, id);
+        assert_eq!(summary, DiffSummary { unchanged: 2, edited: 0, added: 1, removed: 0 });
+        let state = controller.state.read().unwrap();
+        let file = state.filemanager.file(id).unwrap();
+        assert!(matches!(file.data_state(), DataState::Modified(_)), "Edited contents should be held for review");
+        assert_eq!(file.anchors(), anchors_before.as_slice());
+        let code = state.codebook.get_all_qual_codes().iter().find(|qc| qc.id == code_id).unwrap();
+        assert_eq!(code.block_id(), anchors_before[1].id);
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst), writes_before);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reanchor_moves_highlights() {
+        // Setup: Drift reported for an added paragraph
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (id, code_id) = coded_file(&controller).await;
+        set_source(&controller, "a.txt", &["Recorded Tuesday", "Intro", "The key quote is here"]);
+        unload(&controller, id);
+        controller.handle_action(load_file(id)).await.unwrap();
+        let writes_before = controller.project_repo.incremental_writes.load(Ordering::SeqCst);
+
+        // Execute
+        let result = controller.handle_action(resolve(id, DriftResolution::Reanchor)).await.unwrap();
+
+        // Assert: Highlight follows the quote, and the file and moved code are persisted
+        let ActionResult::FileReanchored { file_id, report } = result else { panic!("Expected FileReanchored, got {:?}", result) };
+        assert_eq!(file_id, id);
+        assert_eq!(report.moved, vec![code_id]);
+        let new_block = block_ids(&controller, id).unwrap()[2];
+        let code = controller.state.read().unwrap().codebook.get_all_qual_codes().iter()
+            .find(|qc| qc.id == code_id).cloned().unwrap();
+        assert_eq!((code.block_id(), code.position()), (new_block, (4, 13)));
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst) - writes_before, 2);
+
+        // A later load sees the accepted version as unchanged
+        unload(&controller, id);
+        let result = controller.handle_action(load_file(id)).await.unwrap();
+        assert!(matches!(result, ActionResult::FileLoaded(_)), "Got {:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_accept_orphans_highlights_on_changed_blocks() {
+        // Setup: The coded paragraph is reworded
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (id, code_id) = coded_file(&controller).await;
+        set_source(&controller, "a.txt", &["Intro", "The key quote was here"]);
+        unload(&controller, id);
+        controller.handle_action(load_file(id)).await.unwrap();
+
+        // Execute
+        let result = controller.handle_action(resolve(id, DriftResolution::Accept)).await.unwrap();
+
+        // Assert
+        let ActionResult::FileReanchored { report, .. } = result else { panic!("Expected FileReanchored, got {:?}", result) };
+        assert_eq!(report.orphaned, vec![code_id]);
+        assert!(report.moved.is_empty());
+        let state = controller.state.read().unwrap();
+        let code = state.codebook.get_all_qual_codes().iter().find(|qc| qc.id == code_id).unwrap();
+        assert!(code.is_orphaned());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_restore_original_drops_edited_contents() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (id, _) = coded_file(&controller).await;
+        set_source(&controller, "a.txt", &["Something else entirely"]);
+        unload(&controller, id);
+        controller.handle_action(load_file(id)).await.unwrap();
+        let anchors_before = controller.state.read().unwrap().filemanager.file(id).unwrap().anchors().to_vec();
+
+        let result = controller.handle_action(resolve(id, DriftResolution::RestoreOriginal)).await.unwrap();
+
+        assert!(matches!(result, ActionResult::Success));
+        let state = controller.state.read().unwrap();
+        let file = state.filemanager.file(id).unwrap();
+        assert!(matches!(file.data_state(), DataState::Empty));
+        assert_eq!(file.anchors(), anchors_before.as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_touched_source_refreshes_fingerprint() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (id, _) = coded_file(&controller).await;
+
+        // Execute: Same contents with a new modification time, as after saving it unchanged
+        controller.file_loader.touched.lock().unwrap().push("a.txt".to_string());
+        unload(&controller, id);
+        let result = controller.handle_action(load_file(id)).await.unwrap();
+
+        // Assert
+        assert!(matches!(result, ActionResult::FileLoaded(loaded) if loaded == id), "Got {:?}", result);
+        let state = controller.state.read().unwrap();
+        let file = state.filemanager.file(id).unwrap();
+        assert!(file.blocks().is_some());
+        assert!(file.fingerprint().unwrap().modified.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_missing_source_is_reported() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (id, _) = coded_file(&controller).await;
+
+        controller.file_loader.missing.lock().unwrap().push("a.txt".to_string());
+        unload(&controller, id);
+        let result = controller.handle_action(load_file(id)).await.unwrap();
+
+        assert!(matches!(result, ActionResult::FileDrifted { drift: FileDrift::Missing, .. }), "Got {:?}", result);
+        let state = controller.state.read().unwrap();
+        assert!(matches!(state.filemanager.file(id).unwrap().data_state(), DataState::Error));
+    }
+
+    fn relink(file_id: FileId, path: &str, whole_folder: bool) -> Action {
+        Action::File(FileAction::RelinkFile { file_id, path: PathBuf::from(path), whole_folder })
+    }
+
+    /// Registers `path` as added, with its current contents fingerprinted
+    async fn added(controller: &TestController, path: &str) -> FileId {
+        let ActionResult::FileAdded(id) = controller.handle_action(add_file(&format!("/tmp/study/{}", path))).await.unwrap() else {
+            panic!("Expected FileAdded");
+        };
+        id
+    }
+
+    #[tokio::test]
+    async fn test_find_file_proposes_copies_with_same_contents() {
+        // Setup: The file went missing, and two files with its name exist elsewhere
+        let controller = loaded_controller(FakeRepo::default()).await;
+        set_source(&controller, "interviews/a.txt", &["Transcript"]);
+        let id = added(&controller, "interviews/a.txt").await;
+        controller.file_loader.missing.lock().unwrap().push("interviews/a.txt".to_string());
+        set_source(&controller, "archive/a.txt", &["Transcript"]);
+        set_source(&controller, "drafts/a.txt", &["Earlier draft"]);
+        controller.file_loader.on_disk.lock().unwrap().extend(["drafts/a.txt".to_string(), "archive/a.txt".to_string()]);
+
+        // Execute
+        let result = controller.handle_action(Action::File(FileAction::FindFile { file_id: id, folders: vec![] })).await.unwrap();
+
+        // Assert
+        let ActionResult::RelinkProposals(proposals) = result else { panic!("Expected RelinkProposals, got {:?}", result) };
+        assert_eq!(proposals, vec![RelinkProposal { file_id: id, path: "archive/a.txt".to_string(), matched_by: RelinkMatch::Content }]);
+    }
+
+    #[tokio::test]
+    async fn test_relink_whole_folder_moves_missing_siblings() {
+        // Setup: Coded files whose folder was moved into an archive
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (a, code_id) = coded_file(&controller).await;
+        controller.state.write().unwrap().filemanager.file_mut(a).unwrap().set_path("interviews/a.txt".to_string());
+        let b = register(&controller, "interviews/b.txt");
+        let notes = register(&controller, "notes.txt");
+        controller.file_loader.missing.lock().unwrap().extend(["interviews/a.txt".to_string(), "interviews/b.txt".to_string()]);
+        let writes_before = controller.project_repo.incremental_writes.load(Ordering::SeqCst);
+
+        // Execute
+        let result = controller.handle_action(relink(a, "/tmp/study/archive/interviews/a.txt", true)).await.unwrap();
+
+        // Assert: Both files point into the archive, the code is still on a, and the rest is untouched
+        let ActionResult::FilesRelinked(ids) = result else { panic!("Expected FilesRelinked, got {:?}", result) };
+        assert_eq!(ids, vec![a, b]);
+        let state = controller.state.read().unwrap();
+        assert_eq!(state.filemanager.file(a).unwrap().path(), "archive/interviews/a.txt");
+        assert_eq!(state.filemanager.file(b).unwrap().path(), "archive/interviews/b.txt");
+        assert_eq!(state.filemanager.file(notes).unwrap().path(), "notes.txt");
+        assert!(state.filemanager.file(a).unwrap().blocks().is_none(), "Blocks from the old location should be dropped");
+        let code_block = state.codebook.get_all_qual_codes().iter().find(|qc| qc.id == code_id).unwrap().block_id();
+        assert!(state.filemanager.file(a).unwrap().anchors().iter().any(|anchor| anchor.id == code_block));
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst) - writes_before, 2);
+        assert!(!is_modified(&controller));
+    }
+
+    #[tokio::test]
+    async fn test_relink_skips_siblings_with_other_contents() {
+        // Setup: b's new location holds a different file of the same name
+        let controller = loaded_controller(FakeRepo::default()).await;
+        set_source(&controller, "interviews/b.txt", &["Second interview"]);
+        let a = added(&controller, "interviews/a.txt").await;
+        let b = added(&controller, "interviews/b.txt").await;
+        controller.file_loader.missing.lock().unwrap().extend(["interviews/a.txt".to_string(), "interviews/b.txt".to_string()]);
+        set_source(&controller, "archive/interviews/b.txt", &["Someone else"]);
+
+        // Execute
+        let result = controller.handle_action(relink(a, "/tmp/study/archive/interviews/a.txt", true)).await.unwrap();
+
+        // Assert
+        assert!(matches!(result, ActionResult::FilesRelinked(ids) if ids == vec![a]));
+        assert_eq!(controller.state.read().unwrap().filemanager.file(b).unwrap().path(), "interviews/b.txt");
+    }
+
+    #[tokio::test]
+    async fn test_relink_single_file_leaves_siblings() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let a = register(&controller, "interviews/a.txt");
+        let b = register(&controller, "interviews/b.txt");
+        controller.file_loader.missing.lock().unwrap().extend(["interviews/a.txt".to_string(), "interviews/b.txt".to_string()]);
+
+        let result = controller.handle_action(relink(a, "/tmp/study/archive/interviews/a.txt", false)).await.unwrap();
+
+        assert!(matches!(result, ActionResult::FilesRelinked(ids) if ids == vec![a]));
+        assert_eq!(controller.state.read().unwrap().filemanager.file(b).unwrap().path(), "interviews/b.txt");
+    }
+
+    #[tokio::test]
+    async fn test_relinked_file_loads_as_moved() {
+        // Setup: The same contents turn up in another folder and the file is pointed there
+        let controller = loaded_controller(FakeRepo::default()).await;
+        set_source(&controller, "interviews/a.txt", &["First interview"]);
+        let a = added(&controller, "interviews/a.txt").await;
+        controller.file_loader.missing.lock().unwrap().push("interviews/a.txt".to_string());
+        set_source(&controller, "archive/a.txt", &["First interview"]);
+        controller.handle_action(relink(a, "/tmp/study/archive/a.txt", false)).await.unwrap();
+
+        // Execute
+        let result = controller.handle_action(load_file(a)).await.unwrap();
+
+        // Assert: Reported once, with the fingerprint now taken at the new path
+        assert!(matches!(result, ActionResult::FileDrifted { drift: FileDrift::Moved, .. }), "Got {:?}", result);
+        let state = controller.state.read().unwrap();
+        let file = state.filemanager.file(a).unwrap();
+        assert!(file.blocks().is_some());
+        assert_eq!(file.fingerprint().unwrap().path.as_deref(), Some("archive/a.txt"));
+    }
+
+    fn embed_sources(embed: bool) -> Action {
+        Action::Project(ProjectAction::EmbedSources(embed))
+    }
+
+    fn snapshot_of(controller: &TestController, id: FileId) -> Option<SourceSnapshot> {
+        controller.state.read().unwrap().filemanager.file(id).unwrap().snapshot().cloned()
+    }
+
+    #[tokio::test]
+    async fn test_embedding_snapshots_every_file() {
+        // Setup: One loaded file and one never loaded
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (a, _) = coded_file(&controller).await;
+        let b = register(&controller, "b.txt");
+        let writes_before = controller.project_repo.incremental_writes.load(Ordering::SeqCst);
+
+        // Execute
+        controller.handle_action(embed_sources(true)).await.unwrap();
+
+        // Assert: Both carry their text, and the setting waits for a full save
+        let a_blocks = snapshot_of(&controller, a).expect("Loaded file should be snapshotted").blocks(a);
+        assert_eq!(a_blocks.iter().map(|b| b.id).collect::<Vec<_>>(), block_ids(&controller, a).unwrap());
+        assert_eq!(snapshot_of(&controller, b).map(|s| s.blocks(b).len()), Some(1));
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst) - writes_before, 2);
+        assert!(is_modified(&controller), "The project setting needs a save");
+    }
+
+    #[tokio::test]
+    async fn test_missing_source_loads_from_snapshot() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+        controller.handle_action(embed_sources(true)).await.unwrap();
+        let (id, code_id) = coded_file(&controller).await;
+        let coded_blocks = block_ids(&controller, id).unwrap();
+
+        // Execute: The project travels without its sources
+        controller.file_loader.missing.lock().unwrap().push("a.txt".to_string());
+        unload(&controller, id);
+        let result = controller.handle_action(load_file(id)).await.unwrap();
+
+        // Assert: The UI is told, and the code's block is there
+        assert!(matches!(result, ActionResult::FileLoadedFromSnapshot(loaded) if loaded == id), "Got {:?}", result);
+        assert_eq!(block_ids(&controller, id).unwrap(), coded_blocks);
+        let state = controller.state.read().unwrap();
+        let code = state.codebook.get_all_qual_codes().iter().find(|qc| qc.id == code_id).unwrap();
+        assert!(coded_blocks.contains(&code.block_id()));
+    }
+
+    #[tokio::test]
+    async fn test_file_can_opt_out_of_embedding() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (id, _) = coded_file(&controller).await;
+        controller.handle_action(embed_sources(true)).await.unwrap();
+        assert!(snapshot_of(&controller, id).is_some());
+
+        // Execute
+        controller.handle_action(Action::File(FileAction::EmbedSource { file_id: id, embed: Some(false) })).await.unwrap();
+
+        // Assert: Dropped now, and not retaken when the file is committed again
+        assert!(snapshot_of(&controller, id).is_none());
+        set_source(&controller, "a.txt", &["Intro", "The key quote was here"]);
+        unload(&controller, id);
+        controller.handle_action(load_file(id)).await.unwrap();
+        controller.handle_action(resolve(id, DriftResolution::Accept)).await.unwrap();
+        assert!(snapshot_of(&controller, id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_edited_file_is_not_snapshotted() {
+        // Setup: The source changed since coding and hasn't been resolved
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let (id, _) = coded_file(&controller).await;
+        set_source(&controller, "a.txt", &["Intro", "Rewritten"]);
+        unload(&controller, id);
+
+        // Execute
+        controller.handle_action(Action::File(FileAction::EmbedSource { file_id: id, embed: Some(true) })).await.unwrap();
+
+        // Assert
+        assert!(snapshot_of(&controller, id).is_none(), "Only the coded version should be embedded");
+    }
+
+    #[tokio::test]
+    async fn test_restore_original_shows_snapshot() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+        controller.handle_action(embed_sources(true)).await.unwrap();
+        let (id, _) = coded_file(&controller).await;
+        let coded_blocks = block_ids(&controller, id).unwrap();
+        set_source(&controller, "a.txt", &["Something else entirely"]);
+        unload(&controller, id);
+        controller.handle_action(load_file(id)).await.unwrap();
+
+        // Execute
+        let result = controller.handle_action(resolve(id, DriftResolution::RestoreOriginal)).await.unwrap();
+
+        // Assert
+        assert!(matches!(result, ActionResult::FileLoadedFromSnapshot(_)), "Got {:?}", result);
+        assert_eq!(block_ids(&controller, id).unwrap(), coded_blocks);
+    }
+
+    #[tokio::test]
+    async fn test_failed_load_marks_file_error() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let id = register(&controller, "broken.txt");
+
+        let err = controller.handle_action(load_file(id)).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<FileError>(), Some(FileError::Read(_))), "Got: {}", err);
+        let state = controller.state.read().unwrap();
+        assert!(matches!(state.filemanager.file(id).unwrap().data_state(), DataState::Error));
+    }
+
+    #[tokio::test]
+    async fn test_load_unknown_file_is_rejected() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let unknown = FileList::new().add_file("elsewhere.txt".to_string(), FileType::PlainText);
+
+        let err = controller.handle_action(load_file(unknown)).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<FileListError>(), Some(FileListError::FileNotFound(_))), "Got: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_add_file_without_project_is_rejected() {
+        let state = Arc::new(RwLock::new(AppState::new(DataState::Empty, AppConfig::default())));
+        let controller = AppController::new(state, FakeRepo::default(), FakeLoader::default(), FakeConfig).await.unwrap();
+
+        let err = controller.handle_action(add_file("/tmp/study/a.txt")).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Load(_))), "Got: {}", err);
+    }
+}
+
+// ===== Survey import =====
+
+mod surveys {
+    use super::*;
+
+    /// Controller whose loader knows `survey.csv`: two respondents answering Q1 and Q2, with
+    /// their age group as an attribute
+    async fn survey_controller(repo: FakeRepo) -> Arc<TestController> {
+        let controller = loaded_controller(repo).await;
+        let row = |cells: &[&str]| cells.iter().map(|c| c.to_string()).collect();
+        let table = SurveyTable {
+            sheets: Vec::new(),
+            columns: row(&["ID", "Age", "Q1", "Q2"]),
+            rows: vec![
+                row(&["R1", "18-24", "Too expensive", "More evening slots"]),
+                row(&["R2", "65+", "Friendly staff", ""]),
+            ],
+        };
+        controller.file_loader.tables.lock().unwrap().insert("survey.csv".to_string(), table);
+        controller
+    }
+
+    fn import(path: &str, text_columns: &[&str], grouping: SurveyGrouping) -> Action {
+        Action::File(FileAction::ImportSurvey {
+            path: PathBuf::from(path),
+            selection: SurveySelection {
+                sheet: None,
+                id_column: "ID".to_string(),
+                text_columns: text_columns.iter().map(|c| c.to_string()).collect(),
+                attribute_columns: vec!["Age".to_string()],
+            },
+            grouping,
+        })
+    }
+
+    fn imported(result: ActionResult) -> Vec<FileId> {
+        match result {
+            ActionResult::FilesAdded(ids) => ids,
+            other => panic!("Expected FilesAdded, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_by_column_adds_a_file_per_question() {
+        // Setup
+        let controller = survey_controller(FakeRepo::default()).await;
+
+        // Execute
+        let result = controller.handle_action(import("/tmp/study/survey.csv", &["Q1", "Q2"], SurveyGrouping::ByColumn)).await.unwrap();
+
+        // Assert: Both files read the same spreadsheet, each its own column
+        let ids = imported(result);
+        assert!(is_modified(&controller), "Imported cases should wait for a save like any added file");
+        let state = controller.state.read().unwrap();
+        let scopes: Vec<SurveyScope> = ids.iter()
+            .map(|id| state.filemanager.file(*id).unwrap().survey().unwrap().scope.clone())
+            .collect();
+        assert_eq!(scopes, vec![SurveyScope::Column("Q1".to_string()), SurveyScope::Column("Q2".to_string())]);
+        for id in &ids {
+            let file = state.filemanager.file(*id).unwrap();
+            assert_eq!(file.path(), "survey.csv");
+            assert_eq!(file.file_type(), &FileType::Csv);
+            assert!(file.fingerprint().is_some());
+        }
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_import_by_respondent_loads_answers_as_a_case() {
+        // Setup
+        let controller = survey_controller(FakeRepo::default()).await;
+        let ids = imported(controller.handle_action(import("/tmp/study/survey.csv", &["Q1", "Q2"], SurveyGrouping::ByRespondent)).await.unwrap());
+
+        // Execute
+        let result = controller.handle_action(Action::File(FileAction::LoadFile(ids[0]))).await.unwrap();
+
+        // Assert: R1's answers, each labelled with its question and R1's attributes
+        assert_eq!(ids.len(), 2);
+        assert!(matches!(result, ActionResult::FileLoaded(id) if id == ids[0]));
+        let state = controller.state.read().unwrap();
+        let blocks = state.filemanager.file(ids[0]).unwrap().blocks().unwrap();
+        let answers: Vec<(&str, Option<&str>)> = blocks.iter().map(|b| (b.content.as_str(), b.meta.question())).collect();
+        assert_eq!(answers, vec![("Too expensive", Some("Q1")), ("More evening slots", Some("Q2"))]);
+        assert!(blocks.iter().all(|b| b.meta.case() == Some("R1") && b.meta.attribute("Age") == Some("18-24")));
+    }
+
+    #[tokio::test]
+    async fn test_bad_selection_adds_nothing() {
+        let controller = survey_controller(FakeRepo::default()).await;
+
+        let err = controller.handle_action(import("/tmp/study/survey.csv", &["Q9"], SurveyGrouping::ByColumn)).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<FileError>(), Some(FileError::Parse(m)) if m.contains("Q9")), "Got: {}", err);
+        assert_eq!(controller.state.read().unwrap().filemanager.file_count(), 0);
+        assert!(!is_modified(&controller));
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_documents() {
+        let controller = survey_controller(FakeRepo::default()).await;
+
+        let err = controller.handle_action(import("/tmp/study/notes.txt", &["Q1"], SurveyGrouping::ByColumn)).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<FileError>(), Some(FileError::Unsupported(_))), "Got: {}", err);
+        assert_eq!(controller.state.read().unwrap().filemanager.file_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_preview_returns_the_columns_to_choose_from() {
+        let controller = survey_controller(FakeRepo::default()).await;
+
+        let result = controller.handle_action(Action::File(FileAction::PreviewSurvey { path: PathBuf::from("/tmp/study/survey.csv"), sheet: None })).await.unwrap();
+
+        let ActionResult::SurveyPreview(table) = result else { panic!("Expected SurveyPreview, got {:?}", result) };
+        assert_eq!(table.columns, vec!["ID", "Age", "Q1", "Q2"]);
+    }
+}
+
+// ===== Folder import =====
+
+mod folders {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    /// Controller whose project holds `a.txt`, with `files` in the project folder. Files named
+    /// in `contents` share content with the others given the same text.
+    async fn folder_controller(files: &[&str], contents: &[(&str, &str)]) -> Arc<TestController> {
+        let controller = loaded_controller(FakeRepo::default()).await;
+        {
+            let mut sources = controller.file_loader.sources.lock().unwrap();
+            sources.insert("a.txt".to_string(), vec!["Interview A".to_string()]);
+            for (path, text) in contents {
+                sources.insert(path.to_string(), vec![text.to_string()]);
+            }
+        }
+        controller.handle_action(Action::File(FileAction::AddFile { path: PathBuf::from("/tmp/study/a.txt"), mime: None })).await.unwrap();
+        *controller.file_loader.on_disk.lock().unwrap() = files.iter().map(|f| f.to_string()).collect();
+        controller
+    }
+
+    fn add_folder(progress: Option<mpsc::UnboundedSender<ImportProgress>>, cancel: &CancelToken) -> Action {
+        Action::File(FileAction::AddFolder {
+            path: PathBuf::from("/tmp/study"),
+            filter: FolderFilter { recursive: true, ..FolderFilter::default() },
+            progress,
+            cancel: cancel.clone(),
+        })
+    }
+
+    fn imported(result: ActionResult) -> FolderImportReport {
+        match result {
+            ActionResult::FolderImported(report) => report,
+            other => panic!("Expected FolderImported, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_folder_adds_new_files_and_reports_the_rest() {
+        // Setup: A copy of a.txt, two identical new files, an unsupported file and the project's
+        // backup, next to files that only look like the project's
+        let controller = folder_controller(
+            &["a-copy.txt", "b.txt", "c.txt", "photo.jpg", "project.json-notes.txt", "project.json.bak.1", "sub/d.txt", "sub/project.json.bak.1"],
+            &[("a-copy.txt", "Interview A"), ("b.txt", "Interview B"), ("c.txt", "Interview B")],
+        ).await;
+        controller.handle_action(Action::Project(ProjectAction::SaveProject)).await.unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let writes_before = controller.project_repo.incremental_writes.load(Ordering::SeqCst);
+
+        // Execute
+        let report = imported(controller.handle_action(add_folder(Some(sender), &CancelToken::new())).await.unwrap());
+        assert!(is_modified(&controller), "Added files should wait for a save");
+
+        // Assert
+        let outcomes: Vec<(&str, &ImportOutcome)> = report.files.iter()
+            .map(|(path, outcome)| (path.strip_prefix("/tmp/study").unwrap().to_str().unwrap(), outcome))
+            .collect();
+        let added = report.added();
+        assert_eq!(outcomes.len(), 7, "Only the project's own backup is passed over");
+        assert_eq!(outcomes[0], ("a-copy.txt", &ImportOutcome::Duplicate("a.txt".to_string())));
+        assert_eq!(outcomes[1], ("b.txt", &ImportOutcome::Added(added[0])));
+        assert_eq!(outcomes[2], ("c.txt", &ImportOutcome::Duplicate("b.txt".to_string())));
+        assert!(matches!(outcomes[3], ("photo.jpg", ImportOutcome::Failed(_))));
+        assert_eq!(outcomes[4], ("project.json-notes.txt", &ImportOutcome::Added(added[1])));
+        assert_eq!(outcomes[5], ("sub/d.txt", &ImportOutcome::Added(added[2])));
+        assert!(matches!(outcomes[6], ("sub/project.json.bak.1", ImportOutcome::Failed(_))));
+        assert!(!report.was_cancelled());
+
+        let state = controller.state.read().unwrap();
+        let paths: Vec<&str> = state.filemanager.get_all_files().map(|f| f.path()).collect();
+        assert_eq!(paths, vec!["a.txt", "b.txt", "project.json-notes.txt", "sub/d.txt"]);
+        assert!(state.filemanager.file(added[0]).unwrap().fingerprint().is_some());
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst), writes_before + 3);
+
+        let mut events = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            events.push((event.done, event.total));
+        }
+        assert_eq!(events, (1..=7).map(|done| (done, 7)).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_folder_import_keeps_what_it_got_through() {
+        // Setup: Cancel once the third of twelve files is being read
+        let files: Vec<String> = (1..=12).map(|i| format!("{:02}.txt", i)).collect();
+        let names: Vec<&str> = files.iter().map(String::as_str).collect();
+        let controller = folder_controller(&names, &[]).await;
+        let cancel = CancelToken::new();
+        *controller.file_loader.cancel_at.lock().unwrap() = Some(("03.txt".to_string(), cancel.clone()));
+
+        // Execute
+        let report = imported(controller.handle_action(add_folder(None, &cancel)).await.unwrap());
+
+        // Assert: The file in flight is finished, the rest are never started
+        assert_eq!(report.added().len(), 3);
+        assert_eq!(report.not_started, 9);
+        assert!(report.was_cancelled());
+        assert_eq!(controller.state.read().unwrap().filemanager.file_count(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_folder_import_stops_when_the_project_is_closed() {
+        // Setup: Close the project once the twelfth of twenty files is being read
+        let files: Vec<String> = (1..=20).map(|i| format!("{:02}.txt", i)).collect();
+        let names: Vec<&str> = files.iter().map(String::as_str).collect();
+        let controller = folder_controller(&names, &[]).await;
+        *controller.file_loader.close_at.lock().unwrap() = Some(("12.txt".to_string(), controller.state.clone()));
+        let writes_before = controller.project_repo.incremental_writes.load(Ordering::SeqCst);
+
+        // Execute
+        let report = imported(controller.handle_action(add_folder(None, &CancelToken::new())).await.unwrap());
+
+        // Assert: What came before was persisted, the file it closed on is reported and nothing more is tried
+        assert_eq!(report.added().len(), 11);
+        assert!(matches!(report.files.last(), Some((_, ImportOutcome::Failed(_)))));
+        assert_eq!(report.files.len(), 12);
+        assert_eq!(report.not_started, 8);
+        assert!(report.was_cancelled());
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst), writes_before + 11);
+    }
+}
+
+// ===== Schema and coding actions =====
+
+mod editing {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_apply_code_captures_block_context() {
+        // Setup: A loaded file with one block
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let block_id = {
+            let mut state = controller.state.write().unwrap();
+            let file_id = state.filemanager.add_file("a.txt".to_string(), FileType::PlainText);
+            let block = TextBlock::new(file_id, 0, "before HIGHLIGHT after".to_string());
+            let block_id = block.id;
+            state.filemanager.file_mut(file_id).unwrap().set_data_state(DataState::Loaded(vec![block]));
+            block_id
+        };
+        let ActionResult::CodeCreated(code_def_id) = controller.handle_action(create_code("A")).await.unwrap() else {
+            panic!("Expected CodeCreated");
+        };
+
+        // Execute
+        let result = controller.handle_action(Action::Coding(CodingAction::ApplyCode {
+            code_def_id,
+            highlight: Highlight::new(block_id, 7, 16),
+            snippet: "HIGHLIGHT".to_string(),
+        })).await.unwrap();
+
+        // Assert
+        let ActionResult::CodeApplied(id) = result else { panic!("Expected CodeApplied") };
+        let state = controller.state.read().unwrap();
+        let code = state.codebook.get_all_qual_codes().iter().find(|qc| qc.id == id).unwrap();
+        assert_eq!(code.context_before(), "before ");
+        assert_eq!(code.context_after(), " after");
+    }
+
+    #[tokio::test]
+    async fn test_edits_are_persisted_incrementally() {
+        // Setup
+        let controller = loaded_controller(FakeRepo::default()).await;
+
+        // Execute: Create a code and apply it
+        let ActionResult::CodeCreated(code_def_id) = controller.handle_action(create_code("A")).await.unwrap() else {
+            panic!("Expected CodeCreated");
+        };
+        let block = TextBlock::new(FileList::new().add_file("a.txt".to_string(), FileType::PlainText), 0, String::new());
+        controller.handle_action(Action::Coding(CodingAction::ApplyCode {
+            code_def_id,
+            highlight: Highlight::new(block.id, 0, 0),
+            snippet: String::new(),
+        })).await.unwrap();
+
+        // Assert: One write per mutation, no full save, project clean
+        assert_eq!(controller.project_repo.incremental_writes.load(Ordering::SeqCst), 2);
+        assert_eq!(controller.project_repo.saves.load(Ordering::SeqCst), 0);
+        assert!(!is_modified(&controller), "Incrementally persisted edits should leave the project clean");
+    }
+
+    #[tokio::test]
+    async fn test_failed_incremental_write_leaves_project_modified() {
+        let controller = loaded_controller(FakeRepo::failing_incremental()).await;
+
+        let result = controller.handle_action(create_code("A")).await;
+
+        assert!(result.is_ok(), "The in-memory edit still succeeds");
+        assert!(is_modified(&controller), "The edit should wait for the next full save");
+    }
+
+    #[tokio::test]
+    async fn test_incremental_write_on_dirty_project_stays_modified() {
+        // Setup: An earlier edit that never reached disk
+        let repo = FakeRepo::failing_incremental();
+        let controller = loaded_controller(repo).await;
+        controller.handle_action(create_code("A")).await.unwrap();
+        controller.project_repo.fail_incremental.store(false, Ordering::SeqCst);
+
+        // Execute
+        controller.handle_action(create_code("B")).await.unwrap();
+
+        // Assert: Writing B alone doesn't make A saved
+        assert!(is_modified(&controller));
+    }
+
+    #[tokio::test]
+    async fn test_apply_unknown_code_def_is_rejected() {
+        let controller = loaded_controller(FakeRepo::default()).await;
+        let mut codebook = CodeBook::new();
+        let unknown = codebook.create_code_def("Elsewhere".to_string(), 1, None);
+        let block = TextBlock::new(FileList::new().add_file("a.txt".to_string(), FileType::PlainText), 0, String::new());
+
+        let err = controller.handle_action(Action::Coding(CodingAction::ApplyCode {
+            code_def_id: unknown,
+            highlight: Highlight::new(block.id, 0, 0),
+            snippet: String::new(),
+        })).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<CodeBookError>(), Some(CodeBookError::CodeDefNotFound(_))));
+        assert!(!is_modified(&controller), "Rejected edits should not dirty the project");
+    }
+}
@@ -1,10 +1,18 @@
 #![allow(dead_code)]
 use uuid::Uuid;
 use indexmap::IndexMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
-use std::path::{PathBuf};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
+use std::io::{Read, Write};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
 
 // Unique Uuid's to enforce type safety and enable entities to reference each other.
 // Cross-referencing Uuid's vs embedding object references to avoid lifetime shinanigans, and for look up performance.
@@ -30,6 +38,8 @@ pub enum ProjectError {
     Load(String),
     InvalidFormat(String),
     Corrupted(String),
+    Locked(ProjectLock),
+    ReadOnly,
 }
 
 impl fmt::Display for ProjectError {
@@ -40,6 +50,12 @@ impl fmt::Display for ProjectError {
             ProjectError::Load(name) => write!(f, "Failed to load project: {:?}", name),
             ProjectError::InvalidFormat(name) => write!(f, "Invalid format for project: {:?}", name),
             ProjectError::Corrupted(name) => write!(f, "Corrupted project: {:?}", name),
+            ProjectError::Locked(lock) => write!(
+                f,
+                "Project is open on {} (process {}) since {}",
+                lock.host, lock.pid, lock.acquired_at.format("%Y-%m-%d %H:%M:%S UTC")
+            ),
+            ProjectError::ReadOnly => write!(f, "Project is open read-only."),
         }
     }
 }
@@ -95,6 +111,7 @@ pub enum FileError {
     Write(String),
     Parse(String),
     Encoding(String),
+    Unsupported(String),
     Unknown(String),
 }
 
@@ -105,6 +122,7 @@ impl fmt::Display for FileError {
             FileError::Write(reference) => write!(f, "Failed to write file: {:?}", reference),
             FileError::Parse(reference) => write!(f, "Failed to parse file: {:?}", reference),
             FileError::Encoding(reference) => write!(f, "Failed to encode file: {:?}", reference),
+            FileError::Unsupported(reference) => write!(f, "Unsupported file: {:?}", reference),
             FileError::Unknown(reference) => write!(f, "Unknown error for file: {:?}", reference),
         }
     }
@@ -112,8 +130,9 @@ impl fmt::Display for FileError {
 
 impl std::error::Error for FileError {}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub enum DataState<T> {
+    #[default]
     Empty,
     Loaded(T),
     Modified(T),
@@ -128,6 +147,9 @@ pub struct QualProject {
     schema_version: u32,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
+    /// Whether files keep a [`SourceSnapshot`] by default. Files can override it.
+    #[serde(default)]
+    embed_sources: bool,
 }
 
 impl QualProject {
@@ -136,10 +158,44 @@ impl QualProject {
         created_at: DateTime<Utc>,
         updated_at: DateTime<Utc>
     ) -> Self {
-        Self { name, schema_version, created_at, updated_at }
+        Self { name, schema_version, created_at, updated_at, embed_sources: false }
     }
     pub fn name(&self) -> &str { &self.name }
     pub fn schema_version(&self) -> u32 { self.schema_version }
+    pub fn created_at(&self) -> DateTime<Utc> { self.created_at }
+    pub fn updated_at(&self) -> DateTime<Utc> { self.updated_at }
+    pub fn touch(&mut self, now: DateTime<Utc>) { self.updated_at = now; }
+    pub fn embed_sources(&self) -> bool { self.embed_sources }
+    pub fn set_embed_sources(&mut self, embed: bool) { self.embed_sources = embed; }
+}
+
+/// Describes how a project file was upgraded on load, so the UI can tell the user
+/// the file will be rewritten in the newer format on the next save.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub applied: Vec<String>,
+}
+
+/// Describes a change journal that was only replayed up to an unreadable entry on load,
+/// so the UI can warn that the changes after it are missing from the project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalRecovery {
+    pub replayed: usize,
+    pub skipped: usize,
+    /// Where the whole journal is kept once the project is next written, for recovering the
+    /// skipped changes by hand. Until then it stays where it is.
+    pub kept_at: PathBuf,
+}
+
+/// Owner recorded in a project's advisory lock file, reported back when another
+/// instance tries to open the same project.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectLock {
+    pub host: String,
+    pub pid: u32,
+    pub acquired_at: DateTime<Utc>,
 }
 
 ///Passed from front end into QualCode when generated
@@ -190,6 +246,9 @@ impl CodeDef {
 }
 
 /// Highlighted instance of a Code in a given file.
+///
+/// `orphaned` is set when the source file changed and the highlighted text couldn't be found
+/// again. The code keeps its last known position and snippet so the user can re-place it.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QualCode {
     pub id: QualCodeId,
@@ -198,18 +257,23 @@ pub struct QualCode {
     snippet: String,
     context_before: String,
     context_after: String,
+    #[serde(default)]
+    orphaned: bool,
 }
 
 impl QualCode {
     // Private so that CodeBook owns construction and maintains ownership
     fn new(def_id: CodeDefId, highlight: Highlight, snippet: String, context_before: String, context_after: String) -> Self {
         let id = QualCodeId(Uuid::new_v4());
-        QualCode { id, def_id, highlight, snippet, context_before, context_after }
+        QualCode { id, def_id, highlight, snippet, context_before, context_after, orphaned: false }
     }
+    pub fn is_orphaned(&self) -> bool { self.orphaned }
     pub fn def_id(&self) -> CodeDefId { self.def_id }
     pub fn block_id(&self) -> BlockId { self.highlight.block_id() }
     pub fn position(&self) -> (usize, usize) { (self.highlight.start(), self.highlight.end()) }
     pub fn snippet(&self) -> &str { &self.snippet }
+    pub fn context_before(&self) -> &str { &self.context_before }
+    pub fn context_after(&self) -> &str { &self.context_after }
 }
 
 /// Collection of CodeDefs associated with a theme
@@ -252,6 +316,12 @@ impl CodeBook {
 
 }
 
+impl Default for CodeBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 //CodeDef methods
 impl CodeBook {
     pub fn create_code_def(&mut self, name: String, color: u8, theme_id: Option<ThemeId>) -> CodeDefId {
@@ -263,6 +333,12 @@ impl CodeBook {
     }
     pub fn code_def(&self, id: CodeDefId) -> Option<&CodeDef> { self.code_defs.get(&id) }
 
+    /// Adds an existing code definition, e.g. one read back from storage.
+    /// Replaces a definition with the same id in place, otherwise appends.
+    pub fn insert_code_def(&mut self, code_def: CodeDef) {
+        self.code_defs.insert(code_def.id, code_def);
+    }
+
     pub fn remove_code_def(&mut self, id: CodeDefId) -> Result<CodeDef, CodeBookError> {
         self.qual_codes.retain(|qc| qc.def_id != id); //remove codes for the def first
         self.code_defs.shift_remove(&id)
@@ -313,6 +389,11 @@ impl CodeBook {
     }
     pub fn theme(&self, id: ThemeId) -> Option<&ThemeDef> { self.themes.get(&id) }
 
+    /// Adds an existing theme. Replaces a theme with the same id in place, otherwise appends.
+    pub fn insert_theme(&mut self, theme: ThemeDef) {
+        self.themes.insert(theme.id, theme);
+    }
+
     pub fn remove_theme(&mut self, id: ThemeId) -> Result<ThemeDef, CodeBookError> {
         // Reset corresponding CodeDef references to None
         for code_def in self.code_defs.values_mut() {
@@ -390,6 +471,13 @@ impl CodeBook {
             self.qual_codes.push(qual_code);
             id
     }
+    /// Adds an existing QualCode. Replaces one with the same id in place, otherwise appends.
+    pub fn insert_qual_code(&mut self, qual_code: QualCode) {
+        match self.qual_codes.iter_mut().find(|qc| qc.id == qual_code.id) {
+            Some(existing) => *existing = qual_code,
+            None => self.qual_codes.push(qual_code),
+        }
+    }
     pub fn remove_qual_code(&mut self, id: QualCodeId) -> Result<(), CodeBookError> {
         let pos = self.qual_codes.iter().position(|qc| qc.id == id)
             .ok_or(CodeBookError::QualCodeNotFound(id))?;
@@ -403,7 +491,7 @@ impl CodeBook {
         block_file_map: &std::collections::HashMap<BlockId, FileId>,
     ) -> impl Iterator<Item = &QualCode> {
         self.qual_codes.iter().filter(move |qc| {
-            block_file_map.get(&qc.highlight.block_id()).map_or(false, |&fid| fid == file_id)
+            block_file_map.get(&qc.highlight.block_id()).is_some_and(|&fid| fid == file_id)
         })
     }
     pub fn remove_codes_for_file(
@@ -412,9 +500,29 @@ impl CodeBook {
         block_file_map: &std::collections::HashMap<BlockId, FileId>,
     ) {
         self.qual_codes.retain(|qc| {
-            block_file_map.get(&qc.highlight.block_id()).map_or(true, |&fid| fid != file_id)
+            block_file_map.get(&qc.highlight.block_id()).is_none_or(|&fid| fid != file_id)
         });
     }
+    /// Codes on blocks attributed to `speaker`. `blocks` is usually [`FileList::loaded_blocks`].
+    pub fn get_codes_for_speaker<'a>(
+        &'a self,
+        speaker: &'a str,
+        blocks: &'a HashMap<BlockId, &TextBlock>,
+    ) -> impl Iterator<Item = &'a QualCode> {
+        self.get_codes_with_attribute(BlockMeta::SPEAKER, speaker, blocks)
+    }
+    /// Codes on blocks whose attribute `key` is `value`, e.g. the answers of survey respondents
+    /// with a given case attribute. `blocks` is usually [`FileList::loaded_blocks`].
+    pub fn get_codes_with_attribute<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+        blocks: &'a HashMap<BlockId, &TextBlock>,
+    ) -> impl Iterator<Item = &'a QualCode> {
+        self.qual_codes.iter().filter(move |qc| {
+            blocks.get(&qc.highlight.block_id()).is_some_and(|block| block.meta.attribute(key) == Some(value))
+        })
+    }
     pub fn get_codes_for_def(&self, def_id: CodeDefId) -> impl Iterator<Item = &QualCode> {
         self.qual_codes.iter().filter(move |qc| qc.def_id == def_id)
     }
@@ -424,15 +532,209 @@ impl CodeBook {
     }
 }
 
+//Re-anchoring
+impl CodeBook {
+    /// Moves the highlights of a file onto its freshly loaded blocks.
+    ///
+    /// `old` are the anchors saved when the file was last loaded. Each old block is matched to
+    /// a new one: same id, then identical content, then the most similar content, preferring the
+    /// nearest position on ties. A highlight is then placed on the occurrence of its snippet
+    /// closest to where it used to be. Highlights without a matching block or snippet are
+    /// flagged orphaned rather than removed.
+    pub fn reanchor(&mut self, old: &[BlockAnchor], new_blocks: &[TextBlock]) -> ReanchorReport {
+        let new_anchors: Vec<BlockAnchor> = new_blocks.iter().map(BlockAnchor::of).collect();
+        let mapping: std::collections::HashMap<BlockId, Option<&TextBlock>> = old.iter()
+            .map(|anchor| (anchor.id, match_block(anchor, &new_anchors).map(|i| &new_blocks[i])))
+            .collect();
+
+        let mut report = ReanchorReport::default();
+        for code in self.qual_codes.iter_mut() {
+            let Some(target) = mapping.get(&code.block_id()) else { continue };
+            let placed = target.and_then(|block| {
+                locate_snippet(&block.content, &code.snippet, code.highlight.start())
+                    .map(|start| Highlight::new(block.id, start, start + code.snippet.len()))
+            });
 
+            match placed {
+                Some(highlight) => {
+                    if highlight.block_id() != code.block_id() || highlight.start() != code.highlight.start() {
+                        code.highlight = highlight;
+                        report.moved.push(code.id);
+                    }
+                    code.orphaned = false;
+                }
+                None => {
+                    code.orphaned = true;
+                    report.orphaned.push(code.id);
+                }
+            }
+        }
+        report
+    }
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+//Accepting changed files without re-anchoring
+impl CodeBook {
+    /// Flags highlights whose block from `old` no longer exists in `new_blocks` as orphaned,
+    /// leaving everything else where it is. Returns the codes that were orphaned.
+    pub fn orphan_changed(&mut self, old: &[BlockAnchor], new_blocks: &[TextBlock]) -> Vec<QualCodeId> {
+        let old_ids: std::collections::HashSet<BlockId> = old.iter().map(|a| a.id).collect();
+        let mut orphaned = Vec::new();
+        for code in self.qual_codes.iter_mut() {
+            let block_id = code.block_id();
+            if old_ids.contains(&block_id) && !new_blocks.iter().any(|b| b.id == block_id) {
+                code.orphaned = true;
+                orphaned.push(code.id);
+            }
+        }
+        orphaned
+    }
+}
+
+/// Compares the blocks a file had when coded with its current blocks, using the same
+/// matching as [`CodeBook::reanchor`]. Blocks that only moved count as unchanged.
+pub fn diff_blocks(old: &[BlockAnchor], new_blocks: &[TextBlock]) -> DiffSummary {
+    let new_anchors: Vec<BlockAnchor> = new_blocks.iter().map(BlockAnchor::of).collect();
+    let mut matched = vec![false; new_anchors.len()];
+    let mut summary = DiffSummary::default();
+
+    for anchor in old {
+        match match_block(anchor, &new_anchors) {
+            Some(i) => {
+                if new_anchors[i].content_hash == anchor.content_hash {
+                    summary.unchanged += 1;
+                } else {
+                    summary.edited += 1;
+                }
+                matched[i] = true;
+            }
+            None => summary.removed += 1,
+        }
+    }
+    summary.added = matched.iter().filter(|m| !**m).count();
+    summary
+}
+
+/// Index of the new block that `anchor` most likely became
+fn match_block(anchor: &BlockAnchor, new: &[BlockAnchor]) -> Option<usize> {
+    let nearest = |candidates: &mut dyn Iterator<Item = (usize, u32)>| {
+        candidates.min_by_key(|&(i, distance)| (distance, new[i].sequence.abs_diff(anchor.sequence))).map(|(i, _)| i)
+    };
+
+    if let Some(i) = new.iter().position(|n| n.id == anchor.id) {
+        return Some(i);
+    }
+    nearest(&mut new.iter().enumerate()
+        .filter(|(_, n)| n.content_hash == anchor.content_hash)
+        .map(|(i, _)| (i, 0)))
+    .or_else(|| nearest(&mut new.iter().enumerate()
+        .map(|(i, n)| (i, (n.simhash ^ anchor.simhash).count_ones()))
+        .filter(|&(_, distance)| distance <= SIMHASH_MAX_DISTANCE)))
+}
+
+/// Byte offset of the occurrence of `snippet` in `content` nearest to `previous_start`
+fn locate_snippet(content: &str, snippet: &str, previous_start: usize) -> Option<usize> {
+    if snippet.is_empty() {
+        return content.is_char_boundary(previous_start).then_some(previous_start);
+    }
+    content.match_indices(snippet)
+        .map(|(start, _)| start)
+        .min_by_key(|start| start.abs_diff(previous_start))
+}
+
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum FileType {
     Pdf,
     PlainText,
     Markdown,
     RichText,
-    Other,
+    /// WebVTT captions, as exported by most transcription and meeting tools
+    WebVtt,
+    /// SubRip subtitles
+    Srt,
+    /// Plain text with a "Speaker Name  00:01:23" line opening each turn
+    Transcript,
+    /// Word 2007 and later
+    Docx,
+    /// OpenDocument text
+    Odt,
+    /// Comma or tab separated values. Imported as a survey unless added as a plain document.
+    Csv,
+    /// Excel 2007 and later workbook, imported as a survey
+    Xlsx,
+    /// A web page as saved from a browser
+    Html,
+    /// EPUB 2 or 3 e-book
+    Epub,
+    /// A single `.eml` message or an mbox mailbox
+    Email,
+    /// Messaging app export in JSON, see the chat loader for the shapes it reads
+    ChatLog,
+    /// A format read by a loader registered from outside the app, named by that loader
+    Other(String),
+}
+
+/// What a [`FormatLoader`](crate::ports::FormatLoader) reads: the file type it produces, how
+/// files in its format are recognised and which [`BlockMeta`] attributes its blocks carry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatInfo {
+    pub file_type: FileType,
+    /// Shown to the user, e.g. "Word document"
+    pub name: String,
+    /// Lowercase, without the dot
+    pub extensions: Vec<String>,
+    pub mime_types: Vec<String>,
+    /// Leading bytes every file in the format starts with. Text formats have none.
+    pub signatures: Vec<Vec<u8>>,
+    /// Attribute keys the loader sets on blocks, e.g. [`BlockMeta::SPEAKER`]
+    pub metadata: Vec<String>,
+}
+
+impl FormatInfo {
+    pub fn new(file_type: FileType, name: impl Into<String>) -> Self {
+        FormatInfo {
+            file_type,
+            name: name.into(),
+            extensions: Vec::new(),
+            mime_types: Vec::new(),
+            signatures: Vec::new(),
+            metadata: Vec::new(),
+        }
+    }
+
+    pub fn with_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions.extend(extensions.iter().map(|e| e.trim_start_matches('.').to_ascii_lowercase()));
+        self
+    }
+
+    pub fn with_mime_types(mut self, mime_types: &[&str]) -> Self {
+        self.mime_types.extend(mime_types.iter().map(|m| m.to_string()));
+        self
+    }
+
+    pub fn with_signature(mut self, signature: &[u8]) -> Self {
+        self.signatures.push(signature.to_vec());
+        self
+    }
+
+    pub fn with_metadata(mut self, keys: &[&str]) -> Self {
+        self.metadata.extend(keys.iter().map(|k| k.to_string()));
+        self
+    }
+
+    /// Whether files in the format are binary, going by whether it has a signature
+    pub fn is_binary(&self) -> bool { !self.signatures.is_empty() }
+    pub fn has_extension(&self, extension: &str) -> bool { self.extensions.iter().any(|e| e.eq_ignore_ascii_case(extension)) }
+    pub fn has_signature(&self, head: &[u8]) -> bool { self.signatures.iter().any(|s| head.starts_with(s)) }
+    /// Whether `mime` is one of the format's types. Case and parameters such as `; charset=utf-8` are ignored.
+    pub fn has_mime_type(&self, mime: &str) -> bool {
+        let essence = mime.split(';').next().unwrap_or_default().trim();
+        self.mime_types.iter().any(|m| m.eq_ignore_ascii_case(essence))
+    }
+    /// Whether blocks from this format can carry the attribute `key`
+    pub fn provides(&self, key: &str) -> bool { self.metadata.iter().any(|k| k == key) }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -440,38 +742,673 @@ pub struct TextBlock {
     pub id: BlockId,
     pub file_id: FileId,
     pub sequence: usize,
+    /// The text as the coder sees it, which `Highlight` offsets index into
     pub content: String,
+    #[serde(default)]
+    pub meta: BlockMeta,
+}
+
+/// What a loader knows about a block beyond its text
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockMeta {
+    #[serde(default)]
+    pub kind: BlockKind,
+    /// Format specific details, e.g. a page number or a speaker, keyed by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl BlockMeta {
+    /// Attribute naming who is speaking, for transcripts and documents that label their turns
+    pub const SPEAKER: &'static str = "speaker";
+    /// Attributes holding where a block starts and ends in its recording, in milliseconds
+    pub const START: &'static str = "start";
+    pub const END: &'static str = "end";
+    /// Attribute naming the survey respondent, or case, a response came from. The case's
+    /// other attributes, e.g. demographics, are stored under their column names.
+    pub const CASE: &'static str = "case";
+    /// Attribute naming the survey column a response answers
+    pub const QUESTION: &'static str = "question";
+    /// Attribute holding the 1-based page of a paged document, such as a PDF, a block is on
+    pub const PAGE: &'static str = "page";
+    /// Attribute holding the address a web page was saved from
+    pub const URL: &'static str = "url";
+    /// Attribute holding the title of the book chapter a block is in
+    pub const CHAPTER: &'static str = "chapter";
+    /// Attributes of a message: who sent it, when as an RFC 3339 timestamp, its subject, and
+    /// the conversation it belongs to
+    pub const AUTHOR: &'static str = "author";
+    pub const SENT: &'static str = "sent";
+    pub const SUBJECT: &'static str = "subject";
+    pub const THREAD: &'static str = "thread";
+
+    pub fn is_default(&self) -> bool { *self == BlockMeta::default() }
+    pub fn attribute(&self, key: &str) -> Option<&str> { self.attributes.get(key).map(String::as_str) }
+    pub fn speaker(&self) -> Option<&str> { self.attribute(Self::SPEAKER) }
+    pub fn case(&self) -> Option<&str> { self.attribute(Self::CASE) }
+    pub fn question(&self) -> Option<&str> { self.attribute(Self::QUESTION) }
+    pub fn page(&self) -> Option<u32> { self.attribute(Self::PAGE)?.parse().ok() }
+    pub fn url(&self) -> Option<&str> { self.attribute(Self::URL) }
+    pub fn chapter(&self) -> Option<&str> { self.attribute(Self::CHAPTER) }
+    pub fn author(&self) -> Option<&str> { self.attribute(Self::AUTHOR) }
+    pub fn subject(&self) -> Option<&str> { self.attribute(Self::SUBJECT) }
+    pub fn thread(&self) -> Option<&str> { self.attribute(Self::THREAD) }
+    pub fn sent(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(self.attribute(Self::SENT)?).ok().map(|sent| sent.with_timezone(&Utc))
+    }
+    /// Whether the block is a message from an email or chat export
+    pub fn is_message(&self) -> bool {
+        [Self::AUTHOR, Self::SENT, Self::THREAD].iter().any(|key| self.attributes.contains_key(*key))
+    }
+    pub fn start(&self) -> Option<Duration> { self.millis(Self::START) }
+    pub fn end(&self) -> Option<Duration> { self.millis(Self::END) }
+
+    fn millis(&self, key: &str) -> Option<Duration> {
+        self.attributes.get(key)?.parse().ok().map(Duration::from_millis)
+    }
+}
+
+/// Structural role of a block in its document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockKind {
+    #[default]
+    Paragraph,
+    /// Level 1 to 6, as in Markdown and HTML
+    Heading(u8),
+    /// `depth` starts at 1 for a top level list
+    ListItem { ordered: bool, depth: u8 },
+    Quote,
+    Code,
+    /// Cells separated by tabs
+    TableRow,
 }
 
 impl TextBlock {
+    /// The id is a UUIDv5 of the file, the block's position and its content, so reloading an
+    /// unchanged file gives the same BlockIds and existing highlights reattach. Edited or
+    /// shifted blocks get new ids and are matched up by [`CodeBook::reanchor`].
     pub fn new(file_id: FileId, sequence: usize, content: String) -> Self {
+        let mut name = (sequence as u64).to_be_bytes().to_vec();
+        name.extend_from_slice(content.as_bytes());
         Self {
-            id: BlockId(Uuid::new_v4()),
+            id: BlockId(Uuid::new_v5(&file_id.0, &name)),
             file_id,
             sequence,
             content,
+            meta: BlockMeta::default(),
+        }
+    }
+
+    /// Metadata doesn't feed into the id: a heading turned into a paragraph is the same text
+    pub fn with_kind(mut self, kind: BlockKind) -> Self {
+        self.meta.kind = kind;
+        self
+    }
+
+    pub fn with_attribute(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.meta.attributes.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// When the block's message was sent, stored as [`BlockMeta::SENT`]
+    pub fn with_sent(self, sent: DateTime<Utc>) -> Self {
+        self.with_attribute(BlockMeta::SENT, sent.to_rfc3339())
+    }
+
+    /// Where the block's audio starts and, if known, ends
+    pub fn with_timing(self, start: Duration, end: Option<Duration>) -> Self {
+        let block = self.with_attribute(BlockMeta::START, start.as_millis().to_string());
+        match end {
+            Some(end) => block.with_attribute(BlockMeta::END, end.as_millis().to_string()),
+            None => block,
         }
     }
 }
 
+/// What the project remembers about a block of a loaded file, so its highlights can be
+/// found again when the file changes. `simhash` is a similarity fingerprint: blocks with
+/// small edits have fingerprints only a few bits apart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockAnchor {
+    pub id: BlockId,
+    pub sequence: usize,
+    pub content_hash: u64,
+    pub simhash: u64,
+}
+
+impl BlockAnchor {
+    pub fn of(block: &TextBlock) -> Self {
+        BlockAnchor {
+            id: block.id,
+            sequence: block.sequence,
+            content_hash: fnv1a(block.content.as_bytes()),
+            simhash: simhash(&block.content),
+        }
+    }
+}
+
+/// Outcome of re-anchoring a file's highlights onto freshly loaded blocks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReanchorReport {
+    /// Codes whose block or offsets changed
+    pub moved: Vec<QualCodeId>,
+    /// Codes whose text couldn't be found in the new blocks
+    pub orphaned: Vec<QualCodeId>,
+}
+
+impl ReanchorReport {
+    pub fn is_empty(&self) -> bool {
+        self.moved.is_empty() && self.orphaned.is_empty()
+    }
+}
+
+/// Maximum differing simhash bits for two blocks to count as the same paragraph
+const SIMHASH_MAX_DISTANCE: u32 = 12;
+
+/// 64-bit FNV-1a. Used instead of std's hasher because the result is persisted and must
+/// not change between Rust versions.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Simhash over word pairs, so word order matters a little and single edits only flip a few bits
+fn simhash(text: &str) -> u64 {
+    let words: Vec<String> = text.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let features: Vec<u64> = match words.len() {
+        0 => return 0,
+        1 => vec![fnv1a(words[0].as_bytes())],
+        _ => words.windows(2).map(|pair| fnv1a(format!("{} {}", pair[0], pair[1]).as_bytes())).collect(),
+    };
+
+    let mut weights = [0i32; 64];
+    for feature in features {
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if feature >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    weights.iter().enumerate().fold(0, |hash, (bit, &weight)| if weight > 0 { hash | 1 << bit } else { hash })
+}
+
+/// What a FileLoader worked out about a file being added to the project.
+/// `path` is relative to the project root when the file lives under it, absolute otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileImport {
+    pub path: String,
+    pub file_type: FileType,
+    pub fingerprint: FileFingerprint,
+}
+
+/// Which files under a folder to add. Patterns are globs like `*.docx` or `interviews/**`,
+/// matched case-insensitively against paths relative to the folder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FolderFilter {
+    /// A file must match one of these, or anything goes when empty
+    pub include: Vec<String>,
+    /// A file matching any of these is left out even if included
+    pub exclude: Vec<String>,
+    /// Whether to look in subfolders too
+    pub recursive: bool,
+}
+
+/// What became of one file in a folder import
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportOutcome {
+    Added(FileId),
+    /// Same content as a file already in the project, or one earlier in the same import,
+    /// whose stored path is given
+    Duplicate(String),
+    Failed(String),
+}
+
+/// Sent as each file of a folder import is finished
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportProgress {
+    /// Files finished so far, including this one
+    pub done: usize,
+    pub total: usize,
+    pub path: PathBuf,
+    pub outcome: ImportOutcome,
+}
+
+/// The result of a folder import, file by file in path order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FolderImportReport {
+    pub files: Vec<(PathBuf, ImportOutcome)>,
+    /// Files that matched the filter but weren't reached because the import was cancelled,
+    /// or stopped when the project was closed
+    pub not_started: usize,
+}
+
+impl FolderImportReport {
+    pub fn added(&self) -> Vec<FileId> {
+        self.files.iter().filter_map(|(_, outcome)| match outcome {
+            ImportOutcome::Added(id) => Some(*id),
+            _ => None,
+        }).collect()
+    }
+    pub fn duplicates(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().filter(|(_, outcome)| matches!(outcome, ImportOutcome::Duplicate(_))).map(|(path, _)| path.as_path())
+    }
+    pub fn failed(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.files.iter().filter_map(|(path, outcome)| match outcome {
+            ImportOutcome::Failed(reason) => Some((path.as_path(), reason.as_str())),
+            _ => None,
+        })
+    }
+    /// Whether the import stopped before reaching every file
+    pub fn was_cancelled(&self) -> bool { self.not_started > 0 }
+}
+
+/// Identity of a source file's bytes, recorded when it's added so later edits can be detected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileFingerprint {
+    /// Hex encoded SHA-256 of the file contents
+    pub sha256: String,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// Stored path of the file when the fingerprint was taken. None for fingerprints from
+    /// before paths were recorded.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl FileFingerprint {
+    /// How the file described by `current` differs from the one this fingerprint was taken of.
+    /// Edits need the loaded blocks to summarise, so they're reported with an empty summary.
+    /// A new modification time alone, e.g. from saving the file unchanged, is no change.
+    pub fn drift_to(&self, current: &FileFingerprint) -> FileDrift {
+        if self.sha256 != current.sha256 || self.size != current.size {
+            FileDrift::Edited(DiffSummary::default())
+        } else if self.path.is_some() && current.path.is_some() && self.path != current.path {
+            FileDrift::Moved
+        } else {
+            FileDrift::Unchanged
+        }
+    }
+
+    /// Records the stored path the fingerprint was taken at
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+}
+
+/// How a source file compares to what the project was coded against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileDrift {
+    Unchanged,
+    /// Same contents at a different path, i.e. the file was relinked after being moved or
+    /// copied. Highlights are unaffected.
+    Moved,
+    /// Contents changed. Highlights stay where they were until the user resolves it.
+    Edited(DiffSummary),
+    /// Nothing readable at the stored path
+    Missing,
+}
+
+/// Block level comparison between the coded version of a file and its current contents
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub unchanged: usize,
+    pub edited: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// What to do about a file whose contents changed since it was coded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftResolution {
+    /// Move highlights onto the edited text, orphaning those that can't be found
+    Reanchor,
+    /// Take the edited file as is. Highlights on blocks that changed are orphaned.
+    Accept,
+    /// Keep the project as coded against the original and discard the edited content. Files
+    /// with a [`SourceSnapshot`] show it instead, otherwise the source file itself has to be
+    /// restored outside the app, e.g. from a backup.
+    RestoreOriginal,
+}
+
+/// Text of a file's blocks as coded, kept inside the project so it still opens when the
+/// source is missing. Stored as deflate compressed, base64 encoded JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceSnapshot {
+    blocks: Vec<SnapshotBlock>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SnapshotBlock {
+    content: String,
+    #[serde(default, skip_serializing_if = "BlockMeta::is_default")]
+    meta: BlockMeta,
+}
+
+impl SourceSnapshot {
+    pub fn of(blocks: &[TextBlock]) -> Self {
+        let blocks = blocks.iter()
+            .map(|b| SnapshotBlock { content: b.content.clone(), meta: b.meta.clone() })
+            .collect();
+        SourceSnapshot { blocks }
+    }
+
+    /// The blocks again. Ids derive from content, so they match the ones the snapshot was taken of.
+    pub fn blocks(&self, file_id: FileId) -> Vec<TextBlock> {
+        self.blocks.iter()
+            .enumerate()
+            .map(|(sequence, block)| TextBlock { meta: block.meta.clone(), ..TextBlock::new(file_id, sequence, block.content.clone()) })
+            .collect()
+    }
+
+    fn encode(&self) -> String {
+        let raw = serde_json::to_vec(&self.blocks).expect("snapshot blocks serialize");
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        // Writing into a Vec can't fail
+        encoder.write_all(&raw).expect("in-memory write");
+        BASE64.encode(encoder.finish().expect("in-memory write"))
+    }
+
+    fn decode(encoded: &str) -> Result<Self, String> {
+        let compressed = BASE64.decode(encoded).map_err(|e| format!("Snapshot is not base64: {}", e))?;
+        let mut raw = Vec::new();
+        DeflateDecoder::new(compressed.as_slice())
+            .read_to_end(&mut raw)
+            .map_err(|e| format!("Snapshot is not deflate data: {}", e))?;
+        let blocks = serde_json::from_slice(&raw).map_err(|e| format!("Snapshot is malformed: {}", e))?;
+        Ok(SourceSnapshot { blocks })
+    }
+}
+
+impl Serialize for SourceSnapshot {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.encode())
+    }
+}
+
+impl<'de> Deserialize<'de> for SourceSnapshot {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        SourceSnapshot::decode(&encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Where a loaded file's blocks came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockSource {
+    /// The source file on disk
+    Live,
+    /// The file's [`SourceSnapshot`], because the source couldn't be read
+    Snapshot,
+}
+
+/// A place a missing file may have gone, found by searching for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelinkProposal {
+    pub file_id: FileId,
+    /// Candidate path, stored the same way as `QualFile` paths
+    pub path: String,
+    pub matched_by: RelinkMatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RelinkMatch {
+    /// Same bytes as the fingerprint recorded for the file
+    Content,
+    /// Same file name. Only used for files added before fingerprinting.
+    Name,
+}
+
+/// The folder move that explains a file going from `old` to `new`, as `(old folder, new folder)`.
+///
+/// Trailing components the two paths share are assumed to have moved together, so
+/// `interviews/a.txt` -> `/backup/study/interviews/a.txt` gives `("", "/backup/study")`.
+/// Files with different names give None, since a rename says nothing about its folder.
+pub fn moved_folder(old: &Path, new: &Path) -> Option<(PathBuf, PathBuf)> {
+    if old.file_name().is_none() || old.file_name() != new.file_name() {
+        return None;
+    }
+    let mut old_dir = old;
+    let mut new_dir = new;
+    while let (Some(old_name), Some(new_name)) = (old_dir.file_name(), new_dir.file_name()) {
+        if old_name != new_name {
+            break;
+        }
+        match (old_dir.parent(), new_dir.parent()) {
+            (Some(old_parent), Some(new_parent)) => {
+                old_dir = old_parent;
+                new_dir = new_parent;
+            }
+            _ => break,
+        }
+    }
+    (old_dir != new_dir).then(|| (old_dir.to_path_buf(), new_dir.to_path_buf()))
+}
+
+/// Columns of a spreadsheet chosen for import as a survey
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SurveySelection {
+    /// Worksheet of an XLSX workbook, the first one when None. Ignored for CSV.
+    #[serde(default)]
+    pub sheet: Option<String>,
+    /// Column identifying each respondent
+    pub id_column: String,
+    /// Open-ended answers, which become the blocks to code
+    pub text_columns: Vec<String>,
+    /// Facts about each respondent, e.g. age group or site, attached to their answers as case attributes
+    #[serde(default)]
+    pub attribute_columns: Vec<String>,
+}
+
+/// How a survey's answers are split into files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurveyGrouping {
+    /// One file per text column, holding every respondent's answer to it
+    ByColumn,
+    /// One file per respondent, holding their answers to each text column
+    ByRespondent,
+}
+
+/// The answers a survey file holds: a text column, or a respondent by id
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SurveyScope {
+    Column(String),
+    Respondent(String),
+}
+
+/// Which cells of a spreadsheet a survey file reads
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SurveyView {
+    pub selection: SurveySelection,
+    pub scope: SurveyScope,
+}
+
+/// A spreadsheet read as text. The header row names the columns and every row after it is
+/// one respondent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SurveyTable {
+    /// Every worksheet of the workbook, to choose from. Empty for CSV.
+    pub sheets: Vec<String>,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Positions of the selected columns, paired with their names
+struct SurveyColumns<'a> {
+    id: usize,
+    text: Vec<(&'a str, usize)>,
+    attributes: Vec<(&'a str, usize)>,
+}
+
+impl SurveyTable {
+    /// Ids of the respondents who answered anything the selection covers, in row order.
+    /// Fails if a selected column is missing, or an id is blank or repeated.
+    pub fn respondents(&self, selection: &SurveySelection) -> Result<Vec<String>, FileError> {
+        let columns = self.select(selection)?;
+        Ok(self.cases(&columns)?.into_iter().map(|(id, _)| id.to_string()).collect())
+    }
+
+    /// The blocks of the survey file `view` describes, one per non-empty answer. Each carries
+    /// [`BlockMeta::CASE`], [`BlockMeta::QUESTION`] and the respondent's attribute columns.
+    pub fn blocks(&self, file_id: FileId, view: &SurveyView) -> Result<Vec<TextBlock>, FileError> {
+        let columns = self.select(&view.selection)?;
+        let cases = self.cases(&columns)?;
+        let answers: Vec<(&str, &[String], &str, usize)> = match &view.scope {
+            SurveyScope::Column(name) => {
+                let &(name, index) = columns.text.iter()
+                    .find(|(text, _)| text == name)
+                    .ok_or_else(|| FileError::Parse(format!("{:?} is not one of the survey's text columns", name)))?;
+                cases.iter().map(|&(id, row)| (id, row, name, index)).collect()
+            }
+            SurveyScope::Respondent(id) => {
+                let &(id, row) = cases.iter()
+                    .find(|(case, _)| case == id)
+                    .ok_or_else(|| FileError::Parse(format!("No respondent with id {:?}", id)))?;
+                columns.text.iter().map(|&(name, index)| (id, row, name, index)).collect()
+            }
+        };
+
+        let mut blocks = Vec::new();
+        for (id, row, question, index) in answers {
+            let answer = cell(row, index);
+            if answer.is_empty() {
+                continue;
+            }
+            let mut block = TextBlock::new(file_id, blocks.len(), answer.to_string())
+                .with_attribute(BlockMeta::CASE, id)
+                .with_attribute(BlockMeta::QUESTION, question);
+            for &(name, index) in &columns.attributes {
+                let value = cell(row, index);
+                if !value.is_empty() {
+                    block = block.with_attribute(name, value);
+                }
+            }
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    fn select<'a>(&self, selection: &'a SurveySelection) -> Result<SurveyColumns<'a>, FileError> {
+        if selection.text_columns.is_empty() {
+            return Err(FileError::Unsupported("Choose at least one column of answers to code".to_string()));
+        }
+        let chosen: Vec<&String> = std::iter::once(&selection.id_column)
+            .chain(&selection.text_columns)
+            .chain(&selection.attribute_columns)
+            .collect();
+        if let Some(twice) = chosen.iter().enumerate().find(|(i, name)| chosen[..*i].contains(name)).map(|(_, name)| name) {
+            return Err(FileError::Unsupported(format!("Column {:?} is chosen more than once", twice)));
+        }
+        let reserved = [BlockMeta::CASE, BlockMeta::QUESTION, BlockMeta::SPEAKER, BlockMeta::START, BlockMeta::END];
+        if let Some(name) = selection.attribute_columns.iter().find(|name| reserved.contains(&name.as_str())) {
+            return Err(FileError::Unsupported(format!("Column {:?} can't be a case attribute, block metadata uses that name", name)));
+        }
+
+        let named = |names: &'a [String]| names.iter()
+            .map(|name| Ok((name.as_str(), self.column(name)?)))
+            .collect::<Result<Vec<_>, FileError>>();
+        Ok(SurveyColumns {
+            id: self.column(&selection.id_column)?,
+            text: named(&selection.text_columns)?,
+            attributes: named(&selection.attribute_columns)?,
+        })
+    }
+
+    fn column(&self, name: &str) -> Result<usize, FileError> {
+        let mut matches = self.columns.iter().enumerate().filter(|(_, column)| *column == name);
+        match (matches.next(), matches.next()) {
+            (Some((index, _)), None) => Ok(index),
+            (Some(_), Some(_)) => Err(FileError::Parse(format!("More than one column is named {:?}", name))),
+            (None, _) => Err(FileError::Parse(format!("No column named {:?}", name))),
+        }
+    }
+
+    /// Each respondent's id and row. Rows blank in every selected column are skipped.
+    fn cases(&self, columns: &SurveyColumns) -> Result<Vec<(&str, &[String])>, FileError> {
+        let mut cases: Vec<(&str, &[String])> = Vec::new();
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            // Counting the header, as a spreadsheet numbers its rows
+            let number = i + 2;
+            let answered = columns.text.iter().chain(&columns.attributes).any(|&(_, index)| !cell(row, index).is_empty());
+            let id = cell(row, columns.id);
+            if id.is_empty() {
+                if answered {
+                    return Err(FileError::Parse(format!("Row {} has no respondent id", number)));
+                }
+                continue;
+            }
+            if let Some(first) = seen.insert(id, number) {
+                return Err(FileError::Parse(format!("Respondent {:?} appears in rows {} and {}", id, first, number)));
+            }
+            cases.push((id, row));
+        }
+        Ok(cases)
+    }
+}
+
+/// A cell's text, trimmed. Rows can be shorter than the header.
+fn cell(row: &[String], index: usize) -> &str {
+    row.get(index).map_or("", |value| value.trim())
+}
+
 ///File and its data and metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QualFile {
     pub id: FileId,
     path: String,
+    // Loaded blocks are a cache of the source file, not project data
+    #[serde(skip)]
     data_state: DataState<Vec<TextBlock>>,
     file_type: FileType,
+    /// Blocks as of the last load, used to re-find highlights if the file changes
+    #[serde(default)]
+    anchors: Vec<BlockAnchor>,
+    /// Source file as it was when coded. None for files added before fingerprinting.
+    #[serde(default)]
+    fingerprint: Option<FileFingerprint>,
+    /// Overrides the project's `embed_sources` setting for this file
+    #[serde(default)]
+    embed_source: Option<bool>,
+    #[serde(default)]
+    snapshot: Option<SourceSnapshot>,
+    /// The cells this file reads, for a spreadsheet imported as a survey
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    survey: Option<SurveyView>,
 }
 
 impl QualFile {
     fn new(path: String, file_type: FileType) -> Self {
         let id = FileId(Uuid::new_v4());
-        QualFile { id, path, data_state: DataState::Empty, file_type }
+        QualFile {
+            id,
+            path,
+            data_state: DataState::Empty,
+            file_type,
+            anchors: Vec::new(),
+            fingerprint: None,
+            embed_source: None,
+            snapshot: None,
+            survey: None,
+        }
     }
 
     pub fn path(&self) -> &str { &self.path }
     pub fn path_buf(&self) -> PathBuf { PathBuf::from(&self.path) }
+    /// Points the file at a new location. Its id, and so every code on it, stays the same.
+    pub fn set_path(&mut self, path: String) { self.path = path; }
+    pub fn file_type(&self) -> &FileType { &self.file_type }
     pub fn set_data_state(&mut self, data_state: DataState<Vec<TextBlock>>) { self.data_state = data_state; }
+    pub fn data_state(&self) -> &DataState<Vec<TextBlock>> { &self.data_state }
+    pub fn anchors(&self) -> &[BlockAnchor] { &self.anchors }
+    pub fn set_anchors(&mut self, anchors: Vec<BlockAnchor>) { self.anchors = anchors; }
+    pub fn fingerprint(&self) -> Option<&FileFingerprint> { self.fingerprint.as_ref() }
+    pub fn set_fingerprint(&mut self, fingerprint: FileFingerprint) { self.fingerprint = Some(fingerprint); }
+    pub fn embed_source(&self) -> Option<bool> { self.embed_source }
+    pub fn set_embed_source(&mut self, embed: Option<bool>) { self.embed_source = embed; }
+    /// Whether this file should carry a snapshot, given the project's default
+    pub fn embeds_source(&self, project_default: bool) -> bool { self.embed_source.unwrap_or(project_default) }
+    pub fn snapshot(&self) -> Option<&SourceSnapshot> { self.snapshot.as_ref() }
+    pub fn set_snapshot(&mut self, snapshot: Option<SourceSnapshot>) { self.snapshot = snapshot; }
+    pub fn survey(&self) -> Option<&SurveyView> { self.survey.as_ref() }
+    pub fn set_survey(&mut self, survey: Option<SurveyView>) { self.survey = survey; }
     pub fn blocks(&self) -> Option<&[TextBlock]> {
         match &self.data_state {
             DataState::Loaded(blocks) | DataState::Modified(blocks) => Some(blocks),
@@ -480,6 +1417,14 @@ impl QualFile {
     }
 }
 
+/// Messages of one email thread or chat, as grouped by [`FileList::conversations`]
+#[derive(Debug, Clone)]
+pub struct Conversation<'a> {
+    /// The thread id, or the path of the file for messages that name no thread
+    pub key: String,
+    pub messages: Vec<&'a TextBlock>,
+}
+
 ///Collection of files. Manages File addition, removal, retrieval, and ordering
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileList {
@@ -496,6 +1441,10 @@ impl FileList {
         self.files.insert(id, file);
         id
     }
+    /// Adds an existing file. Replaces a file with the same id in place, otherwise appends.
+    pub fn insert_file(&mut self, file: QualFile) {
+        self.files.insert(file.id, file);
+    }
     pub fn remove_file(&mut self, id: FileId) -> Result<(), FileListError> {
         self.files.shift_remove(&id)
             .ok_or(FileListError::FileNotFound(id))?;
@@ -533,6 +1482,37 @@ impl FileList {
         self.files.sort_by(|_, a, _, b| a.path().cmp(b.path()));
     }
     pub fn file_count(&self) -> usize { self.files.len() }
+    /// Every block of the files that are loaded, for looking up where a highlight sits
+    pub fn loaded_blocks(&self) -> HashMap<BlockId, &TextBlock> {
+        self.files.values()
+            .filter_map(QualFile::blocks)
+            .flatten()
+            .map(|block| (block.id, block))
+            .collect()
+    }
+    /// Messages of the loaded files grouped by [`BlockMeta::THREAD`], so a thread split over
+    /// several exports reads as one conversation. Messages that name no thread are grouped by
+    /// file. Conversations come in the order they first appear, and messages oldest first.
+    pub fn conversations(&self) -> Vec<Conversation<'_>> {
+        let mut conversations: IndexMap<String, Vec<&TextBlock>> = IndexMap::new();
+        for file in self.files.values() {
+            for block in file.blocks().into_iter().flatten().filter(|block| block.meta.is_message()) {
+                let key = block.meta.thread().unwrap_or(file.path());
+                conversations.entry(key.to_string()).or_default().push(block);
+            }
+        }
+        conversations.into_iter().map(|(key, mut messages)| {
+            // Stable, so undated messages keep their order ahead of the dated ones
+            messages.sort_by_key(|message| message.meta.sent());
+            Conversation { key, messages }
+        }).collect()
+    }
+}
+
+impl Default for FileList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 
@@ -1146,4 +1146,668 @@ mod file_data_states {
         let file = file_list.file(file_id).unwrap();
         assert!(file.blocks().is_none(), "Should have no blocks after Empty transition");
     }
+
+    #[test]
+    fn test_block_ids_are_stable_per_file_and_position() {
+        // Setup
+        let mut file_list = FileList::new();
+        let file_a = file_list.add_file("a.txt".to_string(), FileType::PlainText);
+        let file_b = file_list.add_file("b.txt".to_string(), FileType::PlainText);
+
+        // Execute: Same file and position built twice, e.g. across two sessions
+        let first = TextBlock::new(file_a, 3, "Content".to_string());
+        let again = TextBlock::new(file_a, 3, "Content".to_string());
+
+        // Assert
+        assert_eq!(first.id, again.id, "Reloading a file should reproduce its block ids");
+        assert_ne!(first.id, TextBlock::new(file_a, 4, "Content".to_string()).id);
+        assert_ne!(first.id, TextBlock::new(file_b, 3, "Content".to_string()).id);
+    }
+
+    #[test]
+    fn test_block_id_depends_on_content() {
+        let file_id = FileList::new().add_file("a.txt".to_string(), FileType::PlainText);
+
+        let original = TextBlock::new(file_id, 0, "Content".to_string());
+        let edited = TextBlock::new(file_id, 0, "Content, edited".to_string());
+
+        assert_ne!(original.id, edited.id, "Edited blocks should not keep a stale id");
+    }
+
+    #[test]
+    fn test_loaded_blocks_are_not_serialized() {
+        // Setup
+        let file = create_test_file("a.txt", 2);
+
+        // Execute
+        let json = serde_json::to_string(&file).unwrap();
+        let restored: QualFile = serde_json::from_str(&json).unwrap();
+
+        // Assert: Blocks come from the source file, not the project
+        assert!(!json.contains("Block content"), "Block text should stay out of the project file");
+        assert!(restored.blocks().is_none());
+        assert_eq!(restored.id, file.id);
+    }
+}
+
+
+// ===== Tests for inserting existing entities =====
+
+mod entity_insertion {
+    use super::*;
+
+    #[test]
+    fn test_insert_existing_code_def_replaces_in_place() {
+        // Setup: Three codes, then an edited copy of the middle one
+        let mut codebook = create_test_codebook();
+        let id_a = codebook.create_code_def("A".to_string(), 1, None);
+        let id_b = codebook.create_code_def("B".to_string(), 1, None);
+        let id_c = codebook.create_code_def("C".to_string(), 1, None);
+        let theme_id = codebook.create_theme("Theme".to_string(), 2);
+        let mut edited = codebook.code_def(id_b).unwrap().clone();
+        edited.set_theme_id(Some(theme_id));
+
+        // Execute
+        codebook.insert_code_def(edited);
+
+        // Assert: Same position, updated theme
+        let ids: Vec<CodeDefId> = codebook.get_all_code_defs().map(|d| d.id).collect();
+        assert_eq!(ids, vec![id_a, id_b, id_c], "Replaced code should keep its position");
+        assert_eq!(codebook.code_def(id_b).unwrap().theme_id(), Some(theme_id));
+    }
+
+    #[test]
+    fn test_insert_into_other_codebook_appends() {
+        // Setup: Entities created in one codebook
+        let mut source = create_test_codebook();
+        let theme_id = source.create_theme("Theme".to_string(), 2);
+        let code_def_id = source.create_code_def("Code".to_string(), 1, Some(theme_id));
+        let file = create_test_file("a.txt", 1);
+        let qual_code_id = apply_test_code(&mut source, file.blocks().unwrap()[0].id, code_def_id, "snip");
+
+        // Execute: Copy them into an empty codebook
+        let mut target = create_test_codebook();
+        target.insert_theme(source.theme(theme_id).unwrap().clone());
+        target.insert_code_def(source.code_def(code_def_id).unwrap().clone());
+        target.insert_qual_code(source.get_all_qual_codes()[0].clone());
+        target.insert_qual_code(source.get_all_qual_codes()[0].clone());
+
+        // Assert: Same ids, no duplicate QualCode
+        assert!(target.theme(theme_id).is_some());
+        assert_eq!(target.get_codes_in_theme(theme_id).count(), 1);
+        assert_eq!(target.get_all_qual_codes().len(), 1, "Re-inserting a QualCode should replace it");
+        assert_eq!(target.get_all_qual_codes()[0].id, qual_code_id);
+    }
+
+    #[test]
+    fn test_insert_file_replaces_in_place() {
+        let mut file_list = FileList::new();
+        let id_a = file_list.add_file("a.txt".to_string(), FileType::PlainText);
+        let id_b = file_list.add_file("b.txt".to_string(), FileType::PlainText);
+        let mut loaded = file_list.file(id_a).unwrap().clone();
+        loaded.set_data_state(DataState::Loaded(vec![TextBlock::new(id_a, 0, "text".to_string())]));
+
+        file_list.insert_file(loaded);
+
+        let ids: Vec<FileId> = file_list.get_all_files().map(|f| f.id).collect();
+        assert_eq!(ids, vec![id_a, id_b]);
+        assert!(file_list.file(id_a).unwrap().blocks().is_some());
+    }
+}
+
+
+// ===== Tests for re-anchoring highlights after a file changes =====
+
+mod reanchoring {
+    use super::*;
+
+    /// Blocks for `paragraphs` as a loader would produce them
+    fn blocks(file_id: FileId, paragraphs: &[&str]) -> Vec<TextBlock> {
+        paragraphs.iter().enumerate().map(|(i, p)| TextBlock::new(file_id, i, p.to_string())).collect()
+    }
+
+    fn anchors(blocks: &[TextBlock]) -> Vec<BlockAnchor> {
+        blocks.iter().map(BlockAnchor::of).collect()
+    }
+
+    /// Codebook with one code on `snippet` in `block`
+    fn coded(block: &TextBlock, snippet: &str) -> (CodeBook, QualCodeId) {
+        let mut codebook = CodeBook::new();
+        let def = codebook.create_code_def("Theme".to_string(), 1, None);
+        let start = block.content.find(snippet).expect("snippet in block");
+        let id = codebook.apply_code(def, Highlight::new(block.id, start, start + snippet.len()), snippet.to_string(), String::new(), String::new());
+        (codebook, id)
+    }
+
+    fn code(codebook: &CodeBook, id: QualCodeId) -> &QualCode {
+        codebook.get_all_qual_codes().iter().find(|qc| qc.id == id).unwrap()
+    }
+
+    fn file_id() -> FileId {
+        FileList::new().add_file("interview.txt".to_string(), FileType::PlainText)
+    }
+
+    const INTRO: &str = "Thanks for joining us today.";
+    const QUOTE: &str = "I felt the support group really helped me cope with the move to a new city.";
+
+    #[test]
+    fn test_unchanged_file_keeps_highlights() {
+        // Setup
+        let file = file_id();
+        let old = blocks(file, &[INTRO, QUOTE]);
+        let (mut codebook, id) = coded(&old[1], "support group");
+
+        // Execute
+        let report = codebook.reanchor(&anchors(&old), &blocks(file, &[INTRO, QUOTE]));
+
+        // Assert
+        assert!(report.is_empty(), "Nothing should move: {:?}", report);
+        assert_eq!(code(&codebook, id).block_id(), old[1].id);
+    }
+
+    #[test]
+    fn test_inserted_paragraph_moves_highlight_to_shifted_block() {
+        // Setup
+        let file = file_id();
+        let old = blocks(file, &[INTRO, QUOTE]);
+        let (mut codebook, id) = coded(&old[1], "support group");
+
+        // Execute: A new paragraph pushes the quote down one position
+        let new = blocks(file, &["Interview recorded on Tuesday.", INTRO, QUOTE]);
+        let report = codebook.reanchor(&anchors(&old), &new);
+
+        // Assert
+        assert_eq!(report.moved, vec![id]);
+        let code = code(&codebook, id);
+        assert_eq!(code.block_id(), new[2].id);
+        assert_eq!(&new[2].content[code.position().0..code.position().1], "support group");
+        assert!(!code.is_orphaned());
+    }
+
+    #[test]
+    fn test_edited_paragraph_is_matched_by_similarity() {
+        // Setup
+        let file = file_id();
+        let old = blocks(file, &[INTRO, QUOTE]);
+        let (mut codebook, id) = coded(&old[1], "helped me cope");
+
+        // Execute: The transcriber fixed a word earlier in the paragraph
+        let edited = QUOTE.replace("I felt", "Honestly I felt");
+        let new = blocks(file, &[INTRO, &edited]);
+        let report = codebook.reanchor(&anchors(&old), &new);
+
+        // Assert: Same block position, offsets shifted by the insertion
+        assert_eq!(report.moved, vec![id]);
+        let code = code(&codebook, id);
+        assert_eq!(code.block_id(), new[1].id);
+        assert_eq!(&new[1].content[code.position().0..code.position().1], "helped me cope");
+    }
+
+    #[test]
+    fn test_removed_snippet_is_orphaned() {
+        // Setup
+        let file = file_id();
+        let old = blocks(file, &[INTRO, QUOTE]);
+        let (mut codebook, id) = coded(&old[1], "support group");
+
+        // Execute: The coded words were edited away
+        let new = blocks(file, &[INTRO, &QUOTE.replace("support group", "peer circle")]);
+        let report = codebook.reanchor(&anchors(&old), &new);
+
+        // Assert: Flagged, not lost
+        assert_eq!(report.orphaned, vec![id]);
+        assert!(code(&codebook, id).is_orphaned());
+        assert_eq!(code(&codebook, id).snippet(), "support group");
+    }
+
+    #[test]
+    fn test_removed_paragraph_is_orphaned() {
+        let file = file_id();
+        let old = blocks(file, &[INTRO, QUOTE]);
+        let (mut codebook, id) = coded(&old[1], "support group");
+
+        let report = codebook.reanchor(&anchors(&old), &blocks(file, &[INTRO, "Something else entirely, about the weather."]));
+
+        assert_eq!(report.orphaned, vec![id]);
+    }
+
+    #[test]
+    fn test_other_files_are_untouched() {
+        // Setup: Code on a block of another file
+        let other = blocks(file_id(), &[QUOTE]);
+        let (mut codebook, id) = coded(&other[0], "support group");
+        let file = file_id();
+
+        // Execute
+        let report = codebook.reanchor(&anchors(&blocks(file, &[QUOTE])), &blocks(file, &[INTRO]));
+
+        // Assert
+        assert!(report.is_empty());
+        assert_eq!(code(&codebook, id).block_id(), other[0].id);
+    }
+
+    #[test]
+    fn test_orphaned_flag_round_trips() {
+        let file = file_id();
+        let old = blocks(file, &[QUOTE]);
+        let (mut codebook, _) = coded(&old[0], "support group");
+        codebook.reanchor(&anchors(&old), &blocks(file, &[INTRO]));
+
+        let restored: CodeBook = serde_json::from_str(&serde_json::to_string(&codebook).unwrap()).unwrap();
+
+        assert!(restored.get_all_qual_codes()[0].is_orphaned());
+    }
+
+    #[test]
+    fn test_diff_counts_block_changes() {
+        // Setup
+        let file = file_id();
+        let old = blocks(file, &[INTRO, QUOTE, "Goodbye and thanks."]);
+
+        // Execute: A paragraph inserted, the quote reworded, the closing dropped
+        let new = blocks(file, &["Recorded Tuesday.", INTRO, &QUOTE.replace("I felt", "Honestly I felt")]);
+        let summary = diff_blocks(&anchors(&old), &new);
+
+        // Assert
+        assert_eq!(summary, DiffSummary { unchanged: 1, edited: 1, added: 1, removed: 1 });
+    }
+
+    #[test]
+    fn test_accepting_changes_orphans_only_changed_blocks() {
+        // Setup: Codes on both paragraphs
+        let file = file_id();
+        let old = blocks(file, &[INTRO, QUOTE]);
+        let (mut codebook, quote_code) = coded(&old[1], "support group");
+        let def = codebook.create_code_def("Other".to_string(), 2, None);
+        let intro_code = codebook.apply_code(def, Highlight::new(old[0].id, 0, 6), "Thanks".to_string(), String::new(), String::new());
+
+        // Execute: The quote is reworded
+        let new = blocks(file, &[INTRO, &QUOTE.replace("really", "truly")]);
+        let orphaned = codebook.orphan_changed(&anchors(&old), &new);
+
+        // Assert: Nothing moves, the code on the changed block is flagged
+        assert_eq!(orphaned, vec![quote_code]);
+        assert!(code(&codebook, quote_code).is_orphaned());
+        assert_eq!(code(&codebook, quote_code).block_id(), old[1].id);
+        assert!(!code(&codebook, intro_code).is_orphaned());
+    }
+
+    #[test]
+    fn test_fingerprint_drift() {
+        let original = FileFingerprint { sha256: "abc".to_string(), size: 3, modified: None, path: None }.with_path("a.txt");
+        let touched = FileFingerprint { modified: Some(chrono::Utc::now()), ..original.clone() };
+        let moved = original.clone().with_path("archive/a.txt");
+        let edited = FileFingerprint { sha256: "abd".to_string(), ..original.clone() };
+        let unrecorded = FileFingerprint { path: None, ..original.clone() };
+
+        assert_eq!(original.drift_to(&original), FileDrift::Unchanged);
+        assert_eq!(original.drift_to(&touched), FileDrift::Unchanged, "Saving without changes is not a move");
+        assert_eq!(original.drift_to(&moved), FileDrift::Moved);
+        assert_eq!(unrecorded.drift_to(&moved), FileDrift::Unchanged);
+        assert!(matches!(original.drift_to(&edited), FileDrift::Edited(_)));
+        assert!(matches!(moved.drift_to(&edited), FileDrift::Edited(_)));
+    }
+}
+
+// ===== Tests for relinking moved files =====
+
+mod relinking {
+    use super::*;
+    use std::path::Path;
+
+    fn folders(old: &str, new: &str) -> Option<(String, String)> {
+        moved_folder(Path::new(old), Path::new(new))
+            .map(|(o, n)| (o.to_string_lossy().into_owned(), n.to_string_lossy().into_owned()))
+    }
+
+    #[test]
+    fn test_shared_trailing_folders_moved_together() {
+        assert_eq!(folders("interviews/2023/a.txt", "/backup/interviews/2023/a.txt"), Some((String::new(), "/backup".to_string())));
+        assert_eq!(folders("interviews/a.txt", "archive/a.txt"), Some(("interviews".to_string(), "archive".to_string())));
+    }
+
+    #[test]
+    fn test_renamed_or_unmoved_file_has_no_folder_move() {
+        assert_eq!(folders("interviews/a.txt", "interviews/b.txt"), None);
+        assert_eq!(folders("interviews/a.txt", "interviews/a.txt"), None);
+    }
+
+    #[test]
+    fn test_set_path_keeps_identity() {
+        let mut files = FileList::new();
+        let id = files.add_file("a.txt".to_string(), FileType::PlainText);
+
+        files.file_mut(id).unwrap().set_path("moved/a.txt".to_string());
+
+        let file = files.file(id).unwrap();
+        assert_eq!((file.id, file.path()), (id, "moved/a.txt"));
+    }
+}
+
+// ===== Tests for embedded source snapshots =====
+
+mod snapshots {
+    use super::*;
+
+    fn blocks(file_id: FileId) -> Vec<TextBlock> {
+        vec![
+            TextBlock::new(file_id, 0, "Moving".to_string()).with_kind(BlockKind::Heading(2)),
+            TextBlock::new(file_id, 1, "Q: How did the move go?".to_string()).with_attribute("page", "3"),
+            TextBlock::new(file_id, 2, "A: Hard at first.\nBetter now. ✓".to_string()),
+            TextBlock::new(file_id, 3, String::new()),
+        ]
+    }
+
+    #[test]
+    fn test_snapshot_restores_identical_blocks() {
+        // Setup
+        let file_id = FileList::new().add_file("a.txt".to_string(), FileType::PlainText);
+        let original = blocks(file_id);
+
+        // Execute: Through serialization, as a saved project would
+        let json = serde_json::to_string(&SourceSnapshot::of(&original)).unwrap();
+        let restored: SourceSnapshot = serde_json::from_str(&json).unwrap();
+
+        // Assert: Same ids, so highlights attach to snapshot blocks as they would to the source,
+        // and the same metadata
+        let ids = |blocks: &[TextBlock]| blocks.iter().map(|b| (b.id, b.content.clone(), b.meta.clone())).collect::<Vec<_>>();
+        assert_eq!(ids(&restored.blocks(file_id)), ids(&original));
+    }
+
+    #[test]
+    fn test_snapshot_is_compressed() {
+        let file_id = FileList::new().add_file("a.txt".to_string(), FileType::PlainText);
+        let text = "The same sentence said again and again. ".repeat(200);
+
+        let json = serde_json::to_string(&SourceSnapshot::of(&[TextBlock::new(file_id, 0, text.clone())])).unwrap();
+
+        assert!(json.len() < text.len() / 10, "Snapshot is {} bytes for {} bytes of text", json.len(), text.len());
+    }
+
+    #[test]
+    fn test_corrupted_snapshot_is_rejected() {
+        assert!(serde_json::from_str::<SourceSnapshot>("\"not a snapshot\"").is_err());
+        assert!(serde_json::from_str::<SourceSnapshot>("\"AAAA\"").is_err());
+    }
+
+    #[test]
+    fn test_file_setting_overrides_project() {
+        let mut files = FileList::new();
+        let id = files.add_file("a.txt".to_string(), FileType::PlainText);
+        let file = files.file_mut(id).unwrap();
+
+        assert!(file.embeds_source(true));
+        file.set_embed_source(Some(false));
+        assert!(!file.embeds_source(true));
+        file.set_embed_source(Some(true));
+        assert!(file.embeds_source(false));
+    }
+}
+
+// ===== Tests for speaker and timing metadata =====
+
+mod speaker_turns {
+    use super::*;
+    use std::time::Duration;
+
+    /// Loaded transcript whose turns alternate between the given speakers
+    fn transcript(speakers: &[&str]) -> FileList {
+        let mut files = FileList::new();
+        let id = files.add_file("interview.vtt".to_string(), FileType::WebVtt);
+        let blocks = speakers.iter().enumerate().map(|(i, speaker)| {
+            let start = Duration::from_secs(i as u64 * 10);
+            TextBlock::new(id, i, format!("Turn {}", i))
+                .with_timing(start, Some(start + Duration::from_secs(10)))
+                .with_attribute(BlockMeta::SPEAKER, *speaker)
+        }).collect();
+        files.file_mut(id).unwrap().set_data_state(DataState::Loaded(blocks));
+        files
+    }
+
+    #[test]
+    fn test_timing_round_trips_through_attributes() {
+        let file = create_test_file("call.vtt", 0);
+        let block = TextBlock::new(file.id, 0, "Hi".to_string())
+            .with_timing(Duration::from_millis(83_250), None);
+
+        assert_eq!(block.meta.start(), Some(Duration::from_millis(83_250)));
+        assert_eq!(block.meta.end(), None);
+        assert_eq!(block.meta.attributes[BlockMeta::START], "83250");
+        assert!(block.meta.speaker().is_none());
+    }
+
+    #[test]
+    fn test_get_codes_for_speaker_filters_by_block_speaker() {
+        // Setup: Code every turn of a two person interview
+        let files = transcript(&["Interviewer", "P1", "Interviewer", "P1"]);
+        let blocks = files.loaded_blocks();
+        let mut codebook = create_test_codebook();
+        let code_def_id = codebook.create_code_def("Trust".to_string(), 1, None);
+        let turns = files.get_all_files().next().unwrap().blocks().unwrap();
+        let ids: Vec<QualCodeId> = turns.iter()
+            .map(|turn| apply_test_code(&mut codebook, turn.id, code_def_id, "Turn"))
+            .collect();
+
+        // Execute
+        let by_participant: Vec<QualCodeId> = codebook.get_codes_for_speaker("P1", &blocks).map(|qc| qc.id).collect();
+
+        // Assert: Each code can be traced back to where its turn starts in the audio
+        assert_eq!(by_participant, vec![ids[1], ids[3]]);
+        let starts: Vec<_> = codebook.get_codes_for_speaker("P1", &blocks)
+            .map(|qc| blocks[&qc.block_id()].meta.start())
+            .collect();
+        assert_eq!(starts, vec![Some(Duration::from_secs(10)), Some(Duration::from_secs(30))]);
+        assert_eq!(codebook.get_codes_for_speaker("Nobody", &blocks).count(), 0);
+    }
+
+    #[test]
+    fn test_loaded_blocks_skips_unloaded_files() {
+        let mut files = transcript(&["A", "B"]);
+        let unloaded = files.add_file("later.txt".to_string(), FileType::PlainText);
+
+        let blocks = files.loaded_blocks();
+
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks.values().all(|block| block.file_id != unloaded));
+    }
+}
+
+// ===== Tests for survey responses as cases =====
+mod survey_cases {
+    use super::*;
+
+    /// Three respondents answering two open questions, with age group and site as attributes.
+    /// R2 skipped Q2 and a trailing blank row follows.
+    fn survey() -> SurveyTable {
+        let row = |cells: &[&str]| cells.iter().map(|c| c.to_string()).collect();
+        SurveyTable {
+            sheets: Vec::new(),
+            columns: row(&["ID", "Age", "Site", "Q1", "Q2"]),
+            rows: vec![
+                row(&["R1", "18-24", "North", "Too expensive", "More evening slots"]),
+                row(&["R2", "65+", "South", " Friendly staff ", ""]),
+                row(&["R3", "18-24", "", "Hard to park", "Nothing"]),
+                row(&["", "", "", "", ""]),
+            ],
+        }
+    }
+
+    fn selection() -> SurveySelection {
+        SurveySelection {
+            sheet: None,
+            id_column: "ID".to_string(),
+            text_columns: vec!["Q1".to_string(), "Q2".to_string()],
+            attribute_columns: vec!["Age".to_string(), "Site".to_string()],
+        }
+    }
+
+    fn view(scope: SurveyScope) -> SurveyView {
+        SurveyView { selection: selection(), scope }
+    }
+
+    #[test]
+    fn test_column_file_holds_every_answer_with_case_attributes() {
+        let file_id = FileList::new().add_file("survey.csv".to_string(), FileType::Csv);
+
+        let blocks = survey().blocks(file_id, &view(SurveyScope::Column("Q2".to_string()))).unwrap();
+
+        // R2 left Q2 blank, so has no block
+        let answers: Vec<(&str, Option<&str>)> = blocks.iter().map(|b| (b.content.as_str(), b.meta.case())).collect();
+        assert_eq!(answers, vec![("More evening slots", Some("R1")), ("Nothing", Some("R3"))]);
+        assert!(blocks.iter().all(|b| b.meta.question() == Some("Q2")));
+        assert_eq!(blocks[0].meta.attribute("Age"), Some("18-24"));
+        assert_eq!(blocks[0].meta.attribute("Site"), Some("North"));
+        assert_eq!(blocks[1].meta.attribute("Site"), None, "Blank attributes are left out");
+    }
+
+    #[test]
+    fn test_respondent_file_holds_one_answer_per_question() {
+        let file_id = FileList::new().add_file("survey.csv".to_string(), FileType::Csv);
+
+        let blocks = survey().blocks(file_id, &view(SurveyScope::Respondent("R1".to_string()))).unwrap();
+        let trimmed = survey().blocks(file_id, &view(SurveyScope::Respondent("R2".to_string()))).unwrap();
+
+        let questions: Vec<Option<&str>> = blocks.iter().map(|b| b.meta.question()).collect();
+        assert_eq!(questions, vec![Some("Q1"), Some("Q2")]);
+        assert!(blocks.iter().all(|b| b.meta.case() == Some("R1") && b.meta.attribute("Age") == Some("18-24")));
+        assert_eq!(trimmed.len(), 1);
+        assert_eq!(trimmed[0].content, "Friendly staff");
+    }
+
+    #[test]
+    fn test_blocks_are_stable_across_reads() {
+        let file_id = FileList::new().add_file("survey.csv".to_string(), FileType::Csv);
+        let scope = view(SurveyScope::Column("Q1".to_string()));
+
+        let first: Vec<BlockId> = survey().blocks(file_id, &scope).unwrap().iter().map(|b| b.id).collect();
+        let second: Vec<BlockId> = survey().blocks(file_id, &scope).unwrap().iter().map(|b| b.id).collect();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_respondents_skip_blank_rows_and_reject_bad_ids() {
+        let mut table = survey();
+        assert_eq!(table.respondents(&selection()).unwrap(), vec!["R1", "R2", "R3"]);
+
+        table.rows[2][0] = "R1".to_string();
+        let duplicate = table.respondents(&selection()).unwrap_err();
+        assert!(matches!(&duplicate, FileError::Parse(m) if m.contains("rows 2 and 4")), "{}", duplicate);
+
+        table.rows[2][0] = String::new();
+        let missing = table.respondents(&selection()).unwrap_err();
+        assert!(matches!(&missing, FileError::Parse(m) if m.contains("Row 4")), "{}", missing);
+    }
+
+    #[test]
+    fn test_selection_is_checked_against_the_header() {
+        let table = survey();
+        let with = |change: fn(&mut SurveySelection)| {
+            let mut selection = selection();
+            change(&mut selection);
+            table.respondents(&selection).unwrap_err()
+        };
+
+        assert!(matches!(with(|s| s.text_columns = vec!["Q9".to_string()]), FileError::Parse(m) if m.contains("Q9")));
+        assert!(matches!(with(|s| s.text_columns.clear()), FileError::Unsupported(_)));
+        assert!(matches!(with(|s| s.attribute_columns.push("Q1".to_string())), FileError::Unsupported(m) if m.contains("more than once")));
+        assert!(matches!(with(|s| s.id_column = "case".to_string()), FileError::Parse(_)));
+    }
+
+    #[test]
+    fn test_reserved_attribute_names_are_rejected() {
+        let mut table = survey();
+        table.columns[2] = "speaker".to_string();
+        let mut selection = selection();
+        selection.attribute_columns = vec!["speaker".to_string()];
+
+        let error = table.respondents(&selection).unwrap_err();
+
+        assert!(matches!(error, FileError::Unsupported(m) if m.contains("speaker")));
+    }
+
+    #[test]
+    fn test_get_codes_with_attribute_queries_case_attributes() {
+        // Setup: Load Q1 as a file and code every answer
+        let mut files = FileList::new();
+        let file_id = files.add_file("survey.csv".to_string(), FileType::Csv);
+        let blocks = survey().blocks(file_id, &view(SurveyScope::Column("Q1".to_string()))).unwrap();
+        files.file_mut(file_id).unwrap().set_data_state(DataState::Loaded(blocks));
+        let mut codebook = create_test_codebook();
+        let code_def_id = codebook.create_code_def("Access".to_string(), 1, None);
+        let ids: Vec<QualCodeId> = files.file(file_id).unwrap().blocks().unwrap().iter()
+            .map(|block| apply_test_code(&mut codebook, block.id, code_def_id, "Answer"))
+            .collect();
+        let blocks = files.loaded_blocks();
+
+        // Execute
+        let young: Vec<QualCodeId> = codebook.get_codes_with_attribute("Age", "18-24", &blocks).map(|qc| qc.id).collect();
+        let r2: Vec<QualCodeId> = codebook.get_codes_with_attribute(BlockMeta::CASE, "R2", &blocks).map(|qc| qc.id).collect();
+
+        // Assert
+        assert_eq!(young, vec![ids[0], ids[2]]);
+        assert_eq!(r2, vec![ids[1]]);
+    }
+}
+
+// ===== Tests for email and chat conversations =====
+mod conversations {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    /// A message from `author` at `minute` past noon, in `thread` if given
+    fn message(file_id: FileId, sequence: usize, author: &str, minute: u32, thread: Option<&str>) -> TextBlock {
+        let block = TextBlock::new(file_id, sequence, format!("{} at {}", author, minute))
+            .with_attribute(BlockMeta::AUTHOR, author)
+            .with_sent(Utc.with_ymd_and_hms(2024, 3, 1, 12, minute, 0).unwrap());
+        match thread {
+            Some(thread) => block.with_attribute(BlockMeta::THREAD, thread),
+            None => block,
+        }
+    }
+
+    fn contents<'a>(conversation: &Conversation<'a>) -> Vec<&'a str> {
+        conversation.messages.iter().map(|message| message.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_sent_round_trips_through_attributes() {
+        let file = create_test_file("inbox.mbox", 0);
+        let sent = Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 15).unwrap();
+
+        let block = TextBlock::new(file.id, 0, "Hi".to_string()).with_sent(sent);
+
+        assert_eq!(block.meta.sent(), Some(sent));
+        assert!(block.meta.is_message());
+        assert!(!TextBlock::new(file.id, 1, "Plain".to_string()).meta.is_message());
+    }
+
+    #[test]
+    fn test_conversations_group_threads_across_files_in_time_order() {
+        // Setup: A thread split over a mailbox and a later export, plus a chat with no thread ids
+        let mut files = FileList::new();
+        let inbox = files.add_file("inbox.mbox".to_string(), FileType::Email);
+        let export = files.add_file("export.mbox".to_string(), FileType::Email);
+        let chat = files.add_file("team.json".to_string(), FileType::ChatLog);
+        let notes = files.add_file("notes.txt".to_string(), FileType::PlainText);
+        files.file_mut(inbox).unwrap().set_data_state(DataState::Loaded(vec![
+            message(inbox, 0, "Ana", 5, Some("<a@mail>")),
+            message(inbox, 1, "Ben", 1, Some("<b@mail>")),
+        ]));
+        files.file_mut(export).unwrap().set_data_state(DataState::Loaded(vec![message(export, 0, "Cy", 2, Some("<a@mail>"))]));
+        files.file_mut(chat).unwrap().set_data_state(DataState::Loaded(vec![
+            message(chat, 0, "Dee", 9, None),
+            message(chat, 1, "Eve", 8, None),
+        ]));
+        files.file_mut(notes).unwrap().set_data_state(DataState::Loaded(vec![TextBlock::new(notes, 0, "Not a message".to_string())]));
+
+        // Execute
+        let conversations = files.conversations();
+
+        // Assert
+        let keys: Vec<&str> = conversations.iter().map(|c| c.key.as_str()).collect();
+        assert_eq!(keys, vec!["<a@mail>", "<b@mail>", "team.json"]);
+        assert_eq!(contents(&conversations[0]), vec!["Cy at 2", "Ana at 5"]);
+        assert_eq!(contents(&conversations[2]), vec!["Eve at 8", "Dee at 9"]);
+    }
 }
@@ -1,42 +1,216 @@
 use crate::domain::*;
 use crate::application::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use indexmap::IndexMap;
 use anyhow::Result;
 use async_trait::async_trait;
 
 #[async_trait]
-pub trait ProjectRepository {
+pub trait ProjectRepository: Send + Sync {
     async fn new_project(
         &self,
         path: &Path,
         name: String,
     ) -> Result<QualProject>;
+    /// Writes the whole project. `covered` is the [`journal_mark`](Self::journal_mark) taken
+    /// together with the snapshot, so the save only drops journal entries the snapshot contains.
     async fn save_project(
             &self,
             path: &Path,
             project: QualProject,
             codebook: CodeBook,
-            filemanager: FileList
+            filemanager: FileList,
+            covered: u64
         ) -> Result<()>;
     async fn load_project(&self, path: &Path) -> Result<(QualProject, CodeBook, FileList)>;
 
-    //leaving these commented until I have the app + infra implementing them
-    //async fn insert_code_def(&self, code: CodeDef) -> Result<()>;
-    //async fn insert_theme_def(&self, code: ThemeDef) -> Result<()>;
-    //async fn insert_qual_code(&self, code: QualCode);
-    //async fn insert_file(&self, code: QualFile) -> Result<()>;
-    //async fn delete_code_def(&self, id: CodeDefId) -> Result<()>;
-    //async fn delete_theme_def(&self, id: ThemeId) -> Result<()>;
-    //async fn delete_qual_code(&self, id: QualCodeId) -> Result<()>;
-    //async fn delete_file(&self, id: FileId) -> Result<()>;
+    /// Background save triggered by the autosave task rather than the user.
+    /// Returns `Ok(false)` when the write was skipped, e.g. because a manual save took priority,
+    /// so the caller knows the project is still unsaved. Backends without special handling just save.
+    async fn autosave_project(
+            &self,
+            path: &Path,
+            project: QualProject,
+            codebook: CodeBook,
+            filemanager: FileList,
+            covered: u64
+        ) -> Result<bool> {
+        self.save_project(path, project, codebook, filemanager, covered).await?;
+        Ok(true)
+    }
+
+    /// How far the change journal has got. Callers read it while the state can't change and
+    /// pass it to the save of that state: entries journaled after the mark may be missing from
+    /// the snapshot and are kept. Backends without a journal can rely on the default.
+    fn journal_mark(&self, _path: &Path) -> u64 {
+        0
+    }
+
+    /// Schema migrations applied by the most recent `load_project`, if any. Taking the
+    /// report clears it. Backends that never migrate can rely on the default.
+    fn take_migration_report(&self) -> Option<MigrationReport> {
+        None
+    }
+
+    /// Damage the most recent `load_project` found in the change journal, if any. Taking the
+    /// report clears it. Backends without a journal can rely on the default.
+    fn take_journal_recovery(&self) -> Option<JournalRecovery> {
+        None
+    }
+
+    /// Whether `path` is one of the files kept for the project at `project`: the project itself
+    /// or a companion such as a backup, journal or lock. Bulk imports pass over these.
+    fn is_project_file(&self, project: &Path, path: &Path) -> bool {
+        path == project
+    }
+
+    /// Takes the advisory lock that stops two instances editing the same project.
+    /// Fails with `ProjectError::Locked` if someone else holds it, unless `force` is set,
+    /// in which case their lock is taken over. Backends without locking can rely on the default.
+    async fn lock_project(&self, _path: &Path, _force: bool) -> Result<()> {
+        Ok(())
+    }
+
+    /// Releases a lock taken by `lock_project`. A lock held by someone else is left alone.
+    async fn unlock_project(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    // Incremental writes for single mutations. Inserts are upserts: an entity that already exists
+    // is replaced in place and keeps its position. Deletes follow the CodeBook cascade rules and
+    // deleting something that isn't stored is not an error. Moves mirror the CodeBook/FileList
+    // `move_*_to_index` methods and leave the order alone for unknown ids or out-of-range indexes.
+    // save_project remains the compaction step that rewrites everything.
+    async fn insert_code_def(&self, path: &Path, code: CodeDef) -> Result<()>;
+    async fn insert_theme_def(&self, path: &Path, theme: ThemeDef) -> Result<()>;
+    async fn insert_qual_code(&self, path: &Path, code: QualCode) -> Result<()>;
+    async fn insert_file(&self, path: &Path, file: QualFile) -> Result<()>;
+    async fn delete_code_def(&self, path: &Path, id: CodeDefId) -> Result<()>;
+    async fn delete_theme_def(&self, path: &Path, id: ThemeId) -> Result<()>;
+    async fn delete_qual_code(&self, path: &Path, id: QualCodeId) -> Result<()>;
+    async fn delete_file(&self, path: &Path, id: FileId) -> Result<()>;
+    async fn move_code_def(&self, path: &Path, id: CodeDefId, new_index: usize) -> Result<()>;
+    async fn move_theme_def(&self, path: &Path, id: ThemeId, new_index: usize) -> Result<()>;
+    async fn move_file(&self, path: &Path, id: FileId, new_index: usize) -> Result<()>;
 }
 
 #[async_trait]
-pub trait FileLoader {
+pub trait FileLoader: Send + Sync {
+
+    /// Checks that the file at `path` can be imported and works out its type. `root` is the
+    /// project directory, which the returned path is made relative to. `mime` is a type the
+    /// file came labelled with, e.g. by a drag and drop, and counts for more than its extension.
+    /// Registering the file in the FileList (and persisting it) is left to the caller.
+    async fn add_file(&self, root: &Path, path: &Path, mime: Option<&str>) -> Result<FileImport>;
+
+    /// Reads `file` from disk and splits it into TextBlocks. Relative file paths are resolved
+    /// against `root`. The same unchanged file must always produce the same blocks.
+    async fn load_file(&self, root: &Path, file: &QualFile) -> Result<Vec<TextBlock>>;
+
+    /// Like `load_file`, but falls back to the snapshot embedded in the project when the source
+    /// can't be read. The [`BlockSource`] says which one the blocks came from.
+    async fn load_or_snapshot(&self, root: &Path, file: &QualFile) -> Result<(Vec<TextBlock>, BlockSource)> {
+        match self.load_file(root, file).await {
+            Ok(blocks) => Ok((blocks, BlockSource::Live)),
+            Err(e) => match file.snapshot() {
+                Some(snapshot) => Ok((snapshot.blocks(file.id), BlockSource::Snapshot)),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Fingerprint of `file` as it is on disk now, or None if it can't be found
+    async fn fingerprint(&self, root: &Path, file: &QualFile) -> Result<Option<FileFingerprint>>;
+
+    /// Searches `folders` and everything below them for files that could be `file` at a new
+    /// location, best matches first. Fingerprinted files are matched on content, older ones
+    /// by name. Proposed paths are relative to `root` like those from `add_file`.
+    async fn find_candidates(&self, root: &Path, folders: &[PathBuf], file: &QualFile) -> Result<Vec<RelinkProposal>>;
+
+    /// Reads the spreadsheet at `path` as text for survey import. `sheet` picks an XLSX
+    /// worksheet, the first one when None, and is ignored for CSV.
+    async fn read_table(&self, path: &Path, sheet: Option<&str>) -> Result<SurveyTable>;
+
+    /// Every file under `folder` that `filter` lets through, sorted by path. Hidden files
+    /// and folders are left out.
+    async fn find_files(&self, folder: &Path, filter: &FolderFilter) -> Result<Vec<PathBuf>>;
+
+    /// The formats `add_file` accepts, for file pickers and to show what metadata each
+    /// format's blocks carry. Loaders that don't say can rely on the default.
+    fn formats(&self) -> Vec<FormatInfo> {
+        Vec::new()
+    }
+}
+
+/// Parser for one document format. Registering one with a [`LoaderRegistry`] adds a format the
+/// app doesn't know, under [`FileType::Other`], or replaces the built-in parser for a type.
+pub trait FormatLoader: Send + Sync {
+    fn format(&self) -> &FormatInfo;
+
+    /// Whether a file starting with `head` is in this format whatever its extension says.
+    /// Checks the format's signatures unless overridden, e.g. to look inside a zip archive.
+    fn sniff(&self, head: &[u8]) -> bool {
+        self.format().has_signature(head)
+    }
+
+    /// Whether text with no telling extension reads like this format, e.g. a transcript
+    /// saved as `.txt`. Only asked once signatures and extensions have settled nothing.
+    fn recognise(&self, _head: &[u8]) -> bool {
+        false
+    }
+
+    /// Splits a file's content into blocks. Called off the async runtime, so may block.
+    fn parse(&self, file_id: FileId, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError>;
+}
+
+/// Format loaders keyed by the file type they produce. Loaders registered later are asked
+/// about a file first, so an added loader can claim files a built-in one would otherwise take.
+#[derive(Clone, Default)]
+pub struct LoaderRegistry {
+    loaders: IndexMap<FileType, Arc<dyn FormatLoader>>,
+}
+
+impl LoaderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `loader`, replacing and returning the loader already registered for its file type
+    pub fn register(&mut self, loader: Arc<dyn FormatLoader>) -> Option<Arc<dyn FormatLoader>> {
+        let file_type = loader.format().file_type.clone();
+        // Removed first so a replacement is asked first too
+        let replaced = self.loaders.shift_remove(&file_type);
+        self.loaders.insert(file_type, loader);
+        replaced
+    }
+
+    pub fn with_loader(mut self, loader: impl FormatLoader + 'static) -> Self {
+        self.register(Arc::new(loader));
+        self
+    }
+
+    pub fn get(&self, file_type: &FileType) -> Option<&Arc<dyn FormatLoader>> {
+        self.loaders.get(file_type)
+    }
+
+    /// Every loader, in the order they are asked about a file
+    pub fn loaders(&self) -> impl Iterator<Item = &Arc<dyn FormatLoader>> {
+        self.loaders.values().rev()
+    }
+
+    /// What each registered format reads, in registration order
+    pub fn formats(&self) -> Vec<FormatInfo> {
+        self.loaders.values().map(|loader| loader.format().clone()).collect()
+    }
 
-    //need to figure out how this relates to the insert_file function in project repo. might just be load_file for now.
-    async fn add_file(&self,  file_list: FileList, path: &Path) -> Result<(QualFile, FileType)>;
-    async fn load_file(&self, file: FileId) -> Result<Vec<TextBlock>>;
+    /// Parses `bytes` with the loader for `file_type`
+    pub fn parse(&self, file_id: FileId, file_type: &FileType, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+        match self.get(file_type) {
+            Some(loader) => loader.parse(file_id, bytes),
+            None => Err(FileError::Unsupported(format!("No loader for {:?} files is registered", file_type))),
+        }
+    }
 }
 
 #[async_trait]
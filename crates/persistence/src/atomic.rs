@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use chrono::{DateTime, Utc};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// A rolling backup of a project file. `index` 1 is the most recent.
+#[derive(Debug, Clone)]
+pub struct BackupInfo {
+    pub index: usize,
+    pub path: PathBuf,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// `project.json` -> `project.json.bak.N`
+pub fn backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".bak.{}", index));
+    path.with_file_name(name)
+}
+
+/// Temp file next to the target so the final rename never crosses a filesystem boundary.
+/// `project.json` -> `.project.json.tmp-{pid}-{nanos}`
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".tmp-{}-{}", std::process::id(), nanos));
+    path.with_file_name(name)
+}
+
+/// Name of the file a temp file from [`temp_path`] was being written for, or None if
+/// `file_name` isn't one
+pub(crate) fn temp_target(file_name: &str) -> Option<&str> {
+    let (target, stamp) = file_name.strip_prefix('.')?.rsplit_once(".tmp-")?;
+    let (pid, nanos) = stamp.split_once('-')?;
+    let numeric = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    (!target.is_empty() && numeric(pid) && numeric(nanos)).then_some(target)
+}
+
+/// Writes `bytes` to `path` so that readers only ever see the old or the new contents.
+///
+/// The data goes to a temp file in the same directory, is fsynced, and is then renamed over
+/// the target. If `keep_backups` is non-zero the previous contents are rotated into
+/// `path.bak.1 ..= path.bak.keep_backups` before the rename.
+pub async fn write_atomic(path: &Path, bytes: &[u8], keep_backups: usize) -> std::io::Result<()> {
+    let tmp = temp_path(path);
+
+    if let Err(e) = write_synced(&tmp, bytes).await {
+        let _ = fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+
+    if keep_backups > 0
+        && fs::try_exists(path).await.unwrap_or(false)
+        && let Err(e) = rotate_backups(path, keep_backups).await
+    {
+        let _ = fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+
+    if let Err(e) = fs::rename(&tmp, path).await {
+        let _ = fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+
+    sync_parent_dir(path).await
+}
+
+/// Lists existing backups for `path`, newest first.
+pub async fn list_backups(path: &Path, keep_backups: usize) -> Vec<BackupInfo> {
+    let mut backups = Vec::new();
+    for index in 1..=keep_backups {
+        let backup = backup_path(path, index);
+        if let Ok(meta) = fs::metadata(&backup).await {
+            backups.push(BackupInfo {
+                index,
+                path: backup,
+                modified: meta.modified().ok().map(DateTime::<Utc>::from),
+            });
+        }
+    }
+    backups
+}
+
+async fn write_synced(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut file = fs::File::create(path).await?;
+    file.write_all(bytes).await?;
+    file.sync_all().await
+}
+
+/// Shifts `bak.N` to `bak.N+1` (dropping the oldest) and copies the current file into `bak.1`.
+/// Copy rather than rename so the live file stays in place until the new one replaces it.
+async fn rotate_backups(path: &Path, keep_backups: usize) -> std::io::Result<()> {
+    let oldest = backup_path(path, keep_backups);
+    if fs::try_exists(&oldest).await.unwrap_or(false) {
+        fs::remove_file(&oldest).await?;
+    }
+    for index in (1..keep_backups).rev() {
+        let from = backup_path(path, index);
+        if fs::try_exists(&from).await.unwrap_or(false) {
+            fs::rename(&from, backup_path(path, index + 1)).await?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1)).await?;
+    Ok(())
+}
+
+/// Persists the rename itself. Directory fsync is a no-op concept outside unix.
+async fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        fs::File::open(parent).await?.sync_all().await?;
+    }
+    Ok(())
+}
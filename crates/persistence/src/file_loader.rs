@@ -0,0 +1,358 @@
+use app_core::domain::{FileError, FileFingerprint, FileImport, FileType, FolderFilter, FormatInfo, QualFile, RelinkMatch, RelinkProposal, SurveyTable, TextBlock};
+use app_core::ports::{FileLoader, FormatLoader, LoaderRegistry};
+use crate::loaders::{builtin, encoding, located, spreadsheet};
+
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use anyhow::{Context, Result};
+
+/// How much of a file is read to sniff its type
+const SNIFF_LEN: u64 = 8 * 1024;
+/// Chunk size for hashing, so large files are never held in memory whole
+const HASH_CHUNK: usize = 64 * 1024;
+/// Formats that were replaced, with what to save them as instead
+const LEGACY: [(&str, &str); 2] = [("doc", "legacy Word document. Save it as .docx"), ("xls", "legacy Excel workbook. Save it as .xlsx")];
+
+/// Filesystem backed file loader, reading each format with the loader registered for it
+#[derive(Clone)]
+pub struct FsFileLoader {
+    loaders: LoaderRegistry,
+}
+
+impl FsFileLoader {
+    /// Loader for the built-in formats
+    pub fn new() -> Self {
+        FsFileLoader { loaders: builtin::registry() }
+    }
+
+    /// Adds a format, or replaces the loader for one, see [`LoaderRegistry::register`]
+    pub fn with_loader(mut self, loader: impl FormatLoader + 'static) -> Self {
+        self.loaders = self.loaders.with_loader(loader);
+        self
+    }
+
+    pub fn loaders(&self) -> &LoaderRegistry {
+        &self.loaders
+    }
+
+    /// Works out the file type from its leading bytes, the MIME type it came with if any, and
+    /// its extension.
+    ///
+    /// Formats with a signature are recognised by content even under the wrong MIME type or
+    /// extension. Text formats have no signature, so the MIME type and then the extension decide
+    /// between them, and other files are accepted as plain text if their content looks like text
+    /// and no loader recognises it, e.g. as a transcript.
+    pub fn detect(&self, path: &Path, head: &[u8], mime: Option<&str>) -> Result<FileType, FileError> {
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        if let Some(loader) = self.loaders.loaders().find(|loader| loader.sniff(head)) {
+            return Ok(loader.format().file_type.clone());
+        }
+
+        let labelled = mime.and_then(|mime| self.claim(|format| format.has_mime_type(mime))
+            .map(|format| (format!("is labelled {}", mime), format)));
+        let named = extension.as_deref().and_then(|extension| self.claim(|format| format.has_extension(extension))
+            .map(|format| (format!("has a .{} extension", extension), format)));
+        for (claim, format) in labelled.into_iter().chain(named) {
+            if format.is_binary() {
+                // A zip archive whose first entries don't give it away
+                return match format.has_signature(head) {
+                    true => Ok(format.file_type.clone()),
+                    false => Err(FileError::Unsupported(format!("{} {} but is not a {} file", path.display(), claim, format.name))),
+                };
+            }
+            if looks_like_text(head) {
+                return Ok(format.file_type.clone());
+            }
+        }
+
+        if head.starts_with(builtin::ZIP_SIGNATURE) {
+            return Err(FileError::Unsupported(format!("{} is a zip archive but not a supported document", path.display())));
+        }
+        if let Some((_, legacy)) = LEGACY.iter().find(|(legacy, _)| extension.as_deref() == Some(*legacy)) {
+            return Err(FileError::Unsupported(format!("{} is a {} and add that instead", path.display(), legacy)));
+        }
+        if !looks_like_text(head) {
+            return Err(FileError::Unsupported(format!("{} is not a supported document type", path.display())));
+        }
+        Ok(self.loaders.loaders()
+            .find(|loader| loader.recognise(head))
+            .map_or(FileType::PlainText, |loader| loader.format().file_type.clone()))
+    }
+
+    /// The first format `claims` picks out. Plain text is what's left over, so .txt files may
+    /// still turn out to be transcripts.
+    fn claim(&self, claims: impl Fn(&FormatInfo) -> bool) -> Option<&FormatInfo> {
+        self.loaders.loaders()
+            .map(|loader| loader.format())
+            .find(|format| format.file_type != FileType::PlainText && claims(format))
+    }
+}
+
+impl Default for FsFileLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl FileLoader for FsFileLoader {
+    async fn add_file(&self, root: &Path, path: &Path, mime: Option<&str>) -> Result<FileImport> {
+        let meta = fs::metadata(path)
+            .await
+            .map_err(|e| FileError::Read(format!("{}: {}", path.display(), e)))?;
+        if !meta.is_file() {
+            return Err(FileError::Unsupported(format!("{} is not a regular file", path.display())).into());
+        }
+
+        let head = read_head(path)
+            .await
+            .map_err(|e| FileError::Read(format!("{}: {}", path.display(), e)))?;
+        let file_type = self.detect(path, &head, mime)?;
+        let fingerprint = fingerprint_path(path)
+            .await
+            .map_err(|e| FileError::Read(format!("{}: {}", path.display(), e)))?;
+        let path = project_relative(root, path).await;
+
+        Ok(FileImport {
+            fingerprint: fingerprint.with_path(path.clone()),
+            path,
+            file_type,
+        })
+    }
+
+    async fn load_file(&self, root: &Path, file: &QualFile) -> Result<Vec<TextBlock>> {
+        let path = resolve_path(root, file);
+        let bytes = fs::read(&path)
+            .await
+            .map_err(|e| FileError::Read(format!("{}: {}", path.display(), e)))?;
+
+        let file_id = file.id;
+        let file_type = file.file_type().clone();
+        let survey = file.survey().cloned();
+        let loaders = self.loaders.clone();
+        // Parsing is CPU bound, so it runs off the async runtime
+        let parse = move || match survey {
+            // A spreadsheet imported as a survey reads only the cells its view selects
+            Some(view) => spreadsheet::read(&file_type, &bytes, view.selection.sheet.as_deref())?.blocks(file_id, &view),
+            None => loaders.parse(file_id, &file_type, &bytes),
+        };
+        let parsed = tokio::task::spawn_blocking(parse)
+            .await
+            .context("Parser task panicked")?;
+        Ok(parsed.map_err(|e| located(&path, e))?)
+    }
+
+    async fn fingerprint(&self, root: &Path, file: &QualFile) -> Result<Option<FileFingerprint>> {
+        let path = resolve_path(root, file);
+        match fingerprint_path(&path).await {
+            Ok(fingerprint) => Ok(Some(fingerprint.with_path(file.path()))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(FileError::Read(format!("{}: {}", path.display(), e)).into()),
+        }
+    }
+
+    async fn find_candidates(&self, root: &Path, folders: &[PathBuf], file: &QualFile) -> Result<Vec<RelinkProposal>> {
+        let stored_path = file.path_buf();
+        let Some(name) = stored_path.file_name() else {
+            return Ok(Vec::new());
+        };
+        let current = canonical_or_original(&resolve_path(root, file)).await;
+        let mut seen = HashSet::new();
+        let mut proposals = Vec::new();
+
+        for folder in folders {
+            for path in walk_files(folder).await {
+                // Folders can overlap, e.g. a user folder inside the project
+                let path = canonical_or_original(&path).await;
+                if path == current || !seen.insert(path.clone()) {
+                    continue;
+                }
+                let matched_by = match file.fingerprint() {
+                    Some(stored) => {
+                        // Sizes are cheap to compare, so only same sized files get hashed
+                        let same_size = fs::metadata(&path).await.is_ok_and(|meta| meta.len() == stored.size);
+                        if !same_size || !fingerprint_path(&path).await.is_ok_and(|found| found.sha256 == stored.sha256) {
+                            continue;
+                        }
+                        RelinkMatch::Content
+                    }
+                    None if path.file_name() == Some(name) => RelinkMatch::Name,
+                    None => continue,
+                };
+                proposals.push(RelinkProposal { file_id: file.id, path: project_relative(root, &path).await, matched_by });
+            }
+        }
+
+        // A copy under the same name is more likely the original than a renamed one
+        proposals.sort_by_key(|p| (p.matched_by, Path::new(&p.path).file_name() != Some(name)));
+        Ok(proposals)
+    }
+
+    async fn read_table(&self, path: &Path, sheet: Option<&str>) -> Result<SurveyTable> {
+        let bytes = fs::read(path)
+            .await
+            .map_err(|e| FileError::Read(format!("{}: {}", path.display(), e)))?;
+        let file_type = self.detect(path, &bytes[..bytes.len().min(SNIFF_LEN as usize)], None)?;
+
+        let sheet = sheet.map(str::to_string);
+        let table = tokio::task::spawn_blocking(move || spreadsheet::read(&file_type, &bytes, sheet.as_deref()))
+            .await
+            .context("Parser task panicked")?;
+        Ok(table.map_err(|e| located(path, e))?)
+    }
+
+    async fn find_files(&self, folder: &Path, filter: &FolderFilter) -> Result<Vec<PathBuf>> {
+        let meta = fs::metadata(folder)
+            .await
+            .map_err(|e| FileError::Read(format!("{}: {}", folder.display(), e)))?;
+        if !meta.is_dir() {
+            return Err(FileError::Unsupported(format!("{} is not a folder", folder.display())).into());
+        }
+        let include = glob_set(&filter.include)?;
+        let exclude = glob_set(&filter.exclude)?;
+
+        let files = match filter.recursive {
+            true => walk_files(folder).await,
+            false => top_level_files(folder).await,
+        };
+        Ok(files.into_iter().filter(|path| {
+            let Ok(relative) = path.strip_prefix(folder) else { return false };
+            let hidden = relative.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            !hidden
+                && (filter.include.is_empty() || include.is_match(relative))
+                && !exclude.is_match(relative)
+        }).collect())
+    }
+
+    fn formats(&self) -> Vec<FormatInfo> {
+        self.loaders.formats()
+    }
+}
+
+/// Matches any of `patterns`, ignoring case since users type them by hand
+fn glob_set(patterns: &[String]) -> Result<GlobSet, FileError> {
+    let mut set = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| FileError::Parse(format!("Invalid pattern {:?}: {}", pattern, e)))?;
+        set.add(glob);
+    }
+    set.build().map_err(|e| FileError::Parse(format!("Invalid patterns: {}", e)))
+}
+
+/// Every file under `folder`. Hidden folders and symlinked folders are skipped, the latter so
+/// a link cycle can't trap the walk, and unreadable folders are passed over rather than failing it.
+pub async fn walk_files(folder: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![folder.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else { continue };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type().await else { continue };
+            if file_type.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    pending.push(path);
+                }
+            } else if fs::metadata(&path).await.is_ok_and(|meta| meta.is_file()) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// The files directly in `folder`, sorted
+async fn top_level_files(folder: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let Ok(mut entries) = fs::read_dir(folder).await else { return files };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if fs::metadata(&path).await.is_ok_and(|meta| meta.is_file()) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+/// SHA-256, size and modification time of the file at `path`. The stored path is left for the
+/// caller to record, see [`FileFingerprint::with_path`].
+pub async fn fingerprint_path(path: &Path) -> std::io::Result<FileFingerprint> {
+    let mut file = fs::File::open(path).await?;
+    let meta = file.metadata().await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; HASH_CHUNK];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(FileFingerprint {
+        sha256: hex::encode(hasher.finalize()),
+        size: meta.len(),
+        // Not every platform or filesystem records it
+        modified: meta.modified().ok().map(DateTime::<Utc>::from),
+        path: None,
+    })
+}
+
+/// Where a project file lives on disk. Stored paths are relative to the project root unless
+/// the file was added from outside it.
+pub fn resolve_path(root: &Path, file: &QualFile) -> PathBuf {
+    let path = file.path_buf();
+    if path.is_absolute() { path } else { root.join(path) }
+}
+
+async fn read_head(path: &Path) -> std::io::Result<Vec<u8>> {
+    let file = fs::File::open(path).await?;
+    let mut head = Vec::with_capacity(SNIFF_LEN as usize);
+    file.take(SNIFF_LEN).read_to_end(&mut head).await?;
+    Ok(head)
+}
+
+/// [`FsFileLoader::detect`] for the built-in formats
+pub fn detect_file_type(path: &Path, head: &[u8]) -> Result<FileType, FileError> {
+    FsFileLoader::new().detect(path, head, None)
+}
+
+/// Binary formats almost always contain NUL bytes early on, text only does as UTF-16
+fn looks_like_text(head: &[u8]) -> bool {
+    head.starts_with(&[0xFF, 0xFE])
+        || head.starts_with(&[0xFE, 0xFF])
+        || !head.contains(&0)
+        || encoding::sniff_utf16(head).is_some()
+}
+
+/// `path` relative to `root` if it lives under it, otherwise absolute. Both sides are
+/// canonicalized first so `..` components and symlinked roots still match.
+async fn project_relative(root: &Path, path: &Path) -> String {
+    let absolute = canonical_or_original(path).await;
+    let root = canonical_or_original(root).await;
+
+    absolute.strip_prefix(&root)
+        .unwrap_or(&absolute)
+        .to_string_lossy()
+        .into_owned()
+}
+
+async fn canonical_or_original(path: &Path) -> PathBuf {
+    fs::canonicalize(path).await.unwrap_or_else(|_| path.to_path_buf())
+}
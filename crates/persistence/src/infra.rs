@@ -1,30 +1,243 @@
 #![allow(dead_code, unused_variables)]
-use app_core::domain::{QualProject, CodeBook, FileList, ProjectError};
+use app_core::domain::{
+    QualProject, CodeBook, FileList, ProjectError, MigrationReport, JournalRecovery,
+    CodeDef, CodeDefId, ThemeDef, ThemeId, QualCode, QualCodeId, QualFile, FileId,
+};
 use app_core::ports::ProjectRepository;
+use crate::atomic::{self, BackupInfo};
+use crate::migration::{MigrationRegistry, CURRENT_SCHEMA_VERSION};
+use crate::journal::{self, JournalEntry};
+use crate::lock;
 
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicBool};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use tokio::sync::Mutex;
 use chrono::Utc;
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
+use serde_json::error::Category;
 use tokio::fs;
 use anyhow::Result;
 
 
+/// Number of `.bak.N` files kept next to the project unless configured otherwise.
+pub const DEFAULT_BACKUP_COUNT: usize = 3;
+
+/// JSON file backed project repository.
+///
+/// All writes go through `write_lock`, so two saves never hit the same file at once.
+/// The autosave/manual flags let a background autosave get out of the way of the user:
+/// - `manual_save_pending` is set while a manual save waits for the lock, `manual_save_active` while it writes.
+///   An autosave that sees either one gives up, since the manual save will write newer state anyway.
+/// - `autosave_active` is set while an autosave runs. An autosave requested meanwhile is folded into the
+///   next one by setting `autosave_pending` and returning without writing.
+///
+/// Journal entries are numbered from `journal_seq`, which [`journal_mark`](ProjectRepository::journal_mark)
+/// reports, so a save only drops the entries its snapshot was taken after.
+///
+/// Between processes, `project.json.lock` records which instance has the project open. Every write
+/// refuses with `ProjectError::Locked` once another live instance holds that lock.
 pub struct JsonRepository {
     file_path: PathBuf,
+    backup_count: usize,
+    migrations: MigrationRegistry,
+    last_migration: std::sync::Mutex<Option<MigrationReport>>,
+    /// Project path and version of the last migrated load, until a save keeps the original
+    unsaved_migration: std::sync::Mutex<Option<(PathBuf, u32)>>,
+    last_recovery: std::sync::Mutex<Option<JournalRecovery>>,
+    damaged_journal: std::sync::Mutex<Option<DamagedJournal>>,
+    /// Sequence number of the last journal entry appended or loaded
+    journal_seq: AtomicU64,
+    write_lock: Arc<Mutex<()>>,
     autosave_active: Arc<AtomicBool>,
     autosave_pending: Arc<AtomicBool>,
     manual_save_active: Arc<AtomicBool>,
     manual_save_pending: Arc<AtomicBool>,
 }
 
+/// A journal the last load could only replay up to `readable_len` bytes, waiting for the
+/// next write to move it to `kept_at`
+struct DamagedJournal {
+    path: PathBuf,
+    readable_len: u64,
+    kept_at: PathBuf,
+}
+
+/// Clears a flag when dropped so an early return or error can't leave it stuck.
+struct FlagGuard<'a>(&'a AtomicBool);
+
+impl<'a> FlagGuard<'a> {
+    fn set(flag: &'a AtomicBool) -> Self {
+        flag.store(true, Ordering::SeqCst);
+        FlagGuard(flag)
+    }
+}
+
+impl Drop for FlagGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl JsonRepository {
+    pub fn new(file_path: PathBuf) -> Self {
+        JsonRepository {
+            file_path,
+            backup_count: DEFAULT_BACKUP_COUNT,
+            migrations: MigrationRegistry::default(),
+            last_migration: std::sync::Mutex::new(None),
+            unsaved_migration: std::sync::Mutex::new(None),
+            last_recovery: std::sync::Mutex::new(None),
+            damaged_journal: std::sync::Mutex::new(None),
+            journal_seq: AtomicU64::new(0),
+            write_lock: Arc::new(Mutex::new(())),
+            autosave_active: Arc::new(AtomicBool::new(false)),
+            autosave_pending: Arc::new(AtomicBool::new(false)),
+            manual_save_active: Arc::new(AtomicBool::new(false)),
+            manual_save_pending: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Sets how many rolling backups to keep. Zero disables backups.
+    pub fn with_backup_count(mut self, backup_count: usize) -> Self {
+        self.backup_count = backup_count;
+        self
+    }
+
+    /// Replaces the migration steps used on load. Mainly useful for testing upgrade paths.
+    pub fn with_migrations(mut self, migrations: MigrationRegistry) -> Self {
+        self.migrations = migrations;
+        self
+    }
+
+    /// True when an autosave was requested while another was in flight and hasn't run yet.
+    pub fn autosave_pending(&self) -> bool {
+        self.autosave_pending.load(Ordering::SeqCst)
+    }
+
+    /// Backups of the project at `path`, newest first.
+    pub async fn list_backups(&self, path: &Path) -> Vec<BackupInfo> {
+        atomic::list_backups(path, self.backup_count).await
+    }
+
+    /// Replaces the project at `path` with backup `index` (1 = most recent).
+    ///
+    /// The backup is parsed before anything is written, so restoring a damaged backup
+    /// fails without touching the current file. The file being replaced is rotated into
+    /// the backups like any other save, so a restore can itself be undone. Any journal is
+    /// discarded, since its entries belong to the replaced snapshot.
+    pub async fn restore_backup(&self, path: &Path, index: usize) -> Result<()> {
+        let backup = atomic::backup_path(path, index);
+        let bytes = fs::read(&backup)
+            .await
+            .map_err(|e| ProjectError::Load(format!("Failed to read backup {}: {}", backup.display(), e)))?;
+
+        self.parse_project_file(&bytes)?;
+
+        let _write = self.write_lock.lock().await;
+        lock::ensure_not_locked_by_other(path).await?;
+        atomic::write_atomic(path, &bytes, self.backup_count)
+            .await
+            .map_err(|e| ProjectError::Save(format!("Failed to restore backup: {}", e)))?;
+        // Journaled changes were made on top of the snapshot we just replaced
+        self.set_aside_damaged_journal(path).await?;
+        journal::clear(path).await?;
+        // The replaced file went into the backups, and the restored one was never migrated
+        self.unsaved_migration.lock().unwrap().take_if(|(migrated, _)| migrated == path);
+
+        Ok(())
+    }
+
+    /// Parses a project document, upgrading it to the current schema first if needed
+    fn parse_project_file(&self, bytes: &[u8]) -> Result<(ProjectFile, Option<MigrationReport>), ProjectError> {
+        let mut doc: serde_json::Value = serde_json::from_slice(bytes)
+            .map_err(map_parse_error)?;
+
+        let report = self.migrations.migrate(&mut doc)?;
+
+        let project_file: ProjectFile = serde_json::from_value(doc)
+            .map_err(map_parse_error)?;
+
+        Ok((project_file, report))
+    }
+
+    /// Records a single change in the journal instead of rewriting the snapshot
+    async fn append_journal(&self, path: &Path, entry: JournalEntry) -> Result<()> {
+        let _write = self.write_lock.lock().await;
+        lock::ensure_not_locked_by_other(path).await?;
+
+        if !fs::try_exists(path).await.unwrap_or(false) {
+            return Err(ProjectError::Save(format!("Project file does not exist: {}", path.display())).into());
+        }
+        self.set_aside_damaged_journal(path).await?;
+        // Numbered under `write_lock`, so entries appear in the file in sequence order
+        let seq = self.journal_seq.fetch_add(1, Ordering::SeqCst) + 1;
+        journal::append(path, seq, entry).await?;
+        Ok(())
+    }
+
+    /// Drops the journal entries a snapshot now covers. `covered` is the journal mark taken with
+    /// the snapshot: entries appended after that may be missing from it and are kept.
+    /// A failed trim isn't a failed save, since replaying covered entries is harmless.
+    /// Callers must hold `write_lock`.
+    async fn trim_journal(&self, path: &Path, covered: u64) {
+        let _ = journal::trim(path, covered).await;
+    }
+
+    /// Copies a migrated project's original file to a versioned name before the first save
+    /// replaces it, since autosaves don't rotate backups. Loading leaves the disk alone, so a
+    /// read-only open of an old project writes nothing. Callers must hold `write_lock`.
+    async fn keep_pre_migration_copy(&self, path: &Path) -> Result<(), ProjectError> {
+        let pending = self.unsaved_migration.lock().unwrap().clone();
+        let Some((migrated, version)) = pending.filter(|(migrated, _)| migrated == path) else {
+            return Ok(());
+        };
+        let original = versioned_copy_path(&migrated, version);
+        if !fs::try_exists(&original).await.unwrap_or(false) {
+            fs::copy(&migrated, &original)
+                .await
+                .map_err(|e| ProjectError::Save(format!("Failed to keep pre-migration copy: {}", e)))?;
+        }
+        *self.unsaved_migration.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Moves a damaged journal aside before the first write after loading it, leaving only the
+    /// entries that were replayed, so new entries never follow the unreadable one. Loading
+    /// leaves it in place, so a read-only open writes nothing. Callers must hold `write_lock`.
+    async fn set_aside_damaged_journal(&self, path: &Path) -> Result<(), ProjectError> {
+        let pending = self.damaged_journal.lock().unwrap().take_if(|damaged| damaged.path == path);
+        let Some(damaged) = pending else {
+            return Ok(());
+        };
+        let result = journal::set_aside(path, damaged.readable_len, &damaged.kept_at).await;
+        if result.is_err() {
+            *self.damaged_journal.lock().unwrap() = Some(damaged);
+        }
+        result
+    }
+
+    /// Callers must hold `write_lock`.
+    async fn write_json(&self, path: &Path, json: &str, keep_backups: usize) -> Result<(), ProjectError> {
+        atomic::write_atomic(path, json.as_bytes(), keep_backups)
+            .await
+            .map_err(|e| ProjectError::Save(format!("Failed to write file: {}", e)))
+    }
+}
+
+fn serialize_project_file(project_file: &ProjectFile) -> Result<String, ProjectError> {
+    serde_json::to_string_pretty(project_file)
+        .map_err(|e| ProjectError::Save(format!("Serialization failed: {}", e)))
+}
+
+/// On-disk envelope for a project. Field names are the JSON keys, so renaming
+/// any of these is a breaking change to the file format.
 #[derive(Serialize, Deserialize)]
-struct ProjectFile {
-    project: QualProject,
-    codebook: CodeBook,
-    filemanager: FileList,
+pub(crate) struct ProjectFile {
+    pub(crate) project: QualProject,
+    pub(crate) codebook: CodeBook,
+    pub(crate) filemanager: FileList,
 }
 
 #[async_trait]
@@ -33,7 +246,7 @@ impl ProjectRepository for JsonRepository {
         let now = Utc::now();
         let project = QualProject::new(
             name,
-            1,
+            CURRENT_SCHEMA_VERSION,
             now,
             now
         );
@@ -47,19 +260,209 @@ impl ProjectRepository for JsonRepository {
             filemanager,
         };
 
-        let json = serde_json::to_string_pretty(&project_file)
-            .map_err(|e| ProjectError::Save(format!("Serialization failed: {}", e)))?;
-
-        fs::write(path, json)
-            .await
-            .map_err(|e| ProjectError::Save(format!("Failed to write file: {}", e)))?;
+        let json = serialize_project_file(&project_file)?;
+        let _write = self.write_lock.lock().await;
+        lock::ensure_not_locked_by_other(path).await?;
+        self.write_json(path, &json, self.backup_count).await?;
+        self.set_aside_damaged_journal(path).await?;
+        journal::clear(path).await?;
+        self.unsaved_migration.lock().unwrap().take_if(|(migrated, _)| migrated == path);
 
         Ok(project)
     }
-    async fn save_project(&self, path: &Path, project: QualProject, codebook: CodeBook, filemanager: FileList) -> Result<()> {
-        todo!("implment save");
+    async fn save_project(&self, path: &Path, project: QualProject, codebook: CodeBook, filemanager: FileList, covered: u64) -> Result<()> {
+        let _write = {
+            let _pending = FlagGuard::set(&self.manual_save_pending);
+            self.write_lock.lock().await
+        };
+        let _active = FlagGuard::set(&self.manual_save_active);
+        lock::ensure_not_locked_by_other(path).await?;
+
+        let mut project = project;
+        project.touch(Utc::now());
+
+        let project_file = ProjectFile {
+            project,
+            codebook,
+            filemanager,
+        };
+
+        let json = serialize_project_file(&project_file)?;
+        self.keep_pre_migration_copy(path).await?;
+        self.set_aside_damaged_journal(path).await?;
+        self.write_json(path, &json, self.backup_count).await?;
+        self.trim_journal(path, covered).await;
+
+        Ok(())
+    }
+    async fn autosave_project(&self, path: &Path, project: QualProject, codebook: CodeBook, filemanager: FileList, covered: u64) -> Result<bool> {
+        if self.manual_save_pending.load(Ordering::SeqCst) || self.manual_save_active.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+        if self.autosave_active.swap(true, Ordering::SeqCst) {
+            self.autosave_pending.store(true, Ordering::SeqCst);
+            return Ok(false);
+        }
+        let _active = FlagGuard(&self.autosave_active);
+        self.autosave_pending.store(false, Ordering::SeqCst);
+
+        let _write = self.write_lock.lock().await;
+        lock::ensure_not_locked_by_other(path).await?;
+
+        let mut project = project;
+        project.touch(Utc::now());
+
+        let project_file = ProjectFile {
+            project,
+            codebook,
+            filemanager,
+        };
+
+        let json = serialize_project_file(&project_file)?;
+
+        // A manual save queued up while we serialized. Let it write the newer state.
+        if self.manual_save_pending.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+
+        // Autosaves don't rotate backups, otherwise a few minutes of editing would push
+        // every manually saved version out of the backup window.
+        self.keep_pre_migration_copy(path).await?;
+        self.set_aside_damaged_journal(path).await?;
+        self.write_json(path, &json, 0).await?;
+        self.trim_journal(path, covered).await;
+
+        Ok(true)
     }
     async fn load_project(&self, path: &Path) -> Result<(QualProject, CodeBook, FileList)> {
-        todo!("implmement load");
+        let bytes = fs::read(path)
+            .await
+            .map_err(|e| ProjectError::Load(format!("Failed to read file: {}", e)))?;
+
+        let (mut project_file, report) = self.parse_project_file(&bytes)?;
+
+        // Changes journaled after the last full save, e.g. before a crash
+        let replay = journal::read(path, &self.migrations).await?;
+        let replayed = replay.entries.len();
+        self.journal_seq.fetch_max(replay.last_seq, Ordering::SeqCst);
+        for entry in replay.entries {
+            entry.apply(&mut project_file.codebook, &mut project_file.filemanager);
+        }
+        let damaged = match replay.skipped {
+            0 => None,
+            _ => Some(DamagedJournal {
+                path: path.to_path_buf(),
+                readable_len: replay.readable_len,
+                kept_at: journal::damaged_journal_path(path).await,
+            }),
+        };
+        *self.last_recovery.lock().unwrap() = damaged.as_ref()
+            .map(|damaged| JournalRecovery { replayed, skipped: replay.skipped, kept_at: damaged.kept_at.clone() });
+        *self.damaged_journal.lock().unwrap() = damaged;
+
+        // The original is copied aside by the first save that rewrites it in the new format
+        *self.unsaved_migration.lock().unwrap() = report.as_ref().map(|report| (path.to_path_buf(), report.from_version));
+        *self.last_migration.lock().unwrap() = report;
+
+        Ok((project_file.project, project_file.codebook, project_file.filemanager))
+    }
+    fn journal_mark(&self, _path: &Path) -> u64 {
+        self.journal_seq.load(Ordering::SeqCst)
+    }
+    fn take_migration_report(&self) -> Option<MigrationReport> {
+        self.last_migration.lock().unwrap().take()
+    }
+    fn take_journal_recovery(&self) -> Option<JournalRecovery> {
+        self.last_recovery.lock().unwrap().take()
+    }
+    fn is_project_file(&self, project: &Path, path: &Path) -> bool {
+        is_project_file(project, path)
+    }
+    async fn lock_project(&self, path: &Path, force: bool) -> Result<()> {
+        lock::acquire(path, force).await?;
+        Ok(())
+    }
+    async fn unlock_project(&self, path: &Path) -> Result<()> {
+        lock::release(path).await?;
+        Ok(())
+    }
+    async fn insert_code_def(&self, path: &Path, code: CodeDef) -> Result<()> {
+        self.append_journal(path, JournalEntry::CodeDefInserted(code)).await
+    }
+    async fn insert_theme_def(&self, path: &Path, theme: ThemeDef) -> Result<()> {
+        self.append_journal(path, JournalEntry::ThemeInserted(theme)).await
+    }
+    async fn insert_qual_code(&self, path: &Path, code: QualCode) -> Result<()> {
+        self.append_journal(path, JournalEntry::QualCodeInserted(code)).await
+    }
+    async fn insert_file(&self, path: &Path, file: QualFile) -> Result<()> {
+        self.append_journal(path, JournalEntry::FileInserted(Box::new(file))).await
+    }
+    async fn delete_code_def(&self, path: &Path, id: CodeDefId) -> Result<()> {
+        self.append_journal(path, JournalEntry::CodeDefDeleted(id)).await
+    }
+    async fn delete_theme_def(&self, path: &Path, id: ThemeId) -> Result<()> {
+        self.append_journal(path, JournalEntry::ThemeDeleted(id)).await
+    }
+    async fn delete_qual_code(&self, path: &Path, id: QualCodeId) -> Result<()> {
+        self.append_journal(path, JournalEntry::QualCodeDeleted(id)).await
+    }
+    async fn delete_file(&self, path: &Path, id: FileId) -> Result<()> {
+        self.append_journal(path, JournalEntry::FileDeleted(id)).await
+    }
+    async fn move_code_def(&self, path: &Path, id: CodeDefId, new_index: usize) -> Result<()> {
+        self.append_journal(path, JournalEntry::CodeDefMoved { id, index: new_index }).await
+    }
+    async fn move_theme_def(&self, path: &Path, id: ThemeId, new_index: usize) -> Result<()> {
+        self.append_journal(path, JournalEntry::ThemeMoved { id, index: new_index }).await
+    }
+    async fn move_file(&self, path: &Path, id: FileId, new_index: usize) -> Result<()> {
+        self.append_journal(path, JournalEntry::FileMoved { id, index: new_index }).await
+    }
+}
+
+/// `project.json` -> `project.json.v1`
+pub(crate) fn versioned_copy_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{}", version));
+    path.with_file_name(name)
+}
+
+/// Whether `path` is the project at `project` or a file kept beside it under its name: a
+/// backup, versioned copy, journal, kept-aside journal or lock, a half-written temp file of
+/// any of those, or SQLite's own rollback and WAL files. Paths are compared whole, so a file elsewhere or one
+/// that merely starts with the project's name is not counted.
+pub(crate) fn is_project_file(project: &Path, path: &Path) -> bool {
+    if path.parent() != project.parent() {
+        return false;
+    }
+    let name = project.file_name().and_then(|name| name.to_str());
+    let file_name = path.file_name().and_then(|name| name.to_str());
+    let (Some(name), Some(file_name)) = (name, file_name) else {
+        return path == project;
+    };
+    if let Some(target) = atomic::temp_target(file_name) {
+        return is_project_file(project, &path.with_file_name(target));
+    }
+    let Some(suffix) = file_name.strip_prefix(name) else {
+        return false;
+    };
+    let numbered = |rest: &str| !rest.is_empty() && rest.bytes().all(|b| b.is_ascii_digit());
+    match suffix {
+        "" | ".lock" | ".journal" | ".journal.damaged" | "-journal" | "-wal" | "-shm" => true,
+        _ => suffix.strip_prefix(".bak.").is_some_and(numbered)
+            || suffix.strip_prefix(".v").is_some_and(numbered)
+            || suffix.strip_prefix(".journal.damaged.").is_some_and(numbered),
+    }
+}
+
+/// Sorts serde_json failures into the project error the UI should surface.
+/// Syntax and EOF errors mean the bytes on disk are damaged (e.g. a truncated write),
+/// while data errors mean valid JSON that doesn't match the project shape.
+pub(crate) fn map_parse_error(e: serde_json::Error) -> ProjectError {
+    match e.classify() {
+        Category::Io => ProjectError::Load(format!("Failed to read file: {}", e)),
+        Category::Syntax | Category::Eof => ProjectError::Corrupted(format!("Invalid JSON: {}", e)),
+        Category::Data => ProjectError::InvalidFormat(format!("Unexpected project structure: {}", e)),
     }
 }
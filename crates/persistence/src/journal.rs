@@ -0,0 +1,266 @@
+use app_core::domain::*;
+use crate::migration::{MigrationRegistry, CURRENT_SCHEMA_VERSION};
+
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::fs;
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+/// A single domain mutation recorded since the last full save.
+///
+/// Every entry is idempotent (inserts are upserts, deletes of missing entities are no-ops,
+/// moves target an absolute index), so replaying an entry the snapshot already contains is safe.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", content = "data", rename_all = "snake_case")]
+pub enum JournalEntry {
+    CodeDefInserted(CodeDef),
+    CodeDefDeleted(CodeDefId),
+    CodeDefMoved { id: CodeDefId, index: usize },
+    ThemeInserted(ThemeDef),
+    ThemeDeleted(ThemeId),
+    ThemeMoved { id: ThemeId, index: usize },
+    QualCodeInserted(QualCode),
+    QualCodeDeleted(QualCodeId),
+    FileInserted(Box<QualFile>),
+    FileDeleted(FileId),
+    FileMoved { id: FileId, index: usize },
+}
+
+impl JournalEntry {
+    /// Applies the mutation. Not-found and index errors are ignored: the entry described a
+    /// state that a later entry or the snapshot has already moved past.
+    pub fn apply(self, codebook: &mut CodeBook, files: &mut FileList) {
+        match self {
+            JournalEntry::CodeDefInserted(code) => codebook.insert_code_def(code),
+            JournalEntry::CodeDefDeleted(id) => { let _ = codebook.remove_code_def(id); }
+            JournalEntry::CodeDefMoved { id, index } => { let _ = codebook.move_code_def_to_index(id, index); }
+            JournalEntry::ThemeInserted(theme) => codebook.insert_theme(theme),
+            JournalEntry::ThemeDeleted(id) => { let _ = codebook.remove_theme(id); }
+            JournalEntry::ThemeMoved { id, index } => { let _ = codebook.move_theme_to_index(id, index); }
+            JournalEntry::QualCodeInserted(code) => codebook.insert_qual_code(code),
+            JournalEntry::QualCodeDeleted(id) => { let _ = codebook.remove_qual_code(id); }
+            JournalEntry::FileInserted(file) => files.insert_file(*file),
+            JournalEntry::FileDeleted(id) => { let _ = files.remove_file(id); }
+            JournalEntry::FileMoved { id, index } => { let _ = files.move_file_to_index(id, index); }
+        }
+    }
+}
+
+/// One JSON line in the journal file
+#[derive(Serialize, Deserialize)]
+struct JournalRecord {
+    /// Increases with every append. Journals written before sequence numbers read as 0,
+    /// which any save covers since loading replayed them into the saved state.
+    #[serde(default)]
+    seq: u64,
+    /// Schema the entry was written under. The journal arrived in version 1, before entries
+    /// recorded theirs.
+    #[serde(default = "first_schema_version")]
+    schema_version: u32,
+    at: DateTime<Utc>,
+    entry: JournalEntry,
+}
+
+fn first_schema_version() -> u32 { 1 }
+
+/// Just the sequence number of a journal line, readable whatever schema its entry is in
+#[derive(Deserialize)]
+struct RecordSeq {
+    #[serde(default)]
+    seq: u64,
+}
+
+/// Parses one journal line, first upgrading its entry to the current schema
+fn parse_record(line: &str, migrations: &MigrationRegistry) -> Option<JournalRecord> {
+    let mut record: serde_json::Value = serde_json::from_str(line).ok()?;
+    let version = match record.get("schema_version") {
+        Some(version) => u32::try_from(version.as_u64()?).ok()?,
+        None => first_schema_version(),
+    };
+    migrations.migrate_entry(version, &mut record["entry"]).ok()?;
+    serde_json::from_value(record).ok()
+}
+
+/// `project.json` -> `project.json.journal`
+pub fn journal_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".journal");
+    path.with_file_name(name)
+}
+
+/// Appends one entry under sequence number `seq` and syncs it to disk before returning
+pub async fn append(path: &Path, seq: u64, entry: JournalEntry) -> Result<(), ProjectError> {
+    let record = JournalRecord { seq, schema_version: CURRENT_SCHEMA_VERSION, at: Utc::now(), entry };
+    let mut line = serde_json::to_string(&record)
+        .map_err(|e| ProjectError::Save(format!("Journal serialization failed: {}", e)))?;
+    line.push('\n');
+
+    let journal = journal_path(path);
+    drop_torn_tail(&journal)
+        .await
+        .map_err(|e| ProjectError::Save(format!("Failed to repair journal: {}", e)))?;
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&journal)
+        .await
+        .map_err(|e| ProjectError::Save(format!("Failed to open journal: {}", e)))?;
+    file.write_all(line.as_bytes())
+        .await
+        .map_err(|e| ProjectError::Save(format!("Failed to append to journal: {}", e)))?;
+    file.sync_data()
+        .await
+        .map_err(|e| ProjectError::Save(format!("Failed to sync journal: {}", e)))
+}
+
+/// Cuts off a partial last line left by a crash mid-append, so the next entry starts on a
+/// fresh line instead of being glued onto the fragment.
+async fn drop_torn_tail(journal: &Path) -> std::io::Result<()> {
+    let mut file = match fs::OpenOptions::new().read(true).write(true).open(journal).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata().await?.len();
+    if len == 0 {
+        return Ok(());
+    }
+    file.seek(SeekFrom::End(-1)).await?;
+    if file.read_u8().await? == b'\n' {
+        return Ok(());
+    }
+
+    file.seek(SeekFrom::Start(0)).await?;
+    let mut bytes = Vec::with_capacity(len as usize);
+    file.read_to_end(&mut bytes).await?;
+    let keep = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    file.set_len(keep as u64).await?;
+    file.sync_all().await
+}
+
+/// What a load found in the journal
+#[derive(Debug, Default)]
+pub struct Replay {
+    pub entries: Vec<JournalEntry>,
+    /// Lines from the first unreadable one on, which were left out
+    pub skipped: usize,
+    /// Length in bytes of the lines before the first unreadable one
+    pub readable_len: u64,
+    /// Highest sequence number among the entries
+    pub last_seq: u64,
+}
+
+/// Reads the journal's entries, upgrading any written under an older schema with `migrations`.
+/// A missing journal is empty.
+///
+/// An unreadable final line is the expected result of a crash mid-append and is dropped.
+/// An unreadable line anywhere else means the journal itself is damaged: reading stops there,
+/// since later entries may depend on it, and the rest is counted as skipped.
+pub async fn read(path: &Path, migrations: &MigrationRegistry) -> Result<Replay, ProjectError> {
+    let contents = match fs::read_to_string(journal_path(path)).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Replay::default()),
+        Err(e) => return Err(ProjectError::Load(format!("Failed to read journal: {}", e))),
+    };
+
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in contents.split_inclusive('\n') {
+        if !line.trim().is_empty() {
+            lines.push((offset, line));
+        }
+        offset += line.len();
+    }
+
+    let mut replay = Replay { readable_len: contents.len() as u64, ..Replay::default() };
+    for (i, &(offset, line)) in lines.iter().enumerate() {
+        match parse_record(line, migrations) {
+            Some(record) => {
+                replay.last_seq = replay.last_seq.max(record.seq);
+                replay.entries.push(record.entry);
+            }
+            None if i == lines.len() - 1 && !line.ends_with('\n') => break,
+            None => {
+                replay.skipped = lines.len() - i;
+                replay.readable_len = offset as u64;
+                break;
+            }
+        }
+    }
+    Ok(replay)
+}
+
+/// `project.json` -> `project.json.journal.damaged`, numbered after the first so an earlier
+/// damaged journal is never overwritten
+pub async fn damaged_journal_path(path: &Path) -> PathBuf {
+    let journal = journal_path(path);
+    let mut name = journal.file_name().unwrap_or_default().to_os_string();
+    name.push(".damaged");
+    let mut kept = journal.with_file_name(&name);
+    let mut n = 2;
+    while fs::try_exists(&kept).await.unwrap_or(false) {
+        kept = journal.with_file_name(format!("{}.{}", name.to_string_lossy(), n));
+        n += 1;
+    }
+    kept
+}
+
+/// Copies a damaged journal to `kept_at`, then cuts the journal back to its first
+/// `readable_len` bytes so new entries follow the ones that were replayed
+pub async fn set_aside(path: &Path, readable_len: u64, kept_at: &Path) -> Result<(), ProjectError> {
+    let journal = journal_path(path);
+    let bytes = match fs::read(&journal).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(ProjectError::Save(format!("Failed to read journal: {}", e))),
+    };
+    crate::atomic::write_atomic(kept_at, &bytes, 0)
+        .await
+        .map_err(|e| ProjectError::Save(format!("Failed to keep damaged journal: {}", e)))?;
+
+    let keep = usize::try_from(readable_len).unwrap_or(usize::MAX).min(bytes.len());
+    if keep == 0 {
+        return clear(path).await;
+    }
+    crate::atomic::write_atomic(&journal, &bytes[..keep], 0)
+        .await
+        .map_err(|e| ProjectError::Save(format!("Failed to rewrite journal: {}", e)))
+}
+
+/// Drops the entries numbered `covered` or lower, i.e. the ones a snapshot now contains.
+/// Later entries, and any line that can't be read, are kept for the next load or save.
+pub async fn trim(path: &Path, covered: u64) -> Result<(), ProjectError> {
+    let journal = journal_path(path);
+    let contents = match fs::read_to_string(&journal).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(ProjectError::Save(format!("Failed to read journal: {}", e))),
+    };
+
+    let kept: String = contents
+        .split_inclusive('\n')
+        .filter(|line| !line.trim().is_empty())
+        .filter(|line| serde_json::from_str::<RecordSeq>(line).map_or(true, |record| record.seq > covered))
+        .collect();
+    if kept.is_empty() {
+        return clear(path).await;
+    }
+    if kept.len() == contents.len() {
+        return Ok(());
+    }
+    crate::atomic::write_atomic(&journal, kept.as_bytes(), 0)
+        .await
+        .map_err(|e| ProjectError::Save(format!("Failed to rewrite journal: {}", e)))
+}
+
+/// Removes the journal entirely
+pub async fn clear(path: &Path) -> Result<(), ProjectError> {
+    match fs::remove_file(journal_path(path)).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(ProjectError::Save(format!("Failed to clear journal: {}", e))),
+    }
+}
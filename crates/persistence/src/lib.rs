@@ -1 +1,11 @@
 pub mod infra;
+pub mod atomic;
+pub mod migration;
+pub mod journal;
+pub mod lock;
+pub mod file_loader;
+pub mod loaders;
+pub mod sqlite;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,49 @@
+//! Format specific parsers that turn a source file's bytes into TextBlocks.
+//!
+//! Parsers work on bytes already read from disk and report problems without the file's
+//! path. [`FsFileLoader`](crate::file_loader::FsFileLoader) adds it with [`located`].
+
+pub mod encoding;
+pub mod text;
+pub mod markdown;
+pub mod pdf;
+pub mod office;
+pub mod docx;
+pub mod odt;
+pub mod rtf;
+pub mod transcript;
+pub mod spreadsheet;
+pub mod html;
+pub mod epub;
+pub mod email;
+pub mod chat;
+pub mod builtin;
+
+use app_core::domain::FileError;
+use std::path::Path;
+
+/// Longest label taken to be a speaker's name rather than the start of a sentence
+const MAX_SPEAKER_CHARS: usize = 40;
+const MAX_SPEAKER_WORDS: usize = 5;
+
+/// Whether a label like the "Interviewer" in "Interviewer: ..." reads as someone's name
+pub fn plausible_speaker(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().count() <= MAX_SPEAKER_CHARS
+        && name.split_whitespace().count() <= MAX_SPEAKER_WORDS
+        && name.chars().any(char::is_alphabetic)
+        && !name.contains(['\t', '\n'])
+}
+
+/// Prefixes an error's message with the file it came from
+pub fn located(path: &Path, error: FileError) -> FileError {
+    let at = |message: String| format!("{}: {}", path.display(), message);
+    match error {
+        FileError::Read(m) => FileError::Read(at(m)),
+        FileError::Write(m) => FileError::Write(at(m)),
+        FileError::Parse(m) => FileError::Parse(at(m)),
+        FileError::Encoding(m) => FileError::Encoding(at(m)),
+        FileError::Unsupported(m) => FileError::Unsupported(at(m)),
+        FileError::Unknown(m) => FileError::Unknown(at(m)),
+    }
+}
@@ -0,0 +1,167 @@
+//! The formats the app reads out of the box, registered like any other
+//! [`FormatLoader`] so an added loader can take one over.
+
+use app_core::domain::{BlockMeta, FileError, FileId, FileType, FormatInfo, TextBlock};
+use app_core::ports::{FormatLoader, LoaderRegistry};
+use crate::loaders::{chat, docx, email, encoding, epub, html, markdown, odt, pdf, rtf, text, transcript};
+
+pub const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+/// Covers `.odt` and its template variant `.ott`
+const ODT_MIMETYPE: &[u8] = b"application/vnd.oasis.opendocument.text";
+const EPUB_MIMETYPE: &[u8] = b"application/epub+zip";
+
+/// A format with its parser and sniffers as plain functions
+struct Builtin {
+    format: FormatInfo,
+    parse: fn(FileId, &[u8]) -> Result<Vec<TextBlock>, FileError>,
+    sniff: Option<fn(&[u8]) -> bool>,
+    recognise: Option<fn(&[u8]) -> bool>,
+}
+
+impl Builtin {
+    fn new(format: FormatInfo, parse: fn(FileId, &[u8]) -> Result<Vec<TextBlock>, FileError>) -> Self {
+        Builtin { format, parse, sniff: None, recognise: None }
+    }
+
+    fn with_sniff(mut self, sniff: fn(&[u8]) -> bool) -> Self {
+        self.sniff = Some(sniff);
+        self
+    }
+
+    fn with_recognise(mut self, recognise: fn(&[u8]) -> bool) -> Self {
+        self.recognise = Some(recognise);
+        self
+    }
+}
+
+impl FormatLoader for Builtin {
+    fn format(&self) -> &FormatInfo {
+        &self.format
+    }
+
+    fn sniff(&self, head: &[u8]) -> bool {
+        match self.sniff {
+            Some(sniff) => sniff(head),
+            None => self.format.has_signature(head),
+        }
+    }
+
+    fn recognise(&self, head: &[u8]) -> bool {
+        self.recognise.is_some_and(|recognise| recognise(head))
+    }
+
+    fn parse(&self, file_id: FileId, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+        (self.parse)(file_id, bytes)
+    }
+}
+
+/// Every built-in format. Later ones are asked first, so the order matters where sniffers
+/// could overlap: Word documents before workbooks and HTML before transcripts.
+pub fn registry() -> LoaderRegistry {
+    let timed = [BlockMeta::SPEAKER, BlockMeta::START, BlockMeta::END];
+    let survey = [BlockMeta::CASE, BlockMeta::QUESTION];
+    let loaders = [
+        Builtin::new(FormatInfo::new(FileType::PlainText, "Plain text")
+            .with_extensions(&["txt", "text"])
+            .with_mime_types(&["text/plain"]), |id, bytes| Ok(text::parse(id, &encoding::decode_text(bytes)?))),
+        Builtin::new(FormatInfo::new(FileType::Markdown, "Markdown")
+            .with_extensions(&["md", "markdown"])
+            .with_mime_types(&["text/markdown"]), |id, bytes| Ok(markdown::parse(id, &encoding::decode_text(bytes)?))),
+        Builtin::new(FormatInfo::new(FileType::Transcript, "Transcript")
+            .with_metadata(&timed), |id, bytes| Ok(transcript::parse_speaker_turns(id, &encoding::decode_text(bytes)?)))
+            .with_recognise(|head| encoding::decode_text(head).is_ok_and(|text| transcript::looks_like_speaker_turns(&text))),
+        Builtin::new(FormatInfo::new(FileType::Srt, "SubRip subtitles")
+            .with_extensions(&["srt"])
+            .with_mime_types(&["application/x-subrip"])
+            .with_metadata(&timed), |id, bytes| transcript::parse_srt(id, &encoding::decode_text(bytes)?)),
+        Builtin::new(FormatInfo::new(FileType::Csv, "CSV")
+            .with_extensions(&["csv", "tsv"])
+            .with_mime_types(&["text/csv", "text/tab-separated-values"]),
+            // A CSV added as a document rather than imported as a survey is read like any
+            // text, so its blocks carry no case or question
+            |id, bytes| Ok(text::parse(id, &encoding::decode_text(bytes)?))),
+        Builtin::new(FormatInfo::new(FileType::ChatLog, "Chat log")
+            .with_metadata(&[BlockMeta::AUTHOR, BlockMeta::SENT, BlockMeta::THREAD]), |id, bytes| chat::parse(id, &encoding::decode_text(bytes)?))
+            // Not claimed by the .json extension or MIME type, which all sorts of files share
+            .with_recognise(|head| encoding::decode_text(head).is_ok_and(|text| chat::looks_like_chat(&text))),
+        Builtin::new(FormatInfo::new(FileType::Email, "Email")
+            .with_extensions(&["eml", "mbox", "mbx"])
+            .with_mime_types(&["message/rfc822", "application/mbox"])
+            .with_metadata(&[BlockMeta::AUTHOR, BlockMeta::SENT, BlockMeta::SUBJECT, BlockMeta::THREAD]), email::parse)
+            // Mailboxes are often saved without an extension
+            .with_recognise(email::looks_like_mbox),
+        Builtin::new(FormatInfo::new(FileType::Html, "Web page")
+            .with_extensions(&["html", "htm", "xhtml", "xht"])
+            .with_mime_types(&["text/html", "application/xhtml+xml"])
+            .with_metadata(&[BlockMeta::URL]), |id, bytes| Ok(html::parse(id, &html::decode(bytes)?)))
+            // Pages saved without an extension, or with the wrong one
+            .with_recognise(is_html),
+        Builtin::new(FormatInfo::new(FileType::WebVtt, "WebVTT captions")
+            .with_extensions(&["vtt"])
+            .with_mime_types(&["text/vtt"])
+            .with_signature(b"WEBVTT")
+            .with_metadata(&timed), |id, bytes| transcript::parse_webvtt(id, &encoding::decode_text(bytes)?))
+            .with_sniff(|head| is_webvtt(head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head))),
+        Builtin::new(FormatInfo::new(FileType::RichText, "Rich Text")
+            .with_extensions(&["rtf"])
+            .with_mime_types(&["application/rtf", "text/rtf"])
+            .with_signature(b"{\\rtf"), rtf::parse),
+        Builtin::new(FormatInfo::new(FileType::Pdf, "PDF")
+            .with_extensions(&["pdf"])
+            .with_mime_types(&["application/pdf"])
+            .with_signature(b"%PDF-")
+            .with_metadata(&[BlockMeta::PAGE]), pdf::parse),
+        Builtin::new(FormatInfo::new(FileType::Xlsx, "Excel workbook")
+            .with_extensions(&["xlsx"])
+            .with_mime_types(&["application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"])
+            .with_signature(ZIP_SIGNATURE)
+            .with_metadata(&survey), |_, _| Err(FileError::Unsupported("Workbooks are imported as surveys, choosing the columns to code".to_string())))
+            .with_sniff(|head| archive_mimetype(head).is_none() && contains(head, b"xl/")),
+        Builtin::new(FormatInfo::new(FileType::Docx, "Word document")
+            .with_extensions(&["docx"])
+            .with_mime_types(&["application/vnd.openxmlformats-officedocument.wordprocessingml.document"])
+            .with_signature(ZIP_SIGNATURE)
+            .with_metadata(&[BlockMeta::SPEAKER]), docx::parse)
+            // Word documents have no fixed first entry, but writers put the `word/` parts early
+            .with_sniff(|head| archive_mimetype(head).is_none() && contains(head, b"word/")),
+        Builtin::new(FormatInfo::new(FileType::Odt, "OpenDocument text")
+            .with_extensions(&["odt", "ott"])
+            .with_mime_types(&["application/vnd.oasis.opendocument.text"])
+            .with_signature(ZIP_SIGNATURE)
+            .with_metadata(&[BlockMeta::SPEAKER]), odt::parse)
+            .with_sniff(|head| archive_mimetype(head).is_some_and(|mimetype| mimetype.starts_with(ODT_MIMETYPE))),
+        Builtin::new(FormatInfo::new(FileType::Epub, "EPUB book")
+            .with_extensions(&["epub"])
+            .with_mime_types(&["application/epub+zip"])
+            .with_signature(ZIP_SIGNATURE)
+            .with_metadata(&[BlockMeta::CHAPTER]), epub::parse)
+            .with_sniff(|head| archive_mimetype(head).is_some_and(|mimetype| mimetype.starts_with(EPUB_MIMETYPE))),
+    ];
+    loaders.into_iter().fold(LoaderRegistry::new(), LoaderRegistry::with_loader)
+}
+
+/// The type an OpenDocument or EPUB archive names in the uncompressed `mimetype` entry it
+/// must begin with
+fn archive_mimetype(head: &[u8]) -> Option<&[u8]> {
+    // The first local file header is 30 bytes, followed by the entry's name and data
+    let named = head.starts_with(ZIP_SIGNATURE) && head.get(30..38) == Some(b"mimetype".as_slice());
+    named.then(|| &head[38..])
+}
+
+fn contains(head: &[u8], needle: &[u8]) -> bool {
+    head.starts_with(ZIP_SIGNATURE) && head.windows(needle.len()).any(|window| window == needle)
+}
+
+/// WebVTT files must open with "WEBVTT" followed by whitespace or nothing
+fn is_webvtt(head: &[u8]) -> bool {
+    head.strip_prefix(b"WEBVTT")
+        .is_some_and(|rest| rest.first().is_none_or(u8::is_ascii_whitespace))
+}
+
+/// Whether text opens like an HTML document, with a doctype or `<html>` tag
+fn is_html(head: &[u8]) -> bool {
+    let head = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+    let start = head.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(head.len());
+    let opening = head[start..].iter().take(14).map(u8::to_ascii_lowercase).collect::<Vec<_>>();
+    opening.starts_with(b"<!doctype html") || opening.starts_with(b"<html")
+}
@@ -0,0 +1,133 @@
+//! Chat logs exported as JSON: a list of messages, or an object holding one under
+//! `"messages"`. Exports differ in what they call a message's fields, so each is looked up
+//! under the names Telegram, Discord, Slack and most hand-rolled exports use.
+
+use app_core::domain::{BlockMeta, FileError, FileId, TextBlock};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::Value;
+
+/// Slack's `user` is an id, so the display name in its `user_profile` comes first
+const AUTHOR_FIELDS: [&str; 6] = ["author", "from", "sender", "user_name", "user_profile", "user"];
+const TEXT_FIELDS: [&str; 4] = ["text", "content", "body", "message"];
+const TIME_FIELDS: [&str; 6] = ["timestamp", "date", "time", "ts", "sent", "created_at"];
+const THREAD_FIELDS: [&str; 5] = ["thread", "thread_id", "thread_ts", "conversation", "conversation_id"];
+/// Fields naming the whole log, the thread of messages that don't name their own
+const LOG_NAME_FIELDS: [&str; 3] = ["name", "title", "channel"];
+/// Epoch timestamps above this are taken to be in milliseconds
+const MAX_EPOCH_SECONDS: f64 = 1e11;
+
+/// Parses a chat log into one block per message, carrying its [`BlockMeta::AUTHOR`],
+/// [`BlockMeta::SENT`] and [`BlockMeta::THREAD`]. Messages without text, such as joins or
+/// shared files, are left out.
+pub fn parse(file_id: FileId, json: &str) -> Result<Vec<TextBlock>, FileError> {
+    let log: Value = serde_json::from_str(json)
+        .map_err(|e| FileError::Parse(format!("Malformed JSON: {}", e)))?;
+    let (messages, log_name) = match &log {
+        Value::Array(messages) => (messages, None),
+        Value::Object(fields) => match fields.get("messages") {
+            Some(Value::Array(messages)) => (messages, LOG_NAME_FIELDS.iter().find_map(|&field| string(fields.get(field)?))),
+            _ => return Err(FileError::Parse("Not a chat log, it has no \"messages\" list".to_string())),
+        },
+        _ => return Err(FileError::Parse("Not a chat log, expected a list of messages".to_string())),
+    };
+
+    let mut blocks = Vec::new();
+    for message in messages {
+        let Value::Object(fields) = message else {
+            return Err(FileError::Parse(format!("Message {} is not an object", blocks.len() + 1)));
+        };
+        let text = TEXT_FIELDS.iter().find_map(|&field| text(fields.get(field)?)).unwrap_or_default();
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let mut block = TextBlock::new(file_id, blocks.len(), text.trim().replace("\r\n", "\n"));
+        if let Some(author) = AUTHOR_FIELDS.iter().find_map(|&field| name(fields.get(field)?)) {
+            block = block.with_attribute(BlockMeta::AUTHOR, author);
+        }
+        if let Some(sent) = TIME_FIELDS.iter().find_map(|&field| timestamp(fields.get(field)?)) {
+            block = block.with_sent(sent);
+        }
+        if let Some(thread) = THREAD_FIELDS.iter().find_map(|&field| string(fields.get(field)?)).or_else(|| log_name.clone()) {
+            block = block.with_attribute(BlockMeta::THREAD, thread);
+        }
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+/// Whether the start of a JSON file looks like a chat log: a list of messages, on its own or
+/// under `"messages"`, whose first message names its author and when it was sent. Only the
+/// head of the file is seen, so this looks for the keys rather than parsing it.
+pub fn looks_like_chat(head: &str) -> bool {
+    let head = head.trim_start_matches('\u{FEFF}').trim_start();
+    let messages = match head.starts_with('{') {
+        true => after_key(head, "messages").and_then(|value| value.strip_prefix('[')),
+        false => head.strip_prefix('['),
+    };
+    let Some(first) = messages.and_then(|messages| messages.trim_start().strip_prefix('{')) else {
+        return false;
+    };
+    AUTHOR_FIELDS.iter().any(|&field| after_key(first, field).is_some())
+        && TIME_FIELDS.iter().any(|&field| after_key(first, field).is_some())
+}
+
+/// The JSON text following the first `"key":`, from the start of its value
+fn after_key<'a>(json: &'a str, key: &str) -> Option<&'a str> {
+    let quoted = format!("\"{}\"", key);
+    json.match_indices(&quoted)
+        .find_map(|(i, _)| json[i + quoted.len()..].trim_start().strip_prefix(':'))
+        .map(str::trim_start)
+}
+
+/// A non-empty string or number as text
+fn string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// A person named by a string, or by an object like Discord's `{"name": ..., "nickname": ...}`
+fn name(value: &Value) -> Option<String> {
+    match value {
+        Value::Object(fields) => ["nickname", "real_name", "display_name", "name", "username"].iter()
+            .find_map(|&field| string(fields.get(field)?)),
+        value => string(value),
+    }
+}
+
+/// Message text, which Telegram writes as a list of plain strings and formatted pieces
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(pieces) => Some(pieces.iter().filter_map(|piece| match piece {
+            Value::String(s) => Some(s.as_str()),
+            Value::Object(fields) => fields.get("text").and_then(Value::as_str),
+            _ => None,
+        }).collect()),
+        _ => None,
+    }
+}
+
+/// An RFC 3339 date, a date and time without zone taken as UTC, or seconds or milliseconds
+/// since the epoch, as a number or a string like Slack's `"1700000000.000200"`
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    let epoch = |seconds: f64| {
+        let seconds = if seconds > MAX_EPOCH_SECONDS { seconds / 1000.0 } else { seconds };
+        DateTime::from_timestamp_millis((seconds * 1000.0).round() as i64)
+    };
+    match value {
+        Value::Number(n) => epoch(n.as_f64()?),
+        Value::String(s) => {
+            let s = s.trim();
+            DateTime::parse_from_rfc3339(s).map(|at| at.with_timezone(&Utc)).ok()
+                .or_else(|| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f").ok().map(|at| at.and_utc()))
+                .or_else(|| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").ok().map(|at| at.and_utc()))
+                .or_else(|| s.parse::<f64>().ok().and_then(epoch))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,238 @@
+use app_core::domain::{BlockKind, FileError, FileId, TextBlock};
+use crate::loaders::office::{attribute, named_heading, open_archive, read_entry, xml_error, BlockCollector};
+
+use std::collections::{HashMap, HashSet};
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+
+const DOCUMENT: &str = "word/document.xml";
+const STYLES: &str = "word/styles.xml";
+const NUMBERING: &str = "word/numbering.xml";
+
+/// Extracts the body of a Word document as one block per paragraph or table row.
+///
+/// Heading styles become [`BlockKind::Heading`] and numbered or bulleted paragraphs
+/// [`BlockKind::ListItem`]. Headers, footers, footnotes, comments, text boxes and deleted
+/// tracked changes are left out, inserted tracked changes are kept.
+pub fn parse(file_id: FileId, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+    let mut archive = open_archive(bytes)?;
+    let document = read_entry(&mut archive, DOCUMENT)?
+        .ok_or_else(|| FileError::Parse("Not a Word document, it has no word/document.xml".to_string()))?;
+    let styles = match read_entry(&mut archive, STYLES)? {
+        Some(xml) => read_styles(&xml)?,
+        None => Styles::default(),
+    };
+    let numbering = match read_entry(&mut archive, NUMBERING)? {
+        Some(xml) => read_numbering(&xml)?,
+        None => Numbering::default(),
+    };
+
+    read_body(file_id, &document, &styles, &numbering)
+}
+
+#[derive(Default)]
+struct Styles {
+    /// Heading level of each paragraph style that is a heading
+    headings: HashMap<String, u8>,
+    /// Styles that make their text bold
+    bold: HashSet<String>,
+}
+
+/// Whether each list level is numbered rather than bulleted
+#[derive(Default)]
+struct Numbering {
+    /// `numId` to `abstractNumId`
+    instances: HashMap<String, String>,
+    ordered: HashMap<(String, u8), bool>,
+}
+
+impl Numbering {
+    fn is_ordered(&self, num_id: &str, level: u8) -> bool {
+        self.instances.get(num_id)
+            .and_then(|abstract_id| self.ordered.get(&(abstract_id.clone(), level)))
+            .copied()
+            .unwrap_or(false)
+    }
+}
+
+fn read_body(file_id: FileId, xml: &[u8], styles: &Styles, numbering: &Numbering) -> Result<Vec<TextBlock>, FileError> {
+    let mut reader = Reader::from_reader(xml);
+    let mut collector = BlockCollector::new(file_id);
+    let mut paragraph = ParagraphProperties::default();
+    let (mut in_properties, mut in_run, mut in_text, mut bold) = (false, false, false, false);
+
+    loop {
+        let event = reader.read_event().map_err(|e| xml_error(DOCUMENT, e))?;
+        let empty = matches!(event, Event::Empty(_));
+        match event {
+            Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                b"p" => {
+                    collector.start_paragraph();
+                    paragraph = ParagraphProperties::default();
+                    if empty {
+                        collector.end_paragraph(BlockKind::Paragraph);
+                    }
+                }
+                b"pPr" => in_properties = !empty,
+                b"pStyle" if in_properties => paragraph.heading = attribute(&element, b"val")
+                    .and_then(|style| styles.headings.get(&style).copied())
+                    .or(paragraph.heading),
+                b"outlineLvl" if in_properties => {
+                    if let Some(level) = attribute(&element, b"val").and_then(|l| outline_heading(&l)) {
+                        paragraph.heading = Some(level);
+                    }
+                }
+                b"numId" if in_properties => paragraph.num_id = attribute(&element, b"val"),
+                b"ilvl" if in_properties => paragraph.level = attribute(&element, b"val").and_then(|l| l.parse().ok()).unwrap_or(0),
+                b"r" => {
+                    in_run = !empty;
+                    bold = false;
+                }
+                b"rStyle" if in_run && styles.bold.contains(&attribute(&element, b"val").unwrap_or_default()) => bold = true,
+                b"b" if in_run && !in_properties => bold = is_on(&element),
+                b"t" => in_text = !empty,
+                b"tab" if in_run && !in_properties => collector.push("\t", false),
+                b"br" | b"cr" if in_run => collector.push("\n", false),
+                b"noBreakHyphen" => collector.push("-", bold),
+                b"tbl" if !empty => collector.start_table(),
+                b"tr" if !empty => collector.start_row(),
+                b"tc" => collector.start_cell(),
+                // Text boxes and shapes hold paragraphs of their own, usually twice over for
+                // older readers
+                b"drawing" | b"pict" | b"object" | b"AlternateContent" if !empty => {
+                    reader.read_to_end(element.name()).map_err(|e| xml_error(DOCUMENT, e))?;
+                }
+                _ => {}
+            },
+            Event::End(element) => match element.local_name().as_ref() {
+                b"p" => collector.end_paragraph(paragraph.kind(numbering)),
+                b"pPr" => in_properties = false,
+                b"r" => in_run = false,
+                b"t" => in_text = false,
+                b"tbl" => collector.end_table(),
+                b"tr" => collector.end_row(),
+                _ => {}
+            },
+            Event::Text(text) if in_text => {
+                let text = text.unescape().map_err(|e| xml_error(DOCUMENT, e))?;
+                collector.push(&text, bold);
+            }
+            Event::CData(text) if in_text => collector.push(&String::from_utf8_lossy(&text), bold),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(collector.finish())
+}
+
+#[derive(Default)]
+struct ParagraphProperties {
+    heading: Option<u8>,
+    num_id: Option<String>,
+    level: u8,
+}
+
+impl ParagraphProperties {
+    fn kind(&self, numbering: &Numbering) -> BlockKind {
+        match (self.heading, self.num_id.as_deref()) {
+            (Some(level), _) => BlockKind::Heading(level),
+            // Numbering id 0 turns off numbering a paragraph style would otherwise add
+            (None, Some(num_id)) if num_id != "0" => BlockKind::ListItem {
+                ordered: numbering.is_ordered(num_id, self.level),
+                depth: self.level.saturating_add(1),
+            },
+            _ => BlockKind::Paragraph,
+        }
+    }
+}
+
+fn read_styles(xml: &[u8]) -> Result<Styles, FileError> {
+    let mut reader = Reader::from_reader(xml);
+    let mut styles = Styles::default();
+    let mut style: Option<String> = None;
+    let mut in_run_properties = false;
+
+    loop {
+        match reader.read_event().map_err(|e| xml_error(STYLES, e))? {
+            Event::Start(element) | Event::Empty(element) => {
+                let Some(id) = &style else {
+                    if element.local_name().as_ref() == b"style" {
+                        style = attribute(&element, b"styleId");
+                    }
+                    continue;
+                };
+                match element.local_name().as_ref() {
+                    b"name" => {
+                        if let Some(level) = attribute(&element, b"val").and_then(|name| named_heading(&name)) {
+                            styles.headings.insert(id.clone(), level);
+                        }
+                    }
+                    b"outlineLvl" => {
+                        if let Some(level) = attribute(&element, b"val").and_then(|l| outline_heading(&l)) {
+                            styles.headings.entry(id.clone()).or_insert(level);
+                        }
+                    }
+                    b"rPr" => in_run_properties = true,
+                    b"b" if in_run_properties && is_on(&element) => {
+                        styles.bold.insert(id.clone());
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(element) => match element.local_name().as_ref() {
+                b"style" => style = None,
+                b"rPr" => in_run_properties = false,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(styles)
+}
+
+fn read_numbering(xml: &[u8]) -> Result<Numbering, FileError> {
+    let mut reader = Reader::from_reader(xml);
+    let mut numbering = Numbering::default();
+    let (mut abstract_id, mut num_id, mut level) = (None::<String>, None::<String>, 0u8);
+
+    loop {
+        match reader.read_event().map_err(|e| xml_error(NUMBERING, e))? {
+            Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                b"abstractNum" => abstract_id = attribute(&element, b"abstractNumId"),
+                b"num" => num_id = attribute(&element, b"numId"),
+                b"lvl" => level = attribute(&element, b"ilvl").and_then(|l| l.parse().ok()).unwrap_or(0),
+                b"numFmt" => {
+                    if let (Some(id), Some(format)) = (&abstract_id, attribute(&element, b"val")) {
+                        numbering.ordered.insert((id.clone(), level), format != "bullet" && format != "none");
+                    }
+                }
+                b"abstractNumId" => {
+                    if let (Some(num), Some(id)) = (&num_id, attribute(&element, b"val")) {
+                        numbering.instances.insert(num.clone(), id);
+                    }
+                }
+                _ => {}
+            },
+            Event::End(element) => match element.local_name().as_ref() {
+                b"abstractNum" => abstract_id = None,
+                b"num" => num_id = None,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(numbering)
+}
+
+/// Outline levels count from 0, and 9 means body text
+fn outline_heading(level: &str) -> Option<u8> {
+    let level: u8 = level.parse().ok()?;
+    (level < 9).then(|| (level + 1).min(6))
+}
+
+/// Toggle properties like `<w:b/>` are on unless their value says otherwise
+fn is_on(element: &BytesStart) -> bool {
+    !matches!(attribute(element, b"val").as_deref(), Some("0" | "false" | "off" | "none"))
+}
@@ -0,0 +1,420 @@
+//! Email: single `.eml` messages and mbox mailboxes. MIME is decoded by hand, covering
+//! multipart bodies, base64, quoted-printable and RFC 2047 encoded headers, which is all a
+//! message's readable text needs. Attachments are left out.
+
+use app_core::domain::{BlockKind, BlockMeta, FileError, FileId, TextBlock};
+use crate::loaders::html;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use base64::Engine;
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use chrono::{DateTime, Utc};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+/// Mail clients wrap base64 at 76 columns and don't always pad it
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+/// How deep multiparts may nest before the rest is ignored
+const MAX_DEPTH: usize = 16;
+/// Reply prefixes stripped from subjects when threading by subject, compared in lowercase
+const REPLY_PREFIXES: [&str; 6] = ["re:", "fwd:", "fw:", "aw:", "wg:", "sv:"];
+
+/// Parses a message or mailbox into one block per message, carrying its
+/// [`BlockMeta::AUTHOR`], [`BlockMeta::SENT`], [`BlockMeta::SUBJECT`] and [`BlockMeta::THREAD`].
+///
+/// The text is the message's plain text part, or its HTML part when there is none, with
+/// quoted replies and the "On ... wrote:" lines introducing them stripped. Messages with
+/// nothing of their own to say, e.g. a bare forward, are left out.
+pub fn parse(file_id: FileId, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+    let messages = split_mbox(bytes);
+    if messages.iter().all(|message| message.iter().all(u8::is_ascii_whitespace)) {
+        return Err(FileError::Parse("No messages found".to_string()));
+    }
+
+    let mut blocks = Vec::new();
+    for raw in &messages {
+        let message = Part::parse(raw);
+        if message.headers.is_empty() {
+            return Err(FileError::Parse("A message has no headers, so this isn't an email".to_string()));
+        }
+        let text = strip_quoted(&message.text(0).unwrap_or_default());
+        if text.is_empty() {
+            continue;
+        }
+
+        let mut block = TextBlock::new(file_id, blocks.len(), text);
+        if let Some(author) = message.header("from").filter(|from| !from.is_empty()) {
+            block = block.with_attribute(BlockMeta::AUTHOR, author);
+        }
+        if let Some(sent) = message.header("date").and_then(|date| parse_date(&date)) {
+            block = block.with_sent(sent);
+        }
+        if let Some(subject) = message.header("subject").filter(|subject| !subject.is_empty()) {
+            block = block.with_attribute(BlockMeta::SUBJECT, subject);
+        }
+        if let Some(thread) = message.thread() {
+            block = block.with_attribute(BlockMeta::THREAD, thread);
+        }
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+/// Whether text opens like an mbox mailbox: a "From " separator line followed by a header
+pub fn looks_like_mbox(head: &[u8]) -> bool {
+    let mut lines = head.split(|&b| b == b'\n');
+    lines.next().is_some_and(|line| line.starts_with(b"From "))
+        && lines.next().is_some_and(|line| header_name(line).is_some())
+}
+
+/// The messages of an mbox mailbox, or the whole of a single message. Lines escaped as
+/// ">From " are unescaped, as mboxrd writes them.
+fn split_mbox(bytes: &[u8]) -> Vec<Cow<'_, [u8]>> {
+    if !bytes.starts_with(b"From ") {
+        return vec![Cow::Borrowed(bytes)];
+    }
+
+    let mut messages = Vec::new();
+    let mut current: Option<Vec<u8>> = None;
+    let mut after_blank = true;
+    for line in lines(bytes) {
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        if after_blank && content.starts_with(b"From ") {
+            messages.extend(current.take().map(Cow::Owned));
+            current = Some(Vec::new());
+        } else if let Some(message) = &mut current {
+            let quoted = line.iter().take_while(|&&b| b == b'>').count();
+            match quoted > 0 && line[quoted..].starts_with(b"From ") {
+                true => message.extend_from_slice(&line[1..]),
+                false => message.extend_from_slice(line),
+            }
+        }
+        after_blank = content.is_empty();
+    }
+    messages.extend(current.map(Cow::Owned));
+    messages
+}
+
+/// Lines of `bytes`, each with its line ending
+fn lines(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    bytes.split_inclusive(|&b| b == b'\n')
+}
+
+/// A message or one part of a multipart body
+struct Part<'a> {
+    /// Lowercased names and decoded values, in order
+    headers: Vec<(String, String)>,
+    body: &'a [u8],
+}
+
+impl<'a> Part<'a> {
+    /// Splits off the header section, which ends at the first empty line. Folded header
+    /// lines are joined back up.
+    fn parse(bytes: &'a [u8]) -> Self {
+        let mut raw: Vec<(String, Vec<u8>)> = Vec::new();
+        let mut offset = 0;
+        for line in lines(bytes) {
+            let content = line.trim_ascii_end();
+            if content.is_empty() {
+                offset += line.len();
+                break;
+            }
+            match (line.first(), raw.last_mut()) {
+                (Some(b' ' | b'\t'), Some((_, value))) => {
+                    value.push(b' ');
+                    value.extend_from_slice(content.trim_ascii_start());
+                }
+                _ => match header_name(content) {
+                    Some(name) => raw.push((name.to_ascii_lowercase(), content[name.len() + 1..].trim_ascii().to_vec())),
+                    // Not a header, so the body starts without a blank line
+                    None => break,
+                },
+            }
+            offset += line.len();
+        }
+
+        let headers = raw.into_iter().map(|(name, value)| (name, decode_words(&decode_8bit(&value, None)))).collect();
+        Part { headers, body: &bytes[offset..] }
+    }
+
+    fn header(&self, name: &str) -> Option<String> {
+        self.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.clone())
+    }
+
+    /// MIME type in lowercase, plain text if unstated, and its parameters
+    fn content_type(&self) -> (String, HashMap<String, String>) {
+        let value = self.header("content-type").unwrap_or_default();
+        let mut fields = split_params(&value).into_iter();
+        let mime = fields.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let params = fields.filter_map(|field| {
+            let (key, value) = field.split_once('=')?;
+            Some((key.trim().to_ascii_lowercase(), value.trim().trim_matches('"').to_string()))
+        }).collect();
+        (if mime.is_empty() { "text/plain".to_string() } else { mime }, params)
+    }
+
+    /// Readable text of this part, preferring plain text over HTML in alternatives
+    fn text(&self, depth: usize) -> Option<String> {
+        let disposition = self.header("content-disposition").unwrap_or_default().to_ascii_lowercase();
+        if depth > MAX_DEPTH || disposition.starts_with("attachment") {
+            return None;
+        }
+        let (mime, params) = self.content_type();
+
+        if mime.starts_with("multipart/") {
+            let parts: Vec<Part> = split_multipart(self.body, params.get("boundary")?).into_iter().map(Part::parse).collect();
+            if mime == "multipart/alternative" {
+                let plain = parts.iter().find(|part| part.content_type().0 == "text/plain");
+                // Otherwise the last one, which is the richest
+                return plain.into_iter().chain(parts.iter().rev()).find_map(|part| part.text(depth + 1));
+            }
+            let texts: Vec<String> = parts.iter().filter_map(|part| part.text(depth + 1)).filter(|text| !text.trim().is_empty()).collect();
+            return (!texts.is_empty()).then(|| texts.join("\n\n"));
+        }
+
+        let text = decode_8bit(&self.decoded_body(), params.get("charset").map(String::as_str));
+        match mime.as_str() {
+            "text/plain" => Some(text.replace("\r\n", "\n")),
+            "text/html" => Some(html::extract(&text).blocks.into_iter()
+                // Quoted replies, as mail clients write them in HTML
+                .filter(|(kind, _)| *kind != BlockKind::Quote)
+                .map(|(_, text)| text)
+                .collect::<Vec<_>>()
+                .join("\n\n")),
+            _ => None,
+        }
+    }
+
+    /// The body with its transfer encoding undone
+    fn decoded_body(&self) -> Cow<'a, [u8]> {
+        let encoding = self.header("content-transfer-encoding").unwrap_or_default().trim().to_ascii_lowercase();
+        match encoding.as_str() {
+            "base64" => {
+                let compact: Vec<u8> = self.body.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+                // A damaged attachment shouldn't lose the rest of the message
+                BASE64.decode(&compact).map(Cow::Owned).unwrap_or(Cow::Borrowed(self.body))
+            }
+            "quoted-printable" => Cow::Owned(decode_quoted_printable(self.body, false)),
+            _ => Cow::Borrowed(self.body),
+        }
+    }
+
+    /// The conversation a message belongs to: Gmail's thread id, the first message the reply
+    /// chain references, the message's own id, or failing those its subject without "Re:"
+    fn thread(&self) -> Option<String> {
+        let first_id = |name: &str| self.header(name).and_then(|value| {
+            let start = value.find('<')?;
+            let end = start + value[start..].find('>')?;
+            Some(value[start..=end].to_string())
+        });
+        self.header("x-gm-thrid")
+            .or_else(|| first_id("references"))
+            .or_else(|| first_id("in-reply-to"))
+            .or_else(|| first_id("message-id"))
+            .or_else(|| self.header("subject").map(|subject| base_subject(&subject)).filter(|subject| !subject.is_empty()))
+    }
+}
+
+/// The name of a header line like "Subject: ...", which may not contain spaces
+fn header_name(line: &[u8]) -> Option<&str> {
+    let colon = line.iter().position(|&b| b == b':')?;
+    let name = std::str::from_utf8(&line[..colon]).ok()?;
+    (!name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic())).then_some(name)
+}
+
+/// Splits a header value at semicolons outside quotes
+fn split_params(value: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let (mut start, mut quoted) = (0, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                fields.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(&value[start..]);
+    fields
+}
+
+/// The parts of a multipart body, between its "--boundary" lines
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut offset = 0;
+    for line in lines(body) {
+        let content = line.trim_ascii_end();
+        if content.starts_with(delimiter.as_bytes()) {
+            if let Some(start) = start {
+                // The line break before a delimiter belongs to it
+                let part = &body[start..offset];
+                let part = part.strip_suffix(b"\n").unwrap_or(part);
+                parts.push(part.strip_suffix(b"\r").unwrap_or(part));
+            }
+            if content[delimiter.len()..].starts_with(b"--") {
+                return parts;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    // An unterminated last part still counts
+    parts.extend(start.map(|start| &body[start..]));
+    parts
+}
+
+/// Decodes text in `charset`, or as UTF-8 falling back to Windows-1252 when undeclared
+fn decode_8bit(bytes: &[u8], charset: Option<&str>) -> String {
+    let declared = charset.and_then(|label| Encoding::for_label(label.trim().as_bytes()));
+    match declared {
+        Some(encoding) => encoding.decode_without_bom_handling(bytes).0.into_owned(),
+        None => match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => WINDOWS_1252.decode_without_bom_handling(bytes).0.into_owned(),
+        },
+    }
+}
+
+/// Quoted-printable: "=XX" is a byte and "=" at the end of a line joins it to the next.
+/// In encoded words `underscores` stand for spaces.
+fn decode_quoted_printable(bytes: &[u8], underscores: bool) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'=' => {
+                let rest = &bytes[i + 1..];
+                let soft_break = rest.iter().take_while(|&&b| b == b' ' || b == b'\t').count();
+                if rest[soft_break..].starts_with(b"\r\n") {
+                    i += 1 + soft_break + 2;
+                } else if rest[soft_break..].starts_with(b"\n") {
+                    i += 1 + soft_break + 1;
+                } else if let Some(byte) = rest.get(..2).and_then(|hex| std::str::from_utf8(hex).ok()).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    decoded.push(byte);
+                    i += 3;
+                } else {
+                    decoded.push(b'=');
+                    i += 1;
+                }
+            }
+            b'_' if underscores => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Decodes RFC 2047 encoded words like `=?utf-8?Q?Caf=C3=A9?=` in a header value. Whitespace
+/// between two encoded words is dropped, as the standard says.
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match encoded_word(candidate) {
+            Some((text, length)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    decoded.push_str(before);
+                }
+                decoded.push_str(&text);
+                rest = &candidate[length..];
+                after_word = true;
+            }
+            None => {
+                decoded.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded.trim().to_string()
+}
+
+/// The text of the encoded word `word` starts with, and its length
+fn encoded_word(word: &str) -> Option<(String, usize)> {
+    let inner = word.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let payload = &inner[..end];
+    if payload.contains(char::is_whitespace) {
+        return None;
+    }
+    let bytes = match encoding {
+        "B" | "b" => BASE64.decode(payload).ok()?,
+        "Q" | "q" => decode_quoted_printable(payload.as_bytes(), true),
+        _ => return None,
+    };
+    // Language tags ride along as "charset*lang"
+    let label = charset.split('*').next().unwrap_or_default();
+    let decoder = Encoding::for_label(label.as_bytes()).unwrap_or(UTF_8);
+    let length = "=?".len() + charset.len() + 1 + encoding.len() + 1 + end + "?=".len();
+    Some((decoder.decode_without_bom_handling(&bytes).0.into_owned(), length))
+}
+
+/// The date of a message. Trailing comments like "(UTC)" are ignored.
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    let value = match value.find('(') {
+        Some(comment) => &value[..comment],
+        None => value,
+    };
+    DateTime::parse_from_rfc2822(value.trim()).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// A subject without its "Re:" and "Fwd:" prefixes
+fn base_subject(subject: &str) -> String {
+    let mut subject = subject.trim();
+    while let Some(prefix) = REPLY_PREFIXES.iter().find(|prefix| subject.get(..prefix.len()).is_some_and(|head| head.eq_ignore_ascii_case(prefix))) {
+        subject = subject[prefix.len()..].trim_start();
+    }
+    subject.to_string()
+}
+
+/// Removes quoted replies: lines starting with ">", and everything from a reply header like
+/// "On Mon, Jane wrote:", "-----Original Message-----" or Outlook's "From: ... Sent: ..." on
+fn strip_quoted(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let cut = (0..lines.len()).find(|&i| starts_reply(&lines[i..])).unwrap_or(lines.len());
+
+    let mut kept: Vec<&str> = Vec::new();
+    for line in &lines[..cut] {
+        if line.starts_with('>') || (line.is_empty() && kept.last().is_none_or(|last| last.is_empty())) {
+            continue;
+        }
+        kept.push(line);
+    }
+    while kept.last().is_some_and(|line| line.is_empty()) {
+        kept.pop();
+    }
+    kept.join("\n")
+}
+
+/// Whether `lines` start with the header a mail client puts above a quoted message
+fn starts_reply(lines: &[&str]) -> bool {
+    let line = lines[0].trim();
+    let next = lines.get(1).map_or("", |next| next.trim());
+    let attribution = line.starts_with("On ") && (line.ends_with("wrote:") || (!line.contains("wrote") && next.ends_with("wrote:")));
+    let original = line.starts_with("-----") && line.to_ascii_lowercase().contains("original message");
+    let outlook = line.starts_with("From: ")
+        && lines[1..].iter().take(3).any(|line| line.starts_with("Sent: ") || line.starts_with("Date: "));
+    attribution || original || outlook
+}
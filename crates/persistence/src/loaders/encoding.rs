@@ -0,0 +1,97 @@
+use app_core::domain::FileError;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+/// Decodes a text file whose encoding isn't declared anywhere.
+///
+/// A byte order mark decides if there is one. Otherwise the bytes are read as UTF-16 if their
+/// NUL pattern looks like it, then tried as UTF-8, and finally as Latin-1. Windows-1252 is used for
+/// that, the superset Windows actually writes, so curly quotes come out right. Latin-1 accepts any
+/// bytes, so content that still has NULs in it is rejected as binary rather than decoded.
+pub fn decode_text(bytes: &[u8]) -> Result<String, FileError> {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return String::from_utf8(rest.to_vec())
+            .map_err(|e| FileError::Encoding(format!("Invalid UTF-8 after byte order mark: {}", e)));
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        return decode_utf16(UTF_16LE, rest);
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        return decode_utf16(UTF_16BE, rest);
+    }
+
+    // First, since UTF-16 with only ASCII in it is also valid UTF-8
+    if let Some(encoding) = sniff_utf16(bytes) {
+        return decode_utf16(encoding, bytes);
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok(text.to_string());
+    }
+    if bytes.contains(&0) {
+        return Err(FileError::Encoding("Contains NUL bytes, so it is binary or in an unknown encoding".to_string()));
+    }
+    Ok(WINDOWS_1252.decode_without_bom_handling(bytes).0.into_owned())
+}
+
+fn decode_utf16(encoding: &'static Encoding, bytes: &[u8]) -> Result<String, FileError> {
+    encoding.decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|text| text.into_owned())
+        .ok_or_else(|| FileError::Encoding(format!("Invalid {}", encoding.name())))
+}
+
+/// UTF-16 without a byte order mark, recognised by mostly ASCII text leaving every other byte NUL.
+/// Works on a file's leading bytes too, so type detection can use it.
+pub fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let units = bytes.len() / 2;
+    if units == 0 {
+        return None;
+    }
+    let nul_at = |parity: usize| bytes.iter().skip(parity).step_by(2).take(units).filter(|b| **b == 0).count();
+    let (even, odd) = (nul_at(0), nul_at(1));
+
+    // Most units of Latin script text have a NUL high byte, none should have a NUL low byte
+    if odd * 2 > units && even == 0 {
+        Some(UTF_16LE)
+    } else if even * 2 > units && odd == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// The encoding for a Windows code page number, as RTF and other older formats declare them.
+/// DOS code pages other than 866 have no WHATWG encoding and give None.
+pub fn for_code_page(code_page: u32) -> Option<&'static Encoding> {
+    let encoding = match code_page {
+        866 => encoding_rs::IBM866,
+        874 => encoding_rs::WINDOWS_874,
+        932 => encoding_rs::SHIFT_JIS,
+        936 => encoding_rs::GBK,
+        949 => encoding_rs::EUC_KR,
+        950 => encoding_rs::BIG5,
+        1200 => UTF_16LE,
+        1201 => UTF_16BE,
+        1250 => encoding_rs::WINDOWS_1250,
+        1251 => encoding_rs::WINDOWS_1251,
+        1252 => WINDOWS_1252,
+        1253 => encoding_rs::WINDOWS_1253,
+        1254 => encoding_rs::WINDOWS_1254,
+        1255 => encoding_rs::WINDOWS_1255,
+        1256 => encoding_rs::WINDOWS_1256,
+        1257 => encoding_rs::WINDOWS_1257,
+        1258 => encoding_rs::WINDOWS_1258,
+        10000 => encoding_rs::MACINTOSH,
+        10007 => encoding_rs::X_MAC_CYRILLIC,
+        20866 => encoding_rs::KOI8_R,
+        21866 => encoding_rs::KOI8_U,
+        28591 => WINDOWS_1252,
+        28592 => encoding_rs::ISO_8859_2,
+        28595 => encoding_rs::ISO_8859_5,
+        28597 => encoding_rs::ISO_8859_7,
+        28605 => encoding_rs::ISO_8859_15,
+        51932 => encoding_rs::EUC_JP,
+        54936 => encoding_rs::GB18030,
+        65001 => encoding_rs::UTF_8,
+        _ => return None,
+    };
+    Some(encoding)
+}
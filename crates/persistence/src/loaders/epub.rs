@@ -0,0 +1,227 @@
+//! EPUB books: a zip archive whose package document lists the chapters, in reading order
+//! in its spine. Each chapter is XHTML and is read like any other page.
+
+use app_core::domain::{BlockKind, BlockMeta, FileError, FileId, TextBlock};
+use crate::loaders::html;
+use crate::loaders::office::{attribute, open_archive, read_entry, xml_error, Archive};
+
+use std::collections::HashMap;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use scraper::{Html, Selector};
+
+const CONTAINER: &str = "META-INF/container.xml";
+
+/// Extracts a book's chapters in spine order as blocks carrying their [`BlockMeta::CHAPTER`].
+///
+/// Chapter titles come from the book's table of contents, or failing that the chapter's first
+/// heading or `<title>`. Spine items marked `linear="no"`, such as pop-up footnotes, and the
+/// table of contents page itself are left out.
+pub fn parse(file_id: FileId, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+    let mut archive = open_archive(bytes)?;
+    let container = read_entry(&mut archive, CONTAINER)?
+        .ok_or_else(|| FileError::Parse(format!("Not an EPUB, it has no {}", CONTAINER)))?;
+    let package_path = rootfile(&container)?;
+    let package = read_entry(&mut archive, &package_path)?
+        .ok_or_else(|| FileError::Parse(format!("The package document {} is missing", package_path)))?;
+    let package = Package::read(&package, &package_path)?;
+    let titles = toc_titles(&mut archive, &package)?;
+
+    let mut blocks = Vec::new();
+    for path in &package.spine {
+        if package.nav.as_ref() == Some(path) {
+            continue;
+        }
+        let xhtml = read_entry(&mut archive, path)?
+            .ok_or_else(|| FileError::Parse(format!("Chapter {} is missing", path)))?;
+        let page = html::extract(&html::decode(&xhtml)?);
+        let chapter = titles.get(path).cloned()
+            .or_else(|| page.blocks.iter().find(|(kind, _)| matches!(kind, BlockKind::Heading(_))).map(|(_, text)| text.clone()))
+            .or(page.title);
+
+        for (kind, content) in page.blocks {
+            let block = TextBlock::new(file_id, blocks.len(), content).with_kind(kind);
+            blocks.push(match &chapter {
+                Some(chapter) => block.with_attribute(BlockMeta::CHAPTER, chapter.clone()),
+                None => block,
+            });
+        }
+    }
+    Ok(blocks)
+}
+
+/// Path of the package document named by `META-INF/container.xml`
+fn rootfile(container: &[u8]) -> Result<String, FileError> {
+    let mut reader = Reader::from_reader(container);
+    loop {
+        match reader.read_event().map_err(|e| xml_error(CONTAINER, e))? {
+            Event::Start(element) | Event::Empty(element) if element.local_name().as_ref() == b"rootfile" => {
+                if let Some(path) = attribute(&element, b"full-path") {
+                    return Ok(path);
+                }
+            }
+            Event::Eof => return Err(FileError::Parse(format!("{} names no package document", CONTAINER))),
+            _ => {}
+        }
+    }
+}
+
+/// What the package document says about the book. Paths are archive paths, resolved
+/// against the package document's folder.
+#[derive(Default)]
+struct Package {
+    /// Chapters in reading order
+    spine: Vec<String>,
+    /// The EPUB 3 navigation document
+    nav: Option<String>,
+    /// The EPUB 2 table of contents
+    ncx: Option<String>,
+}
+
+impl Package {
+    fn read(xml: &[u8], path: &str) -> Result<Self, FileError> {
+        let mut reader = Reader::from_reader(xml);
+        // Manifest id to path and media type
+        let mut manifest: HashMap<String, (String, String)> = HashMap::new();
+        let mut spine: Vec<String> = Vec::new();
+        let mut package = Package::default();
+        let mut toc_id = None;
+
+        loop {
+            match reader.read_event().map_err(|e| xml_error(path, e))? {
+                Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                    b"item" => {
+                        let (Some(id), Some(href)) = (attribute(&element, b"id"), attribute(&element, b"href")) else { continue };
+                        let href = resolve(path, &href);
+                        if attribute(&element, b"properties").is_some_and(|p| p.split_whitespace().any(|p| p == "nav")) {
+                            package.nav = Some(href.clone());
+                        }
+                        manifest.insert(id, (href, attribute(&element, b"media-type").unwrap_or_default()));
+                    }
+                    b"spine" => toc_id = attribute(&element, b"toc"),
+                    b"itemref" if attribute(&element, b"linear").as_deref() != Some("no") => {
+                        if let Some(id) = attribute(&element, b"idref") {
+                            spine.push(id);
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        package.spine = spine.iter()
+            .filter_map(|id| manifest.get(id))
+            .map(|(href, _)| href.clone())
+            .collect();
+        package.ncx = toc_id.and_then(|id| manifest.get(&id))
+            .or_else(|| manifest.values().find(|(_, media_type)| media_type == "application/x-dtbncx+xml"))
+            .map(|(href, _)| href.clone());
+        if package.spine.is_empty() {
+            return Err(FileError::Parse("The book's spine lists no chapters".to_string()));
+        }
+        Ok(package)
+    }
+}
+
+/// Chapter titles from the table of contents, keyed by chapter path. Where a chapter has
+/// several entries, e.g. for its sections, the first is its title.
+fn toc_titles(archive: &mut Archive, package: &Package) -> Result<HashMap<String, String>, FileError> {
+    let mut titles = HashMap::new();
+    if let Some(nav) = &package.nav
+        && let Some(xhtml) = read_entry(archive, nav)?
+    {
+        let document = Html::parse_document(&html::decode(&xhtml)?);
+        let navs: Vec<_> = document.select(&Selector::parse("nav").expect("valid selector")).collect();
+        // The toc is one of several navs, and says so in its epub:type
+        let toc = navs.iter()
+            .find(|nav| nav.value().attrs().any(|(name, value)| name.ends_with("type") && value.split_whitespace().any(|v| v == "toc")))
+            .or(navs.first());
+        let links = Selector::parse("a[href]").expect("valid selector");
+        for link in toc.into_iter().flat_map(|toc| toc.select(&links)) {
+            let title = link.text().collect::<Vec<_>>().join(" ");
+            let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+            if let Some(href) = link.attr("href") && !title.is_empty() {
+                titles.entry(resolve(nav, href)).or_insert(title);
+            }
+        }
+    }
+    if titles.is_empty()
+        && let Some(ncx) = &package.ncx
+        && let Some(xml) = read_entry(archive, ncx)?
+    {
+        read_ncx(&xml, ncx, &mut titles)?;
+    }
+    Ok(titles)
+}
+
+/// Reads an EPUB 2 `toc.ncx`, whose navPoints each hold a label and the chapter it points to
+fn read_ncx(xml: &[u8], path: &str, titles: &mut HashMap<String, String>) -> Result<(), FileError> {
+    let mut reader = Reader::from_reader(xml);
+    let (mut in_label, mut label) = (false, String::new());
+    loop {
+        match reader.read_event().map_err(|e| xml_error(path, e))? {
+            Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                b"navLabel" => {
+                    in_label = true;
+                    label.clear();
+                }
+                b"content" => {
+                    let title = label.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if let Some(src) = attribute(&element, b"src") && !title.is_empty() {
+                        titles.entry(resolve(path, &src)).or_insert(title);
+                    }
+                }
+                _ => {}
+            },
+            Event::End(element) if element.local_name().as_ref() == b"navLabel" => in_label = false,
+            Event::Text(text) if in_label => label.push_str(&text.unescape().map_err(|e| xml_error(path, e))?),
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Archive path of `href` as written in the document at archive path `from`. The fragment
+/// is dropped and percent escapes decoded.
+fn resolve(from: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut parts: Vec<&str> = from.split('/').collect();
+    // The document's own name
+    parts.pop();
+    let decoded = percent_decode(href);
+    for part in decoded.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,272 @@
+//! HTML pages saved from a browser, and the XHTML chapters of EPUB books. Navigation,
+//! scripts, styles and other page furniture are dropped, and what's left becomes one block
+//! per heading, paragraph, list item or table row.
+
+use app_core::domain::{BlockKind, BlockMeta, FileError, FileId, TextBlock};
+use crate::loaders::encoding;
+
+use encoding_rs::Encoding;
+use scraper::{ElementRef, Html, Node, Selector};
+
+/// How far into a file we look for a `<meta charset>` declaration, as browsers do
+const CHARSET_SNIFF_LEN: usize = 1024;
+/// Browsers mark saved pages with a comment like `<!-- saved from url=(0023)https://... -->`
+const SAVED_FROM: &str = "<!-- saved from url=(";
+
+/// Elements that never hold the page's text
+const SKIPPED: [&str; 13] = [
+    "head", "script", "style", "noscript", "template", "nav", "svg", "math",
+    "iframe", "object", "canvas", "button", "select",
+];
+/// Elements that start a new block. Headings, list items, quotes, code and table rows are
+/// handled on their own.
+const BLOCKS: [&str; 24] = [
+    "p", "div", "section", "article", "main", "header", "footer", "aside", "address",
+    "figure", "figcaption", "details", "summary", "dl", "dt", "dd", "table", "thead",
+    "tbody", "tfoot", "caption", "form", "fieldset", "center",
+];
+/// ARIA roles of page furniture
+const FURNITURE_ROLES: [&str; 4] = ["navigation", "banner", "contentinfo", "search"];
+
+/// The readable content of a page, with what it says about itself
+pub struct Page {
+    /// The page's `<title>`
+    pub title: Option<String>,
+    /// Where the page was saved from, if it says
+    pub url: Option<String>,
+    pub blocks: Vec<(BlockKind, String)>,
+}
+
+/// Parses an HTML page into blocks, each carrying the page's [`BlockMeta::URL`] if known
+pub fn parse(file_id: FileId, html: &str) -> Vec<TextBlock> {
+    let page = extract(html);
+    page.blocks.into_iter().enumerate().map(|(i, (kind, content))| {
+        let block = TextBlock::new(file_id, i, content).with_kind(kind);
+        match &page.url {
+            Some(url) => block.with_attribute(BlockMeta::URL, url.clone()),
+            None => block,
+        }
+    }).collect()
+}
+
+/// Decodes an HTML file, going by its byte order mark or `<meta charset>` before guessing.
+/// A declaration the bytes don't match is ignored.
+pub fn decode(bytes: &[u8]) -> Result<String, FileError> {
+    let declared = if bytes.starts_with(b"\xEF\xBB\xBF") || bytes.starts_with(b"\xFF\xFE") || bytes.starts_with(b"\xFE\xFF") {
+        None
+    } else {
+        declared_charset(&bytes[..bytes.len().min(CHARSET_SNIFF_LEN)])
+    };
+    match declared {
+        // UTF-16 can't be declared from inside the file, a declaration saying so is wrong
+        Some(encoding) if encoding.is_single_byte() || encoding == encoding_rs::UTF_8 => {
+            match encoding.decode_without_bom_handling(bytes) {
+                (text, false) => Ok(text.into_owned()),
+                // Pages are often re-saved without their declaration being updated
+                (_, true) => encoding::decode_text(bytes),
+            }
+        }
+        _ => encoding::decode_text(bytes),
+    }
+}
+
+/// The `charset` a `<meta>` tag declares, e.g. `<meta charset="utf-8">` or the older
+/// `<meta http-equiv="Content-Type" content="text/html; charset=windows-1252">`
+fn declared_charset(head: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+    let at = head.find("<meta")?;
+    let at = at + head[at..].find("charset=")? + "charset=".len();
+    let label: String = head[at..].trim_start_matches(['"', '\''])
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+        .collect();
+    Encoding::for_label(label.as_bytes())
+}
+
+/// Reads the content of a page. When the page marks its main content with `<main>`, or has
+/// a single `<article>`, only that is read, leaving out the site's sidebars and footers.
+pub fn extract(html: &str) -> Page {
+    let document = Html::parse_document(html);
+    let select = |selector: &str| document.select(&Selector::parse(selector).expect("valid selector")).collect::<Vec<_>>();
+
+    let title = select("title").first()
+        .map(|title| collapse(&title.text().collect::<String>()))
+        .filter(|title| !title.is_empty());
+    let url = source_url(&document, html);
+
+    let articles = select("article");
+    let content = select("main, [role=main]").first().copied()
+        .or_else(|| (articles.len() == 1).then(|| articles[0]))
+        .unwrap_or_else(|| document.root_element());
+    // Within the chosen content, headers and footers belong to the text
+    let mut extractor = Extractor { in_content: usize::from(content.value().name() != "html"), ..Extractor::default() };
+    extractor.walk(content, BlockKind::Paragraph);
+    extractor.flush(BlockKind::Paragraph);
+
+    Page { title, url, blocks: extractor.blocks }
+}
+
+/// The address a page was saved from: its canonical link, Open Graph URL, the browser's
+/// "saved from" comment or its base URL, whichever comes first
+fn source_url(document: &Html, html: &str) -> Option<String> {
+    let attribute = |selector: &str, name: &str| {
+        document.select(&Selector::parse(selector).expect("valid selector"))
+            .find_map(|element| element.attr(name).map(str::trim).filter(|url| is_web_url(url)).map(str::to_string))
+    };
+    let saved_from = || {
+        let rest = &html[html.find(SAVED_FROM)? + SAVED_FROM.len()..];
+        let url = rest[rest.find(')')? + 1..].split_whitespace().next()?;
+        is_web_url(url).then(|| url.to_string())
+    };
+
+    attribute("link[rel=canonical]", "href")
+        .or_else(|| attribute("meta[property=\"og:url\"]", "content"))
+        .or_else(saved_from)
+        .or_else(|| attribute("base", "href"))
+}
+
+fn is_web_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+#[derive(Default)]
+struct Extractor {
+    blocks: Vec<(BlockKind, String)>,
+    text: String,
+    /// Whether each enclosing list is numbered, innermost last
+    lists: Vec<bool>,
+    /// Depth of `<pre>` elements, where whitespace is kept
+    pre: usize,
+    /// Depth of `<main>` and `<article>` elements, outside which headers, footers and asides are skipped
+    in_content: usize,
+}
+
+impl Extractor {
+    /// Reads the children of `element`, whose text becomes blocks of `kind` unless a child
+    /// starts a block of its own
+    fn walk(&mut self, element: ElementRef, kind: BlockKind) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.push(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(child, kind);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn element(&mut self, element: ElementRef, kind: BlockKind) {
+        let name = element.value().name();
+        if self.is_furniture(element, name) {
+            return;
+        }
+        match name {
+            "br" => self.text.push('\n'),
+            "hr" => self.flush(kind),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name.as_bytes()[1] - b'0';
+                self.block(element, kind, BlockKind::Heading(level));
+            }
+            "ul" | "ol" | "menu" => {
+                self.flush(kind);
+                self.lists.push(name == "ol");
+                self.walk(element, kind);
+                self.flush(kind);
+                self.lists.pop();
+            }
+            "li" => {
+                let depth = self.lists.len().clamp(1, u8::MAX as usize) as u8;
+                let ordered = self.lists.last().copied().unwrap_or(false);
+                self.block(element, kind, BlockKind::ListItem { ordered, depth });
+            }
+            "blockquote" => self.block(element, kind, BlockKind::Quote),
+            "pre" => {
+                self.pre += 1;
+                self.block(element, kind, BlockKind::Code);
+                self.pre -= 1;
+            }
+            "tr" => {
+                self.flush(kind);
+                self.row(element);
+            }
+            "main" | "article" => {
+                self.in_content += 1;
+                self.block(element, kind, kind);
+                self.in_content -= 1;
+            }
+            _ if BLOCKS.contains(&name) => self.block(element, kind, kind),
+            _ => self.walk(element, kind),
+        }
+    }
+
+    /// Navigation and the like, which isn't part of the text
+    fn is_furniture(&self, element: ElementRef, name: &str) -> bool {
+        let attr = |name: &str| element.value().attr(name);
+        SKIPPED.contains(&name)
+            || attr("hidden").is_some()
+            || attr("aria-hidden") == Some("true")
+            || attr("role").is_some_and(|role| FURNITURE_ROLES.contains(&role))
+            || (self.in_content == 0 && matches!(name, "header" | "footer" | "aside"))
+    }
+
+    /// Reads `element` as a block of its own. Text before it belongs to the enclosing block.
+    fn block(&mut self, element: ElementRef, outer: BlockKind, kind: BlockKind) {
+        self.flush(outer);
+        self.walk(element, kind);
+        self.flush(kind);
+    }
+
+    /// A table row becomes one block with its cells separated by tabs. Nested tables end up
+    /// in the cell holding them.
+    fn row(&mut self, row: ElementRef) {
+        let cells: Vec<String> = row.children()
+            .filter_map(ElementRef::wrap)
+            .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+            .map(|cell| {
+                let mut inner = Extractor { in_content: self.in_content, ..Extractor::default() };
+                inner.walk(cell, BlockKind::Paragraph);
+                inner.flush(BlockKind::Paragraph);
+                inner.blocks.into_iter().map(|(_, text)| text.replace('\t', " ")).collect::<Vec<_>>().join("\n")
+            })
+            .collect();
+        let content = cells.join("\t");
+        if !content.trim().is_empty() {
+            self.blocks.push((BlockKind::TableRow, content.trim_end().to_string()));
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        if self.pre > 0 {
+            self.text.push_str(text);
+            return;
+        }
+        for c in text.chars() {
+            if c.is_whitespace() && c != '\u{a0}' {
+                if !self.text.is_empty() && !self.text.ends_with([' ', '\n']) {
+                    self.text.push(' ');
+                }
+            } else {
+                self.text.push(c);
+            }
+        }
+    }
+
+    fn flush(&mut self, kind: BlockKind) {
+        let text = std::mem::take(&mut self.text);
+        let content = match kind {
+            BlockKind::Code => text.trim_matches('\n').trim_end().to_string(),
+            _ => text.lines().map(str::trim).collect::<Vec<_>>().join("\n").trim().to_string(),
+        };
+        if !content.is_empty() {
+            self.blocks.push((kind, content));
+        }
+    }
+}
+
+/// Whitespace runs as single spaces, as a browser shows them
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,101 @@
+use app_core::domain::{BlockKind, FileId, TextBlock};
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+/// Parses Markdown into one block per paragraph, heading, list item, code block or table row.
+///
+/// Blocks hold the rendered text rather than the Markdown source: markup like `#`, `**` and
+/// link targets is dropped, so highlight offsets line up with what the coder reads. The
+/// structure is kept in each block's [`BlockKind`] instead.
+pub fn parse(file_id: FileId, text: &str) -> Vec<TextBlock> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_FOOTNOTES;
+    let mut segmenter = Segmenter { file_id, blocks: Vec::new(), text: String::new(), kind: None, lists: Vec::new(), quotes: 0 };
+
+    for event in Parser::new_ext(text, options) {
+        match event {
+            // A list item's first paragraph belongs to the block the item opened
+            Event::Start(Tag::Paragraph) if segmenter.kind.is_none() || !segmenter.text.trim().is_empty() => {
+                segmenter.open(segmenter.context_kind());
+            }
+            Event::Start(Tag::Heading { level, .. }) => segmenter.open(BlockKind::Heading(level as u8)),
+            Event::Start(Tag::List(first_number)) => {
+                segmenter.flush();
+                segmenter.lists.push(first_number.is_some());
+            }
+            Event::End(TagEnd::List(_)) => {
+                segmenter.flush();
+                segmenter.lists.pop();
+            }
+            Event::Start(Tag::Item) => segmenter.open(segmenter.context_kind()),
+            Event::Start(Tag::BlockQuote(_)) => {
+                segmenter.flush();
+                segmenter.quotes += 1;
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                segmenter.flush();
+                segmenter.quotes = segmenter.quotes.saturating_sub(1);
+            }
+            Event::Start(Tag::CodeBlock(_)) => segmenter.open(BlockKind::Code),
+            Event::Start(Tag::TableHead | Tag::TableRow) => segmenter.open(BlockKind::TableRow),
+            Event::End(TagEnd::TableCell) => segmenter.text.push('\t'),
+            Event::End(
+                TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item | TagEnd::CodeBlock | TagEnd::TableHead | TagEnd::TableRow,
+            ) | Event::Rule => segmenter.flush(),
+            Event::Text(text) | Event::Code(text) => segmenter.push(&text),
+            Event::SoftBreak => segmenter.push(" "),
+            Event::HardBreak => segmenter.push("\n"),
+            // Raw HTML, footnote markers and the like aren't part of the readable text
+            _ => {}
+        }
+    }
+    segmenter.flush();
+    segmenter.blocks
+}
+
+/// Collects text into the open block until a structural event closes it
+struct Segmenter {
+    file_id: FileId,
+    blocks: Vec<TextBlock>,
+    text: String,
+    kind: Option<BlockKind>,
+    /// Whether each enclosing list is ordered, innermost last
+    lists: Vec<bool>,
+    quotes: usize,
+}
+
+impl Segmenter {
+    /// Kind for text that isn't in a heading, code block or table
+    fn context_kind(&self) -> BlockKind {
+        match self.lists.last() {
+            Some(&ordered) => BlockKind::ListItem { ordered, depth: self.lists.len().min(u8::MAX as usize) as u8 },
+            None if self.quotes > 0 => BlockKind::Quote,
+            None => BlockKind::Paragraph,
+        }
+    }
+
+    fn open(&mut self, kind: BlockKind) {
+        self.flush();
+        self.kind = Some(kind);
+    }
+
+    fn push(&mut self, text: &str) {
+        // Text after a nested list ends, still inside the outer item
+        if self.kind.is_none() {
+            self.kind = Some(self.context_kind());
+        }
+        self.text.push_str(text);
+    }
+
+    fn flush(&mut self) {
+        let kind = self.kind.take().unwrap_or_default();
+        let content = match kind {
+            // Indentation is meaningful in code
+            BlockKind::Code => self.text.trim_end(),
+            _ => self.text.trim(),
+        };
+        if !content.trim().is_empty() {
+            let block = TextBlock::new(self.file_id, self.blocks.len(), content.to_string()).with_kind(kind);
+            self.blocks.push(block);
+        }
+        self.text.clear();
+    }
+}
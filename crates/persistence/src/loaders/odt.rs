@@ -0,0 +1,201 @@
+use app_core::domain::{BlockKind, FileError, FileId, TextBlock};
+use crate::loaders::office::{attribute, open_archive, read_entry, xml_error, BlockCollector};
+
+use std::collections::HashMap;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+const CONTENT: &str = "content.xml";
+const STYLES: &str = "styles.xml";
+/// How far up a style's parents we look for its font weight
+const MAX_STYLE_DEPTH: usize = 16;
+
+/// Extracts the body of an OpenDocument text file as one block per paragraph or table row.
+///
+/// `text:h` elements become [`BlockKind::Heading`] at their outline level and paragraphs in
+/// lists [`BlockKind::ListItem`]. Footnotes, annotations, frames, the table of contents and
+/// deleted tracked changes are left out.
+pub fn parse(file_id: FileId, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+    let mut archive = open_archive(bytes)?;
+    let content = read_entry(&mut archive, CONTENT)?
+        .ok_or_else(|| FileError::Parse("Not an OpenDocument file, it has no content.xml".to_string()))?;
+
+    let mut styles = Styles::default();
+    if let Some(xml) = read_entry(&mut archive, STYLES)? {
+        styles.read(&xml, STYLES)?;
+    }
+    // Automatic styles, which is where most bold spans end up, live in content.xml itself
+    styles.read(&content, CONTENT)?;
+
+    read_body(file_id, &content, &styles)
+}
+
+#[derive(Default)]
+struct Styles {
+    /// Explicit font weight and parent of each paragraph and text style
+    text: HashMap<String, (Option<bool>, Option<String>)>,
+    /// Whether each level of a list style is numbered, keyed by style and 1-based level
+    lists: HashMap<(String, u8), bool>,
+}
+
+impl Styles {
+    fn read(&mut self, xml: &[u8], part: &str) -> Result<(), FileError> {
+        let mut reader = Reader::from_reader(xml);
+        let (mut style, mut list) = (None::<String>, None::<String>);
+
+        loop {
+            match reader.read_event().map_err(|e| xml_error(part, e))? {
+                Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                    b"style" => {
+                        style = attribute(&element, b"name");
+                        if let Some(name) = &style {
+                            self.text.insert(name.clone(), (None, attribute(&element, b"parent-style-name")));
+                        }
+                    }
+                    b"text-properties" => {
+                        if let (Some(name), Some(weight)) = (&style, attribute(&element, b"font-weight"))
+                            && let Some(entry) = self.text.get_mut(name)
+                        {
+                            entry.0 = Some(is_bold(&weight));
+                        }
+                    }
+                    b"list-style" => list = attribute(&element, b"name"),
+                    level @ (b"list-level-style-number" | b"list-level-style-bullet" | b"list-level-style-image") => {
+                        let ordered = level == b"list-level-style-number";
+                        if let (Some(name), Some(depth)) = (&list, attribute(&element, b"level").and_then(|l| l.parse().ok())) {
+                            self.lists.insert((name.clone(), depth), ordered);
+                        }
+                    }
+                    _ => {}
+                },
+                Event::End(element) => match element.local_name().as_ref() {
+                    b"style" => style = None,
+                    b"list-style" => list = None,
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The font weight `style` sets, directly or through its parents
+    fn bold(&self, style: &str) -> Option<bool> {
+        let mut name = style;
+        for _ in 0..MAX_STYLE_DEPTH {
+            let (bold, parent) = self.text.get(name)?;
+            if bold.is_some() {
+                return *bold;
+            }
+            name = parent.as_deref()?;
+        }
+        None
+    }
+}
+
+fn read_body(file_id: FileId, xml: &[u8], styles: &Styles) -> Result<Vec<TextBlock>, FileError> {
+    let mut reader = Reader::from_reader(xml);
+    let mut collector = BlockCollector::new(file_id);
+    let mut kind = BlockKind::Paragraph;
+    let mut in_paragraph = false;
+    // Boldness of the paragraph and each span open in it, innermost last
+    let mut bold: Vec<bool> = Vec::new();
+    // Style of each enclosing list, inherited by nested lists that don't name one
+    let mut lists: Vec<Option<String>> = Vec::new();
+
+    loop {
+        let event = reader.read_event().map_err(|e| xml_error(CONTENT, e))?;
+        let current_bold = bold.last().copied().unwrap_or(false);
+        let empty = matches!(event, Event::Empty(_));
+        match event {
+            Event::Start(element) | Event::Empty(element) => {
+                let style_bold = || attribute(&element, b"style-name").and_then(|style| styles.bold(&style));
+                match element.local_name().as_ref() {
+                    b"p" | b"h" if !empty => {
+                        kind = if element.local_name().as_ref() == b"h" {
+                            let level: u8 = attribute(&element, b"outline-level").and_then(|l| l.parse().ok()).unwrap_or(1);
+                            BlockKind::Heading(level.clamp(1, 6))
+                        } else {
+                            list_kind(&lists, styles)
+                        };
+                        in_paragraph = true;
+                        bold = vec![style_bold().unwrap_or(false)];
+                        collector.start_paragraph();
+                    }
+                    b"span" if !empty => bold.push(style_bold().unwrap_or(current_bold)),
+                    b"s" if in_paragraph => {
+                        let count = attribute(&element, b"c").and_then(|c| c.parse().ok()).unwrap_or(1usize);
+                        collector.push(&" ".repeat(count), current_bold);
+                    }
+                    b"tab" if in_paragraph => collector.push("\t", false),
+                    b"line-break" if in_paragraph => collector.push("\n", false),
+                    b"list" if !empty => {
+                        let inherited = lists.last().cloned().flatten();
+                        lists.push(attribute(&element, b"style-name").or(inherited));
+                    }
+                    b"table" if !empty => collector.start_table(),
+                    b"table-row" if !empty => collector.start_row(),
+                    b"table-cell" | b"covered-table-cell" => collector.start_cell(),
+                    b"note" | b"annotation" | b"tracked-changes" | b"frame" | b"table-of-content" if !empty => {
+                        reader.read_to_end(element.name()).map_err(|e| xml_error(CONTENT, e))?;
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(element) => match element.local_name().as_ref() {
+                b"p" | b"h" => {
+                    in_paragraph = false;
+                    bold.clear();
+                    collector.end_paragraph(kind);
+                }
+                b"span" if bold.len() > 1 => {
+                    bold.pop();
+                }
+                b"list" => {
+                    lists.pop();
+                }
+                b"table" => collector.end_table(),
+                b"table-row" => collector.end_row(),
+                _ => {}
+            },
+            Event::Text(text) if in_paragraph => {
+                let text = text.unescape().map_err(|e| xml_error(CONTENT, e))?;
+                collector.push(&collapse_whitespace(&text), current_bold);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(collector.finish())
+}
+
+fn list_kind(lists: &[Option<String>], styles: &Styles) -> BlockKind {
+    let Some(style) = lists.last() else {
+        return BlockKind::Paragraph;
+    };
+    let depth = lists.len().min(u8::MAX as usize) as u8;
+    let ordered = style.as_ref()
+        .and_then(|style| styles.lists.get(&(style.clone(), depth)))
+        .copied()
+        .unwrap_or(false);
+    BlockKind::ListItem { ordered, depth }
+}
+
+/// Runs of whitespace in ODF text are layout and read as one space. Real spaces, tabs and
+/// line breaks are written as `text:s`, `text:tab` and `text:line-break`.
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for (i, word) in text.split(|c: char| c.is_ascii_whitespace()).enumerate() {
+        if i > 0 && !collapsed.ends_with(' ') {
+            collapsed.push(' ');
+        }
+        collapsed.push_str(word);
+    }
+    collapsed
+}
+
+/// `fo:font-weight` is "bold", "normal" or a CSS style number
+fn is_bold(weight: &str) -> bool {
+    weight == "bold" || weight.parse::<u16>().is_ok_and(|weight| weight >= 600)
+}
@@ -0,0 +1,199 @@
+//! Pieces shared by the word processor formats. DOCX and ODT are zip archives of XML parts,
+//! and all of them including RTF are read into blocks the same way.
+
+use app_core::domain::{BlockKind, BlockMeta, FileError, FileId, TextBlock};
+use crate::loaders::plausible_speaker;
+
+use std::io::{Cursor, Read};
+use quick_xml::events::BytesStart;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+/// Largest archive entry we inflate. Real documents are far smaller, this stops zip bombs.
+const MAX_ENTRY: u64 = 256 * 1024 * 1024;
+
+pub type Archive<'a> = ZipArchive<Cursor<&'a [u8]>>;
+
+pub fn open_archive(bytes: &[u8]) -> Result<Archive<'_>, FileError> {
+    ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| FileError::Parse(format!("Not a readable document archive: {}", e)))
+}
+
+/// The contents of the archive entry called `name`, or None if there isn't one
+pub fn read_entry(archive: &mut Archive, name: &str) -> Result<Option<Vec<u8>>, FileError> {
+    let entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(FileError::Parse(format!("Failed to open {}: {}", name, e))),
+    };
+
+    let mut contents = Vec::new();
+    entry.take(MAX_ENTRY + 1).read_to_end(&mut contents)
+        .map_err(|e| FileError::Parse(format!("Failed to inflate {}: {}", name, e)))?;
+    if contents.len() as u64 > MAX_ENTRY {
+        return Err(FileError::Parse(format!("{} is too large to load", name)));
+    }
+    Ok(Some(contents))
+}
+
+pub fn xml_error(part: &str, error: impl std::fmt::Display) -> FileError {
+    FileError::Parse(format!("Malformed {}: {}", part, error))
+}
+
+/// Text of one paragraph or table row as it is read run by run
+#[derive(Default)]
+struct Paragraph {
+    text: String,
+    /// End of the bold text the paragraph opens with
+    lead_end: usize,
+    lead_closed: bool,
+}
+
+impl Paragraph {
+    fn push(&mut self, text: &str, bold: bool) {
+        self.text.push_str(text);
+        if self.lead_closed {
+            return;
+        }
+        if bold {
+            self.lead_end = self.text.len();
+        } else if !text.trim().is_empty() {
+            self.lead_closed = true;
+        }
+    }
+
+    /// The name in a bold label like "**Interviewer:**" or "**Interviewer**:" opening the
+    /// paragraph. A bold first table cell or bold text before a tab counts without the colon.
+    fn speaker(&self) -> Option<&str> {
+        let label = self.text[..self.lead_end].trim();
+        let rest = self.text[self.lead_end..].trim_start_matches(' ');
+        let name = label.strip_suffix(':')
+            .or_else(|| (rest.starts_with(':') || rest.starts_with('\t')).then_some(label))?
+            .trim_end();
+
+        plausible_speaker(name).then_some(name)
+    }
+}
+
+/// Turns paragraph and table events from a document's XML into blocks.
+///
+/// Each paragraph outside a table becomes a block. A top level table row becomes one
+/// [`BlockKind::TableRow`] block with its cells separated by tabs and the paragraphs within a
+/// cell by newlines, and nested tables are flattened into the cell holding them.
+pub struct BlockCollector {
+    file_id: FileId,
+    blocks: Vec<TextBlock>,
+    paragraph: Option<Paragraph>,
+    row: Option<Paragraph>,
+    tables: usize,
+    cells: usize,
+    cell_has_paragraph: bool,
+}
+
+impl BlockCollector {
+    pub fn new(file_id: FileId) -> Self {
+        Self { file_id, blocks: Vec::new(), paragraph: None, row: None, tables: 0, cells: 0, cell_has_paragraph: false }
+    }
+
+    pub fn start_paragraph(&mut self) {
+        if let Some(row) = &mut self.row {
+            if self.cell_has_paragraph {
+                row.push("\n", false);
+            }
+            self.cell_has_paragraph = true;
+        } else {
+            self.paragraph = Some(Paragraph::default());
+        }
+    }
+
+    /// Adds text to the open paragraph or row. Text outside of both is dropped.
+    pub fn push(&mut self, text: &str, bold: bool) {
+        if let Some(open) = self.row.as_mut().or(self.paragraph.as_mut()) {
+            open.push(text, bold);
+        }
+    }
+
+    pub fn end_paragraph(&mut self, kind: BlockKind) {
+        if self.row.is_none()
+            && let Some(paragraph) = self.paragraph.take()
+        {
+            self.emit(paragraph, kind);
+        }
+    }
+
+    pub fn start_table(&mut self) {
+        self.tables += 1;
+    }
+
+    pub fn end_table(&mut self) {
+        self.tables = self.tables.saturating_sub(1);
+    }
+
+    pub fn start_row(&mut self) {
+        if self.tables == 1 {
+            self.row = Some(Paragraph::default());
+            self.cells = 0;
+        }
+    }
+
+    pub fn end_row(&mut self) {
+        if self.tables == 1
+            && let Some(row) = self.row.take()
+        {
+            self.emit(row, BlockKind::TableRow);
+        }
+    }
+
+    pub fn start_cell(&mut self) {
+        if self.tables == 1
+            && let Some(row) = &mut self.row
+        {
+            if self.cells > 0 {
+                row.push("\t", false);
+            }
+            self.cells += 1;
+            self.cell_has_paragraph = false;
+        }
+    }
+
+    pub fn finish(self) -> Vec<TextBlock> {
+        self.blocks
+    }
+
+    fn emit(&mut self, paragraph: Paragraph, kind: BlockKind) {
+        // Leading empty cells are kept so columns still line up
+        let content = match kind {
+            BlockKind::TableRow => paragraph.text.trim_end(),
+            _ => paragraph.text.trim(),
+        };
+        if content.trim().is_empty() {
+            return;
+        }
+
+        let mut block = TextBlock::new(self.file_id, self.blocks.len(), content.to_string()).with_kind(kind);
+        if !matches!(kind, BlockKind::Heading(_))
+            && let Some(speaker) = paragraph.speaker()
+        {
+            block = block.with_attribute(BlockMeta::SPEAKER, speaker);
+        }
+        self.blocks.push(block);
+    }
+}
+
+/// Heading level for a style named like Word's built in "heading 1" or "Title"
+pub fn named_heading(name: &str) -> Option<u8> {
+    let name = name.to_ascii_lowercase();
+    if name == "title" {
+        return Some(1);
+    }
+    let level: u8 = name.strip_prefix("heading")?.trim().parse().ok()?;
+    (level >= 1).then(|| level.min(6))
+}
+
+/// Value of the attribute whose name without its namespace prefix is `name`
+pub fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element.attributes()
+        .flatten()
+        .find(|attribute| attribute.key.local_name().as_ref() == name)
+        .and_then(|attribute| attribute.unescape_value().ok().map(|value| value.into_owned()))
+}
@@ -0,0 +1,64 @@
+use app_core::domain::{BlockMeta, FileError, FileId, TextBlock};
+use crate::loaders::text::segment_paragraphs;
+
+use std::panic::{self, AssertUnwindSafe};
+use lopdf::Document;
+use pdf_extract::{output_doc_page, PlainTextOutput};
+
+/// Extracts a PDF's text layer as one block per paragraph, each tagged with its [`BlockMeta::PAGE`].
+///
+/// Paragraphs are told apart by vertical gaps between lines, and lines within a paragraph are
+/// joined with spaces, undoing end-of-line hyphenation. A paragraph that runs over a page
+/// break becomes two blocks, one per page, so every block has a single page to cite.
+pub fn parse(file_id: FileId, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+    let document = Document::load_mem(bytes)
+        .map_err(|e| FileError::Parse(format!("Not a readable PDF: {}", e)))?;
+    // Documents that only have an owner password are decrypted while loading
+    if document.is_encrypted() && document.encryption_state.is_none() {
+        return Err(FileError::Parse("The PDF is password protected. Save an unprotected copy and add that instead".to_string()));
+    }
+
+    let mut blocks = Vec::new();
+    for page in document.get_pages().into_keys() {
+        for paragraph in segment_paragraphs(&page_text(&document, page)?) {
+            let block = TextBlock::new(file_id, blocks.len(), join_lines(paragraph)).with_attribute(BlockMeta::PAGE, page.to_string());
+            blocks.push(block);
+        }
+    }
+
+    if blocks.is_empty() {
+        return Err(FileError::Parse("The PDF has no text layer, it is probably scanned images. Run OCR on it first".to_string()));
+    }
+    Ok(blocks)
+}
+
+fn page_text(document: &Document, page: u32) -> Result<String, FileError> {
+    let mut text = String::new();
+    // The extractor panics on some malformed content streams rather than returning an error
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut output = PlainTextOutput::new(&mut text);
+        output_doc_page(document, &mut output, page)
+    }));
+    match result {
+        Ok(Ok(())) => Ok(text),
+        Ok(Err(e)) => Err(FileError::Parse(format!("Failed to read page {}: {}", page, e))),
+        Err(_) => Err(FileError::Parse(format!("Page {} is malformed", page))),
+    }
+}
+
+/// Reflows a paragraph's lines into one line, rejoining words hyphenated across a line break
+fn join_lines(paragraph: &str) -> String {
+    let mut joined = String::with_capacity(paragraph.len());
+    for line in paragraph.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let hyphenated = joined.strip_suffix('-')
+            .is_some_and(|before| before.ends_with(char::is_alphabetic))
+            && line.starts_with(char::is_lowercase);
+        if hyphenated {
+            joined.pop();
+        } else if !joined.is_empty() {
+            joined.push(' ');
+        }
+        joined.push_str(line);
+    }
+    joined
+}
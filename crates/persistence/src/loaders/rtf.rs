@@ -0,0 +1,518 @@
+use app_core::domain::{BlockKind, FileError, FileId, TextBlock};
+use crate::loaders::encoding::for_code_page;
+use crate::loaders::office::{named_heading, BlockCollector};
+
+use std::collections::HashMap;
+use encoding_rs::{Encoding, WINDOWS_1252};
+
+/// Longest control word the spec allows, longer runs of letters are text
+const MAX_WORD: usize = 32;
+
+/// Extracts an RTF document's body text as one block per paragraph or table row.
+///
+/// Control words are dropped except those that shape the text: paragraph, cell and row
+/// breaks, heading styles and outline levels, list paragraphs, bold for speaker labels and
+/// special characters. `\uN` escapes are decoded, and `\'hh` bytes and raw 8-bit text use the
+/// code page of the current font, falling back to the document's `\ansicpg`. Headers,
+/// footers, footnotes, annotations, pictures, field instructions and other destinations
+/// that aren't body text are skipped.
+pub fn parse(file_id: FileId, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+    if !bytes.starts_with(b"{\\rtf") {
+        return Err(FileError::Parse("Not an RTF document, it doesn't start with {\\rtf".to_string()));
+    }
+    let mut parser = Parser::new(file_id);
+    parser.run(bytes);
+    Ok(parser.finish())
+}
+
+/// What a group's text is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Destination {
+    Body,
+    FontTable,
+    StyleSheet,
+    /// The rendered bullet or number of a list paragraph
+    ListText,
+    Skip,
+}
+
+/// Character formatting and destination, which groups inherit and restore when they close
+#[derive(Debug, Clone, Copy)]
+struct Group {
+    destination: Destination,
+    bold: bool,
+    font: Option<i32>,
+    /// Fallback characters following a `\uN` escape
+    unicode_skip: usize,
+}
+
+/// Paragraph formatting, reset by `\pard`
+#[derive(Default)]
+struct ParagraphProperties {
+    in_table: bool,
+    style: Option<i32>,
+    outline: Option<u8>,
+    list_level: Option<u8>,
+    list_text: String,
+}
+
+struct Parser {
+    collector: BlockCollector,
+    groups: Vec<Group>,
+    /// Set by `\*`, which marks the next control word as an optional destination
+    ignorable: bool,
+    default_encoding: &'static Encoding,
+    fonts: HashMap<i32, &'static Encoding>,
+    default_font: Option<i32>,
+    /// The font table entry being read
+    font_entry: Option<i32>,
+    /// Heading level of each stylesheet entry named like a heading
+    headings: HashMap<i32, u8>,
+    style_entry: Option<(i32, String)>,
+    paragraph: ParagraphProperties,
+    /// Text bytes not yet decoded, so multi-byte characters split across `\'hh` escapes
+    /// decode together
+    pending: Vec<u8>,
+    /// Fallback characters still to skip after a `\uN`
+    skip: usize,
+    high_surrogate: Option<u16>,
+    paragraph_open: bool,
+    in_row: bool,
+    cell_open: bool,
+}
+
+impl Parser {
+    fn new(file_id: FileId) -> Self {
+        let root = Group { destination: Destination::Body, bold: false, font: None, unicode_skip: 1 };
+        Self {
+            collector: BlockCollector::new(file_id),
+            groups: vec![root],
+            ignorable: false,
+            default_encoding: WINDOWS_1252,
+            fonts: HashMap::new(),
+            default_font: None,
+            font_entry: None,
+            headings: HashMap::new(),
+            style_entry: None,
+            paragraph: ParagraphProperties::default(),
+            pending: Vec::new(),
+            skip: 0,
+            high_surrogate: None,
+            paragraph_open: false,
+            in_row: false,
+            cell_open: false,
+        }
+    }
+
+    fn group(&self) -> &Group {
+        self.groups.last().expect("the root group is never popped")
+    }
+
+    fn group_mut(&mut self) -> &mut Group {
+        self.groups.last_mut().expect("the root group is never popped")
+    }
+
+    fn run(&mut self, bytes: &[u8]) {
+        // The document's own outer group is the root, so skip its opening brace
+        let mut i = 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'{' => {
+                    self.flush();
+                    self.skip = 0;
+                    let group = *self.group();
+                    self.groups.push(group);
+                    i += 1;
+                }
+                b'}' => {
+                    self.flush();
+                    self.skip = 0;
+                    if self.groups.len() == 1 {
+                        break;
+                    }
+                    self.close_group();
+                    i += 1;
+                }
+                b'\\' => i = self.control(bytes, i + 1),
+                // Line breaks in RTF source are formatting, `\par` and `\line` are the real ones
+                b'\r' | b'\n' => i += 1,
+                byte => {
+                    self.text_byte(byte);
+                    i += 1;
+                }
+            }
+        }
+        self.flush();
+    }
+
+    fn finish(mut self) -> Vec<TextBlock> {
+        self.end_paragraph();
+        self.end_row();
+        self.collector.finish()
+    }
+
+    /// Handles the control word or symbol starting at `i`, just past its backslash, and
+    /// returns where the next token starts
+    fn control(&mut self, bytes: &[u8], mut i: usize) -> usize {
+        let Some(&first) = bytes.get(i) else { return i };
+
+        if !first.is_ascii_alphabetic() {
+            i += 1;
+            match first {
+                b'\'' => {
+                    let byte = bytes.get(i..i + 2)
+                        .and_then(|hex| std::str::from_utf8(hex).ok())
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    if let Some(byte) = byte {
+                        self.text_byte(byte);
+                        return i + 2;
+                    }
+                }
+                b'\\' | b'{' | b'}' => self.text_byte(first),
+                b'*' => self.ignorable = true,
+                // Anything else is a symbol with its own meaning
+                symbol => {
+                    if self.skip_fallback() {
+                        return i;
+                    }
+                    match symbol {
+                        b'~' => self.text("\u{a0}"),
+                        b'_' => self.text("-"),
+                        b'\r' | b'\n' => self.end_paragraph(),
+                        // `\-` is an optional hyphen, `\:` marks index subentries
+                        _ => {}
+                    }
+                }
+            }
+            return i;
+        }
+
+        let start = i;
+        while i < bytes.len() && bytes[i].is_ascii_alphabetic() && i - start < MAX_WORD {
+            i += 1;
+        }
+        let word = std::str::from_utf8(&bytes[start..i]).unwrap_or_default().to_string();
+
+        let number_start = i;
+        if bytes.get(i) == Some(&b'-') && bytes.get(i + 1).is_some_and(u8::is_ascii_digit) {
+            i += 1;
+        }
+        while i < bytes.len() && bytes[i].is_ascii_digit() && i - number_start < 11 {
+            i += 1;
+        }
+        let parameter = std::str::from_utf8(&bytes[number_start..i]).ok().and_then(|n| n.parse::<i32>().ok());
+        // A space ends a control word and isn't part of the text
+        if bytes.get(i) == Some(&b' ') {
+            i += 1;
+        }
+
+        if word == "bin" {
+            // Binary data follows unescaped, so it has to be stepped over by length
+            return i + parameter.unwrap_or(0).max(0) as usize;
+        }
+        self.word(&word, parameter);
+        i
+    }
+
+    fn word(&mut self, word: &str, parameter: Option<i32>) {
+        self.flush();
+        if std::mem::take(&mut self.ignorable) {
+            self.group_mut().destination = Destination::Skip;
+            return;
+        }
+        if word != "u" && self.skip_fallback() {
+            return;
+        }
+        if let Some(destination) = destination(word) {
+            self.group_mut().destination = destination;
+            return;
+        }
+
+        match self.group().destination {
+            Destination::Body => self.body_word(word, parameter),
+            Destination::FontTable => self.font_table_word(word, parameter),
+            Destination::StyleSheet => {
+                if word == "s" {
+                    self.style_entry = Some((parameter.unwrap_or(0), String::new()));
+                }
+            }
+            Destination::ListText => {
+                if word == "u" {
+                    self.unicode(parameter.unwrap_or(0));
+                }
+            }
+            Destination::Skip => {}
+        }
+    }
+
+    fn body_word(&mut self, word: &str, parameter: Option<i32>) {
+        let on = parameter != Some(0);
+        match word {
+            "ansi" => self.set_default_encoding(1252),
+            "mac" => self.set_default_encoding(10000),
+            "pc" => self.set_default_encoding(437),
+            "pca" => self.set_default_encoding(850),
+            "ansicpg" => self.set_default_encoding(parameter.unwrap_or(1252).max(0) as u32),
+            "deff" => self.default_font = parameter,
+            "f" => self.group_mut().font = parameter,
+            "uc" => self.group_mut().unicode_skip = parameter.unwrap_or(1).max(0) as usize,
+            "u" => self.unicode(parameter.unwrap_or(0)),
+            "b" => self.group_mut().bold = on,
+            "plain" => {
+                let font = self.default_font;
+                let group = self.group_mut();
+                group.bold = false;
+                group.font = font;
+            }
+            // Writers put a list paragraph's marker before or after its `\pard`
+            "pard" => {
+                let list_text = std::mem::take(&mut self.paragraph.list_text);
+                self.paragraph = ParagraphProperties { list_text, ..ParagraphProperties::default() };
+            }
+            "intbl" => self.paragraph.in_table = on,
+            "s" => self.paragraph.style = parameter,
+            "outlinelevel" => self.paragraph.outline = parameter
+                .filter(|level| (0..9).contains(level))
+                .map(|level| (level as u8 + 1).min(6)),
+            "ls" => self.paragraph.list_level = Some(self.paragraph.list_level.unwrap_or(0)),
+            "ilvl" => self.paragraph.list_level = Some(parameter.unwrap_or(0).clamp(0, 8) as u8),
+            "par" | "sect" => self.end_paragraph(),
+            "nestcell" => self.end_paragraph(),
+            "cell" => self.end_cell(),
+            "row" => self.end_row(),
+            "line" => self.text("\n"),
+            "tab" => self.text("\t"),
+            "emdash" => self.text("\u{2014}"),
+            "endash" => self.text("\u{2013}"),
+            "emspace" | "enspace" | "qmspace" => self.text(" "),
+            "bullet" => self.text("\u{2022}"),
+            "lquote" => self.text("\u{2018}"),
+            "rquote" => self.text("\u{2019}"),
+            "ldblquote" => self.text("\u{201c}"),
+            "rdblquote" => self.text("\u{201d}"),
+            _ => {}
+        }
+    }
+
+    fn font_table_word(&mut self, word: &str, parameter: Option<i32>) {
+        if word == "f" {
+            self.font_entry = parameter;
+        }
+        let Some(font) = self.font_entry else { return };
+        let code_page = match word {
+            "fcharset" => charset_code_page(parameter.unwrap_or(0)),
+            "cpg" => parameter.map(|page| page.max(0) as u32),
+            _ => None,
+        };
+        if let Some(encoding) = code_page.and_then(for_code_page) {
+            self.fonts.insert(font, encoding);
+        }
+    }
+
+    fn set_default_encoding(&mut self, code_page: u32) {
+        // Code pages encoding_rs doesn't know are closest to Windows-1252 for the ASCII range
+        self.default_encoding = for_code_page(code_page).unwrap_or(WINDOWS_1252);
+    }
+
+    fn encoding(&self) -> &'static Encoding {
+        self.group().font.or(self.default_font)
+            .and_then(|font| self.fonts.get(&font).copied())
+            .unwrap_or(self.default_encoding)
+    }
+
+    /// Consumes a fallback character after a `\uN` escape, returning whether it did
+    fn skip_fallback(&mut self) -> bool {
+        if self.skip > 0 {
+            self.skip -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn unicode(&mut self, value: i32) {
+        // Values over 32767 are written as negative numbers
+        let unit = if value < 0 { value + 65536 } else { value } as u16;
+        self.skip = self.group().unicode_skip;
+
+        match (self.high_surrogate.take(), unit) {
+            (_, 0xD800..=0xDBFF) => self.high_surrogate = Some(unit),
+            (Some(high), 0xDC00..=0xDFFF) => {
+                let text = String::from_utf16_lossy(&[high, unit]);
+                self.text(&text);
+            }
+            (_, unit) => {
+                let text = char::from_u32(unit as u32).unwrap_or(char::REPLACEMENT_CHARACTER).to_string();
+                self.text(&text);
+            }
+        }
+    }
+
+    fn text_byte(&mut self, byte: u8) {
+        if !self.skip_fallback() {
+            self.pending.push(byte);
+        }
+    }
+
+    /// Decodes the pending text bytes in the current group's code page
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let bytes = std::mem::take(&mut self.pending);
+        let text = self.encoding().decode_without_bom_handling(&bytes).0.into_owned();
+        self.text(&text);
+    }
+
+    fn text(&mut self, text: &str) {
+        match self.group().destination {
+            Destination::Body => {
+                self.open_paragraph();
+                let bold = self.group().bold;
+                self.collector.push(text, bold);
+            }
+            Destination::StyleSheet => self.style_text(text),
+            Destination::ListText => self.paragraph.list_text.push_str(text),
+            Destination::FontTable | Destination::Skip => {}
+        }
+    }
+
+    /// Adds to the name of the stylesheet entry being read, which ends with a semicolon
+    fn style_text(&mut self, text: &str) {
+        let Some((style, name)) = &mut self.style_entry else { return };
+        match text.split_once(';') {
+            Some((last, _)) => {
+                name.push_str(last);
+                if let Some(level) = named_heading(name.trim()) {
+                    self.headings.insert(*style, level);
+                }
+                self.style_entry = None;
+            }
+            None => name.push_str(text),
+        }
+    }
+
+    fn close_group(&mut self) {
+        let closed = self.groups.pop().expect("checked by the caller");
+        // Fonts and styles are declared one group each, so an entry never outlives its group
+        match closed.destination {
+            Destination::FontTable => self.font_entry = None,
+            Destination::StyleSheet => {
+                if let Some((style, name)) = self.style_entry.take()
+                    && let Some(level) = named_heading(name.trim())
+                {
+                    self.headings.insert(style, level);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn open_paragraph(&mut self) {
+        if self.in_row && !self.paragraph.in_table {
+            self.end_row();
+        }
+        if self.paragraph.in_table && !self.in_row {
+            self.collector.start_table();
+            self.collector.start_row();
+            self.in_row = true;
+            self.cell_open = false;
+        }
+        if self.in_row && !self.cell_open {
+            self.collector.start_cell();
+            self.cell_open = true;
+            self.paragraph_open = false;
+        }
+        if !self.paragraph_open {
+            self.collector.start_paragraph();
+            self.paragraph_open = true;
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        if std::mem::take(&mut self.paragraph_open) {
+            let kind = self.paragraph_kind();
+            self.collector.end_paragraph(kind);
+        }
+        self.paragraph.list_text.clear();
+    }
+
+    fn paragraph_kind(&self) -> BlockKind {
+        let heading = self.paragraph.outline
+            .or_else(|| self.paragraph.style.and_then(|style| self.headings.get(&style).copied()));
+        if let Some(level) = heading {
+            return BlockKind::Heading(level);
+        }
+
+        let list_text = self.paragraph.list_text.trim();
+        if list_text.is_empty() && self.paragraph.list_level.is_none() {
+            return BlockKind::Paragraph;
+        }
+        BlockKind::ListItem {
+            // Numbered markers like "1." or "a)" have something alphanumeric in them
+            ordered: list_text.chars().any(char::is_alphanumeric),
+            depth: self.paragraph.list_level.unwrap_or(0) + 1,
+        }
+    }
+
+    fn end_cell(&mut self) {
+        self.end_paragraph();
+        if !self.in_row {
+            self.paragraph.in_table = true;
+            self.open_paragraph();
+        } else if !self.cell_open {
+            // An empty cell still takes up a column
+            self.collector.start_cell();
+        }
+        self.cell_open = false;
+        self.paragraph_open = false;
+    }
+
+    fn end_row(&mut self) {
+        self.end_paragraph();
+        if std::mem::take(&mut self.in_row) {
+            self.collector.end_row();
+            self.collector.end_table();
+        }
+        self.cell_open = false;
+    }
+}
+
+/// The destination a control word opens, for those whose groups aren't body text
+fn destination(word: &str) -> Option<Destination> {
+    let destination = match word {
+        "fonttbl" => Destination::FontTable,
+        "stylesheet" => Destination::StyleSheet,
+        "listtext" | "pntext" => Destination::ListText,
+        "colortbl" | "info" | "pict" | "object" | "header" | "headerl" | "headerr" | "headerf" | "footer"
+        | "footerl" | "footerr" | "footerf" | "footnote" | "annotation" | "fldinst" | "listtable"
+        | "listoverridetable" | "revtbl" | "rsidtbl" | "generator" | "xe" | "tc" | "txe" | "shp"
+        | "shpinst" | "nonshppict" | "pntxta" | "pntxtb" | "themedata" | "colorschememapping"
+        | "latentstyles" | "datastore" | "filetbl" | "private" => Destination::Skip,
+        _ => return None,
+    };
+    Some(destination)
+}
+
+/// Windows code page for an `\fcharset` value
+fn charset_code_page(charset: i32) -> Option<u32> {
+    let code_page = match charset {
+        77 => 10000,
+        128 => 932,
+        129 => 949,
+        134 => 936,
+        136 => 950,
+        161 => 1253,
+        162 => 1254,
+        163 => 1258,
+        177 => 1255,
+        178 => 1256,
+        186 => 1257,
+        204 => 1251,
+        222 => 874,
+        238 => 1250,
+        // ANSI, default and symbol fonts use the document's code page
+        _ => return None,
+    };
+    Some(code_page)
+}
@@ -0,0 +1,92 @@
+//! CSV and XLSX files read as a [`SurveyTable`] for survey import. Which cells become
+//! blocks is up to the file's [`SurveyView`](app_core::domain::SurveyView), see
+//! [`SurveyTable::blocks`].
+
+use app_core::domain::{FileError, FileType, SurveyTable};
+use crate::loaders::encoding;
+
+use std::io::Cursor;
+use calamine::{open_workbook_from_rs, Data, Reader, Xlsx};
+
+/// Delimiters a CSV export may use, in order of preference when the header is ambiguous
+const DELIMITERS: [u8; 3] = [b',', b'\t', b';'];
+
+/// Reads a CSV or XLSX spreadsheet. `sheet` picks an XLSX worksheet, the first when None.
+pub fn read(file_type: &FileType, bytes: &[u8], sheet: Option<&str>) -> Result<SurveyTable, FileError> {
+    match file_type {
+        FileType::Csv => read_csv(&encoding::decode_text(bytes)?),
+        FileType::Xlsx => read_xlsx(bytes, sheet),
+        other => Err(FileError::Unsupported(format!("{:?} files aren't spreadsheets", other))),
+    }
+}
+
+/// Reads comma, tab or semicolon separated values, whichever the header row uses most.
+/// Quoted cells may span lines.
+pub fn read_csv(text: &str) -> Result<SurveyTable, FileError> {
+    let header = text.lines().next().unwrap_or_default();
+    // max_by_key keeps the last of equal counts, so preference runs backwards
+    let delimiter = DELIMITERS.iter()
+        .rev()
+        .copied()
+        .max_by_key(|&d| header.bytes().filter(|&b| b == d).count())
+        .unwrap_or(b',');
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| FileError::Parse(format!("Malformed CSV: {}", e)))?;
+        rows.push(record.iter().map(|cell| cell.replace("\r\n", "\n")).collect());
+    }
+    table(Vec::new(), rows)
+}
+
+/// Reads one worksheet of a workbook. Dates are written as ISO dates, and as date and time
+/// when they have one.
+pub fn read_xlsx(bytes: &[u8], sheet: Option<&str>) -> Result<SurveyTable, FileError> {
+    let mut workbook: Xlsx<_> = open_workbook_from_rs(Cursor::new(bytes))
+        .map_err(|e| FileError::Parse(format!("Not a readable workbook: {}", e)))?;
+    let sheets = workbook.sheet_names();
+    let name = match sheet {
+        Some(name) if sheets.iter().any(|s| s == name) => name.to_string(),
+        Some(name) => return Err(FileError::Parse(format!("The workbook has no sheet named {:?}", name))),
+        None => sheets.first().cloned().ok_or_else(|| FileError::Parse("The workbook has no sheets".to_string()))?,
+    };
+    let range = workbook.worksheet_range(&name)
+        .map_err(|e| FileError::Parse(format!("Failed to read sheet {:?}: {}", name, e)))?;
+
+    let rows = range.rows().map(|row| row.iter().map(cell_text).collect()).collect();
+    table(sheets, rows)
+}
+
+/// Splits off the header row. Blank headers are named after their position, so every
+/// column can still be chosen.
+fn table(sheets: Vec<String>, mut rows: Vec<Vec<String>>) -> Result<SurveyTable, FileError> {
+    if rows.is_empty() {
+        return Err(FileError::Parse("The spreadsheet is empty, it needs a header row naming its columns".to_string()));
+    }
+    let columns = rows.remove(0)
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| match name.trim() {
+            "" => format!("Column {}", i + 1),
+            name => name.to_string(),
+        })
+        .collect();
+    Ok(SurveyTable { sheets, columns, rows })
+}
+
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(date) if date.is_datetime() => match date.as_datetime() {
+            Some(at) if at.time() == chrono::NaiveTime::MIN => at.date().to_string(),
+            Some(at) => at.to_string(),
+            None => cell.to_string(),
+        },
+        Data::Error(_) => String::new(),
+        _ => cell.to_string(),
+    }
+}
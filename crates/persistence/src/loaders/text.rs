@@ -0,0 +1,37 @@
+use app_core::domain::{FileId, TextBlock};
+
+/// One block per paragraph. Line endings are normalised to `\n` first, so the same text
+/// saved on Windows gives the same blocks.
+pub fn parse(file_id: FileId, text: &str) -> Vec<TextBlock> {
+    let text = text.replace("\r\n", "\n");
+    segment_paragraphs(&text)
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, paragraph)| TextBlock::new(file_id, sequence, paragraph.to_string()))
+        .collect()
+}
+
+/// Splits text into paragraphs on blank lines. Line breaks inside a paragraph are kept,
+/// and surrounding whitespace-only lines are dropped, so the output only depends on the text.
+pub fn segment_paragraphs(text: &str) -> Vec<&str> {
+    let mut paragraphs = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut end = 0;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            if let Some(s) = start.take() {
+                paragraphs.push(&text[s..end]);
+            }
+        } else {
+            start.get_or_insert(offset);
+            end = offset + line.trim_end().len();
+        }
+        offset += line.len();
+    }
+    if let Some(s) = start {
+        paragraphs.push(&text[s..end]);
+    }
+    paragraphs
+}
@@ -0,0 +1,304 @@
+//! Timed transcripts: WebVTT and SRT captions, and plain text with a speaker and timestamp
+//! line opening each turn. Every format ends up as one block per speaker turn carrying
+//! [`BlockMeta::SPEAKER`], [`BlockMeta::START`] and, where known, [`BlockMeta::END`].
+
+use app_core::domain::{BlockMeta, FileError, FileId, TextBlock};
+use crate::loaders::plausible_speaker;
+
+use std::time::Duration;
+
+/// Footer Otter adds to its exports, which isn't part of the last turn
+const OTTER_FOOTER: &str = "Transcribed by https://otter.ai";
+
+/// Parses WebVTT captions into speaker turns.
+///
+/// The speaker comes from a cue's `<v Name>` voice span, or failing that a "Name:" prefix as
+/// Zoom and Teams write them. See [`into_turns`] for how cues are merged.
+pub fn parse_webvtt(file_id: FileId, text: &str) -> Result<Vec<TextBlock>, FileError> {
+    let text = text.replace("\r\n", "\n");
+    let mut sections = text.split("\n\n");
+
+    let header = sections.next().unwrap_or_default();
+    let is_webvtt = header.strip_prefix("WEBVTT")
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t', '\n']));
+    if !is_webvtt {
+        return Err(FileError::Parse("Not a WebVTT file, it doesn't start with WEBVTT".to_string()));
+    }
+
+    let cues = sections
+        .filter(|section| !section.starts_with("NOTE") && !section.starts_with("STYLE") && !section.starts_with("REGION"))
+        .filter_map(|section| cue(section, voice_or_prefix))
+        .collect();
+    into_turns(file_id, cues)
+}
+
+/// Parses SRT subtitles into speaker turns, taking speakers from "Name:" prefixes.
+/// See [`into_turns`] for how cues are merged.
+pub fn parse_srt(file_id: FileId, text: &str) -> Result<Vec<TextBlock>, FileError> {
+    let text = text.replace("\r\n", "\n");
+    let cues = text.split("\n\n")
+        .filter_map(|section| cue(section.trim_start_matches('\n'), |text| speaker_prefix(&strip_markup(text))))
+        .collect();
+    into_turns(file_id, cues)
+}
+
+/// Parses a plain text transcript where each turn opens with a line like
+/// "Jane Doe  00:01:23", as Otter and similar tools export them.
+///
+/// A turn ends where the next one starts. Text before the first turn becomes a block with no
+/// speaker or timing.
+pub fn parse_speaker_turns(file_id: FileId, text: &str) -> Vec<TextBlock> {
+    let text = text.replace("\r\n", "\n");
+    let mut turns: Vec<(Option<TurnHeader>, Vec<&str>)> = Vec::new();
+    let mut after_blank = true;
+
+    for line in text.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            after_blank = true;
+            continue;
+        }
+        if line.trim() == OTTER_FOOTER {
+            continue;
+        }
+        match turn_header(line).filter(|_| after_blank) {
+            Some(header) => turns.push((Some(header), Vec::new())),
+            None => match turns.last_mut() {
+                Some((_, lines)) => lines.push(line.trim()),
+                None => turns.push((None, vec![line.trim()])),
+            },
+        }
+        after_blank = false;
+    }
+
+    let starts: Vec<Option<Duration>> = turns.iter().map(|(header, _)| header.map(|(_, start)| start)).collect();
+    let mut blocks = Vec::new();
+    for (i, (header, lines)) in turns.iter().enumerate() {
+        let content = lines.join("\n");
+        if content.is_empty() {
+            continue;
+        }
+        let mut block = TextBlock::new(file_id, blocks.len(), content);
+        if let Some((speaker, start)) = header {
+            let end = starts.get(i + 1).copied().flatten();
+            block = block.with_timing(*start, end);
+            if let Some(speaker) = speaker {
+                block = block.with_attribute(BlockMeta::SPEAKER, *speaker);
+            }
+        }
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Whether text opens like a transcript [`parse_speaker_turns`] reads: its first line is a
+/// turn header, and at least one more follows with a later timestamp
+pub fn looks_like_speaker_turns(text: &str) -> bool {
+    let mut lines = text.lines().map(str::trim_end).skip_while(|line| line.trim().is_empty());
+    let Some(first) = lines.next().and_then(turn_header) else {
+        return false;
+    };
+
+    let mut after_blank = false;
+    for line in lines {
+        if line.trim().is_empty() {
+            after_blank = true;
+            continue;
+        }
+        if after_blank && let Some((_, start)) = turn_header(line) {
+            return start >= first.1;
+        }
+        after_blank = false;
+    }
+    false
+}
+
+/// The speaker, if named, and start of a turn
+type TurnHeader<'a> = (Option<&'a str>, Duration);
+
+/// A speaker turn header: a name, whitespace and a clock time, or a clock time alone
+fn turn_header(line: &str) -> Option<TurnHeader<'_>> {
+    let line = line.trim();
+    let (name, time) = match line.rsplit_once(char::is_whitespace) {
+        Some((name, time)) => (Some(name.trim()), time),
+        None => (None, line),
+    };
+    // Plain transcripts give whole seconds, fractions mean something else
+    if time.contains(['.', ',']) {
+        return None;
+    }
+    let start = parse_timestamp(time)?;
+    match name {
+        Some(name) if !plausible_speaker(name) => None,
+        name => Some((name, start)),
+    }
+}
+
+/// One caption, before cues are merged into turns
+struct Cue {
+    start: Duration,
+    end: Duration,
+    speaker: Option<String>,
+    text: String,
+}
+
+/// Reads a caption section: an optional identifier line, a "start --> end" timing line and
+/// the payload. `speaker` splits the payload into speaker and plain text.
+fn cue(section: &str, speaker: fn(&str) -> (Option<String>, String)) -> Option<Cue> {
+    let mut lines = section.lines();
+    let timing = lines.by_ref().take(2).find(|line| line.contains("-->"))?;
+    let (start, rest) = timing.split_once("-->")?;
+    let start = parse_timestamp(start.trim())?;
+    let end = parse_timestamp(rest.split_whitespace().next()?)?;
+
+    let payload = lines.map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(" ");
+    let (speaker, text) = speaker(&payload);
+    Some(Cue { start, end, speaker, text })
+}
+
+/// Merges cues into speaker turns.
+///
+/// A cue with a different speaker starts a new turn, and a cue without one continues the
+/// current turn, since captions often only name a speaker when it changes. When no cue names
+/// anyone there are no turns to find, so every cue is its own block.
+fn into_turns(file_id: FileId, cues: Vec<Cue>) -> Result<Vec<TextBlock>, FileError> {
+    if cues.is_empty() {
+        return Err(FileError::Parse("No caption cues found".to_string()));
+    }
+
+    let named = cues.iter().any(|cue| cue.speaker.is_some());
+    let mut turns: Vec<Cue> = Vec::new();
+    for cue in cues.into_iter().filter(|cue| !cue.text.is_empty()) {
+        match turns.last_mut() {
+            Some(turn) if named && (cue.speaker.is_none() || cue.speaker == turn.speaker) => {
+                turn.text.push(' ');
+                turn.text.push_str(&cue.text);
+                turn.end = turn.end.max(cue.end);
+            }
+            _ => turns.push(cue),
+        }
+    }
+
+    Ok(turns.into_iter().enumerate().map(|(i, turn)| {
+        let block = TextBlock::new(file_id, i, turn.text).with_timing(turn.start, Some(turn.end));
+        match turn.speaker {
+            Some(speaker) => block.with_attribute(BlockMeta::SPEAKER, speaker),
+            None => block,
+        }
+    }).collect())
+}
+
+/// A WebVTT payload's speaker from its first `<v>` span, or from a "Name:" prefix
+fn voice_or_prefix(payload: &str) -> (Option<String>, String) {
+    let voice = payload.find("<v").and_then(|at| {
+        let tag = &payload[at + 2..at + payload[at..].find('>')?];
+        // `<v Name>` or `<v.class Name>`
+        let (_, name) = tag.split_once([' ', '\t'])?;
+        Some(name.trim().to_string()).filter(|name| !name.is_empty())
+    });
+    match voice {
+        Some(name) => (Some(name), strip_markup(payload)),
+        None => speaker_prefix(&strip_markup(payload)),
+    }
+}
+
+/// Splits "Name: text" into its speaker and text if the part before the colon reads as a name
+fn speaker_prefix(text: &str) -> (Option<String>, String) {
+    if let Some((name, rest)) = text.split_once(':')
+        && (rest.is_empty() || rest.starts_with(char::is_whitespace))
+        && plausible_speaker(name.trim())
+    {
+        return (Some(name.trim().to_string()), rest.trim().to_string());
+    }
+    (None, text.trim().to_string())
+}
+
+/// Removes `<...>` tags and `{\...}` SSA overrides and decodes character references
+fn strip_markup(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find(['<', '{']) {
+        plain.push_str(&rest[..at]);
+        let close = if rest[at..].starts_with('<') { '>' } else { '}' };
+        let is_markup = close == '>' || rest[at + 1..].starts_with('\\');
+        match rest[at..].find(close) {
+            Some(end) if is_markup => rest = &rest[at + end + 1..],
+            _ => {
+                plain.push_str(&rest[at..at + 1]);
+                rest = &rest[at + 1..];
+            }
+        }
+    }
+    plain.push_str(rest);
+    decode_references(&plain)
+}
+
+fn decode_references(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        decoded.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let reference = rest.find(';').filter(|&end| end <= 10).map(|end| (&rest[1..end], end));
+        let character = reference.and_then(|(name, _)| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            "lrm" => Some('\u{200e}'),
+            "rlm" => Some('\u{200f}'),
+            _ => {
+                let number = name.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (character, reference) {
+            (Some(character), Some((_, end))) => {
+                decoded.push(character);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Reads "hh:mm:ss.ttt", "mm:ss.ttt", SRT's "hh:mm:ss,ttt" or a plain "m:ss"
+fn parse_timestamp(text: &str) -> Option<Duration> {
+    let (clock, fraction) = match text.split_once(['.', ',']) {
+        Some((clock, fraction)) => (clock, Some(fraction)),
+        None => (text, None),
+    };
+
+    let fields: Vec<&str> = clock.split(':').collect();
+    if !(2..=3).contains(&fields.len()) || fields.iter().any(|f| f.is_empty() || !f.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+    let mut seconds: u64 = 0;
+    for (i, field) in fields.iter().enumerate() {
+        let value: u64 = field.parse().ok()?;
+        // Only the leading field may run past 59
+        if i > 0 && (value >= 60 || field.len() != 2) {
+            return None;
+        }
+        seconds = seconds.checked_mul(60)?.checked_add(value)?;
+    }
+
+    let millis = match fraction {
+        None => 0,
+        Some(digits) if (1..=3).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()) => {
+            digits.parse::<u64>().ok()? * 10u64.pow(3 - digits.len() as u32)
+        }
+        Some(_) => return None,
+    };
+    Some(Duration::from_millis(seconds.checked_mul(1000)?.checked_add(millis)?))
+}
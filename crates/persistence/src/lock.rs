@@ -0,0 +1,131 @@
+use app_core::domain::ProjectLock;
+use app_core::domain::ProjectError;
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// `project.json` -> `project.json.lock`
+pub fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+/// Lock describing this process
+pub fn current_owner() -> ProjectLock {
+    ProjectLock {
+        host: gethostname::gethostname().to_string_lossy().into_owned(),
+        pid: std::process::id(),
+        acquired_at: Utc::now(),
+    }
+}
+
+/// Takes the lock for the project at `path`.
+///
+/// An existing lock is taken over if it belongs to this process, if it is stale (same host,
+/// process no longer running), or if `force` is set. Otherwise its owner is returned in
+/// `ProjectError::Locked`. The lock is advisory: it only stops instances that check it.
+pub async fn acquire(path: &Path, force: bool) -> Result<ProjectLock, ProjectError> {
+    let file = lock_path(path);
+    let owner = current_owner();
+    let contents = serde_json::to_vec_pretty(&owner)
+        .map_err(|e| ProjectError::Save(format!("Lock serialization failed: {}", e)))?;
+
+    // Two rounds: if the existing lock can be removed, the retry creates ours. Losing that
+    // race to another instance ends up in the Locked error below.
+    for _ in 0..2 {
+        match create_new(&file, &contents).await {
+            Ok(()) => return Ok(owner),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(ProjectError::Save(format!("Failed to create lock file: {}", e))),
+        }
+
+        match read(path).await {
+            Some(existing) if is_ours(&existing) => {
+                crate::atomic::write_atomic(&file, &contents, 0)
+                    .await
+                    .map_err(|e| ProjectError::Save(format!("Failed to refresh lock file: {}", e)))?;
+                return Ok(owner);
+            }
+            Some(existing) if !force && !is_stale(&existing) => return Err(ProjectError::Locked(existing)),
+            // Stale, forced, or unreadable (a crash while the lock was being written)
+            _ => remove(&file).await?,
+        }
+    }
+
+    match read(path).await {
+        Some(existing) => Err(ProjectError::Locked(existing)),
+        None => Err(ProjectError::Save("Failed to acquire project lock".to_string())),
+    }
+}
+
+/// Removes the lock if this process holds it
+pub async fn release(path: &Path) -> Result<(), ProjectError> {
+    match read(path).await {
+        Some(existing) if is_ours(&existing) => remove(&lock_path(path)).await,
+        _ => Ok(()),
+    }
+}
+
+/// Fails with `ProjectError::Locked` if another live process holds the lock on `path`.
+/// A project nobody has locked can be written freely.
+pub async fn ensure_not_locked_by_other(path: &Path) -> Result<(), ProjectError> {
+    match read(path).await {
+        Some(existing) if !is_ours(&existing) && !is_stale(&existing) => Err(ProjectError::Locked(existing)),
+        _ => Ok(()),
+    }
+}
+
+/// Current lock on `path`, if there is a readable one
+pub async fn read(path: &Path) -> Option<ProjectLock> {
+    let bytes = fs::read(lock_path(path)).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn is_ours(lock: &ProjectLock) -> bool {
+    let owner = current_owner();
+    lock.host == owner.host && lock.pid == owner.pid
+}
+
+/// A lock from this machine whose process has exited. Locks from other hosts are never
+/// considered stale, since there's no way to check their process from here.
+fn is_stale(lock: &ProjectLock) -> bool {
+    lock.host == current_owner().host && !process_alive(lock.pid)
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // kill() treats 0 and negative pids as process groups, ours included, so they can't name
+    // a lock owner
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+    // Signal 0 only checks whether the process exists. EPERM means it exists but belongs
+    // to another user.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    // Without a portable liveness check, assume the owner is still running
+    true
+}
+
+async fn create_new(file: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut handle = fs::OpenOptions::new().write(true).create_new(true).open(file).await?;
+    handle.write_all(contents).await?;
+    handle.sync_all().await
+}
+
+async fn remove(file: &Path) -> Result<(), ProjectError> {
+    match fs::remove_file(file).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(ProjectError::Save(format!("Failed to remove lock file: {}", e))),
+    }
+}
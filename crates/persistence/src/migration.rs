@@ -0,0 +1,139 @@
+use app_core::domain::{MigrationReport, ProjectError};
+use serde_json::Value;
+
+/// Schema version written by this build. Bump it together with a new step in
+/// [`MigrationRegistry::default`] whenever the project file shape changes.
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// Upgrades a raw project document from one schema version to the next.
+/// Steps work on `serde_json::Value` because the old shape no longer matches the domain types.
+pub type MigrationFn = fn(&mut Value) -> Result<(), String>;
+
+pub struct MigrationStep {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: MigrationFn,
+    /// Upgrades one journal entry, for steps that change a type the journal records
+    pub apply_entry: Option<MigrationFn>,
+}
+
+/// Ordered set of `N -> N+1` steps used to bring old project files up to date on load.
+pub struct MigrationRegistry {
+    current: u32,
+    steps: Vec<MigrationStep>,
+}
+
+impl MigrationRegistry {
+    /// Empty registry whose files are expected to be at `current`
+    pub fn new(current: u32) -> Self {
+        MigrationRegistry { current, steps: Vec::new() }
+    }
+
+    pub fn register(mut self, from: u32, description: &'static str, apply: MigrationFn) -> Self {
+        self.steps.push(MigrationStep { from, description, apply, apply_entry: None });
+        self
+    }
+
+    /// Like [`register`](Self::register), also upgrading journal entries with `apply_entry`.
+    /// Entries from before journals recorded their version are passed through every step
+    /// from version 1, so `apply_entry` must leave an already upgraded entry alone.
+    pub fn register_with_entries(mut self, from: u32, description: &'static str, apply: MigrationFn, apply_entry: MigrationFn) -> Self {
+        self.steps.push(MigrationStep { from, description, apply, apply_entry: Some(apply_entry) });
+        self
+    }
+
+    pub fn current_version(&self) -> u32 { self.current }
+
+    /// Runs every step needed to bring `doc` to the current version, updating
+    /// `project.schema_version` as it goes. Returns `None` when the file was already current.
+    ///
+    /// Files from a newer schema are refused rather than read with missing fields.
+    pub fn migrate(&self, doc: &mut Value) -> Result<Option<MigrationReport>, ProjectError> {
+        let from_version = schema_version(doc)?;
+
+        if from_version > self.current {
+            return Err(ProjectError::InvalidFormat(format!(
+                "Project uses schema version {} but this version of the app only supports up to {}",
+                from_version, self.current
+            )));
+        }
+        if from_version == self.current {
+            return Ok(None);
+        }
+
+        let mut applied = Vec::new();
+        for version in from_version..self.current {
+            let step = self.steps.iter()
+                .find(|s| s.from == version)
+                .ok_or_else(|| ProjectError::InvalidFormat(format!("No migration from schema version {}", version)))?;
+
+            (step.apply)(doc)
+                .map_err(|e| ProjectError::InvalidFormat(format!("Migration from schema version {} failed: {}", version, e)))?;
+            doc["project"]["schema_version"] = Value::from(version + 1);
+            applied.push(step.description.to_string());
+        }
+
+        Ok(Some(MigrationReport {
+            from_version,
+            to_version: self.current,
+            applied,
+        }))
+    }
+
+    /// Brings a journal entry written under schema `version` up to the current one. Steps
+    /// without an entry function leave entries as they are.
+    pub fn migrate_entry(&self, version: u32, entry: &mut Value) -> Result<(), ProjectError> {
+        if version > self.current {
+            return Err(ProjectError::InvalidFormat(format!(
+                "Journal entry uses schema version {} but this version of the app only supports up to {}",
+                version, self.current
+            )));
+        }
+        let steps = self.steps.iter().filter(|s| s.from >= version && s.from < self.current);
+        for apply in steps.filter_map(|s| s.apply_entry) {
+            apply(entry).map_err(|e| ProjectError::InvalidFormat(format!("Journal entry migration failed: {}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for MigrationRegistry {
+    fn default() -> Self {
+        MigrationRegistry::new(CURRENT_SCHEMA_VERSION)
+            .register_with_entries(1, "Name the format of files typed Other", name_other_file_types, name_other_file_type_entry)
+    }
+}
+
+/// Name given to the format of files stored as the bare `Other` type
+pub const UNNAMED_FORMAT: &str = "unknown";
+
+/// v1 -> v2: `FileType::Other` became `Other(name)` so added loaders can name their format
+fn name_other_file_types(doc: &mut Value) -> Result<(), String> {
+    let Some(files) = doc["filemanager"]["files"].as_object_mut() else {
+        return Err("filemanager.files is not an object".to_string());
+    };
+    files.values_mut().for_each(name_other_file_type);
+    Ok(())
+}
+
+/// v1 -> v2 for the journal: a file inserted with the bare `Other` type
+fn name_other_file_type_entry(entry: &mut Value) -> Result<(), String> {
+    if entry["op"] == "file_inserted" {
+        name_other_file_type(&mut entry["data"]);
+    }
+    Ok(())
+}
+
+fn name_other_file_type(file: &mut Value) {
+    if file["file_type"] == "Other" {
+        file["file_type"] = serde_json::json!({ "Other": UNNAMED_FORMAT });
+    }
+}
+
+fn schema_version(doc: &Value) -> Result<u32, ProjectError> {
+    doc.get("project")
+        .and_then(|p| p.get("schema_version"))
+        .and_then(Value::as_u64)
+        .and_then(|v| u32::try_from(v).ok())
+        .ok_or_else(|| ProjectError::InvalidFormat("Missing project schema_version".to_string()))
+}
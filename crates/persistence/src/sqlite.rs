@@ -0,0 +1,503 @@
+use app_core::domain::*;
+use app_core::ports::ProjectRepository;
+use crate::infra::{ProjectFile, map_parse_error, versioned_copy_path, is_project_file};
+use crate::migration::{MigrationRegistry, CURRENT_SCHEMA_VERSION};
+use crate::lock;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Transaction, params};
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+
+/// Each entity table stores the serialized entity in `data` alongside the columns needed to
+/// order and cross-reference rows. Keeping the payload in the same serde shape as the JSON
+/// backend lets both backends share one migration pipeline.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS project (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS themes (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS code_defs (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        theme_id TEXT,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS qual_codes (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        def_id TEXT NOT NULL,
+        block_id TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS files (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS qual_codes_by_def ON qual_codes(def_id);
+    CREATE INDEX IF NOT EXISTS code_defs_by_theme ON code_defs(theme_id);
+";
+
+/// Embedded SQLite project repository.
+///
+/// Suited to large projects: the incremental `insert_*`/`delete_*` operations touch a single
+/// row instead of rewriting everything, and `save_project` compacts the whole project in
+/// one transaction. Calls open a short-lived connection on the blocking pool.
+///
+/// Loading only reads: a migrated project stays at its old version on disk until the first
+/// write copies it aside and rewrites it, so a read-only open never touches the database.
+///
+/// Locking works as in the JSON backend: `project.qualdb.lock` records which instance has the
+/// project open, and every write refuses with `ProjectError::Locked` while another holds it.
+pub struct SqliteRepository {
+    migrations: Arc<MigrationRegistry>,
+    last_migration: std::sync::Mutex<Option<MigrationReport>>,
+    /// Project path and version of the last migrated load, until a write upgrades it
+    unsaved_migration: std::sync::Mutex<Option<(PathBuf, u32)>>,
+}
+
+impl SqliteRepository {
+    pub fn new() -> Self {
+        SqliteRepository {
+            migrations: Arc::new(MigrationRegistry::default()),
+            last_migration: std::sync::Mutex::new(None),
+            unsaved_migration: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Replaces the migration steps used on load. Mainly useful for testing upgrade paths.
+    pub fn with_migrations(mut self, migrations: MigrationRegistry) -> Self {
+        self.migrations = Arc::new(migrations);
+        self
+    }
+
+    /// Runs `f` inside a write transaction on a connection to `path`, creating the database if needed
+    async fn write<F>(&self, path: &Path, f: F) -> Result<()>
+    where
+        F: FnOnce(&Transaction) -> Result<(), ProjectError> + Send + 'static,
+    {
+        self.transaction(path, true, f).await
+    }
+
+    /// Like [`Self::write`], but for incremental changes to a project that must already exist
+    async fn update<F>(&self, path: &Path, f: F) -> Result<()>
+    where
+        F: FnOnce(&Transaction) -> Result<(), ProjectError> + Send + 'static,
+    {
+        self.transaction(path, false, f).await
+    }
+
+    /// Brings a project migrated on load up to date on disk first, so rows never mix versions
+    async fn transaction<F>(&self, path: &Path, create: bool, f: F) -> Result<()>
+    where
+        F: FnOnce(&Transaction) -> Result<(), ProjectError> + Send + 'static,
+    {
+        let upgrade = self.unsaved_migration.lock().unwrap().as_ref()
+            .filter(|(migrated, _)| migrated == path)
+            .map(|&(_, from_version)| (from_version, self.migrations.clone()));
+        lock::ensure_not_locked_by_other(path).await?;
+        let path_buf: PathBuf = path.to_path_buf();
+        blocking(move || {
+            let mut conn = open(&path_buf, create)?;
+            if let Some((from_version, migrations)) = upgrade {
+                upgrade_rows(&mut conn, &path_buf, from_version, &migrations)?;
+            }
+            let tx = conn.transaction().map_err(save_error)?;
+            f(&tx)?;
+            tx.commit().map_err(save_error)
+        }).await?;
+        self.unsaved_migration.lock().unwrap().take_if(|(migrated, _)| migrated == path);
+        Ok(())
+    }
+}
+
+impl Default for SqliteRepository {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ProjectRepository for SqliteRepository {
+    async fn new_project(&self, path: &Path, name: String) -> Result<QualProject> {
+        let now = Utc::now();
+        let project = QualProject::new(name, CURRENT_SCHEMA_VERSION, now, now);
+
+        let project_file = ProjectFile {
+            project: project.clone(),
+            codebook: CodeBook::new(),
+            filemanager: FileList::new(),
+        };
+        // A new project replaces whatever was migrated there
+        self.unsaved_migration.lock().unwrap().take_if(|(migrated, _)| migrated == path);
+        self.write(path, move |tx| write_all(tx, &project_file)).await?;
+
+        Ok(project)
+    }
+
+    async fn save_project(&self, path: &Path, project: QualProject, codebook: CodeBook, filemanager: FileList, _covered: u64) -> Result<()> {
+        let mut project = project;
+        project.touch(Utc::now());
+
+        let project_file = ProjectFile { project, codebook, filemanager };
+        self.write(path, move |tx| write_all(tx, &project_file)).await
+    }
+
+    async fn load_project(&self, path: &Path) -> Result<(QualProject, CodeBook, FileList)> {
+        let path_buf = path.to_path_buf();
+        let migrations = self.migrations.clone();
+
+        let (project_file, report) = blocking(move || {
+            let conn = open_read_only(&path_buf)?;
+            let mut doc = read_document(&conn)?;
+            let report = migrations.migrate(&mut doc)?;
+
+            let project_file: ProjectFile = serde_json::from_value(doc)
+                .map_err(map_parse_error)?;
+            Ok((project_file, report))
+        }).await?;
+
+        *self.unsaved_migration.lock().unwrap() = report.as_ref().map(|report| (path.to_path_buf(), report.from_version));
+        *self.last_migration.lock().unwrap() = report;
+        Ok((project_file.project, project_file.codebook, project_file.filemanager))
+    }
+
+    fn take_migration_report(&self) -> Option<MigrationReport> {
+        self.last_migration.lock().unwrap().take()
+    }
+
+    fn is_project_file(&self, project: &Path, path: &Path) -> bool {
+        is_project_file(project, path)
+    }
+
+    async fn lock_project(&self, path: &Path, force: bool) -> Result<()> {
+        lock::acquire(path, force).await?;
+        Ok(())
+    }
+
+    async fn unlock_project(&self, path: &Path) -> Result<()> {
+        lock::release(path).await?;
+        Ok(())
+    }
+
+    async fn insert_code_def(&self, path: &Path, code: CodeDef) -> Result<()> {
+        self.update(path, move |tx| {
+            let id = key(&code.id)?;
+            let theme_id = code.theme_id().map(|t| key(&t)).transpose()?;
+            tx.execute(
+                "INSERT INTO code_defs (id, position, theme_id, data)
+                 VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM code_defs), ?2, ?3)
+                 ON CONFLICT(id) DO UPDATE SET theme_id = excluded.theme_id, data = excluded.data",
+                params![id, theme_id, to_json(&code)?],
+            ).map_err(save_error)?;
+            Ok(())
+        }).await
+    }
+
+    async fn insert_theme_def(&self, path: &Path, theme: ThemeDef) -> Result<()> {
+        self.update(path, move |tx| {
+            tx.execute(
+                "INSERT INTO themes (id, position, data)
+                 VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM themes), ?2)
+                 ON CONFLICT(id) DO UPDATE SET data = excluded.data",
+                params![key(&theme.id)?, to_json(&theme)?],
+            ).map_err(save_error)?;
+            Ok(())
+        }).await
+    }
+
+    async fn insert_qual_code(&self, path: &Path, code: QualCode) -> Result<()> {
+        self.update(path, move |tx| {
+            tx.execute(
+                "INSERT INTO qual_codes (id, position, def_id, block_id, data)
+                 VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM qual_codes), ?2, ?3, ?4)
+                 ON CONFLICT(id) DO UPDATE SET def_id = excluded.def_id, block_id = excluded.block_id, data = excluded.data",
+                params![key(&code.id)?, key(&code.def_id())?, key(&code.block_id())?, to_json(&code)?],
+            ).map_err(save_error)?;
+            Ok(())
+        }).await
+    }
+
+    async fn insert_file(&self, path: &Path, file: QualFile) -> Result<()> {
+        self.update(path, move |tx| {
+            tx.execute(
+                "INSERT INTO files (id, position, data)
+                 VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM files), ?2)
+                 ON CONFLICT(id) DO UPDATE SET data = excluded.data",
+                params![key(&file.id)?, to_json(&file)?],
+            ).map_err(save_error)?;
+            Ok(())
+        }).await
+    }
+
+    /// Deletes a code definition and every QualCode applied with it, matching `CodeBook::remove_code_def`
+    async fn delete_code_def(&self, path: &Path, id: CodeDefId) -> Result<()> {
+        self.update(path, move |tx| {
+            let id = key(&id)?;
+            tx.execute("DELETE FROM qual_codes WHERE def_id = ?1", params![id]).map_err(save_error)?;
+            tx.execute("DELETE FROM code_defs WHERE id = ?1", params![id]).map_err(save_error)?;
+            Ok(())
+        }).await
+    }
+
+    /// Deletes a theme and moves its codes to the top level, matching `CodeBook::remove_theme`
+    async fn delete_theme_def(&self, path: &Path, id: ThemeId) -> Result<()> {
+        self.update(path, move |tx| {
+            let theme_key = key(&id)?;
+            let members: Vec<(String, String)> = {
+                let mut stmt = tx.prepare("SELECT id, data FROM code_defs WHERE theme_id = ?1").map_err(save_error)?;
+                stmt.query_map(params![theme_key], |row| Ok((row.get(0)?, row.get(1)?)))
+                    .map_err(save_error)?
+                    .collect::<rusqlite::Result<_>>()
+                    .map_err(save_error)?
+            };
+            for (code_key, data) in members {
+                let mut code: CodeDef = serde_json::from_str(&data)
+                    .map_err(|e| ProjectError::Corrupted(format!("Invalid code definition row: {}", e)))?;
+                code.set_theme_id(None);
+                tx.execute(
+                    "UPDATE code_defs SET theme_id = NULL, data = ?2 WHERE id = ?1",
+                    params![code_key, to_json(&code)?],
+                ).map_err(save_error)?;
+            }
+            tx.execute("DELETE FROM themes WHERE id = ?1", params![theme_key]).map_err(save_error)?;
+            Ok(())
+        }).await
+    }
+
+    async fn delete_qual_code(&self, path: &Path, id: QualCodeId) -> Result<()> {
+        self.update(path, move |tx| {
+            tx.execute("DELETE FROM qual_codes WHERE id = ?1", params![key(&id)?]).map_err(save_error)?;
+            Ok(())
+        }).await
+    }
+
+    async fn delete_file(&self, path: &Path, id: FileId) -> Result<()> {
+        self.update(path, move |tx| {
+            tx.execute("DELETE FROM files WHERE id = ?1", params![key(&id)?]).map_err(save_error)?;
+            Ok(())
+        }).await
+    }
+
+    async fn move_code_def(&self, path: &Path, id: CodeDefId, new_index: usize) -> Result<()> {
+        self.update(path, move |tx| move_row(tx, "code_defs", &key(&id)?, new_index)).await
+    }
+
+    async fn move_theme_def(&self, path: &Path, id: ThemeId, new_index: usize) -> Result<()> {
+        self.update(path, move |tx| move_row(tx, "themes", &key(&id)?, new_index)).await
+    }
+
+    async fn move_file(&self, path: &Path, id: FileId, new_index: usize) -> Result<()> {
+        self.update(path, move |tx| move_row(tx, "files", &key(&id)?, new_index)).await
+    }
+}
+
+// ===== Row helpers =====
+
+/// Moves a row to `new_index` in its table's order and renumbers positions, mirroring
+/// `IndexMap::move_index`. Missing ids and out-of-range indexes leave the order unchanged.
+fn move_row(tx: &Transaction, table: &str, id: &str, new_index: usize) -> Result<(), ProjectError> {
+    let mut ids: Vec<String> = {
+        let mut stmt = tx.prepare(&format!("SELECT id FROM {} ORDER BY position", table)).map_err(save_error)?;
+        stmt.query_map([], |row| row.get(0))
+            .map_err(save_error)?
+            .collect::<rusqlite::Result<_>>()
+            .map_err(save_error)?
+    };
+    let Some(current) = ids.iter().position(|row_id| row_id == id) else {
+        return Ok(());
+    };
+    if new_index >= ids.len() {
+        return Ok(());
+    }
+
+    let moved = ids.remove(current);
+    ids.insert(new_index, moved);
+
+    let mut stmt = tx.prepare(&format!("UPDATE {} SET position = ?2 WHERE id = ?1", table)).map_err(save_error)?;
+    for (position, row_id) in ids.iter().enumerate() {
+        stmt.execute(params![row_id, position as i64]).map_err(save_error)?;
+    }
+    Ok(())
+}
+
+// ===== Blocking helpers =====
+
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T, ProjectError> + Send + 'static,
+    T: Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(f)
+        .await
+        .context("Database task panicked")?;
+    Ok(result?)
+}
+
+fn open(path: &Path, create: bool) -> Result<Connection, ProjectError> {
+    let mut flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    if create {
+        flags |= OpenFlags::SQLITE_OPEN_CREATE;
+    }
+    let conn = Connection::open_with_flags(path, flags)
+        .map_err(|e| if create { save_error(e) } else { ProjectError::Load(format!("Failed to open database: {}", e)) })?;
+
+    conn.busy_timeout(Duration::from_secs(5)).map_err(open_error)?;
+    // Not WAL: a WAL database makes even read-only connections create -wal and -shm files,
+    // and with short-lived connections there are no long readers for it to help
+    conn.pragma_update(None, "journal_mode", "DELETE").map_err(open_error)?;
+    conn.execute_batch(SCHEMA).map_err(open_error)?;
+    Ok(conn)
+}
+
+/// Opens a project for reading without changing anything on disk: no schema setup or
+/// journal mode switch, and no companion files next to it
+fn open_read_only(path: &Path) -> Result<Connection, ProjectError> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let conn = Connection::open_with_flags(path, flags)
+        .map_err(|e| ProjectError::Load(format!("Failed to open database: {}", e)))?;
+    conn.busy_timeout(Duration::from_secs(5)).map_err(open_error)?;
+    Ok(conn)
+}
+
+/// Copies a project still at `from_version` on disk to a versioned file, then rewrites its
+/// rows at the current schema. Rows another write already upgraded are left alone.
+fn upgrade_rows(conn: &mut Connection, path: &Path, from_version: u32, migrations: &MigrationRegistry) -> Result<(), ProjectError> {
+    let mut doc = read_document(conn)?;
+    if migrations.migrate(&mut doc)?.is_none() {
+        return Ok(());
+    }
+    let project_file: ProjectFile = serde_json::from_value(doc)
+        .map_err(map_parse_error)?;
+
+    let original = versioned_copy_path(path, from_version);
+    if !original.exists() {
+        conn.execute("VACUUM INTO ?1", params![original.to_string_lossy()])
+            .map_err(|e| ProjectError::Save(format!("Failed to keep pre-migration copy: {}", e)))?;
+    }
+    let tx = conn.transaction().map_err(save_error)?;
+    write_all(&tx, &project_file)?;
+    tx.commit().map_err(save_error)
+}
+
+/// Replaces every row with the contents of `project_file`
+fn write_all(tx: &Transaction, project_file: &ProjectFile) -> Result<(), ProjectError> {
+    tx.execute_batch("DELETE FROM project; DELETE FROM themes; DELETE FROM code_defs; DELETE FROM qual_codes; DELETE FROM files;")
+        .map_err(save_error)?;
+
+    tx.execute("INSERT INTO project (id, data) VALUES (1, ?1)", params![to_json(&project_file.project)?])
+        .map_err(save_error)?;
+
+    for (position, theme) in project_file.codebook.get_all_themes().enumerate() {
+        tx.execute(
+            "INSERT INTO themes (id, position, data) VALUES (?1, ?2, ?3)",
+            params![key(&theme.id)?, position as i64, to_json(theme)?],
+        ).map_err(save_error)?;
+    }
+    for (position, code) in project_file.codebook.get_all_code_defs().enumerate() {
+        let theme_id = code.theme_id().map(|t| key(&t)).transpose()?;
+        tx.execute(
+            "INSERT INTO code_defs (id, position, theme_id, data) VALUES (?1, ?2, ?3, ?4)",
+            params![key(&code.id)?, position as i64, theme_id, to_json(code)?],
+        ).map_err(save_error)?;
+    }
+    for (position, code) in project_file.codebook.get_all_qual_codes().iter().enumerate() {
+        tx.execute(
+            "INSERT INTO qual_codes (id, position, def_id, block_id, data) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![key(&code.id)?, position as i64, key(&code.def_id())?, key(&code.block_id())?, to_json(code)?],
+        ).map_err(save_error)?;
+    }
+    for (position, file) in project_file.filemanager.get_all_files().enumerate() {
+        tx.execute(
+            "INSERT INTO files (id, position, data) VALUES (?1, ?2, ?3)",
+            params![key(&file.id)?, position as i64, to_json(file)?],
+        ).map_err(save_error)?;
+    }
+    Ok(())
+}
+
+/// Reassembles the rows into the same document shape the JSON backend stores
+fn read_document(conn: &Connection) -> Result<Value, ProjectError> {
+    let project: String = conn.query_row("SELECT data FROM project WHERE id = 1", [], |row| row.get(0))
+        .optional()
+        .map_err(load_error)?
+        .ok_or_else(|| ProjectError::InvalidFormat("Database has no project record".to_string()))?;
+
+    let keyed_rows = |table: &str| -> Result<Map<String, Value>, ProjectError> {
+        let mut stmt = conn.prepare(&format!("SELECT id, data FROM {} ORDER BY position", table)).map_err(load_error)?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))).map_err(load_error)?;
+        let mut map = Map::new();
+        for row in rows {
+            let (id, data) = row.map_err(load_error)?;
+            map.insert(id, parse_row(&data)?);
+        }
+        Ok(map)
+    };
+
+    let code_defs = keyed_rows("code_defs")?;
+    let themes = keyed_rows("themes")?;
+    let files = keyed_rows("files")?;
+    let qual_codes: Vec<Value> = keyed_rows("qual_codes")?.into_iter().map(|(_, v)| v).collect();
+
+    Ok(json!({
+        "project": parse_row(&project)?,
+        "codebook": { "code_defs": code_defs, "themes": themes, "qual_codes": qual_codes },
+        "filemanager": { "files": files },
+    }))
+}
+
+// ===== Conversions =====
+
+/// Primary key for an ID newtype: its serialized UUID string
+fn key<T: Serialize>(id: &T) -> Result<String, ProjectError> {
+    match serde_json::to_value(id) {
+        Ok(Value::String(s)) => Ok(s),
+        Ok(other) => Err(ProjectError::Save(format!("Unexpected id format: {}", other))),
+        Err(e) => Err(ProjectError::Save(format!("Serialization failed: {}", e))),
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, ProjectError> {
+    serde_json::to_string(value)
+        .map_err(|e| ProjectError::Save(format!("Serialization failed: {}", e)))
+}
+
+fn parse_row(data: &str) -> Result<Value, ProjectError> {
+    serde_json::from_str(data)
+        .map_err(|e| ProjectError::Corrupted(format!("Invalid row data: {}", e)))
+}
+
+fn is_not_a_database(e: &rusqlite::Error) -> bool {
+    matches!(e.sqlite_error_code(), Some(rusqlite::ErrorCode::NotADatabase | rusqlite::ErrorCode::DatabaseCorrupt))
+}
+
+fn open_error(e: rusqlite::Error) -> ProjectError {
+    if is_not_a_database(&e) {
+        ProjectError::Corrupted(format!("Not a valid project database: {}", e))
+    } else {
+        ProjectError::Load(format!("Failed to open database: {}", e))
+    }
+}
+
+fn load_error(e: rusqlite::Error) -> ProjectError {
+    if is_not_a_database(&e) {
+        ProjectError::Corrupted(format!("Not a valid project database: {}", e))
+    } else {
+        ProjectError::Load(format!("Database read failed: {}", e))
+    }
+}
+
+fn save_error(e: rusqlite::Error) -> ProjectError {
+    ProjectError::Save(format!("Database write failed: {}", e))
+}
@@ -0,0 +1,3230 @@
+// Round-trip tests for the persistence backends.
+// Each scenario is written against the ProjectRepository trait so every backend runs the same suite.
+
+use crate::infra::JsonRepository;
+use crate::sqlite::SqliteRepository;
+use app_core::domain::*;
+use app_core::ports::ProjectRepository;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+// ===== Test Helpers =====
+
+/// Temp directory plus the project path inside it. The directory is deleted on drop.
+fn project_path(file_name: &str) -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let path = dir.path().join(file_name);
+    (dir, path)
+}
+
+/// Builds a codebook with themes, themed and top-level codes, and applied highlights
+fn populated_codebook() -> CodeBook {
+    let mut codebook = CodeBook::new();
+    let theme_a = codebook.create_theme("Wellbeing".to_string(), 3);
+    let theme_b = codebook.create_theme("Barriers".to_string(), 7);
+
+    let code_z = codebook.create_code_def("Zeal".to_string(), 1, Some(theme_a));
+    let code_a = codebook.create_code_def("Anxiety".to_string(), 2, Some(theme_b));
+    let code_m = codebook.create_code_def("Money".to_string(), 4, None);
+
+    let block_file = FileList::new().add_file("a.txt".to_string(), FileType::PlainText);
+    let block = TextBlock::new(block_file, 0, "Some interview text".to_string());
+    codebook.apply_code(code_z, Highlight::new(block.id, 5, 14), "interview".to_string(), "Some ".to_string(), " text".to_string());
+    codebook.apply_code(code_a, Highlight::new(block.id, 0, 4), "Some".to_string(), String::new(), " interview".to_string());
+    codebook.apply_code(code_m, Highlight::new(block.id, 15, 19), "text".to_string(), "interview ".to_string(), String::new());
+    codebook
+}
+
+/// Three files, the first with block anchors from an earlier load
+fn populated_filelist() -> FileList {
+    let mut files = FileList::new();
+    let loaded = files.add_file("interviews/b.txt".to_string(), FileType::PlainText);
+    files.add_file("notes.md".to_string(), FileType::Markdown);
+    files.add_file("report.pdf".to_string(), FileType::Pdf);
+
+    let blocks: Vec<TextBlock> = ["First paragraph", "Second paragraph"].iter().enumerate()
+        .map(|(i, text)| TextBlock::new(loaded, i, text.to_string()))
+        .collect();
+    let file = files.file_mut(loaded).unwrap();
+    file.set_anchors(blocks.iter().map(BlockAnchor::of).collect());
+    file.set_embed_source(Some(true));
+    file.set_snapshot(Some(SourceSnapshot::of(&blocks)));
+    file.set_fingerprint(FileFingerprint { sha256: "ab".repeat(32), size: 33, modified: Some(chrono::Utc::now()), path: None });
+    files
+}
+
+fn assert_codebooks_match(expected: &CodeBook, actual: &CodeBook) {
+    let expected_defs: Vec<_> = expected.get_all_code_defs().map(|d| (d.id, d.name().to_string(), d.color(), d.theme_id())).collect();
+    let actual_defs: Vec<_> = actual.get_all_code_defs().map(|d| (d.id, d.name().to_string(), d.color(), d.theme_id())).collect();
+    assert_eq!(expected_defs, actual_defs, "Code defs and their order should survive the round trip");
+
+    let expected_themes: Vec<_> = expected.get_all_themes().map(|t| (t.id, t.name().to_string(), t.color())).collect();
+    let actual_themes: Vec<_> = actual.get_all_themes().map(|t| (t.id, t.name().to_string(), t.color())).collect();
+    assert_eq!(expected_themes, actual_themes, "Themes and their order should survive the round trip");
+
+    let expected_codes: Vec<_> = expected.get_all_qual_codes().iter()
+        .map(|qc| (qc.id, qc.def_id(), qc.block_id(), qc.position(), qc.snippet().to_string()))
+        .collect();
+    let actual_codes: Vec<_> = actual.get_all_qual_codes().iter()
+        .map(|qc| (qc.id, qc.def_id(), qc.block_id(), qc.position(), qc.snippet().to_string()))
+        .collect();
+    assert_eq!(expected_codes, actual_codes, "Qual codes and highlights should survive the round trip");
+}
+
+fn assert_filelists_match(expected: &FileList, actual: &FileList) {
+    let summary = |f: &QualFile| (f.id, f.path().to_string(), f.anchors().to_vec(), f.fingerprint().cloned(), f.embed_source(), f.snapshot().cloned());
+    let expected_files: Vec<_> = expected.get_all_files().map(summary).collect();
+    let actual_files: Vec<_> = actual.get_all_files().map(summary).collect();
+    assert_eq!(expected_files, actual_files, "Files and their order should survive the round trip");
+}
+
+/// Shared by the loader tests, whose parsers only copy the file id onto each block
+mod loader_fixtures {
+    use super::*;
+
+    pub fn file_id() -> FileId {
+        FileList::new().add_file("source.txt".to_string(), FileType::PlainText)
+    }
+
+    pub fn kinds_and_text(blocks: &[TextBlock]) -> Vec<(BlockKind, &str)> {
+        blocks.iter().map(|b| (b.meta.kind, b.content.as_str())).collect()
+    }
+}
+
+// ===== Shared round-trip suite =====
+
+mod round_trip {
+    use super::*;
+
+    pub async fn new_project_loads_empty<R: ProjectRepository>(repo: &R, path: &Path) {
+        // Execute: Create then load
+        let created = repo.new_project(path, "Study".to_string()).await.unwrap();
+        let (project, codebook, files) = repo.load_project(path).await.unwrap();
+
+        // Assert: Empty project with the same metadata
+        assert_eq!(project.name(), created.name());
+        assert_eq!(project.schema_version(), created.schema_version());
+        assert_eq!(codebook.get_all_code_defs().count(), 0, "New project should have no codes");
+        assert_eq!(files.file_count(), 0, "New project should have no files");
+    }
+
+    pub async fn save_then_load_preserves_everything<R: ProjectRepository>(repo: &R, path: &Path) {
+        // Setup: Project with reordered codes and themes
+        let project = repo.new_project(path, "Study".to_string()).await.unwrap();
+        let mut codebook = populated_codebook();
+        codebook.sort_code_defs_by_name();
+        codebook.swap_themes(0, 1).unwrap();
+        let mut files = populated_filelist();
+        files.swap_files(0, 2).unwrap();
+
+        // Execute: Save and reload
+        repo.save_project(path, project.clone(), codebook.clone(), files.clone(), repo.journal_mark(path)).await.unwrap();
+        let (loaded_project, loaded_codebook, loaded_files) = repo.load_project(path).await.unwrap();
+
+        // Assert: Everything comes back in the same order
+        assert_eq!(loaded_project.name(), project.name());
+        assert_eq!(loaded_project.created_at(), project.created_at());
+        assert_codebooks_match(&codebook, &loaded_codebook);
+        assert_filelists_match(&files, &loaded_files);
+    }
+
+    pub async fn save_bumps_updated_at<R: ProjectRepository>(repo: &R, path: &Path) {
+        // Setup: Project whose updated_at is in the past
+        let created = repo.new_project(path, "Study".to_string()).await.unwrap();
+        let mut stale = created.clone();
+        stale.touch(created.updated_at() - chrono::Duration::hours(1));
+
+        // Execute: Save the stale project
+        repo.save_project(path, stale.clone(), CodeBook::new(), FileList::new(), repo.journal_mark(path)).await.unwrap();
+        let (loaded, _, _) = repo.load_project(path).await.unwrap();
+
+        // Assert: updated_at moved forward, created_at untouched
+        assert!(loaded.updated_at() > stale.updated_at(), "Save should bump updated_at");
+        assert_eq!(loaded.created_at(), created.created_at(), "Save should not change created_at");
+    }
+
+    pub async fn incremental_inserts_match_full_save<R: ProjectRepository>(repo: &R, path: &Path) {
+        // Setup: Empty project on disk, populated codebook in memory
+        repo.new_project(path, "Study".to_string()).await.unwrap();
+        let codebook = populated_codebook();
+        let files = populated_filelist();
+
+        // Execute: Write each entity on its own
+        for theme in codebook.get_all_themes() {
+            repo.insert_theme_def(path, theme.clone()).await.unwrap();
+        }
+        for code in codebook.get_all_code_defs() {
+            repo.insert_code_def(path, code.clone()).await.unwrap();
+        }
+        for code in codebook.get_all_qual_codes() {
+            repo.insert_qual_code(path, code.clone()).await.unwrap();
+        }
+        for file in files.get_all_files() {
+            repo.insert_file(path, file.clone()).await.unwrap();
+        }
+
+        // Assert: Same result as a full save
+        let (_, loaded_codebook, loaded_files) = repo.load_project(path).await.unwrap();
+        assert_codebooks_match(&codebook, &loaded_codebook);
+        assert_filelists_match(&files, &loaded_files);
+    }
+
+    pub async fn incremental_deletes_match_codebook_semantics<R: ProjectRepository>(repo: &R, path: &Path) {
+        // Setup: Saved project, and the same removals applied in memory
+        let project = repo.new_project(path, "Study".to_string()).await.unwrap();
+        let mut codebook = populated_codebook();
+        let mut files = populated_filelist();
+        repo.save_project(path, project, codebook.clone(), files.clone(), repo.journal_mark(path)).await.unwrap();
+
+        let theme_id = codebook.get_all_themes().next().unwrap().id;
+        let code_def_id = codebook.get_all_code_defs().last().unwrap().id;
+        let qual_code_id = codebook.get_all_qual_codes()[0].id;
+        let file_id = files.get_all_files().next().unwrap().id;
+
+        codebook.remove_theme(theme_id).unwrap();
+        codebook.remove_code_def(code_def_id).unwrap();
+        codebook.remove_qual_code(qual_code_id).unwrap();
+        files.remove_file(file_id).unwrap();
+
+        // Execute
+        repo.delete_theme_def(path, theme_id).await.unwrap();
+        repo.delete_code_def(path, code_def_id).await.unwrap();
+        repo.delete_qual_code(path, qual_code_id).await.unwrap();
+        repo.delete_file(path, file_id).await.unwrap();
+
+        // Assert
+        let (_, loaded_codebook, loaded_files) = repo.load_project(path).await.unwrap();
+        assert_codebooks_match(&codebook, &loaded_codebook);
+        assert_filelists_match(&files, &loaded_files);
+    }
+
+    pub async fn incremental_moves_match_in_memory_order<R: ProjectRepository>(repo: &R, path: &Path) {
+        // Setup: Saved project, and the same moves applied in memory
+        let project = repo.new_project(path, "Study".to_string()).await.unwrap();
+        let mut codebook = populated_codebook();
+        let mut files = populated_filelist();
+        repo.save_project(path, project, codebook.clone(), files.clone(), repo.journal_mark(path)).await.unwrap();
+
+        let code_def_id = codebook.get_all_code_defs().last().unwrap().id;
+        let theme_id = codebook.get_all_themes().last().unwrap().id;
+        let file_id = files.get_all_files().next().unwrap().id;
+
+        codebook.move_code_def_to_index(code_def_id, 0).unwrap();
+        codebook.move_theme_to_index(theme_id, 0).unwrap();
+        files.move_file_to_index(file_id, 2).unwrap();
+
+        // Execute: Includes an out-of-range move, which must leave the order alone
+        repo.move_code_def(path, code_def_id, 0).await.unwrap();
+        repo.move_theme_def(path, theme_id, 0).await.unwrap();
+        repo.move_file(path, file_id, 2).await.unwrap();
+        repo.move_file(path, file_id, 99).await.unwrap();
+
+        // Assert
+        let (_, loaded_codebook, loaded_files) = repo.load_project(path).await.unwrap();
+        assert_codebooks_match(&codebook, &loaded_codebook);
+        assert_filelists_match(&files, &loaded_files);
+    }
+
+    pub async fn survey_files_keep_their_cells<R: ProjectRepository>(repo: &R, path: &Path) {
+        // Setup: A survey file added incrementally, as an import does
+        repo.new_project(path, "Survey".to_string()).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file("survey.csv".to_string(), FileType::Csv);
+        let selection = SurveySelection {
+            sheet: None,
+            id_column: "ID".to_string(),
+            text_columns: vec!["Q1".to_string(), "Q2".to_string()],
+            attribute_columns: vec!["Age".to_string()],
+        };
+        let view = SurveyView { selection, scope: SurveyScope::Respondent("R7".to_string()) };
+        files.file_mut(id).unwrap().set_survey(Some(view.clone()));
+
+        // Execute
+        repo.insert_file(path, files.file(id).unwrap().clone()).await.unwrap();
+        let (_, _, loaded) = repo.load_project(path).await.unwrap();
+
+        // Assert
+        assert_eq!(loaded.file(id).unwrap().survey(), Some(&view));
+    }
+
+    pub async fn load_missing_file_is_load_error<R: ProjectRepository>(repo: &R, path: &Path) {
+        let err = repo.load_project(path).await.unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Load(_))),
+            "Missing file should be a Load error, got: {}", err
+        );
+    }
+}
+
+// ===== JSON backend =====
+
+mod json {
+    use super::*;
+
+    fn repo(path: &Path) -> JsonRepository {
+        JsonRepository::new(path.to_path_buf())
+    }
+
+    #[tokio::test]
+    async fn test_new_project_loads_empty() {
+        let (_dir, path) = project_path("project.json");
+        round_trip::new_project_loads_empty(&repo(&path), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_save_then_load_preserves_everything() {
+        let (_dir, path) = project_path("project.json");
+        round_trip::save_then_load_preserves_everything(&repo(&path), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_save_bumps_updated_at() {
+        let (_dir, path) = project_path("project.json");
+        round_trip::save_bumps_updated_at(&repo(&path), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_load_missing_file_is_load_error() {
+        let (_dir, path) = project_path("project.json");
+        round_trip::load_missing_file_is_load_error(&repo(&path), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_incremental_inserts_match_full_save() {
+        let (_dir, path) = project_path("project.json");
+        round_trip::incremental_inserts_match_full_save(&repo(&path), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_incremental_deletes_match_codebook_semantics() {
+        let (_dir, path) = project_path("project.json");
+        round_trip::incremental_deletes_match_codebook_semantics(&repo(&path), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_incremental_moves_match_in_memory_order() {
+        let (_dir, path) = project_path("project.json");
+        round_trip::incremental_moves_match_in_memory_order(&repo(&path), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_truncated_json_is_corrupted() {
+        // Setup: Valid project cut off mid-write
+        let (_dir, path) = project_path("project.json");
+        let repo = repo(&path);
+        repo.new_project(&path, "Study".to_string()).await.unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, &json[..json.len() / 2]).unwrap();
+
+        // Execute
+        let err = repo.load_project(&path).await.unwrap_err();
+
+        // Assert
+        assert!(
+            matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Corrupted(_))),
+            "Truncated JSON should be Corrupted, got: {}", err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_shape_is_invalid_format() {
+        // Setup: Valid JSON that isn't a project
+        let (_dir, path) = project_path("project.json");
+        std::fs::write(&path, r#"{"project": {"name": "Study"}, "codebook": []}"#).unwrap();
+
+        // Execute
+        let err = repo(&path).load_project(&path).await.unwrap_err();
+
+        // Assert
+        assert!(
+            matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::InvalidFormat(_))),
+            "Wrong shape should be InvalidFormat, got: {}", err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_survey_files_keep_their_cells() {
+        let (_dir, path) = project_path("project.json");
+        round_trip::survey_files_keep_their_cells(&repo(&path), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_save_to_missing_directory_is_save_error() {
+        let (_dir, path) = project_path("missing/project.json");
+
+        let err = repo(&path)
+            .save_project(&path, QualProject::new("Study".to_string(), 1, chrono::Utc::now(), chrono::Utc::now()), CodeBook::new(), FileList::new(), 0)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Save(_))),
+            "Unwritable path should be a Save error, got: {}", err
+        );
+    }
+}
+
+// ===== SQLite backend =====
+
+mod sqlite {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_new_project_loads_empty() {
+        let (_dir, path) = project_path("project.qualdb");
+        round_trip::new_project_loads_empty(&SqliteRepository::new(), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_save_then_load_preserves_everything() {
+        let (_dir, path) = project_path("project.qualdb");
+        round_trip::save_then_load_preserves_everything(&SqliteRepository::new(), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_save_bumps_updated_at() {
+        let (_dir, path) = project_path("project.qualdb");
+        round_trip::save_bumps_updated_at(&SqliteRepository::new(), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_load_missing_file_is_load_error() {
+        let (_dir, path) = project_path("project.qualdb");
+        round_trip::load_missing_file_is_load_error(&SqliteRepository::new(), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_incremental_inserts_match_full_save() {
+        let (_dir, path) = project_path("project.qualdb");
+        round_trip::incremental_inserts_match_full_save(&SqliteRepository::new(), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_incremental_deletes_match_codebook_semantics() {
+        let (_dir, path) = project_path("project.qualdb");
+        round_trip::incremental_deletes_match_codebook_semantics(&SqliteRepository::new(), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_incremental_moves_match_in_memory_order() {
+        let (_dir, path) = project_path("project.db");
+        round_trip::incremental_moves_match_in_memory_order(&SqliteRepository::new(), &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_non_database_file_is_corrupted() {
+        let (_dir, path) = project_path("project.qualdb");
+        std::fs::write(&path, "this is definitely not sqlite, just some text that is long enough").unwrap();
+
+        let err = SqliteRepository::new().load_project(&path).await.unwrap_err();
+
+        assert!(
+            matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Corrupted(_))),
+            "Garbage file should be Corrupted, got: {}", err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_newer_schema_is_refused() {
+        // Setup: Database written by a future version
+        let (_dir, path) = project_path("project.qualdb");
+        let repo = SqliteRepository::new();
+        repo.new_project(&path, "Study".to_string()).await.unwrap();
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute("UPDATE project SET data = json_set(data, '$.schema_version', 999)", []).unwrap();
+        drop(conn);
+
+        // Execute
+        let err = repo.load_project(&path).await.unwrap_err();
+
+        // Assert
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::InvalidFormat(_))));
+    }
+
+    #[tokio::test]
+    async fn test_survey_files_keep_their_cells() {
+        let (_dir, path) = project_path("project.qualdb");
+        round_trip::survey_files_keep_their_cells(&SqliteRepository::new(), &path).await;
+    }
+
+    /// Registry that knows one version past the current one, with a step that changes nothing
+    fn one_version_ahead() -> crate::migration::MigrationRegistry {
+        let current = crate::migration::CURRENT_SCHEMA_VERSION;
+        crate::migration::MigrationRegistry::new(current + 1)
+            .register(current, "No-op upgrade", |_| Ok(()))
+    }
+
+    /// Names of the files in `dir`, such as a database's `-wal` and `-shm` companions
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_migrating_load_leaves_the_database_alone() {
+        // Setup
+        let (dir, path) = project_path("project.qualdb");
+        SqliteRepository::new().new_project(&path, "Study".to_string()).await.unwrap();
+        let names = file_names(dir.path());
+        let bytes = std::fs::read(&path).unwrap();
+        let repo = SqliteRepository::new().with_migrations(one_version_ahead());
+
+        // Execute
+        let (project, _, _) = repo.load_project(&path).await.unwrap();
+
+        // Assert: Upgraded in memory only
+        assert_eq!(project.schema_version(), crate::migration::CURRENT_SCHEMA_VERSION + 1);
+        assert!(repo.take_migration_report().is_some());
+        assert_eq!(file_names(dir.path()), names);
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+    }
+
+    #[tokio::test]
+    async fn test_first_write_after_migrating_upgrades_rows_and_keeps_copy() {
+        // Setup
+        let (dir, path) = project_path("project.qualdb");
+        SqliteRepository::new().new_project(&path, "Study".to_string()).await.unwrap();
+        let current = crate::migration::CURRENT_SCHEMA_VERSION;
+        let repo = SqliteRepository::new().with_migrations(one_version_ahead());
+        repo.load_project(&path).await.unwrap();
+        repo.take_migration_report();
+
+        // Execute: An incremental write rather than a full save
+        let files = populated_filelist();
+        repo.insert_file(&path, files.get_all_files().next().unwrap().clone()).await.unwrap();
+
+        // Assert: Copied, and stored at the new version alongside the new row
+        assert!(dir.path().join(format!("project.qualdb.v{}", current)).exists(), "Pre-migration copy should be kept");
+        let (reloaded, _, reloaded_files) = repo.load_project(&path).await.unwrap();
+        assert_eq!(reloaded.schema_version(), current + 1);
+        assert_eq!(reloaded_files.file_count(), 1);
+        assert!(repo.take_migration_report().is_none(), "Rows should already be at the new version");
+    }
+}
+
+// ===== Atomic writes and backups =====
+
+mod backups {
+    use super::*;
+    use crate::atomic::backup_path;
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_saves_rotate_backups_up_to_limit() {
+        // Setup
+        let (dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone()).with_backup_count(2);
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+
+        // Execute: Several saves
+        for _ in 0..4 {
+            repo.save_project(&path, project.clone(), CodeBook::new(), FileList::new(), repo.journal_mark(&path)).await.unwrap();
+        }
+
+        // Assert: Exactly two backups and no stray temp files
+        assert_eq!(
+            dir_entries(dir.path()),
+            vec!["project.json", "project.json.bak.1", "project.json.bak.2"],
+            "Only the configured number of backups should be kept"
+        );
+        assert_eq!(repo.list_backups(&path).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_zero_backup_count_keeps_no_backups() {
+        let (dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone()).with_backup_count(0);
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+
+        repo.save_project(&path, project, CodeBook::new(), FileList::new(), repo.journal_mark(&path)).await.unwrap();
+
+        assert_eq!(dir_entries(dir.path()), vec!["project.json"]);
+    }
+
+    #[tokio::test]
+    async fn test_backup_holds_previous_state() {
+        // Setup: Save an empty codebook, then one with codes
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+        repo.save_project(&path, project.clone(), populated_codebook(), FileList::new(), repo.journal_mark(&path)).await.unwrap();
+
+        // Assert: bak.1 is the empty project from new_project
+        let bytes = std::fs::read(backup_path(&path, 1)).unwrap();
+        let backup: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(backup["codebook"]["code_defs"].as_object().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_restore_backup_recovers_from_corruption() {
+        // Setup: Good save, then a second save, then corrupt the live file
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+        let codebook = populated_codebook();
+        repo.save_project(&path, project.clone(), codebook.clone(), FileList::new(), repo.journal_mark(&path)).await.unwrap();
+        repo.save_project(&path, project.clone(), codebook.clone(), FileList::new(), repo.journal_mark(&path)).await.unwrap();
+        std::fs::write(&path, "{\"project\": {").unwrap();
+
+        let err = repo.load_project(&path).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Corrupted(_))));
+
+        // Execute
+        repo.restore_backup(&path, 1).await.unwrap();
+
+        // Assert: Last good state is back
+        let (_, restored, _) = repo.load_project(&path).await.unwrap();
+        assert_codebooks_match(&codebook, &restored);
+    }
+
+    #[tokio::test]
+    async fn test_restore_corrupted_backup_leaves_file_untouched() {
+        // Setup
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+        repo.save_project(&path, project, CodeBook::new(), FileList::new(), repo.journal_mark(&path)).await.unwrap();
+        std::fs::write(backup_path(&path, 1), "not json").unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        // Execute
+        let err = repo.restore_backup(&path, 1).await.unwrap_err();
+
+        // Assert
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Corrupted(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), before, "Live file should be unchanged");
+    }
+
+    #[tokio::test]
+    async fn test_restore_missing_backup_is_load_error() {
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        repo.new_project(&path, "Study".to_string()).await.unwrap();
+
+        let err = repo.restore_backup(&path, 3).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Load(_))));
+    }
+}
+
+// ===== Autosave coordination =====
+
+mod autosave {
+    use super::*;
+
+    fn saved_codes(path: &Path) -> usize {
+        let json: serde_json::Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        json["codebook"]["code_defs"].as_object().unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_autosaves_are_coalesced() {
+        // Setup
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+
+        // Execute: Second autosave arrives while the first is writing
+        let (first, second) = tokio::join!(
+            repo.autosave_project(&path, project.clone(), populated_codebook(), FileList::new(), repo.journal_mark(&path)),
+            repo.autosave_project(&path, project.clone(), CodeBook::new(), FileList::new(), repo.journal_mark(&path)),
+        );
+
+        // Assert: Only the first wrote; the second was folded into a later save
+        assert!(first.unwrap(), "First autosave should write");
+        assert!(!second.unwrap(), "Overlapping autosave should be skipped");
+        assert!(repo.autosave_pending(), "Skipped autosave should be recorded as pending");
+        assert_eq!(saved_codes(&path), 3);
+    }
+
+    #[tokio::test]
+    async fn test_manual_save_takes_priority_over_autosave() {
+        // Setup
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+
+        // Execute: Autosave requested while a manual save is in progress
+        let (manual, auto) = tokio::join!(
+            repo.save_project(&path, project.clone(), populated_codebook(), FileList::new(), repo.journal_mark(&path)),
+            repo.autosave_project(&path, project.clone(), CodeBook::new(), FileList::new(), repo.journal_mark(&path)),
+        );
+
+        // Assert: The manual save's state is on disk
+        manual.unwrap();
+        assert!(!auto.unwrap(), "Autosave should yield to the manual save");
+        assert_eq!(saved_codes(&path), 3);
+    }
+
+    #[tokio::test]
+    async fn test_autosave_does_not_rotate_backups() {
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+
+        for _ in 0..3 {
+            assert!(repo.autosave_project(&path, project.clone(), CodeBook::new(), FileList::new(), repo.journal_mark(&path)).await.unwrap());
+        }
+
+        assert!(repo.list_backups(&path).await.is_empty(), "Autosaves should leave backups to manual saves");
+    }
+}
+
+// ===== Change journal =====
+
+mod journal {
+    use super::*;
+    use crate::journal::journal_path;
+
+    fn saved_codes(path: &Path) -> usize {
+        let json: serde_json::Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        json["codebook"]["code_defs"].as_object().unwrap().len()
+    }
+
+    /// Saved empty project plus a repo that has journaled every code def of `populated_codebook`
+    async fn journaled_project(path: &Path) -> (JsonRepository, CodeBook) {
+        let repo = JsonRepository::new(path.to_path_buf());
+        repo.new_project(path, "Study".to_string()).await.unwrap();
+        let codebook = populated_codebook();
+        for code in codebook.get_all_code_defs() {
+            repo.insert_code_def(path, code.clone()).await.unwrap();
+        }
+        (repo, codebook)
+    }
+
+    #[tokio::test]
+    async fn test_incremental_writes_leave_snapshot_untouched() {
+        // Setup / Execute
+        let (_dir, path) = project_path("project.json");
+        journaled_project(&path).await;
+
+        // Assert: Changes live in the journal until the next full save
+        assert_eq!(saved_codes(&path), 0, "Snapshot should not be rewritten per change");
+        assert!(journal_path(&path).exists(), "Changes should be journaled");
+    }
+
+    #[tokio::test]
+    async fn test_load_replays_journal_after_crash() {
+        // Setup: Changes journaled, then the process "crashes" without saving
+        let (_dir, path) = project_path("project.json");
+        let (_, codebook) = journaled_project(&path).await;
+
+        // Execute: A fresh repository loads the project
+        let (_, loaded, _) = JsonRepository::new(path.clone()).load_project(&path).await.unwrap();
+
+        // Assert
+        assert_eq!(loaded.get_all_code_defs().count(), codebook.get_all_code_defs().count());
+    }
+
+    #[tokio::test]
+    async fn test_save_truncates_journal() {
+        // Setup
+        let (_dir, path) = project_path("project.json");
+        let (repo, codebook) = journaled_project(&path).await;
+        let (project, _, _) = repo.load_project(&path).await.unwrap();
+
+        // Execute
+        repo.save_project(&path, project, codebook, FileList::new(), repo.journal_mark(&path)).await.unwrap();
+
+        // Assert
+        assert!(!journal_path(&path).exists(), "Saved changes should leave the journal");
+        assert_eq!(saved_codes(&path), 3);
+    }
+
+    #[tokio::test]
+    async fn test_save_keeps_entries_journaled_after_the_snapshot() {
+        // Setup: The snapshot and its mark are taken, then another edit is journaled
+        let (_dir, path) = project_path("project.json");
+        let (repo, codebook) = journaled_project(&path).await;
+        let (project, _, _) = repo.load_project(&path).await.unwrap();
+        let covered = repo.journal_mark(&path);
+        let theme = populated_codebook().get_all_themes().next().unwrap().clone();
+        repo.insert_theme_def(&path, theme.clone()).await.unwrap();
+
+        // Execute
+        repo.save_project(&path, project, codebook, FileList::new(), covered).await.unwrap();
+
+        // Assert: The saved entries are gone, the later one survives a reload
+        let (_, reloaded, _) = JsonRepository::new(path.clone()).load_project(&path).await.unwrap();
+        assert_eq!(reloaded.get_all_code_defs().count(), 3);
+        assert!(reloaded.theme(theme.id).is_some(), "An edit made after the snapshot should be kept");
+        assert_eq!(std::fs::read_to_string(journal_path(&path)).unwrap().lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_torn_last_line_is_ignored_and_repaired() {
+        // Setup: A crash mid-append leaves half a line at the end
+        let (_dir, path) = project_path("project.json");
+        let (repo, codebook) = journaled_project(&path).await;
+        let journal = journal_path(&path);
+        let mut bytes = std::fs::read(&journal).unwrap();
+        bytes.extend_from_slice(br#"{"at":"2024-01-01T00:00:00Z","entry":{"op":"code_def_del"#);
+        std::fs::write(&journal, &bytes).unwrap();
+
+        // Execute: Load, then keep editing
+        let (_, loaded, _) = repo.load_project(&path).await.unwrap();
+        let removed = codebook.get_all_code_defs().next().unwrap().id;
+        repo.delete_code_def(&path, removed).await.unwrap();
+
+        // Assert: The fragment was dropped and the new entry is readable
+        assert_eq!(loaded.get_all_code_defs().count(), 3);
+        let (_, reloaded, _) = repo.load_project(&path).await.unwrap();
+        assert_eq!(reloaded.get_all_code_defs().count(), 2);
+        assert!(reloaded.code_def(removed).is_none());
+    }
+
+    /// Journaled project whose second entry has been damaged, with the journal's original bytes
+    async fn damaged_journal_project(path: &Path) -> (JsonRepository, Vec<u8>) {
+        let (repo, _) = journaled_project(path).await;
+        let journal = journal_path(path);
+        let contents = std::fs::read_to_string(&journal).unwrap();
+        let mut lines: Vec<&str> = contents.lines().collect();
+        lines[1] = "not json";
+        std::fs::write(&journal, lines.join("\n") + "\n").unwrap();
+        (repo, std::fs::read(&journal).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_damaged_middle_line_replays_up_to_it() {
+        // Setup
+        let (dir, path) = project_path("project.json");
+        let (repo, damaged) = damaged_journal_project(&path).await;
+        let saved = std::fs::read(&path).unwrap();
+
+        // Execute
+        let (_, codebook, _) = repo.load_project(&path).await.unwrap();
+        let recovery = repo.take_journal_recovery().expect("Damage should be reported");
+
+        // Assert: Loaded with the first entry, and nothing on disk touched yet
+        assert_eq!(codebook.get_all_code_defs().count(), 1);
+        assert_eq!((recovery.replayed, recovery.skipped), (1, 2));
+        assert_eq!(recovery.kept_at, dir.path().join("project.json.journal.damaged"));
+        assert!(!recovery.kept_at.exists());
+        assert_eq!(std::fs::read(journal_path(&path)).unwrap(), damaged);
+        assert_eq!(std::fs::read(&path).unwrap(), saved);
+        assert!(repo.take_journal_recovery().is_none(), "Taking the report should clear it");
+    }
+
+    #[tokio::test]
+    async fn test_next_write_keeps_the_damaged_journal_aside() {
+        // Setup
+        let (_dir, path) = project_path("project.json");
+        let (repo, damaged) = damaged_journal_project(&path).await;
+        repo.load_project(&path).await.unwrap();
+        let kept_at = repo.take_journal_recovery().unwrap().kept_at;
+
+        // Execute: Keep editing after the load
+        let theme = populated_codebook().get_all_themes().next().unwrap().clone();
+        repo.insert_theme_def(&path, theme.clone()).await.unwrap();
+
+        // Assert: The whole journal is kept, and the new entry follows the replayed one
+        assert_eq!(std::fs::read(&kept_at).unwrap(), damaged);
+        let (_, reloaded, _) = repo.load_project(&path).await.unwrap();
+        assert!(repo.take_journal_recovery().is_none());
+        assert_eq!(reloaded.get_all_code_defs().count(), 1);
+        assert!(reloaded.theme(theme.id).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_restore_backup_discards_journal() {
+        // Setup: Two saves so there is a backup, then unsaved journaled changes
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+        repo.save_project(&path, project, CodeBook::new(), FileList::new(), repo.journal_mark(&path)).await.unwrap();
+        repo.insert_code_def(&path, populated_codebook().get_all_code_defs().next().unwrap().clone()).await.unwrap();
+
+        // Execute
+        repo.restore_backup(&path, 1).await.unwrap();
+
+        // Assert
+        assert!(!journal_path(&path).exists(), "Restored snapshot should not replay old changes");
+        let (_, loaded, _) = repo.load_project(&path).await.unwrap();
+        assert_eq!(loaded.get_all_code_defs().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_incremental_write_without_project_is_save_error() {
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+
+        let err = repo.delete_file(&path, FileList::new().add_file("a.txt".to_string(), FileType::PlainText)).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Save(_))));
+        assert!(!journal_path(&path).exists(), "No journal should be created for a missing project");
+    }
+}
+
+// ===== Project locks =====
+
+mod locks {
+    use super::*;
+    use crate::lock::{self, lock_path};
+    use chrono::Utc;
+
+    fn write_lock(path: &Path, host: &str, pid: u32) {
+        let owner = ProjectLock { host: host.to_string(), pid, acquired_at: Utc::now() };
+        std::fs::write(lock_path(path), serde_json::to_vec(&owner).unwrap()).unwrap();
+    }
+
+    /// A pid above any real pid_max, so it is never a running process
+    const DEAD_PID: u32 = i32::MAX as u32;
+
+    #[tokio::test]
+    async fn test_lock_records_owner_and_unlock_removes_it() {
+        // Setup
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+
+        // Execute
+        repo.lock_project(&path, false).await.unwrap();
+        let held = lock::read(&path).await;
+        repo.unlock_project(&path).await.unwrap();
+
+        // Assert
+        let held = held.expect("Lock file should be readable");
+        assert_eq!(held.pid, std::process::id());
+        assert_eq!(held, ProjectLock { acquired_at: held.acquired_at, ..lock::current_owner() });
+        assert!(!lock_path(&path).exists(), "Unlock should remove our lock file");
+    }
+
+    #[tokio::test]
+    async fn test_same_process_can_relock() {
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+
+        repo.lock_project(&path, false).await.unwrap();
+
+        repo.lock_project(&path, false).await.expect("Our own lock should not conflict");
+    }
+
+    #[tokio::test]
+    async fn test_lock_held_elsewhere_is_reported() {
+        // Setup: Another machine on a shared drive has the project open
+        let (_dir, path) = project_path("project.json");
+        write_lock(&path, "other-host", 4242);
+        let repo = JsonRepository::new(path.clone());
+
+        // Execute
+        let err = repo.lock_project(&path, false).await.unwrap_err();
+
+        // Assert
+        let Some(ProjectError::Locked(owner)) = err.downcast_ref::<ProjectError>() else {
+            panic!("Expected Locked, got: {}", err)
+        };
+        assert_eq!((owner.host.as_str(), owner.pid), ("other-host", 4242));
+        assert!(err.to_string().contains("other-host"), "Message should name the owner: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_force_takes_over_lock() {
+        let (_dir, path) = project_path("project.json");
+        write_lock(&path, "other-host", 4242);
+        let repo = JsonRepository::new(path.clone());
+
+        repo.lock_project(&path, true).await.unwrap();
+
+        assert_eq!(lock::read(&path).await.unwrap().pid, std::process::id());
+    }
+
+    #[tokio::test]
+    async fn test_stale_lock_is_cleaned_up() {
+        // Setup: Lock left by a process on this machine that has since died
+        let (_dir, path) = project_path("project.json");
+        write_lock(&path, &lock::current_owner().host, DEAD_PID);
+        let repo = JsonRepository::new(path.clone());
+
+        // Execute
+        repo.lock_project(&path, false).await.expect("Stale lock should not block");
+
+        // Assert
+        assert_eq!(lock::read(&path).await.unwrap().pid, std::process::id());
+    }
+
+    #[tokio::test]
+    async fn test_lock_with_pid_zero_is_stale() {
+        // Setup: A corrupt lock naming pid 0, which kill() would take as our own process group
+        let (_dir, path) = project_path("project.json");
+        write_lock(&path, &lock::current_owner().host, 0);
+        let repo = JsonRepository::new(path.clone());
+
+        // Execute
+        repo.lock_project(&path, false).await.expect("Pid 0 is never a live owner");
+
+        // Assert
+        assert_eq!(lock::read(&path).await.unwrap().pid, std::process::id());
+    }
+
+    #[tokio::test]
+    async fn test_unlock_leaves_foreign_lock() {
+        let (_dir, path) = project_path("project.json");
+        write_lock(&path, "other-host", 4242);
+        let repo = JsonRepository::new(path.clone());
+
+        repo.unlock_project(&path).await.unwrap();
+
+        assert!(lock_path(&path).exists(), "Someone else's lock must not be released");
+    }
+
+    #[tokio::test]
+    async fn test_writes_refused_after_takeover_elsewhere() {
+        // Setup: We opened the project, then another instance forced the lock away
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        repo.lock_project(&path, false).await.unwrap();
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+        write_lock(&path, "other-host", 4242);
+
+        // Execute
+        let save = repo.save_project(&path, project.clone(), populated_codebook(), FileList::new(), repo.journal_mark(&path)).await;
+        let autosave = repo.autosave_project(&path, project, populated_codebook(), FileList::new(), repo.journal_mark(&path)).await;
+        let incremental = repo.delete_file(&path, FileList::new().add_file("a.txt".to_string(), FileType::PlainText)).await;
+
+        // Assert: Nothing of ours clobbered their copy
+        for result in [save.map(|_| ()), autosave.map(|_| ()), incremental] {
+            let err = result.unwrap_err();
+            assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Locked(_))), "Got: {}", err);
+        }
+        let (_, codebook, _) = repo.load_project(&path).await.unwrap();
+        assert_eq!(codebook.get_all_code_defs().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_lock_held_elsewhere_is_reported() {
+        // Setup: Another instance has the database open
+        let (_dir, path) = project_path("project.qualdb");
+        let repo = SqliteRepository::new();
+        repo.new_project(&path, "Study".to_string()).await.unwrap();
+        write_lock(&path, "other-host", 4242);
+
+        // Execute
+        let err = repo.lock_project(&path, false).await.unwrap_err();
+
+        // Assert
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Locked(_))), "Got: {}", err);
+        repo.lock_project(&path, true).await.unwrap();
+        repo.unlock_project(&path).await.unwrap();
+        assert!(!lock_path(&path).exists(), "Unlock should remove our lock file");
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_writes_refused_after_takeover_elsewhere() {
+        // Setup
+        let (_dir, path) = project_path("project.qualdb");
+        let repo = SqliteRepository::new();
+        repo.lock_project(&path, false).await.unwrap();
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+        write_lock(&path, "other-host", 4242);
+
+        // Execute
+        let save = repo.save_project(&path, project, populated_codebook(), FileList::new(), repo.journal_mark(&path)).await;
+        let incremental = repo.delete_file(&path, FileList::new().add_file("a.txt".to_string(), FileType::PlainText)).await;
+
+        // Assert
+        for result in [save, incremental] {
+            let err = result.unwrap_err();
+            assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::Locked(_))), "Got: {}", err);
+        }
+        let (_, codebook, _) = repo.load_project(&path).await.unwrap();
+        assert_eq!(codebook.get_all_code_defs().count(), 0);
+    }
+}
+
+// ===== File import =====
+
+mod file_import {
+    use super::*;
+    use crate::file_loader::FsFileLoader;
+    use app_core::ports::FileLoader;
+
+    /// Project directory with one file written into it
+    fn file_with(name: &str, contents: &[u8]) -> (TempDir, PathBuf) {
+        let (dir, path) = project_path(name);
+        std::fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    async fn import(root: &Path, path: &Path) -> anyhow::Result<FileImport> {
+        FsFileLoader::new().add_file(root, path, None).await
+    }
+
+    fn assert_file_error(result: anyhow::Result<FileImport>, expected: fn(&FileError) -> bool) {
+        let err = result.unwrap_err();
+        let file_error = err.downcast_ref::<FileError>().unwrap_or_else(|| panic!("Expected FileError, got: {}", err));
+        assert!(expected(file_error), "Unexpected error: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_text_types_come_from_extension() {
+        let (dir, notes) = file_with("notes.md", b"# Heading\n\nBody");
+        let transcript = dir.path().join("transcript.TXT");
+        std::fs::write(&transcript, "Interviewer: hello").unwrap();
+
+        assert_eq!(import(dir.path(), &notes).await.unwrap().file_type, FileType::Markdown);
+        assert_eq!(import(dir.path(), &transcript).await.unwrap().file_type, FileType::PlainText);
+    }
+
+    #[tokio::test]
+    async fn test_signature_wins_over_extension() {
+        // Setup: A PDF saved with the wrong extension
+        let (dir, path) = file_with("report.txt", b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n");
+
+        // Execute
+        let imported = import(dir.path(), &path).await.unwrap();
+
+        // Assert
+        assert_eq!(imported.file_type, FileType::Pdf);
+    }
+
+    #[tokio::test]
+    async fn test_rtf_is_sniffed() {
+        let (dir, path) = file_with("memo", br"{\rtf1\ansi Hello}");
+
+        assert_eq!(import(dir.path(), &path).await.unwrap().file_type, FileType::RichText);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_extension_with_text_is_plain_text() {
+        let (dir, path) = file_with("field_notes.log", b"Observed the group at 10am");
+
+        assert_eq!(import(dir.path(), &path).await.unwrap().file_type, FileType::PlainText);
+    }
+
+    #[tokio::test]
+    async fn test_binary_file_is_unsupported() {
+        let (dir, path) = file_with("photo.jpg", &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00]);
+
+        assert_file_error(import(dir.path(), &path).await, |e| matches!(e, FileError::Unsupported(_)));
+    }
+
+    #[tokio::test]
+    async fn test_fake_pdf_is_unsupported() {
+        let (dir, path) = file_with("report.pdf", b"definitely not a pdf");
+
+        assert_file_error(import(dir.path(), &path).await, |e| matches!(e, FileError::Unsupported(_)));
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_is_read_error() {
+        let (dir, path) = project_path("gone.txt");
+
+        assert_file_error(import(dir.path(), &path).await, |e| matches!(e, FileError::Read(_)));
+    }
+
+    #[tokio::test]
+    async fn test_directory_is_unsupported() {
+        let (dir, _) = project_path("project.json");
+
+        assert_file_error(import(dir.path(), dir.path()).await, |e| matches!(e, FileError::Unsupported(_)));
+    }
+
+    #[tokio::test]
+    async fn test_path_is_relative_to_project_root() {
+        // Setup: File in a subfolder, reached through a `..` detour
+        let (dir, _) = project_path("project.json");
+        std::fs::create_dir(dir.path().join("interviews")).unwrap();
+        std::fs::write(dir.path().join("interviews").join("a.txt"), "text").unwrap();
+        let detour = dir.path().join("interviews").join("..").join("interviews").join("a.txt");
+
+        // Execute
+        let imported = import(dir.path(), &detour).await.unwrap();
+
+        // Assert
+        assert_eq!(PathBuf::from(imported.path), Path::new("interviews").join("a.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_path_outside_root_stays_absolute() {
+        let (root, _) = project_path("project.json");
+        let (_elsewhere, path) = file_with("a.txt", b"text");
+
+        let imported = import(root.path(), &path).await.unwrap();
+
+        assert!(Path::new(&imported.path).is_absolute(), "Got: {}", imported.path);
+        assert!(imported.path.ends_with("a.txt"));
+    }
+}
+
+// ===== Folder import =====
+
+mod folder_import {
+    use super::*;
+    use crate::file_loader::FsFileLoader;
+    use app_core::ports::FileLoader;
+
+    /// Folder holding interviews, a nested folder, a hidden file and a hidden folder
+    fn study_folder() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        for name in ["a.txt", "b.MD", "notes.log", ".DS_Store", "round2/c.txt", "round2/draft.txt", ".git/HEAD"] {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "text").unwrap();
+        }
+        dir
+    }
+
+    async fn find(folder: &Path, include: &[&str], exclude: &[&str], recursive: bool) -> anyhow::Result<Vec<String>> {
+        let filter = FolderFilter {
+            include: include.iter().map(|p| p.to_string()).collect(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+            recursive,
+        };
+        let files = FsFileLoader::new().find_files(folder, &filter).await?;
+        Ok(files.iter().map(|path| path.strip_prefix(folder).unwrap().to_string_lossy().replace('\\', "/")).collect())
+    }
+
+    #[tokio::test]
+    async fn test_finds_every_visible_file() {
+        let dir = study_folder();
+
+        let recursive = find(dir.path(), &[], &[], true).await.unwrap();
+        let top_level = find(dir.path(), &[], &[], false).await.unwrap();
+
+        assert_eq!(recursive, vec!["a.txt", "b.MD", "notes.log", "round2/c.txt", "round2/draft.txt"]);
+        assert_eq!(top_level, vec!["a.txt", "b.MD", "notes.log"]);
+    }
+
+    #[tokio::test]
+    async fn test_include_and_exclude_patterns() {
+        // Setup
+        let dir = study_folder();
+
+        // Execute: Patterns ignore case and match files in subfolders too
+        let found = find(dir.path(), &["*.txt", "*.md"], &["**/draft*"], true).await.unwrap();
+
+        // Assert
+        assert_eq!(found, vec!["a.txt", "b.MD", "round2/c.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_pattern_is_a_parse_error() {
+        let dir = study_folder();
+
+        let err = find(dir.path(), &["[a-"], &[], true).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<FileError>(), Some(FileError::Parse(_))), "Unexpected error: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_file_or_missing_folder_is_rejected() {
+        let dir = study_folder();
+
+        let file = find(&dir.path().join("a.txt"), &[], &[], true).await.unwrap_err();
+        let missing = find(&dir.path().join("round3"), &[], &[], true).await.unwrap_err();
+
+        assert!(matches!(file.downcast_ref::<FileError>(), Some(FileError::Unsupported(_))), "Unexpected error: {}", file);
+        assert!(matches!(missing.downcast_ref::<FileError>(), Some(FileError::Read(_))), "Unexpected error: {}", missing);
+    }
+
+    #[test]
+    fn test_only_the_project_and_its_companions_are_project_files() {
+        let project = Path::new("/study/project.json");
+        let is_project_file = |name: &str| JsonRepository::new(PathBuf::new()).is_project_file(project, &Path::new("/study").join(name));
+
+        for name in ["project.json", "project.json.lock", "project.json.journal", "project.json.journal.damaged",
+                     "project.json.journal.damaged.2", "project.json.bak.1", "project.json.v1"] {
+            assert!(is_project_file(name), "{} belongs to the project", name);
+        }
+        for target in [project.to_path_buf(), crate::journal::journal_path(project)] {
+            let temp = crate::atomic::temp_path(&target);
+            assert!(is_project_file(temp.file_name().unwrap().to_str().unwrap()), "{} is a half-written save", temp.display());
+        }
+        for name in ["project.json-notes.txt", "project.json.bak.old", "project.json.version", "sub/project.json.bak.1", "other.json.bak.1",
+                     "project.json.tmp-12-345", ".other.json.tmp-12-345"] {
+            assert!(!is_project_file(name), "{} is the user's own file", name);
+        }
+        let database = Path::new("/study/project.qualdb");
+        assert!(SqliteRepository::new().is_project_file(database, Path::new("/study/project.qualdb-wal")));
+    }
+}
+
+// ===== File loading =====
+
+mod file_loading {
+    use super::*;
+    use crate::file_loader::FsFileLoader;
+    use crate::loaders::text::segment_paragraphs;
+    use app_core::ports::FileLoader;
+
+    /// Registers `name` in a fresh FileList after writing `contents` next to the project
+    fn project_file(dir: &TempDir, name: &str, contents: &[u8], file_type: FileType) -> QualFile {
+        std::fs::write(dir.path().join(name), contents).unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(name.to_string(), file_type);
+        files.file(id).unwrap().clone()
+    }
+
+    #[test]
+    fn test_paragraphs_split_on_blank_lines() {
+        let text = "\nFirst line\nstill first\n\n  \r\nSecond\r\n\n\n\nThird  \n";
+
+        assert_eq!(segment_paragraphs(text), vec!["First line\nstill first", "Second", "Third"]);
+        assert!(segment_paragraphs(" \n\n").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_segments_and_is_reproducible() {
+        // Setup
+        let (dir, _) = project_path("project.json");
+        let file = project_file(&dir, "a.txt", "Q: How are you?\n\nA: Fine.\nReally.\n".as_bytes(), FileType::PlainText);
+        let loader = FsFileLoader::new();
+
+        // Execute: Load twice, as two sessions would
+        let first = loader.load_file(dir.path(), &file).await.unwrap();
+        let second = loader.load_file(dir.path(), &file).await.unwrap();
+
+        // Assert
+        let contents: Vec<&str> = first.iter().map(|b| b.content.as_str()).collect();
+        assert_eq!(contents, vec!["Q: How are you?", "A: Fine.\nReally."]);
+        assert!(first.iter().enumerate().all(|(i, b)| b.sequence == i && b.file_id == file.id));
+        let ids = |blocks: &[TextBlock]| blocks.iter().map(|b| b.id).collect::<Vec<_>>();
+        assert_eq!(ids(&first), ids(&second), "Same file should give the same BlockIds");
+    }
+
+    #[tokio::test]
+    async fn test_utf8_bom_is_stripped() {
+        let (dir, _) = project_path("project.json");
+        let file = project_file(&dir, "notes.md", b"\xEF\xBB\xBF# Notes", FileType::Markdown);
+
+        let blocks = FsFileLoader::new().load_file(dir.path(), &file).await.unwrap();
+
+        assert_eq!((blocks[0].content.as_str(), blocks[0].meta.kind), ("Notes", BlockKind::Heading(1)));
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_text_is_encoding_error() {
+        let (dir, _) = project_path("project.json");
+        let file = project_file(&dir, "a.txt", b"\xEF\xBB\xBFcaf\xE9", FileType::PlainText);
+
+        let err = FsFileLoader::new().load_file(dir.path(), &file).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<FileError>(), Some(FileError::Encoding(m)) if m.contains("a.txt")), "Got: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_utf16_transcript_is_importable() {
+        // Setup: BOM-less UTF-16, as some Windows tools write it
+        let (dir, path) = project_path("a.txt");
+        let bytes: Vec<u8> = "Interviewer: Hi\n\nRespondent: Hello".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        std::fs::write(&path, &bytes).unwrap();
+        let loader = FsFileLoader::new();
+
+        // Execute
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+        let blocks = loader.load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        // Assert
+        assert_eq!(blocks.iter().map(|b| b.content.as_str()).collect::<Vec<_>>(), vec!["Interviewer: Hi", "Respondent: Hello"]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_source_is_read_error() {
+        let (dir, _) = project_path("project.json");
+        let file = project_file(&dir, "a.txt", b"text", FileType::PlainText);
+        std::fs::remove_file(dir.path().join("a.txt")).unwrap();
+
+        let err = FsFileLoader::new().load_file(dir.path(), &file).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<FileError>(), Some(FileError::Read(_))), "Got: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_fingerprint_tracks_contents() {
+        // Setup
+        let (dir, _) = project_path("project.json");
+        let file = project_file(&dir, "a.txt", b"First version", FileType::PlainText);
+        let loader = FsFileLoader::new();
+        let original = loader.fingerprint(dir.path(), &file).await.unwrap().unwrap();
+
+        // Execute
+        let again = loader.fingerprint(dir.path(), &file).await.unwrap().unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"Second version").unwrap();
+        let edited = loader.fingerprint(dir.path(), &file).await.unwrap().unwrap();
+
+        // Assert
+        assert_eq!(original.sha256.len(), 64);
+        assert_eq!(original.size, 13);
+        assert_eq!(original.drift_to(&again), FileDrift::Unchanged);
+        assert!(matches!(original.drift_to(&edited), FileDrift::Edited(_)));
+    }
+
+    #[tokio::test]
+    async fn test_missing_source_has_no_fingerprint() {
+        let (dir, _) = project_path("project.json");
+        let file = project_file(&dir, "a.txt", b"text", FileType::PlainText);
+        std::fs::remove_file(dir.path().join("a.txt")).unwrap();
+
+        assert!(FsFileLoader::new().fingerprint(dir.path(), &file).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_import_fingerprint_matches_later_check() {
+        let (dir, path) = project_path("a.txt");
+        std::fs::write(&path, b"Transcript").unwrap();
+        let loader = FsFileLoader::new();
+
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+        let current = loader.fingerprint(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        assert_eq!(current, Some(imported.fingerprint));
+    }
+
+    /// `a.txt` registered with a fingerprint, then moved away from where it was added
+    async fn moved_file(dir: &TempDir, contents: &[u8]) -> QualFile {
+        let loader = FsFileLoader::new();
+        let original = dir.path().join("a.txt");
+        std::fs::write(&original, contents).unwrap();
+        let imported = loader.add_file(dir.path(), &original, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+        files.file_mut(id).unwrap().set_fingerprint(imported.fingerprint);
+        std::fs::remove_file(&original).unwrap();
+        files.file(id).unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_candidates_are_matched_by_contents() {
+        // Setup: The file now lives in a subfolder, next to a same named file and a renamed copy
+        let (dir, _) = project_path("project.json");
+        let file = moved_file(&dir, b"Interview transcript").await;
+        std::fs::create_dir_all(dir.path().join("archive/old")).unwrap();
+        std::fs::write(dir.path().join("archive/old/a.txt"), b"Interview transcript").unwrap();
+        std::fs::write(dir.path().join("archive/copy.txt"), b"Interview transcript").unwrap();
+        std::fs::write(dir.path().join("archive/a.txt"), b"Something different!").unwrap();
+
+        // Execute: The project folder is also passed as a user folder
+        let folders = [dir.path().to_path_buf(), dir.path().join("archive")];
+        let proposals = FsFileLoader::new().find_candidates(dir.path(), &folders, &file).await.unwrap();
+
+        // Assert: Same name first, no duplicates, and paths relative to the project
+        let paths: Vec<&str> = proposals.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(paths, vec!["archive/old/a.txt", "archive/copy.txt"]);
+        assert!(proposals.iter().all(|p| p.matched_by == RelinkMatch::Content && p.file_id == file.id));
+    }
+
+    #[tokio::test]
+    async fn test_files_without_fingerprint_are_matched_by_name() {
+        let (dir, _) = project_path("project.json");
+        std::fs::create_dir_all(dir.path().join("moved")).unwrap();
+        std::fs::write(dir.path().join("moved/a.txt"), b"Anything").unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file("a.txt".to_string(), FileType::PlainText);
+
+        let proposals = FsFileLoader::new().find_candidates(dir.path(), &[dir.path().to_path_buf()], files.file(id).unwrap()).await.unwrap();
+
+        assert_eq!(proposals, vec![RelinkProposal { file_id: id, path: "moved/a.txt".to_string(), matched_by: RelinkMatch::Name }]);
+    }
+
+    #[tokio::test]
+    async fn test_hidden_folders_are_not_searched() {
+        let (dir, _) = project_path("project.json");
+        let file = moved_file(&dir, b"Interview transcript").await;
+        std::fs::create_dir_all(dir.path().join(".trash")).unwrap();
+        std::fs::write(dir.path().join(".trash/a.txt"), b"Interview transcript").unwrap();
+
+        let proposals = FsFileLoader::new().find_candidates(dir.path(), &[dir.path().to_path_buf()], &file).await.unwrap();
+
+        assert!(proposals.is_empty(), "Got: {:?}", proposals);
+    }
+}
+
+// ===== Schema migrations =====
+
+mod migrations {
+    use super::*;
+    use crate::migration::{MigrationRegistry, CURRENT_SCHEMA_VERSION};
+    use serde_json::{json, Value};
+
+    /// A v1 document from before `name` was called `title` and before themes existed
+    fn legacy_v1_document() -> Value {
+        json!({
+            "project": {
+                "title": "Old study",
+                "schema_version": 1,
+                "created_at": "2024-01-01T00:00:00Z",
+                "updated_at": "2024-01-02T00:00:00Z"
+            },
+            "codebook": { "code_defs": {}, "qual_codes": [] },
+            "filemanager": { "files": {} }
+        })
+    }
+
+    fn rename_title(doc: &mut Value) -> Result<(), String> {
+        let project = doc["project"].as_object_mut().ok_or("project is not an object")?;
+        let title = project.remove("title").ok_or("missing title")?;
+        project.insert("name".to_string(), title);
+        Ok(())
+    }
+
+    fn add_themes(doc: &mut Value) -> Result<(), String> {
+        doc["codebook"]["themes"] = json!({});
+        Ok(())
+    }
+
+    fn test_registry() -> MigrationRegistry {
+        MigrationRegistry::new(3)
+            .register(1, "Rename project title to name", rename_title)
+            .register(2, "Add themes to codebook", add_themes)
+    }
+
+    #[tokio::test]
+    async fn test_load_runs_all_steps_and_reports() {
+        // Setup
+        let (_dir, path) = project_path("project.json");
+        std::fs::write(&path, legacy_v1_document().to_string()).unwrap();
+        let repo = JsonRepository::new(path.clone()).with_migrations(test_registry());
+
+        // Execute
+        let (project, codebook, _) = repo.load_project(&path).await.unwrap();
+        let report = repo.take_migration_report().expect("Migration should be reported");
+
+        // Assert: Upgraded in memory only
+        assert_eq!(project.name(), "Old study");
+        assert_eq!(project.schema_version(), 3);
+        assert_eq!(codebook.get_all_themes().count(), 0);
+        assert_eq!((report.from_version, report.to_version), (1, 3));
+        assert_eq!(report.applied, vec!["Rename project title to name", "Add themes to codebook"]);
+        assert!(repo.take_migration_report().is_none(), "Taking the report should clear it");
+    }
+
+    /// Every file in `dir` with its contents
+    fn directory_contents(dir: &Path) -> Vec<(String, Vec<u8>)> {
+        let mut entries: Vec<(String, Vec<u8>)> = std::fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .map(|path| (path.file_name().unwrap().to_string_lossy().into_owned(), std::fs::read(&path).unwrap()))
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    #[tokio::test]
+    async fn test_migrating_load_leaves_the_directory_unchanged() {
+        // Setup: A v1 file, opened the way a read-only open does, with just a load
+        let (dir, path) = project_path("project.json");
+        std::fs::write(&path, legacy_v1_document().to_string()).unwrap();
+        let before = directory_contents(dir.path());
+        let repo = JsonRepository::new(path.clone()).with_migrations(test_registry());
+
+        // Execute
+        repo.load_project(&path).await.unwrap();
+
+        // Assert
+        assert!(repo.take_migration_report().is_some());
+        assert_eq!(directory_contents(dir.path()), before);
+    }
+
+    #[tokio::test]
+    async fn test_first_save_after_migrating_keeps_the_original() {
+        // Setup
+        let (dir, path) = project_path("project.json");
+        let original = legacy_v1_document().to_string();
+        std::fs::write(&path, &original).unwrap();
+        let repo = JsonRepository::new(path.clone()).with_migrations(test_registry());
+        let (project, codebook, files) = repo.load_project(&path).await.unwrap();
+
+        // Execute: An autosave, which doesn't rotate backups, then a manual save
+        repo.autosave_project(&path, project.clone(), codebook.clone(), files.clone(), repo.journal_mark(&path)).await.unwrap();
+        repo.save_project(&path, project, codebook, files, repo.journal_mark(&path)).await.unwrap();
+
+        // Assert: The copy holds the v1 file rather than the autosaved one
+        assert_eq!(std::fs::read_to_string(dir.path().join("project.json.v1")).unwrap(), original);
+    }
+
+    /// A project saved by a v1 build, with a file of the bare `Other` type it used for formats
+    /// it had no loader for
+    const V1_PROJECT_WITH_OTHER_FILE: &str = r#"{
+  "project": {
+    "name": "Field study",
+    "schema_version": 1,
+    "created_at": "2026-10-17T16:05:40.483167346Z",
+    "updated_at": "2026-10-17T16:05:40.484394855Z",
+    "embed_sources": false
+  },
+  "codebook": {
+    "code_defs": {},
+    "themes": {},
+    "qual_codes": []
+  },
+  "filemanager": {
+    "files": {
+      "1e9ae213-3288-49ed-a674-42da3dc6a68d": {
+        "id": "1e9ae213-3288-49ed-a674-42da3dc6a68d",
+        "path": "interviews/ana.txt",
+        "file_type": "PlainText",
+        "anchors": [],
+        "fingerprint": null,
+        "embed_source": null,
+        "snapshot": null
+      },
+      "7b3ec156-9674-447e-b0f0-d87dee4113b2": {
+        "id": "7b3ec156-9674-447e-b0f0-d87dee4113b2",
+        "path": "notes/site-visit.xyz",
+        "file_type": "Other",
+        "anchors": [],
+        "fingerprint": null,
+        "embed_source": null,
+        "snapshot": null
+      }
+    }
+  }
+}"#;
+
+    #[tokio::test]
+    async fn test_v1_other_file_type_gets_a_name() {
+        // Setup
+        let (_dir, path) = project_path("project.json");
+        std::fs::write(&path, V1_PROJECT_WITH_OTHER_FILE).unwrap();
+        let repo = JsonRepository::new(path.clone());
+
+        // Execute: Load with the built-in steps, save, and load again
+        let (project, codebook, files) = repo.load_project(&path).await.unwrap();
+        let report = repo.take_migration_report().expect("A v1 project should be migrated");
+        repo.save_project(&path, project, codebook, files.clone(), repo.journal_mark(&path)).await.unwrap();
+        let (saved, _, reloaded) = repo.load_project(&path).await.unwrap();
+
+        // Assert: Only the Other file changed, and the saved file needs no further migration
+        let types: Vec<(&str, &FileType)> = files.get_all_files().map(|f| (f.path(), f.file_type())).collect();
+        let unnamed = FileType::Other(crate::migration::UNNAMED_FORMAT.to_string());
+        assert_eq!(types, vec![("interviews/ana.txt", &FileType::PlainText), ("notes/site-visit.xyz", &unnamed)]);
+        assert_eq!(report.applied, vec!["Name the format of files typed Other"]);
+        assert_eq!(saved.schema_version(), CURRENT_SCHEMA_VERSION);
+        assert_eq!(reloaded.get_all_files().nth(1).unwrap().file_type(), &unnamed);
+        assert!(repo.take_migration_report().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_v1_journal_with_other_file_is_replayed() {
+        // Setup: A v1 build journaled an Other file and then a code before crashing. Its
+        // records carry neither a sequence number nor a schema version.
+        let (_dir, path) = project_path("project.json");
+        std::fs::write(&path, V1_PROJECT_WITH_OTHER_FILE).unwrap();
+        let file_id = "0c5f3a8e-7d2b-4e61-9a3f-2b8d1c4e5f60";
+        let added = serde_json::json!({ "at": "2026-10-17T16:06:00Z", "entry": { "op": "file_inserted", "data": {
+            "id": file_id, "path": "notes/market.xyz", "file_type": "Other", "anchors": [],
+            "fingerprint": null, "embed_source": null, "snapshot": null,
+        } } });
+        let code = populated_codebook().get_all_code_defs().next().unwrap().clone();
+        let coded = serde_json::json!({ "at": "2026-10-17T16:06:01Z", "entry": { "op": "code_def_inserted", "data": code } });
+        std::fs::write(crate::journal::journal_path(&path), format!("{}\n{}\n", added, coded)).unwrap();
+        let repo = JsonRepository::new(path.clone());
+
+        // Execute
+        let (_, codebook, files) = repo.load_project(&path).await.unwrap();
+
+        // Assert: Both entries replayed, with the file's format named like the snapshot's
+        assert!(repo.take_journal_recovery().is_none(), "A v1 entry is not damage");
+        let file = files.get_all_files().find(|f| f.path() == "notes/market.xyz").expect("Journaled file should be replayed");
+        assert_eq!(file.file_type(), &FileType::Other(crate::migration::UNNAMED_FORMAT.to_string()));
+        assert!(codebook.code_def(code.id).is_some(), "Entries after the file should be replayed too");
+    }
+
+    #[tokio::test]
+    async fn test_current_version_is_not_migrated() {
+        let (dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        let project = repo.new_project(&path, "Study".to_string()).await.unwrap();
+
+        repo.load_project(&path).await.unwrap();
+
+        assert_eq!(project.schema_version(), CURRENT_SCHEMA_VERSION);
+        assert!(repo.take_migration_report().is_none());
+        assert!(!dir.path().join(format!("project.json.v{}", CURRENT_SCHEMA_VERSION)).exists());
+    }
+
+    #[tokio::test]
+    async fn test_newer_schema_is_refused() {
+        // Setup: File written by a future version
+        let (_dir, path) = project_path("project.json");
+        let repo = JsonRepository::new(path.clone());
+        repo.new_project(&path, "Study".to_string()).await.unwrap();
+        let mut doc: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        doc["project"]["schema_version"] = json!(CURRENT_SCHEMA_VERSION + 1);
+        std::fs::write(&path, doc.to_string()).unwrap();
+
+        // Execute
+        let err = repo.load_project(&path).await.unwrap_err();
+
+        // Assert
+        assert!(
+            matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::InvalidFormat(_))),
+            "Newer schema should be InvalidFormat, got: {}", err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_step_is_invalid_format() {
+        let (_dir, path) = project_path("project.json");
+        std::fs::write(&path, legacy_v1_document().to_string()).unwrap();
+        let registry = MigrationRegistry::new(3).register(1, "Rename project title to name", rename_title);
+        let repo = JsonRepository::new(path.clone()).with_migrations(registry);
+
+        let err = repo.load_project(&path).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::InvalidFormat(_))));
+    }
+
+    #[tokio::test]
+    async fn test_failing_step_is_invalid_format() {
+        // Setup: v1 file that is missing the field the first step needs
+        let (_dir, path) = project_path("project.json");
+        let mut doc = legacy_v1_document();
+        doc["project"].as_object_mut().unwrap().remove("title");
+        std::fs::write(&path, doc.to_string()).unwrap();
+        let repo = JsonRepository::new(path.clone()).with_migrations(test_registry());
+
+        // Execute
+        let err = repo.load_project(&path).await.unwrap_err();
+
+        // Assert
+        assert!(matches!(err.downcast_ref::<ProjectError>(), Some(ProjectError::InvalidFormat(_))));
+    }
+}
+
+// ===== Text and Markdown loaders =====
+
+mod text_loaders {
+    use super::*;
+    use super::loader_fixtures::file_id;
+    use crate::loaders::encoding::decode_text;
+    use crate::loaders::{markdown, text};
+
+    fn contents(blocks: &[TextBlock]) -> Vec<(&str, BlockKind)> {
+        blocks.iter().map(|b| (b.content.as_str(), b.meta.kind)).collect()
+    }
+
+    #[test]
+    fn test_byte_order_marks_pick_the_encoding() {
+        let le: Vec<u8> = [0xFF, 0xFE].into_iter().chain("Größe".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        let be: Vec<u8> = [0xFE, 0xFF].into_iter().chain("Größe".encode_utf16().flat_map(u16::to_be_bytes)).collect();
+
+        assert_eq!(decode_text(&le).unwrap(), "Größe");
+        assert_eq!(decode_text(&be).unwrap(), "Größe");
+        assert_eq!(decode_text("\u{FEFF}Größe".as_bytes()).unwrap(), "Größe");
+    }
+
+    #[test]
+    fn test_non_utf8_falls_back_to_latin1() {
+        // Windows-1252 curly quotes around a Latin-1 accented word
+        assert_eq!(decode_text(b"\x93caf\xE9\x94").unwrap(), "\u{201C}café\u{201D}");
+    }
+
+    #[test]
+    fn test_binary_and_broken_utf16_are_rejected() {
+        assert!(matches!(decode_text(b"\x89PNG\x00\x00\xFF"), Err(FileError::Encoding(_))));
+        // Unpaired high surrogate
+        assert!(matches!(decode_text(&[0xFF, 0xFE, 0x00, 0xD8, 0x41, 0x00]), Err(FileError::Encoding(_))));
+    }
+
+    #[test]
+    fn test_windows_line_endings_give_same_blocks() {
+        let id = file_id();
+
+        let unix = text::parse(id, "First\nline\n\nSecond\n");
+        let windows = text::parse(id, "First\r\nline\r\n\r\nSecond\r\n");
+
+        let ids = |blocks: &[TextBlock]| blocks.iter().map(|b| b.id).collect::<Vec<_>>();
+        assert_eq!(ids(&unix), ids(&windows));
+        assert_eq!(windows[0].content, "First\nline");
+    }
+
+    #[test]
+    fn test_markdown_structure_becomes_block_kinds() {
+        // Setup
+        let source = "# Interview 3\n\nIntro text.\n\n## Themes\n\n- Housing\n  - Rent\n- Work\n\n1. First\n\n> Quoted\n> words\n\n```\n  let x = 1;\n```\n\n| Q | A |\n|---|---|\n| Age | 34 |\n";
+
+        // Execute
+        let blocks = markdown::parse(file_id(), source);
+
+        // Assert
+        assert_eq!(contents(&blocks), vec![
+            ("Interview 3", BlockKind::Heading(1)),
+            ("Intro text.", BlockKind::Paragraph),
+            ("Themes", BlockKind::Heading(2)),
+            ("Housing", BlockKind::ListItem { ordered: false, depth: 1 }),
+            ("Rent", BlockKind::ListItem { ordered: false, depth: 2 }),
+            ("Work", BlockKind::ListItem { ordered: false, depth: 1 }),
+            ("First", BlockKind::ListItem { ordered: true, depth: 1 }),
+            ("Quoted words", BlockKind::Quote),
+            ("  let x = 1;", BlockKind::Code),
+            ("Q\tA", BlockKind::TableRow),
+            ("Age\t34", BlockKind::TableRow),
+        ]);
+        assert!(blocks.iter().enumerate().all(|(i, b)| b.sequence == i));
+    }
+
+    #[test]
+    fn test_markdown_blocks_hold_the_visible_text() {
+        // Setup
+        let blocks = markdown::parse(file_id(), "She said **it was _hard_** at [first](https://example.org).  \nThen `better`.");
+
+        // Execute: Offsets as the UI would compute them from what it displays
+        let content = &blocks[0].content;
+        let start = content.find("hard").unwrap();
+
+        // Assert
+        assert_eq!(content, "She said it was hard at first.\nThen better.");
+        assert_eq!(&content[start..start + 4], "hard");
+    }
+
+    #[test]
+    fn test_loose_list_items_keep_their_kind() {
+        let blocks = markdown::parse(file_id(), "- One\n\n  More about one\n\n- Two\n");
+
+        assert_eq!(contents(&blocks), vec![
+            ("One", BlockKind::ListItem { ordered: false, depth: 1 }),
+            ("More about one", BlockKind::ListItem { ordered: false, depth: 1 }),
+            ("Two", BlockKind::ListItem { ordered: false, depth: 1 }),
+        ]);
+    }
+}
+
+// ===== PDF loader =====
+
+mod pdf_loader {
+    use super::*;
+    use super::loader_fixtures::file_id;
+    use crate::file_loader::FsFileLoader;
+    use crate::loaders::pdf;
+    use app_core::ports::FileLoader;
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Document, Object, Stream};
+
+    /// PDF with a page per entry, each line set in 12pt Helvetica. An empty line leaves
+    /// a paragraph sized gap.
+    fn pdf_with(pages: &[&[&str]], encrypted: bool) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+        let resources_id = doc.add_object(dictionary! { "Font" => dictionary! { "F1" => font_id } });
+
+        let mut kids: Vec<Object> = Vec::new();
+        for lines in pages {
+            let mut operations = vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                Operation::new("Td", vec![72.into(), 720.into()]),
+            ];
+            for line in lines.iter() {
+                if !line.is_empty() {
+                    operations.push(Operation::new("Tj", vec![Object::string_literal(*line)]));
+                }
+                operations.push(Operation::new("Td", vec![0.into(), (-14).into()]));
+            }
+            operations.push(Operation::new("ET", vec![]));
+            let content = Content { operations }.encode().unwrap();
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content));
+            kids.push(doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id, "Contents" => content_id }).into());
+        }
+        let count = kids.len() as i64;
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+        }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+
+        if encrypted {
+            // Standard security handler with a user password, which "" won't match
+            let encrypt_id = doc.add_object(dictionary! {
+                "Filter" => "Standard",
+                "V" => 1,
+                "R" => 2,
+                "O" => Object::string_literal(vec![1u8; 32]),
+                "U" => Object::string_literal(vec![2u8; 32]),
+                "P" => -44,
+            });
+            doc.trailer.set("Encrypt", encrypt_id);
+            doc.trailer.set("ID", vec![Object::string_literal(vec![3u8; 16]), Object::string_literal(vec![3u8; 16])]);
+        }
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn pages_and_text(blocks: &[TextBlock]) -> Vec<(Option<u32>, &str)> {
+        blocks.iter().map(|b| (b.meta.page(), b.content.as_str())).collect()
+    }
+
+    #[test]
+    fn test_paragraphs_are_tagged_with_their_page() {
+        // Setup
+        let bytes = pdf_with(&[
+            &["Executive summary", "", "Most respondents found the", "service easy to use."],
+            &["Second page finding."],
+        ], false);
+
+        // Execute
+        let blocks = pdf::parse(file_id(), &bytes).unwrap();
+
+        // Assert: Lines of a paragraph are reflowed into one
+        assert_eq!(pages_and_text(&blocks), vec![
+            (Some(1), "Executive summary"),
+            (Some(1), "Most respondents found the service easy to use."),
+            (Some(2), "Second page finding."),
+        ]);
+    }
+
+    #[test]
+    fn test_hyphenated_line_breaks_are_rejoined() {
+        let bytes = pdf_with(&[&["Participants described the sup-", "port as well-", "Known locally."]], false);
+
+        let blocks = pdf::parse(file_id(), &bytes).unwrap();
+
+        assert_eq!(blocks[0].content, "Participants described the support as well- Known locally.");
+    }
+
+    #[test]
+    fn test_image_only_pdf_is_parse_error() {
+        let bytes = pdf_with(&[&[], &[]], false);
+
+        let err = pdf::parse(file_id(), &bytes).unwrap_err();
+
+        assert!(matches!(&err, FileError::Parse(m) if m.contains("OCR")), "Got: {}", err);
+    }
+
+    #[test]
+    fn test_password_protected_pdf_is_parse_error() {
+        let bytes = pdf_with(&[&["Secret"]], true);
+
+        let err = pdf::parse(file_id(), &bytes).unwrap_err();
+
+        assert!(matches!(&err, FileError::Parse(m) if m.contains("password")), "Got: {}", err);
+    }
+
+    #[test]
+    fn test_truncated_pdf_is_parse_error() {
+        let bytes = pdf_with(&[&["Text"]], false);
+
+        let err = pdf::parse(file_id(), &bytes[..bytes.len() / 3]).unwrap_err();
+
+        assert!(matches!(err, FileError::Parse(_)), "Got: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_pdf_loads_through_file_loader() {
+        let (dir, path) = project_path("report.pdf");
+        std::fs::write(&path, pdf_with(&[&["Findings"]], false)).unwrap();
+        let loader = FsFileLoader::new();
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+
+        let blocks = loader.load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        assert_eq!(pages_and_text(&blocks), vec![(Some(1), "Findings")]);
+    }
+}
+
+// ===== DOCX and ODT loaders =====
+
+mod office_loaders {
+    use super::*;
+    use super::loader_fixtures::{file_id, kinds_and_text};
+    use crate::file_loader::{detect_file_type, FsFileLoader};
+    use crate::loaders::{docx, odt};
+    use app_core::ports::FileLoader;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main""#;
+    const ODF: &str = concat!(
+        r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" "#,
+        r#"xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" "#,
+        r#"xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" "#,
+        r#"xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" "#,
+        r#"xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0""#,
+    );
+
+    /// Zip archive of the given entries in order. The first is stored uncompressed, as
+    /// OpenDocument requires of its mimetype entry.
+    pub(super) fn archive(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (i, (name, contents)) in entries.iter().enumerate() {
+            let method = if i == 0 { CompressionMethod::Stored } else { CompressionMethod::Deflated };
+            zip.start_file(*name, SimpleFileOptions::default().compression_method(method)).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// Word document with `body` as the contents of `w:body`. Its styles give "Heading1"
+    /// and a localized "berschrift2" heading levels, and "Strong" bold text. Numbering 1 is
+    /// bulleted and 2 numbered.
+    fn docx_with(body: &str) -> Vec<u8> {
+        let styles = format!(r#"<w:styles {W}>
+            <w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/></w:style>
+            <w:style w:type="paragraph" w:styleId="berschrift2"><w:name w:val="heading 2"/></w:style>
+            <w:style w:type="character" w:styleId="Strong"><w:name w:val="Strong"/><w:rPr><w:b/></w:rPr></w:style>
+        </w:styles>"#);
+        let numbering = format!(r#"<w:numbering {W}>
+            <w:abstractNum w:abstractNumId="0"><w:lvl w:ilvl="0"><w:numFmt w:val="bullet"/></w:lvl></w:abstractNum>
+            <w:abstractNum w:abstractNumId="1"><w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/></w:lvl><w:lvl w:ilvl="1"><w:numFmt w:val="lowerLetter"/></w:lvl></w:abstractNum>
+            <w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>
+            <w:num w:numId="2"><w:abstractNumId w:val="1"/></w:num>
+        </w:numbering>"#);
+        let document = format!(r#"<?xml version="1.0" encoding="UTF-8"?><w:document {W}><w:body>{body}</w:body></w:document>"#);
+        archive(&[
+            ("[Content_Types].xml", "<Types/>"),
+            ("word/document.xml", &document),
+            ("word/styles.xml", &styles),
+            ("word/numbering.xml", &numbering),
+        ])
+    }
+
+    /// OpenDocument text with `body` as the contents of `office:text`. Automatic style "T1"
+    /// is bold and "T2" normal weight, list style "L1" numbered at level 1 and bulleted at 2.
+    fn odt_with(body: &str) -> Vec<u8> {
+        let content = format!(r#"<?xml version="1.0" encoding="UTF-8"?><office:document-content {ODF}>
+            <office:automatic-styles>
+                <style:style style:name="T1" style:family="text"><style:text-properties fo:font-weight="bold"/></style:style>
+                <style:style style:name="T2" style:family="text"><style:text-properties fo:font-weight="normal"/></style:style>
+                <style:style style:name="P1" style:family="paragraph" style:parent-style-name="Label"/>
+                <text:list-style style:name="L1">
+                    <text:list-level-style-number text:level="1"/>
+                    <text:list-level-style-bullet text:level="2"/>
+                </text:list-style>
+            </office:automatic-styles>
+            <office:body><office:text>{body}</office:text></office:body>
+        </office:document-content>"#);
+        let styles = format!(r#"<office:document-styles {ODF}><office:styles>
+            <style:style style:name="Label" style:family="paragraph"><style:text-properties fo:font-weight="700"/></style:style>
+        </office:styles></office:document-styles>"#);
+        archive(&[
+            ("mimetype", "application/vnd.oasis.opendocument.text"),
+            ("content.xml", &content),
+            ("styles.xml", &styles),
+        ])
+    }
+
+    fn speakers(blocks: &[TextBlock]) -> Vec<Option<&str>> {
+        blocks.iter().map(|b| b.meta.speaker()).collect()
+    }
+
+    #[test]
+    fn test_docx_headings_and_lists_get_block_kinds() {
+        // Setup
+        let bytes = docx_with(r#"
+            <w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Interview 3</w:t></w:r></w:p>
+            <w:p><w:pPr><w:pStyle w:val="berschrift2"/></w:pPr><w:r><w:t>Background</w:t></w:r></w:p>
+            <w:p><w:r><w:t xml:space="preserve">Recorded in </w:t></w:r><w:r><w:t>March &amp; April.</w:t></w:r></w:p>
+            <w:p/>
+            <w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Bullet</w:t></w:r></w:p>
+            <w:p><w:pPr><w:numPr><w:ilvl w:val="1"/><w:numId w:val="2"/></w:numPr></w:pPr><w:r><w:t>Nested step</w:t></w:r></w:p>
+            <w:p><w:pPr><w:outlineLvl w:val="2"/></w:pPr><w:r><w:t>Outlined</w:t></w:r></w:p>
+        "#);
+
+        // Execute
+        let blocks = docx::parse(file_id(), &bytes).unwrap();
+
+        // Assert: The empty paragraph is dropped
+        assert_eq!(kinds_and_text(&blocks), vec![
+            (BlockKind::Heading(1), "Interview 3"),
+            (BlockKind::Heading(2), "Background"),
+            (BlockKind::Paragraph, "Recorded in March & April."),
+            (BlockKind::ListItem { ordered: false, depth: 1 }, "Bullet"),
+            (BlockKind::ListItem { ordered: true, depth: 2 }, "Nested step"),
+            (BlockKind::Heading(3), "Outlined"),
+        ]);
+    }
+
+    #[test]
+    fn test_docx_bold_speaker_labels_become_metadata() {
+        // Setup
+        let bytes = docx_with(r#"
+            <w:p><w:r><w:rPr><w:b/></w:rPr><w:t>Interviewer:</w:t></w:r><w:r><w:t xml:space="preserve"> How did it start?</w:t></w:r></w:p>
+            <w:p><w:r><w:rPr><w:rStyle w:val="Strong"/></w:rPr><w:t>P3</w:t></w:r><w:r><w:t>: With a phone call.</w:t></w:r></w:p>
+            <w:p><w:r><w:rPr><w:b/></w:rPr><w:t>Dr Smith</w:t></w:r><w:r><w:tab/><w:t>Go on.</w:t></w:r></w:p>
+            <w:p><w:r><w:t>Note: not a speaker.</w:t></w:r></w:p>
+            <w:p><w:r><w:rPr><w:b/></w:rPr><w:t>This whole sentence was emphasised by the transcriber:</w:t></w:r></w:p>
+            <w:p><w:r><w:rPr><w:b w:val="0"/></w:rPr><w:t>Off:</w:t></w:r><w:r><w:t xml:space="preserve"> bold turned off</w:t></w:r></w:p>
+        "#);
+
+        // Execute
+        let blocks = docx::parse(file_id(), &bytes).unwrap();
+
+        // Assert: Labels stay in the text so highlights can include them
+        assert_eq!(blocks[0].content, "Interviewer: How did it start?");
+        assert_eq!(speakers(&blocks), vec![Some("Interviewer"), Some("P3"), Some("Dr Smith"), None, None, None]);
+    }
+
+    #[test]
+    fn test_docx_table_rows_are_tab_separated_blocks() {
+        // Setup: A two column transcript table with a nested table in one cell
+        let bytes = docx_with(r#"
+            <w:tbl>
+                <w:tr>
+                    <w:tc><w:p><w:r><w:rPr><w:b/></w:rPr><w:t>Interviewer</w:t></w:r></w:p></w:tc>
+                    <w:tc><w:p><w:r><w:t>First question</w:t></w:r></w:p><w:p><w:r><w:t>continued</w:t></w:r></w:p></w:tc>
+                </w:tr>
+                <w:tr>
+                    <w:tc><w:p/></w:tc>
+                    <w:tc><w:tbl><w:tr><w:tc><w:p><w:r><w:t>Inner</w:t></w:r></w:p></w:tc></w:tr></w:tbl></w:tc>
+                </w:tr>
+            </w:tbl>
+            <w:p><w:r><w:t>After</w:t></w:r></w:p>
+        "#);
+
+        // Execute
+        let blocks = docx::parse(file_id(), &bytes).unwrap();
+
+        // Assert
+        assert_eq!(kinds_and_text(&blocks), vec![
+            (BlockKind::TableRow, "Interviewer\tFirst question\ncontinued"),
+            (BlockKind::TableRow, "\tInner"),
+            (BlockKind::Paragraph, "After"),
+        ]);
+        assert_eq!(speakers(&blocks), vec![Some("Interviewer"), None, None]);
+    }
+
+    #[test]
+    fn test_docx_skips_deletions_and_text_boxes() {
+        let bytes = docx_with(r#"
+            <w:p>
+                <w:r><w:t>Kept</w:t></w:r>
+                <w:del><w:r><w:delText>removed</w:delText></w:r></w:del>
+                <w:ins><w:r><w:t xml:space="preserve"> inserted</w:t></w:r></w:ins>
+                <w:r><w:drawing><w:p><w:r><w:t>Caption</w:t></w:r></w:p></w:drawing></w:r>
+                <w:r><w:br/><w:t>next line</w:t></w:r>
+            </w:p>
+        "#);
+
+        let blocks = docx::parse(file_id(), &bytes).unwrap();
+
+        assert_eq!(kinds_and_text(&blocks), vec![(BlockKind::Paragraph, "Kept inserted\nnext line")]);
+    }
+
+    #[test]
+    fn test_docx_without_document_part_is_parse_error() {
+        let bytes = archive(&[("[Content_Types].xml", "<Types/>")]);
+
+        let err = docx::parse(file_id(), &bytes).unwrap_err();
+
+        assert!(matches!(&err, FileError::Parse(m) if m.contains("word/document.xml")), "Got: {}", err);
+    }
+
+    #[test]
+    fn test_odt_headings_lists_and_tables() {
+        // Setup
+        let bytes = odt_with(r#"
+            <text:h text:outline-level="2">Session<text:s text:c="2"/>one</text:h>
+            <text:p>Plain<text:tab/>text<text:line-break/>wrapped
+            here</text:p>
+            <text:list text:style-name="L1">
+                <text:list-item><text:p>First</text:p>
+                    <text:list><text:list-item><text:p>Sub point</text:p></text:list-item></text:list>
+                </text:list-item>
+            </text:list>
+            <table:table>
+                <table:table-row><table:table-cell><text:p>A</text:p></table:table-cell><table:table-cell><text:p>B</text:p></table:table-cell></table:table-row>
+            </table:table>
+            <text:p>Body<text:note><text:note-body><text:p>Footnote</text:p></text:note-body></text:note></text:p>
+        "#);
+
+        // Execute
+        let blocks = odt::parse(file_id(), &bytes).unwrap();
+
+        // Assert: Nested lists inherit their parent's style
+        assert_eq!(kinds_and_text(&blocks), vec![
+            (BlockKind::Heading(2), "Session  one"),
+            (BlockKind::Paragraph, "Plain\ttext\nwrapped here"),
+            (BlockKind::ListItem { ordered: true, depth: 1 }, "First"),
+            (BlockKind::ListItem { ordered: false, depth: 2 }, "Sub point"),
+            (BlockKind::TableRow, "A\tB"),
+            (BlockKind::Paragraph, "Body"),
+        ]);
+    }
+
+    #[test]
+    fn test_odt_bold_speaker_labels_become_metadata() {
+        // Setup: P1 is bold through its parent style in styles.xml
+        let bytes = odt_with(r#"
+            <text:p><text:span text:style-name="T1">Interviewer:</text:span> Tell me more.</text:p>
+            <text:p text:style-name="P1">Respondent:<text:span text:style-name="T2"> It was fine.</text:span></text:p>
+            <text:p>Moderator: plain label</text:p>
+        "#);
+
+        // Execute
+        let blocks = odt::parse(file_id(), &bytes).unwrap();
+
+        // Assert
+        assert_eq!(speakers(&blocks), vec![Some("Interviewer"), Some("Respondent"), None]);
+    }
+
+    #[test]
+    fn test_office_types_are_sniffed() {
+        // Setup
+        let docx = docx_with("");
+        let odt = odt_with("");
+        let other_zip = archive(&[("data.csv", "a,b")]);
+
+        // Assert: ODT is recognised by its mimetype entry, DOCX by its parts or extension
+        assert_eq!(detect_file_type(Path::new("interview"), &docx).unwrap(), FileType::Docx);
+        assert_eq!(detect_file_type(Path::new("interview.zip"), &odt).unwrap(), FileType::Odt);
+        assert_eq!(detect_file_type(Path::new("interview.docx"), &other_zip).unwrap(), FileType::Docx);
+        assert!(matches!(detect_file_type(Path::new("data.zip"), &other_zip), Err(FileError::Unsupported(_))));
+        assert!(matches!(detect_file_type(Path::new("fake.docx"), b"plain text"), Err(FileError::Unsupported(_))));
+        assert!(matches!(detect_file_type(Path::new("old.doc"), &[0xD0, 0xCF, 0x11, 0xE0, 0, 0]), Err(FileError::Unsupported(m)) if m.contains(".docx")));
+    }
+
+    #[tokio::test]
+    async fn test_docx_loads_through_file_loader() {
+        let (dir, path) = project_path("interview.docx");
+        std::fs::write(&path, docx_with(r#"<w:p><w:r><w:rPr><w:b/></w:rPr><w:t>P1:</w:t></w:r><w:r><w:t xml:space="preserve"> Hello</w:t></w:r></w:p>"#)).unwrap();
+        let loader = FsFileLoader::new();
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+
+        let blocks = loader.load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        assert_eq!(files.file(id).unwrap().file_type(), &FileType::Docx);
+        assert_eq!(kinds_and_text(&blocks), vec![(BlockKind::Paragraph, "P1: Hello")]);
+        assert_eq!(speakers(&blocks), vec![Some("P1")]);
+    }
+}
+
+// ===== RTF loader =====
+
+mod rtf_loader {
+    use super::*;
+    use super::loader_fixtures::{file_id, kinds_and_text};
+    use crate::file_loader::FsFileLoader;
+    use crate::loaders::rtf;
+    use app_core::ports::FileLoader;
+
+    const HEADER: &str = r"{\rtf1\ansi\ansicpg1252\deff0{\fonttbl{\f0\fswiss\fcharset0 Arial;}{\f1\fnil\fcharset128 MS Mincho;}{\f2\fnil\fcharset204 Arial Cyr;}}
+{\colortbl ;\red255\green0\blue0;}
+{\stylesheet{\s0 Normal;}{\s1\b\fs32 heading 1;}{\s2\b\fs28 \sbasedon1 heading 2;}}
+{\*\generator Riched20 10.0.19041}{\info{\author Transcription Co}}
+";
+
+    fn rtf_with(body: &str) -> Vec<u8> {
+        format!("{}{}}}", HEADER, body).into_bytes()
+    }
+
+    fn texts(blocks: &[TextBlock]) -> Vec<&str> {
+        blocks.iter().map(|b| b.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_paragraphs_headings_and_lists() {
+        // Setup
+        let bytes = rtf_with(r"\pard\s1\b\fs32 Interview notes\b0\par
+\pard\s0\f0\fs24 First paragraph with \i italic\i0  and a \{brace\}.\par
+\pard\outlinelevel1 Outlined\par
+\pard\s0 Wrapped across
+source lines\line second line\par
+{\listtext\pard\plain\f3 \'b7\tab}\pard\ls1\ilvl0 Bullet\par
+{\listtext\pard\plain 2.\tab}\pard\ls2\ilvl1 Numbered\par
+\pard\par
+");
+
+        // Execute
+        let blocks = rtf::parse(file_id(), &bytes).unwrap();
+
+        // Assert: Header tables and metadata aren't text, and empty paragraphs are dropped
+        assert_eq!(kinds_and_text(&blocks), vec![
+            (BlockKind::Heading(1), "Interview notes"),
+            (BlockKind::Paragraph, "First paragraph with italic and a {brace}."),
+            (BlockKind::Heading(2), "Outlined"),
+            (BlockKind::Paragraph, "Wrapped acrosssource lines\nsecond line"),
+            (BlockKind::ListItem { ordered: false, depth: 1 }, "Bullet"),
+            (BlockKind::ListItem { ordered: true, depth: 2 }, "Numbered"),
+        ]);
+    }
+
+    #[test]
+    fn test_unicode_escapes_skip_their_fallbacks() {
+        // Setup: Negative values, a surrogate pair, and \uc changing the fallback length
+        let bytes = rtf_with(r"\pard \u8220?quoted\u8221?, caf\u233\'e9, \u-10179?\u-8704?\par
+{\uc2 \u8364\'80E done}\par
+{\uc0 \u8212 end}\par
+");
+
+        // Execute
+        let blocks = rtf::parse(file_id(), &bytes).unwrap();
+
+        // Assert
+        assert_eq!(texts(&blocks), vec!["\u{201c}quoted\u{201d}, caf\u{e9}, \u{1f600}", "\u{20ac} done", "\u{2014}end"]);
+    }
+
+    #[test]
+    fn test_hex_bytes_use_font_and_document_code_pages() {
+        // Setup: Windows-1252 by default, Shift_JIS for the charset 128 font, Cyrillic for 204
+        let bytes = rtf_with(r"\pard\f0 na\'efve \'93quote\'94\par
+\pard{\f1 \'82\'a0\'82\'a2}\par
+\pard{\f2 \'cf\'f0\'e8\'e2\'e5\'f2}\par
+");
+
+        // Execute
+        let blocks = rtf::parse(file_id(), &bytes).unwrap();
+
+        // Assert
+        assert_eq!(texts(&blocks), vec!["na\u{ef}ve \u{201c}quote\u{201d}", "\u{3042}\u{3044}", "Привет"]);
+    }
+
+    #[test]
+    fn test_ansicpg_sets_the_default_code_page() {
+        let bytes = br"{\rtf1\ansi\ansicpg1251 \'cf\'f0\'e8\'e2\'e5\'f2\par}";
+
+        let blocks = rtf::parse(file_id(), bytes).unwrap();
+
+        assert_eq!(texts(&blocks), vec!["Привет"]);
+    }
+
+    #[test]
+    fn test_non_body_destinations_are_skipped() {
+        // Setup
+        let bytes = rtf_with(r#"{\header \pard Page header\par}
+\pard See {\field{\*\fldinst HYPERLINK "https://example.com"}{\fldrslt the site}}{\footnote \pard Footnote text}.
+{\pict\pngblip 89504e470d0a}{\*\bkmkstart mark}{\*\bkmkend mark}\par
+\pard Binary {\object\objdata \bin4 {}\}} after\par
+"#);
+
+        // Execute
+        let blocks = rtf::parse(file_id(), &bytes).unwrap();
+
+        // Assert: Field results are kept, their instructions aren't
+        assert_eq!(texts(&blocks), vec!["See the site.", "Binary  after"]);
+    }
+
+    #[test]
+    fn test_bold_speaker_labels_and_table_rows() {
+        // Setup
+        let bytes = rtf_with(r"\pard{\b Interviewer:} Where were you?\par
+\pard\plain {\b P2}: At home.\par
+\pard Note: not bold\par
+\trowd\cellx2000\cellx6000
+\pard\intbl{\b Interviewer}\cell First question\par continued\cell\row
+\pard\intbl\cell Answer\cell\row
+\pard After the table\par
+");
+
+        // Execute
+        let blocks = rtf::parse(file_id(), &bytes).unwrap();
+
+        // Assert: Empty cells keep their column
+        assert_eq!(kinds_and_text(&blocks), vec![
+            (BlockKind::Paragraph, "Interviewer: Where were you?"),
+            (BlockKind::Paragraph, "P2: At home."),
+            (BlockKind::Paragraph, "Note: not bold"),
+            (BlockKind::TableRow, "Interviewer\tFirst question\ncontinued"),
+            (BlockKind::TableRow, "\tAnswer"),
+            (BlockKind::Paragraph, "After the table"),
+        ]);
+        let speakers: Vec<_> = blocks.iter().map(|b| b.meta.speaker()).collect();
+        assert_eq!(speakers, vec![Some("Interviewer"), Some("P2"), None, Some("Interviewer"), None, None]);
+    }
+
+    #[test]
+    fn test_non_rtf_is_parse_error() {
+        let err = rtf::parse(file_id(), b"Just text").unwrap_err();
+
+        assert!(matches!(err, FileError::Parse(_)), "Got: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_rtf_loads_through_file_loader() {
+        let (dir, path) = project_path("memo.rtf");
+        std::fs::write(&path, rtf_with(r"\pard Hello \'93world\'94\par")).unwrap();
+        let loader = FsFileLoader::new();
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+
+        let blocks = loader.load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        assert_eq!(texts(&blocks), vec!["Hello \u{201c}world\u{201d}"]);
+    }
+}
+
+// ===== Transcript loaders =====
+
+mod transcript_loaders {
+    use super::*;
+    use super::loader_fixtures::file_id;
+    use crate::file_loader::{detect_file_type, FsFileLoader};
+    use crate::loaders::transcript::{looks_like_speaker_turns, parse_speaker_turns, parse_srt, parse_webvtt};
+    use app_core::ports::FileLoader;
+    use std::time::Duration;
+
+    /// Speaker, start and end in milliseconds, and text of a block
+    type Turn<'a> = (Option<&'a str>, Option<u128>, Option<u128>, &'a str);
+
+    fn turns(blocks: &[TextBlock]) -> Vec<Turn<'_>> {
+        blocks.iter().map(|b| (
+            b.meta.speaker(),
+            b.meta.start().map(|t| t.as_millis()),
+            b.meta.end().map(|t| t.as_millis()),
+            b.content.as_str(),
+        )).collect()
+    }
+
+    #[test]
+    fn test_webvtt_voice_spans_become_speaker_turns() {
+        // Setup: The second cue continues Roger's turn without naming him
+        let vtt = "WEBVTT - Interview 4\r\n\r\nNOTE exported by a tool\r\n\r\n1\r\n00:00:01.000 --> 00:00:04.500 align:start\r\n<v Roger Bingham>We are in New York City\r\n\r\n00:04.500 --> 00:06.000\r\nand it is <i>cold</i>\r\n\r\n00:00:06.000 --> 00:00:09.250\r\n<v.loud Neil>Tom &amp; Jerry&#39;s fault</v>\r\n";
+
+        // Execute
+        let blocks = parse_webvtt(file_id(), vtt).unwrap();
+
+        // Assert: Cue lines and timings are merged, markup is dropped
+        assert_eq!(turns(&blocks), vec![
+            (Some("Roger Bingham"), Some(1000), Some(6000), "We are in New York City and it is cold"),
+            (Some("Neil"), Some(6000), Some(9250), "Tom & Jerry's fault"),
+        ]);
+    }
+
+    #[test]
+    fn test_webvtt_name_prefixes_merge_consecutive_cues() {
+        // Setup: Zoom style, naming the speaker in every cue
+        let vtt = "WEBVTT\n\n1\n00:00:00.000 --> 00:00:02.000\nJane Doe: Thanks for joining.\n\n2\n00:00:02.000 --> 00:00:05.000\nJane Doe: Shall we start?\n\n3\n00:00:05.000 --> 00:00:06.000\nSam: Sure.\n";
+
+        // Execute
+        let blocks = parse_webvtt(file_id(), vtt).unwrap();
+
+        // Assert
+        assert_eq!(turns(&blocks), vec![
+            (Some("Jane Doe"), Some(0), Some(5000), "Thanks for joining. Shall we start?"),
+            (Some("Sam"), Some(5000), Some(6000), "Sure."),
+        ]);
+    }
+
+    #[test]
+    fn test_srt_without_speakers_keeps_each_cue() {
+        // Setup
+        let srt = "1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>First line</i>\r\nwrapped\r\n\r\n2\r\n00:00:03,000 --> 00:00:04,000\r\n{\\an8}Meet at 10:30 sharp\r\n";
+
+        // Execute
+        let blocks = parse_srt(file_id(), srt).unwrap();
+
+        // Assert: A colon that isn't after a name isn't a speaker label
+        assert_eq!(turns(&blocks), vec![
+            (None, Some(1000), Some(2500), "First line wrapped"),
+            (None, Some(3000), Some(4000), "Meet at 10:30 sharp"),
+        ]);
+    }
+
+    #[test]
+    fn test_srt_name_prefixes_become_speakers() {
+        let srt = "1\n01:00:00,000 --> 01:00:01,000\nINTERVIEWER: Ready?\n\n2\n01:00:01,000 --> 01:00:03,000\nP2: Yes.\n";
+
+        let blocks = parse_srt(file_id(), srt).unwrap();
+
+        assert_eq!(turns(&blocks), vec![
+            (Some("INTERVIEWER"), Some(3_600_000), Some(3_601_000), "Ready?"),
+            (Some("P2"), Some(3_601_000), Some(3_603_000), "Yes."),
+        ]);
+    }
+
+    #[test]
+    fn test_captions_without_cues_are_parse_errors() {
+        assert!(matches!(parse_webvtt(file_id(), "Just text"), Err(FileError::Parse(_))));
+        assert!(matches!(parse_webvtt(file_id(), "WEBVTT\n\nNOTE nothing\n"), Err(FileError::Parse(_))));
+        assert!(matches!(parse_srt(file_id(), "1\nnot a timing\nText\n"), Err(FileError::Parse(_))));
+    }
+
+    #[test]
+    fn test_speaker_turns_end_where_the_next_starts() {
+        // Setup
+        let text = "Speaker 1  0:00\nHello everyone, thanks for joining.\n\nJane Doe  01:05\nThanks for having me.\nIt was at 10:30 that day.\n\nSpeaker 1  1:02:03\nGreat.\n\nTranscribed by https://otter.ai\n";
+
+        // Execute
+        let blocks = parse_speaker_turns(file_id(), text);
+
+        // Assert: A clock time inside a turn's text doesn't start a new turn
+        assert_eq!(turns(&blocks), vec![
+            (Some("Speaker 1"), Some(0), Some(65_000), "Hello everyone, thanks for joining."),
+            (Some("Jane Doe"), Some(65_000), Some(3_723_000), "Thanks for having me.\nIt was at 10:30 that day."),
+            (Some("Speaker 1"), Some(3_723_000), None, "Great."),
+        ]);
+    }
+
+    #[test]
+    fn test_speaker_turns_are_sniffed_from_text() {
+        assert!(looks_like_speaker_turns("\nAlex  0:03\nHi\n\nBo  0:09\nHello\n"));
+        assert!(looks_like_speaker_turns("0:00\nUnnamed\n\n0:30\nturns\n"));
+        // One header isn't enough to tell a transcript from notes that mention a time
+        assert!(!looks_like_speaker_turns("Standup 9:30\nNotes from the meeting\n"));
+        assert!(!looks_like_speaker_turns("Alex  0:03\nHi\n\nBo  0:01\nTime went backwards\n"));
+        assert!(!looks_like_speaker_turns("Interviewer: hello\n\nP1: hi\n"));
+    }
+
+    #[test]
+    fn test_transcript_types_are_detected() {
+        assert_eq!(detect_file_type(Path::new("call.txt"), b"\xEF\xBB\xBFWEBVTT\n\n").unwrap(), FileType::WebVtt);
+        assert_eq!(detect_file_type(Path::new("call.srt"), b"1\n00:00:01,000 --> 00:00:02,000\nHi\n").unwrap(), FileType::Srt);
+        assert_eq!(detect_file_type(Path::new("call.txt"), b"Alex  0:03\nHi\n\nBo  0:09\nHello\n").unwrap(), FileType::Transcript);
+        assert_eq!(detect_file_type(Path::new("notes.txt"), b"WEBVTTX is a made up word").unwrap(), FileType::PlainText);
+        assert!(matches!(detect_file_type(Path::new("call.vtt"), b"00:01 --> 00:02"), Err(FileError::Unsupported(_))));
+    }
+
+    #[tokio::test]
+    async fn test_transcript_loads_through_file_loader() {
+        let (dir, path) = project_path("otter.txt");
+        std::fs::write(&path, "Alex  0:03\nHi\n\nBo  0:09\nHello\n").unwrap();
+        let loader = FsFileLoader::new();
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+
+        let blocks = loader.load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        assert_eq!(files.file(id).unwrap().file_type(), &FileType::Transcript);
+        assert_eq!(blocks[1].meta.speaker(), Some("Bo"));
+        assert_eq!(blocks[0].meta.end(), Some(Duration::from_secs(9)));
+    }
+}
+
+// ===== Spreadsheet loaders =====
+
+mod spreadsheet_loaders {
+    use super::*;
+    use super::office_loaders::archive;
+    use crate::file_loader::{detect_file_type, FsFileLoader};
+    use crate::loaders::spreadsheet::{read_csv, read_xlsx};
+    use app_core::ports::FileLoader;
+
+    const SHEET_NS: &str = r#"xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main""#;
+    const REL_NS: &str = r#"xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships""#;
+
+    /// Workbook with a sheet per entry. Cells that parse as numbers are stored as numbers,
+    /// everything else as inline strings.
+    fn xlsx(sheets: &[(&str, &[&[&str]])]) -> Vec<u8> {
+        let mut entries = Vec::new();
+        let names: String = sheets.iter().enumerate()
+            .map(|(i, (name, _))| format!(r#"<sheet name="{name}" sheetId="{n}" r:id="rId{n}"/>"#, n = i + 1))
+            .collect();
+        entries.push(("xl/workbook.xml".to_string(), format!(r#"<workbook {SHEET_NS} {REL_NS}><sheets>{names}</sheets></workbook>"#)));
+        let rels: String = (1..=sheets.len())
+            .map(|n| format!(r#"<Relationship Id="rId{n}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{n}.xml"/>"#))
+            .collect();
+        entries.push(("xl/_rels/workbook.xml.rels".to_string(), format!(r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">{rels}</Relationships>"#)));
+
+        for (i, (_, rows)) in sheets.iter().enumerate() {
+            let rows: String = rows.iter().enumerate().map(|(r, cells)| {
+                let cells: String = cells.iter().enumerate().map(|(c, value)| {
+                    let at = format!("{}{}", (b'A' + c as u8) as char, r + 1);
+                    match value.parse::<f64>() {
+                        Ok(_) => format!(r#"<c r="{at}"><v>{value}</v></c>"#),
+                        Err(_) => format!(r#"<c r="{at}" t="inlineStr"><is><t>{value}</t></is></c>"#),
+                    }
+                }).collect();
+                format!(r#"<row r="{}">{cells}</row>"#, r + 1)
+            }).collect();
+            entries.push((format!("xl/worksheets/sheet{}.xml", i + 1), format!(r#"<worksheet {SHEET_NS}><sheetData>{rows}</sheetData></worksheet>"#)));
+        }
+        let entries: Vec<(&str, &str)> = entries.iter().map(|(name, xml)| (name.as_str(), xml.as_str())).collect();
+        archive(&entries)
+    }
+
+    fn selection(text_columns: &[&str], attribute_columns: &[&str]) -> SurveySelection {
+        SurveySelection {
+            sheet: None,
+            id_column: "ID".to_string(),
+            text_columns: text_columns.iter().map(|c| c.to_string()).collect(),
+            attribute_columns: attribute_columns.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_csv_keeps_quoted_cells_whole() {
+        let text = "ID,Age,Comments\r\nR1,34,\"Too expensive, honestly\"\r\nR2,51,\"Line one\r\nline two\"\r\n";
+
+        let table = read_csv(text).unwrap();
+
+        assert_eq!(table.columns, vec!["ID", "Age", "Comments"]);
+        assert_eq!(table.rows, vec![
+            vec!["R1", "34", "Too expensive, honestly"],
+            vec!["R2", "51", "Line one\nline two"],
+        ]);
+        assert!(table.sheets.is_empty());
+    }
+
+    #[test]
+    fn test_csv_delimiter_is_sniffed_from_header() {
+        let semicolons = read_csv("ID;Comments\nR1;Yes, mostly\n").unwrap();
+        let tabs = read_csv("ID\tComments\tAge\nR1\tFine\n").unwrap();
+
+        assert_eq!(semicolons.rows, vec![vec!["R1", "Yes, mostly"]]);
+        assert_eq!(tabs.columns, vec!["ID", "Comments", "Age"]);
+        assert_eq!(tabs.rows, vec![vec!["R1", "Fine"]], "Short rows are kept as they are");
+    }
+
+    #[test]
+    fn test_blank_headers_are_named_by_position() {
+        let table = read_csv("ID,,Q2\nR1,a,b\n").unwrap();
+
+        assert_eq!(table.columns, vec!["ID", "Column 2", "Q2"]);
+        assert!(matches!(read_csv(""), Err(FileError::Parse(_))));
+    }
+
+    #[test]
+    fn test_xlsx_reads_chosen_sheet() {
+        let workbook = xlsx(&[
+            ("About", &[&["Survey of clinic users"]]),
+            ("Responses", &[&["ID", "Age", "Q1"], &["R1", "34", "Long waits"], &["R2", "51", "Fine"]]),
+        ]);
+
+        let first = read_xlsx(&workbook, None).unwrap();
+        let responses = read_xlsx(&workbook, Some("Responses")).unwrap();
+
+        assert_eq!(first.sheets, vec!["About", "Responses"]);
+        assert_eq!(first.columns, vec!["Survey of clinic users"]);
+        assert_eq!(responses.columns, vec!["ID", "Age", "Q1"]);
+        assert_eq!(responses.rows[0], vec!["R1", "34", "Long waits"]);
+        assert!(matches!(read_xlsx(&workbook, Some("Missing")), Err(FileError::Parse(m)) if m.contains("Missing")));
+    }
+
+    #[test]
+    fn test_spreadsheets_are_detected() {
+        let workbook = xlsx(&[("Sheet1", &[&["ID"]])]);
+
+        assert_eq!(detect_file_type(Path::new("survey.csv"), b"ID,Q1\nR1,Hi\n").unwrap(), FileType::Csv);
+        assert_eq!(detect_file_type(Path::new("survey.tsv"), b"ID\tQ1\n").unwrap(), FileType::Csv);
+        assert_eq!(detect_file_type(Path::new("export.zip"), &workbook).unwrap(), FileType::Xlsx);
+        assert!(matches!(detect_file_type(Path::new("old.xls"), b"ID,Q1\n"), Err(FileError::Unsupported(m)) if m.contains("legacy")));
+    }
+
+    #[tokio::test]
+    async fn test_survey_file_loads_its_column_from_xlsx() {
+        // Setup: Import the workbook's second sheet, one file for Q1
+        let (dir, path) = project_path("survey.xlsx");
+        std::fs::write(&path, xlsx(&[
+            ("About", &[&["Notes"]]),
+            ("Responses", &[&["ID", "Age", "Q1"], &["R1", "34", "Long waits"], &["R2", "51", ""], &["R3", "29", "Friendly"]]),
+        ])).unwrap();
+        let loader = FsFileLoader::new();
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+        let mut selection = selection(&["Q1"], &["Age"]);
+        selection.sheet = Some("Responses".to_string());
+        files.file_mut(id).unwrap().set_survey(Some(SurveyView { selection, scope: SurveyScope::Column("Q1".to_string()) }));
+
+        // Execute
+        let blocks = loader.load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        // Assert
+        assert_eq!(files.file(id).unwrap().file_type(), &FileType::Xlsx);
+        let answers: Vec<(&str, Option<&str>, Option<&str>)> = blocks.iter()
+            .map(|b| (b.content.as_str(), b.meta.case(), b.meta.attribute("Age")))
+            .collect();
+        assert_eq!(answers, vec![("Long waits", Some("R1"), Some("34")), ("Friendly", Some("R3"), Some("29"))]);
+    }
+
+    #[tokio::test]
+    async fn test_csv_without_survey_loads_as_text() {
+        let (dir, path) = project_path("notes.csv");
+        std::fs::write(&path, "ID,Q1\nR1,Hello\n").unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file("notes.csv".to_string(), FileType::Csv);
+
+        let blocks = FsFileLoader::new().load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+        let table = FsFileLoader::new().read_table(&path, None).await.unwrap();
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].content, "ID,Q1\nR1,Hello");
+        assert_eq!(table.columns, vec!["ID", "Q1"]);
+    }
+}
+
+// ===== HTML and EPUB loaders =====
+
+mod web_loaders {
+    use super::*;
+    use super::loader_fixtures::{file_id, kinds_and_text};
+    use super::office_loaders::archive;
+    use crate::file_loader::{detect_file_type, FsFileLoader};
+    use crate::loaders::{epub, html};
+    use app_core::ports::FileLoader;
+
+    /// EPUB with the package document in `OEBPS/`. `chapters` are (manifest id, href, body)
+    /// and `spine` lists manifest ids, with a trailing `!` for `linear="no"`.
+    fn epub_with(chapters: &[(&str, &str, &str)], spine: &[&str], extra_manifest: &str, extra_files: &[(&str, &str)]) -> Vec<u8> {
+        let manifest: String = chapters.iter()
+            .map(|(id, href, _)| format!(r#"<item id="{id}" href="{href}" media-type="application/xhtml+xml"/>"#))
+            .collect();
+        let spine: String = spine.iter()
+            .map(|id| match id.strip_suffix('!') {
+                Some(id) => format!(r#"<itemref idref="{id}" linear="no"/>"#),
+                None => format!(r#"<itemref idref="{id}"/>"#),
+            })
+            .collect();
+        let container = r#"<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#;
+        let package = format!(r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" version="3.0"><manifest>{manifest}{extra_manifest}</manifest><spine toc="ncx">{spine}</spine></package>"#);
+
+        let mut files: Vec<(String, String)> = vec![
+            ("mimetype".to_string(), "application/epub+zip".to_string()),
+            ("META-INF/container.xml".to_string(), container.to_string()),
+            ("OEBPS/content.opf".to_string(), package),
+        ];
+        for (_, href, body) in chapters {
+            let path = format!("OEBPS/{}", href.replace("%20", " "));
+            files.push((path, format!(r#"<?xml version="1.0" encoding="utf-8"?><html xmlns="http://www.w3.org/1999/xhtml"><head><title>Untitled</title></head><body>{body}</body></html>"#)));
+        }
+        files.extend(extra_files.iter().map(|(path, xml)| (path.to_string(), xml.to_string())));
+        let entries: Vec<(&str, &str)> = files.iter().map(|(path, xml)| (path.as_str(), xml.as_str())).collect();
+        archive(&entries)
+    }
+
+    fn chapters(blocks: &[TextBlock]) -> Vec<(Option<&str>, &str)> {
+        blocks.iter().map(|b| (b.meta.chapter(), b.content.as_str())).collect()
+    }
+
+    #[test]
+    fn test_html_keeps_structure_and_drops_furniture() {
+        let page = r#"<!DOCTYPE html><html><head><title>Policy</title><style>p { color: red }</style></head><body>
+            <nav><a href="/">Home</a></nav>
+            <script>track()</script>
+            <h1>Housing  Policy</h1>
+            <p>First   paragraph,<br>second line.</p>
+            <ul><li>Bullet<ol><li>Nested step</li></ol></li></ul>
+            <blockquote><p>Quoted</p></blockquote>
+            <pre>  indented
+    code</pre>
+            <table><tr><th>Year</th><th>Units</th></tr><tr><td>2024</td><td><p>120</p><p>net</p></td></tr></table>
+            <div hidden>Secret</div><div aria-hidden="true">Icon</div>
+            <footer>Copyright</footer>
+        </body></html>"#;
+
+        let blocks = html::parse(file_id(), page);
+
+        assert_eq!(kinds_and_text(&blocks), vec![
+            (BlockKind::Heading(1), "Housing Policy"),
+            (BlockKind::Paragraph, "First paragraph,\nsecond line."),
+            (BlockKind::ListItem { ordered: false, depth: 1 }, "Bullet"),
+            (BlockKind::ListItem { ordered: true, depth: 2 }, "Nested step"),
+            (BlockKind::Quote, "Quoted"),
+            (BlockKind::Code, "  indented\n    code"),
+            (BlockKind::TableRow, "Year\tUnits"),
+            (BlockKind::TableRow, "2024\t120\nnet"),
+        ]);
+    }
+
+    #[test]
+    fn test_html_reads_only_main_content_when_marked() {
+        let page = r#"<html><body>
+            <header>Site name</header>
+            <aside>Related links</aside>
+            <main><article><header><h2>Article title</h2></header><p>Body text</p></article></main>
+            <div>Cookie banner</div>
+        </body></html>"#;
+
+        let blocks = html::parse(file_id(), page);
+
+        assert_eq!(kinds_and_text(&blocks), vec![(BlockKind::Heading(2), "Article title"), (BlockKind::Paragraph, "Body text")]);
+    }
+
+    #[test]
+    fn test_html_records_source_url() {
+        let canonical = r#"<html><head><link rel="canonical" href="https://example.org/policy"><meta property="og:url" content="https://example.org/og"></head><body><p>Text</p></body></html>"#;
+        let saved = "<!DOCTYPE html>\n<!-- saved from url=(0029)https://example.org/saved.htm -->\n<html><body><p>Text</p></body></html>";
+        let local = "<html><body><p>Text</p></body></html>";
+
+        assert_eq!(html::parse(file_id(), canonical)[0].meta.url(), Some("https://example.org/policy"));
+        assert_eq!(html::parse(file_id(), saved)[0].meta.url(), Some("https://example.org/saved.htm"));
+        assert_eq!(html::parse(file_id(), local)[0].meta.url(), None);
+        assert_eq!(html::extract(canonical).title, None);
+    }
+
+    #[test]
+    fn test_html_charset_declaration_is_honoured() {
+        let latin = b"<html><head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1252\"></head><body><p>Caf\xE9 \x93quoted\x94</p></body></html>";
+        let mislabelled = b"<html><head><meta charset=\"utf-8\"></head><body><p>Caf\xE9</p></body></html>";
+
+        let latin = html::parse(file_id(), &html::decode(latin).unwrap());
+        let mislabelled = html::parse(file_id(), &html::decode(mislabelled).unwrap());
+
+        assert_eq!(latin[0].content, "Caf\u{e9} \u{201c}quoted\u{201d}");
+        assert_eq!(mislabelled[0].content, "Caf\u{e9}", "A wrong declaration falls back to guessing");
+    }
+
+    #[test]
+    fn test_epub_follows_spine_with_toc_titles() {
+        // Setup: Manifest order differs from the spine, and the nav page and a footnote are in the spine too
+        let nav = r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body>
+            <nav epub:type="landmarks"><ol><li><a href="text/ch2.xhtml">Start here</a></li></ol></nav>
+            <nav epub:type="toc"><ol>
+                <li><a href="text/ch1.xhtml">1. Beginnings</a></li>
+                <li><a href="text/ch2.xhtml#top">2. The   Middle</a><ol><li><a href="text/ch2.xhtml#s1">Section</a></li></ol></li>
+            </ol></nav></body></html>"#;
+        let book = epub_with(
+            &[
+                ("ch2", "text/ch2.xhtml", "<h1>Middle</h1><p>Second chapter</p>"),
+                ("ch1", "text/ch1.xhtml", "<p>First chapter</p>"),
+                ("note", "text/note.xhtml", "<p>A footnote</p>"),
+            ],
+            &["toc", "ch1", "ch2", "note!"],
+            r#"<item id="toc" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#,
+            &[("OEBPS/nav.xhtml", nav)],
+        );
+
+        // Execute
+        let blocks = epub::parse(file_id(), &book).unwrap();
+
+        // Assert
+        assert_eq!(chapters(&blocks), vec![
+            (Some("1. Beginnings"), "First chapter"),
+            (Some("2. The Middle"), "Middle"),
+            (Some("2. The Middle"), "Second chapter"),
+        ]);
+        assert_eq!(blocks[1].meta.kind, BlockKind::Heading(1));
+        assert_eq!(blocks.iter().map(|b| b.sequence).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_epub2_titles_come_from_ncx_or_headings() {
+        let ncx = r#"<?xml version="1.0"?><ncx xmlns="http://www.daisy.org/z3986/2005/ncx/"><navMap>
+            <navPoint id="p1"><navLabel><text>Opening &amp; Context</text></navLabel><content src="Chapter%20One.html"/></navPoint>
+        </navMap></ncx>"#;
+        let book = epub_with(
+            &[
+                ("c1", "Chapter%20One.html", "<p>One</p>"),
+                ("c2", "two.html", "<h2>Untitled in the toc</h2><p>Two</p>"),
+            ],
+            &["c1", "c2"],
+            r#"<item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>"#,
+            &[("OEBPS/toc.ncx", ncx)],
+        );
+
+        let blocks = epub::parse(file_id(), &book).unwrap();
+
+        assert_eq!(chapters(&blocks), vec![
+            (Some("Opening & Context"), "One"),
+            (Some("Untitled in the toc"), "Untitled in the toc"),
+            (Some("Untitled in the toc"), "Two"),
+        ]);
+    }
+
+    #[test]
+    fn test_epub_without_container_is_parse_error() {
+        let not_a_book = archive(&[("mimetype", "application/epub+zip"), ("chapter.xhtml", "<p>Hi</p>")]);
+
+        assert!(matches!(epub::parse(file_id(), &not_a_book), Err(FileError::Parse(m)) if m.contains("container.xml")));
+    }
+
+    #[test]
+    fn test_web_formats_are_detected() {
+        let book = epub_with(&[("c1", "c1.xhtml", "<p>One</p>")], &["c1"], "", &[]);
+
+        assert_eq!(detect_file_type(Path::new("page.htm"), b"<p>Saved</p>").unwrap(), FileType::Html);
+        assert_eq!(detect_file_type(Path::new("download"), b"\n  <!DOCTYPE HTML>\n<html>").unwrap(), FileType::Html);
+        assert_eq!(detect_file_type(Path::new("notes.txt"), b"<b>not a page</b>").unwrap(), FileType::PlainText);
+        assert_eq!(detect_file_type(Path::new("book.zip"), &book).unwrap(), FileType::Epub);
+        assert!(matches!(detect_file_type(Path::new("book.epub"), b"plain text"), Err(FileError::Unsupported(_))));
+    }
+
+    #[tokio::test]
+    async fn test_epub_loads_through_file_loader() {
+        let (dir, path) = project_path("book.epub");
+        std::fs::write(&path, epub_with(&[("c1", "c1.xhtml", "<p>One</p><p>Two</p>")], &["c1"], "", &[])).unwrap();
+        let loader = FsFileLoader::new();
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+
+        let blocks = loader.load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        assert_eq!(files.file(id).unwrap().file_type(), &FileType::Epub);
+        // No toc and no heading, so the chapter's <title> names it
+        assert_eq!(chapters(&blocks), vec![(Some("Untitled"), "One"), (Some("Untitled"), "Two")]);
+    }
+}
+
+// ===== Email and chat loaders =====
+
+mod message_loaders {
+    use super::*;
+    use super::loader_fixtures::file_id;
+    use crate::file_loader::{detect_file_type, FsFileLoader};
+    use crate::loaders::{chat, email};
+    use app_core::ports::FileLoader;
+
+    fn authors_and_text(blocks: &[TextBlock]) -> Vec<(Option<&str>, &str)> {
+        blocks.iter().map(|b| (b.meta.author(), b.content.as_str())).collect()
+    }
+
+    const REPLY: &str = "From: =?utf-8?Q?Jos=C3=A9_Garc=C3=ADa?= <jose@example.org>\r\n\
+        To: ana@example.org\r\n\
+        Subject: Re: Interview\r\n \tschedule\r\n\
+        Date: Tue, 5 Mar 2024 14:30:00 +0100 (CET)\r\n\
+        Message-ID: <2@example.org>\r\n\
+        In-Reply-To: <1@example.org>\r\n\
+        References: <1@example.org>\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        Content-Transfer-Encoding: quoted-printable\r\n\
+        \r\n\
+        Thursday works, caf=C3=A9 near the =\r\n\
+        station?\r\n\
+        \r\n\
+        On Mon, 4 Mar 2024 at 09:00, Ana <ana@example.org> wrote:\r\n\
+        > Could we meet this week?\r\n";
+
+    #[test]
+    fn test_eml_decodes_headers_and_quoted_printable_and_strips_reply() {
+        let blocks = email::parse(file_id(), REPLY.as_bytes()).unwrap();
+
+        assert_eq!(authors_and_text(&blocks), vec![(Some("José García <jose@example.org>"), "Thursday works, café near the station?")]);
+        let meta = &blocks[0].meta;
+        assert_eq!(meta.subject(), Some("Re: Interview schedule"));
+        assert_eq!(meta.thread(), Some("<1@example.org>"));
+        assert_eq!(meta.sent().unwrap().to_rfc3339(), "2024-03-05T13:30:00+00:00");
+    }
+
+    #[test]
+    fn test_mbox_splits_messages_and_unescapes_from_lines() {
+        let mbox = "From ana@example.org Mon Mar  4 09:00:00 2024\n\
+            From: Ana <ana@example.org>\n\
+            Subject: Interview\n\
+            Message-ID: <1@example.org>\n\
+            \n\
+            Could we meet this week?\n\
+            >From now on I'm free.\n\
+            \n\
+            From jose@example.org Tue Mar  5 13:30:00 2024\n\
+            From: Jose <jose@example.org>\n\
+            Subject: Re: Interview\n\
+            In-Reply-To: <1@example.org>\n\
+            \n\
+            Yes.\n";
+
+        let blocks = email::parse(file_id(), mbox.as_bytes()).unwrap();
+
+        assert_eq!(authors_and_text(&blocks), vec![
+            (Some("Ana <ana@example.org>"), "Could we meet this week?\nFrom now on I'm free."),
+            (Some("Jose <jose@example.org>"), "Yes."),
+        ]);
+        assert!(blocks.iter().all(|b| b.meta.thread() == Some("<1@example.org>")));
+        assert_eq!(blocks.iter().map(|b| b.sequence).collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_multipart_prefers_plain_text_and_skips_attachments() {
+        let message = "From: Ana <ana@example.org>\n\
+            Subject: =?iso-8859-1?B?UulzdW3p?=\n\
+            Content-Type: multipart/mixed; boundary=\"outer\"\n\
+            \n\
+            --outer\n\
+            Content-Type: multipart/alternative; boundary=inner\n\
+            \n\
+            --inner\n\
+            Content-Type: text/html\n\
+            \n\
+            <p>HTML version</p>\n\
+            --inner\n\
+            Content-Type: text/plain; charset=utf-8\n\
+            Content-Transfer-Encoding: base64\n\
+            \n\
+            UGxhaW4gdmVyc2lvbg==\n\
+            --inner--\n\
+            --outer\n\
+            Content-Type: text/plain\n\
+            Content-Disposition: attachment; filename=\"notes.txt\"\n\
+            \n\
+            Attached notes\n\
+            --outer--\n";
+
+        let blocks = email::parse(file_id(), message.as_bytes()).unwrap();
+
+        assert_eq!(authors_and_text(&blocks), vec![(Some("Ana <ana@example.org>"), "Plain version")]);
+        assert_eq!(blocks[0].meta.subject(), Some("Résumé"));
+    }
+
+    #[test]
+    fn test_html_only_email_drops_quoted_blockquotes_and_outlook_replies() {
+        let html = "From: Ben <ben@example.org>\n\
+            Content-Type: text/html; charset=utf-8\n\
+            \n\
+            <html><body><p>See below.</p><blockquote><p>Earlier text</p></blockquote></body></html>\n";
+        let outlook = "From: Ben <ben@example.org>\n\
+            Subject: RE: AW: Budget\n\
+            \n\
+            Agreed.\n\
+            \n\
+            From: Ana <ana@example.org>\n\
+            Sent: Monday, March 4, 2024 9:00 AM\n\
+            Subject: Budget\n\
+            \n\
+            Earlier text\n";
+
+        let html = email::parse(file_id(), html.as_bytes()).unwrap();
+        let outlook = email::parse(file_id(), outlook.as_bytes()).unwrap();
+
+        assert_eq!(html[0].content, "See below.");
+        assert_eq!(outlook[0].content, "Agreed.");
+        assert_eq!(outlook[0].meta.thread(), Some("Budget"), "Without ids, threads go by subject");
+    }
+
+    #[test]
+    fn test_non_ascii_subject_threads_without_prefixes() {
+        let message = "From: Ana <ana@example.org>\n\
+            Subject: Re: Ünïcode\n\
+            \n\
+            Noted.\n";
+        let unprefixed = "From: Ana <ana@example.org>\n\
+            Subject: Ünïcode\n\
+            \n\
+            Noted.\n";
+
+        let reply = email::parse(file_id(), message.as_bytes()).unwrap();
+        let original = email::parse(file_id(), unprefixed.as_bytes()).unwrap();
+
+        assert_eq!(reply[0].meta.thread(), Some("Ünïcode"));
+        assert_eq!(original[0].meta.thread(), Some("Ünïcode"));
+    }
+
+    #[test]
+    fn test_text_without_headers_is_not_an_email() {
+        assert!(matches!(email::parse(file_id(), b"Just some notes\nand more"), Err(FileError::Parse(_))));
+        assert!(matches!(email::parse(file_id(), b"  \n"), Err(FileError::Parse(m)) if m.contains("No messages")));
+    }
+
+    #[test]
+    fn test_chat_log_reads_common_export_shapes() {
+        // Setup: Telegram's object with formatted text, Discord's author objects and Slack's ts strings
+        let telegram = r#"{"name": "Study group", "messages": [
+            {"from": "Ana", "date": "2024-03-04T09:00:00", "text": ["See ", {"type": "link", "text": "the form"}]},
+            {"from": "Ben", "date": "2024-03-04T09:01:00", "text": ""}
+        ]}"#;
+        let discord = r#"{"messages": [{"author": {"name": "ana#1", "nickname": "Ana"}, "timestamp": "2024-03-04T09:00:00+01:00", "content": "Hello"}]}"#;
+        let slack = r#"[{"user": "U1", "user_profile": {"real_name": "Ana"}, "ts": "1709542800.000200", "thread_ts": "1709542800.000200", "text": "Hi"}]"#;
+
+        // Execute
+        let telegram = chat::parse(file_id(), telegram).unwrap();
+        let discord = chat::parse(file_id(), discord).unwrap();
+        let slack = chat::parse(file_id(), slack).unwrap();
+
+        // Assert: Ben's empty message is left out
+        assert_eq!(authors_and_text(&telegram), vec![(Some("Ana"), "See the form")]);
+        assert_eq!(telegram[0].meta.thread(), Some("Study group"));
+        assert_eq!(telegram[0].meta.sent().unwrap().to_rfc3339(), "2024-03-04T09:00:00+00:00");
+        assert_eq!(authors_and_text(&discord), vec![(Some("Ana"), "Hello")]);
+        assert_eq!(discord[0].meta.sent().unwrap().to_rfc3339(), "2024-03-04T08:00:00+00:00");
+        assert_eq!(slack[0].meta.author(), Some("Ana"));
+        assert_eq!(slack[0].meta.thread(), Some("1709542800.000200"));
+        assert_eq!(slack[0].meta.sent().unwrap().to_rfc3339(), "2024-03-04T09:00:00+00:00");
+    }
+
+    #[test]
+    fn test_chat_log_rejects_other_json() {
+        assert!(matches!(chat::parse(file_id(), r#"{"settings": true}"#), Err(FileError::Parse(m)) if m.contains("messages")));
+        assert!(matches!(chat::parse(file_id(), "[1, 2]"), Err(FileError::Parse(_))));
+        assert!(matches!(chat::parse(file_id(), "{"), Err(FileError::Parse(m)) if m.contains("Malformed")));
+    }
+
+    #[test]
+    fn test_message_formats_are_detected() {
+        let mbox = b"From ana@example.org Mon Mar  4 09:00:00 2024\nFrom: Ana\n\nHi\n";
+
+        assert_eq!(detect_file_type(Path::new("reply.eml"), REPLY.as_bytes()).unwrap(), FileType::Email);
+        assert_eq!(detect_file_type(Path::new("Inbox"), mbox).unwrap(), FileType::Email);
+        assert_eq!(detect_file_type(Path::new("letter.txt"), b"From here on\nwe walk.").unwrap(), FileType::PlainText);
+    }
+
+    #[test]
+    fn test_only_chat_shaped_json_is_a_chat_log() {
+        let telegram = br#"{"name": "Study group", "type": "private_group", "messages": [{"id": 1, "from": "Ana", "date": "2024-03-04T09:00:00", "text": "Hi"}"#;
+        let slack = b"\xEF\xBB\xBF[\n  {\"user\": \"U1\", \"ts\": \"1709542800.000200\", \"text\": \"Hi\"}\n]";
+
+        assert_eq!(detect_file_type(Path::new("result.json"), telegram).unwrap(), FileType::ChatLog);
+        assert_eq!(detect_file_type(Path::new("channel"), slack).unwrap(), FileType::ChatLog);
+        assert_eq!(detect_file_type(Path::new("export.json"), b"[]").unwrap(), FileType::PlainText);
+        assert_eq!(detect_file_type(Path::new("package.json"), br#"{"name": "app", "messages": "none"}"#).unwrap(), FileType::PlainText);
+        assert_eq!(detect_file_type(Path::new("codes.json"), br#"[{"code": "Housing", "text": "Rent and repairs"}]"#).unwrap(), FileType::PlainText);
+    }
+
+    #[tokio::test]
+    async fn test_mailbox_loads_through_file_loader() {
+        let (dir, path) = project_path("reply.eml");
+        std::fs::write(&path, REPLY).unwrap();
+        let loader = FsFileLoader::new();
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+
+        let blocks = loader.load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        assert_eq!(files.file(id).unwrap().file_type(), &FileType::Email);
+        assert_eq!(blocks[0].meta.author(), Some("José García <jose@example.org>"));
+    }
+}
+
+// ===== Loader registry =====
+
+mod loader_registry {
+    use super::*;
+    use crate::file_loader::{detect_file_type, FsFileLoader};
+    use crate::loaders::builtin;
+    use app_core::ports::{FileLoader, FormatLoader};
+    use std::collections::HashSet;
+
+    /// A field notes format an outside crate might add: "NOTE|" lines, each with its site
+    struct FieldNotes {
+        format: FormatInfo,
+    }
+
+    impl FieldNotes {
+        fn new() -> Self {
+            let format = FormatInfo::new(FileType::Other("field-notes".to_string()), "Field notes")
+                .with_extensions(&[".fnote"])
+                .with_signature(b"FNOTES\n")
+                .with_metadata(&["site"]);
+            FieldNotes { format }
+        }
+    }
+
+    impl FormatLoader for FieldNotes {
+        fn format(&self) -> &FormatInfo {
+            &self.format
+        }
+
+        fn parse(&self, file_id: FileId, bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+            let text = std::str::from_utf8(bytes).map_err(|e| FileError::Encoding(e.to_string()))?;
+            Ok(text.lines().skip(1).enumerate().filter_map(|(i, line)| {
+                let (site, note) = line.split_once('|')?;
+                Some(TextBlock::new(file_id, i, note.to_string()).with_attribute("site", site))
+            }).collect())
+        }
+    }
+
+    /// Reads every file as one block saying which loader read it
+    struct Stub(FormatInfo);
+
+    impl FormatLoader for Stub {
+        fn format(&self) -> &FormatInfo {
+            &self.0
+        }
+
+        fn parse(&self, file_id: FileId, _bytes: &[u8]) -> Result<Vec<TextBlock>, FileError> {
+            Ok(vec![TextBlock::new(file_id, 0, format!("Read by {}", self.0.name))])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_added_loader_reads_its_format_through_file_loader() {
+        // Setup
+        let (dir, path) = project_path("visit.fnote");
+        std::fs::write(&path, "FNOTES\nNorth|Queue out the door\nSouth|Quiet morning\n").unwrap();
+        let loader = FsFileLoader::new().with_loader(FieldNotes::new());
+
+        // Execute
+        let imported = loader.add_file(dir.path(), &path, None).await.unwrap();
+        let mut files = FileList::new();
+        let id = files.add_file(imported.path, imported.file_type);
+        let blocks = loader.load_file(dir.path(), files.file(id).unwrap()).await.unwrap();
+
+        // Assert
+        assert_eq!(files.file(id).unwrap().file_type(), &FileType::Other("field-notes".to_string()));
+        let notes: Vec<(Option<&str>, &str)> = blocks.iter().map(|b| (b.meta.attribute("site"), b.content.as_str())).collect();
+        assert_eq!(notes, vec![(Some("North"), "Queue out the door"), (Some("South"), "Quiet morning")]);
+        let format = loader.formats().into_iter().find(|f| f.name == "Field notes").unwrap();
+        assert!(format.provides("site"));
+    }
+
+    #[test]
+    fn test_added_loader_is_asked_before_built_ins() {
+        let loader = FsFileLoader::new().with_loader(FieldNotes::new());
+
+        // The signature wins over a .txt extension, which the built-ins would read as text
+        assert_eq!(loader.detect(Path::new("visit.txt"), b"FNOTES\nNorth|Busy", None).unwrap(), FileType::Other("field-notes".to_string()));
+        assert_eq!(detect_file_type(Path::new("visit.txt"), b"FNOTES\nNorth|Busy").unwrap(), FileType::PlainText);
+        assert!(matches!(loader.detect(Path::new("visit.fnote"), b"Just text", None), Err(FileError::Unsupported(m)) if m.contains("Field notes")));
+        assert!(matches!(detect_file_type(Path::new("visit.fnote"), b"\x00\x01binary"), Err(FileError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_mime_type_picks_the_loader_before_the_extension() {
+        let loader = FsFileLoader::new();
+
+        // Labelled text is read in the labelled format, whatever the file is called
+        assert_eq!(loader.detect(Path::new("download"), b"# Notes", Some("text/markdown; charset=UTF-8")).unwrap(), FileType::Markdown);
+        assert_eq!(loader.detect(Path::new("notes.txt"), b"<p>Notes</p>", Some("TEXT/HTML")).unwrap(), FileType::Html);
+        // Signatures still come first, and binary formats still need theirs
+        assert_eq!(loader.detect(Path::new("download"), b"%PDF-1.7", Some("text/plain")).unwrap(), FileType::Pdf);
+        assert!(matches!(loader.detect(Path::new("download"), b"Just text", Some("application/pdf")), Err(FileError::Unsupported(m)) if m.contains("application/pdf")));
+        // Unknown types fall back to the extension, and JSON is only a chat log by its shape
+        assert_eq!(loader.detect(Path::new("notes.md"), b"# Notes", Some("application/x-unknown")).unwrap(), FileType::Markdown);
+        assert_eq!(loader.detect(Path::new("codes"), b"[]", Some("application/json")).unwrap(), FileType::PlainText);
+    }
+
+    #[tokio::test]
+    async fn test_add_file_uses_the_mime_type_it_is_given() {
+        // Setup
+        let (dir, path) = project_path("upload");
+        std::fs::write(&path, "Name,Answer\nAna,Rent\n").unwrap();
+        let loader = FsFileLoader::new();
+
+        // Execute
+        let labelled = loader.add_file(dir.path(), &path, Some("text/csv")).await.unwrap();
+        let unlabelled = loader.add_file(dir.path(), &path, None).await.unwrap();
+
+        // Assert
+        assert_eq!(labelled.file_type, FileType::Csv);
+        assert_eq!(unlabelled.file_type, FileType::PlainText);
+    }
+
+    #[test]
+    fn test_registering_a_built_in_type_replaces_its_loader() {
+        // Setup
+        let pdf = FormatInfo::new(FileType::Pdf, "Better PDF").with_extensions(&["pdf"]).with_signature(b"%PDF-");
+        let mut registry = builtin::registry();
+        let count = registry.formats().len();
+
+        // Execute
+        let replaced = registry.register(std::sync::Arc::new(Stub(pdf)));
+        let file_id = FileList::new().add_file("paper.pdf".to_string(), FileType::Pdf);
+        let blocks = registry.parse(file_id, &FileType::Pdf, b"%PDF-1.7").unwrap();
+
+        // Assert
+        assert_eq!(replaced.map(|old| old.format().name.clone()), Some("PDF".to_string()));
+        assert_eq!(registry.formats().len(), count);
+        assert_eq!(blocks[0].content, "Read by Better PDF");
+    }
+
+    #[test]
+    fn test_unregistered_type_is_unsupported() {
+        let registry = builtin::registry();
+        let file_id = FileList::new().add_file("notes.xyz".to_string(), FileType::Other("xyz".to_string()));
+
+        let err = registry.parse(file_id, &FileType::Other("xyz".to_string()), b"text").unwrap_err();
+
+        assert!(matches!(err, FileError::Unsupported(m) if m.contains("xyz")));
+    }
+
+    #[test]
+    fn test_built_in_formats_declare_distinct_extensions_and_their_metadata() {
+        let formats = builtin::registry().formats();
+        let by_type = |file_type: FileType| formats.iter().find(|f| f.file_type == file_type).unwrap();
+
+        let extensions: Vec<&String> = formats.iter().flat_map(|f| &f.extensions).collect();
+        let distinct: HashSet<&&String> = extensions.iter().collect();
+        assert_eq!(extensions.len(), distinct.len(), "Two formats claim the same extension");
+        assert!(by_type(FileType::WebVtt).provides(BlockMeta::SPEAKER));
+        assert!(by_type(FileType::Email).provides(BlockMeta::THREAD));
+        assert!(by_type(FileType::Epub).provides(BlockMeta::CHAPTER));
+        assert!(!by_type(FileType::PlainText).provides(BlockMeta::SPEAKER));
+        assert!(by_type(FileType::Pdf).provides(BlockMeta::PAGE));
+        assert!(by_type(FileType::Csv).metadata.is_empty(), "CSV added as a document is read as plain text");
+        assert!(by_type(FileType::Docx).is_binary() && !by_type(FileType::Markdown).is_binary());
+    }
+}